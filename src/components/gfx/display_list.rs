@@ -19,11 +19,16 @@ use render_context::RenderContext;
 use text::TextRun;
 
 use extra::arc::Arc;
+use extra::time::precise_time_ns;
+use extra::url::Url;
 use geom::{Point2D, Rect, Size2D, SideOffsets2D};
-use servo_net::image::base::Image;
+use servo_net::image::base::{Animation, Image};
 use servo_util::geometry::Au;
+use servo_util::geometry;
 use servo_util::range::Range;
 use std::cast::transmute_region;
+use std::uint;
+use std::vec;
 use std::vec::VecIterator;
 use style::computed_values::border_style;
 
@@ -46,9 +51,22 @@ impl<E> DisplayListCollection<E> {
         self.lists.push(list);
     }
 
+    /// Builds the spatial index of every list in the collection. This should be called once the
+    /// lists are complete.
+    pub fn build_indices(&mut self) {
+        for list in self.lists.mut_iter() {
+            list.build_index();
+        }
+    }
+
     pub fn draw_lists_into_context(&self, render_context: &mut RenderContext) {
+        let page_rect = render_context.page_rect;
+        let tile_rect = Rect(Point2D(Au::from_frac_px(page_rect.origin.x as f64),
+                                     Au::from_frac_px(page_rect.origin.y as f64)),
+                             Size2D(Au::from_frac_px(page_rect.size.width as f64),
+                                    Au::from_frac_px(page_rect.size.height as f64)));
         for list in self.lists.iter() {
-            list.draw_into_context(render_context);
+            list.draw_into_context(render_context, &tile_rect);
         }
         debug!("{:?}", self.dump());
     }
@@ -61,11 +79,132 @@ impl<E> DisplayListCollection<E> {
             index = index + 1;
         }
     }
+
+    /// Returns the bounds of the animated images in the collection that will show a new frame
+    /// after the given time, along with the time at which they do so, in nanoseconds.
+    pub fn animated_image_frame_times(&self, time: u64) -> ~[(Rect<Au>, u64)] {
+        let mut frame_times = ~[];
+        for list in self.lists.iter() {
+            for item in list.list.iter() {
+                item.collect_animated_image_frame_times(time, &mut frame_times);
+            }
+        }
+        frame_times
+    }
+
+    /// Describes every item of every list in the collection, one item per line, with the children
+    /// of clips indented beneath them. The output is meant to be diffed between runs.
+    pub fn dump_to_str(&self) -> ~str {
+        let mut output = ~"";
+        for (index, list) in self.lists.iter().enumerate() {
+            output.push_str(format!("DisplayList {}\n", index));
+            for item in list.list.iter() {
+                item.dump_into(&mut output, 1);
+            }
+        }
+        output
+    }
 }
 
 /// A list of rendering operations to be performed.
 pub struct DisplayList<E> {
-    list: ~[DisplayItem<E>]
+    list: ~[DisplayItem<E>],
+
+    /// An index of the items of `list` by position, if it has been built. It must be rebuilt if
+    /// `list` changes.
+    index: Option<DisplayItemIndex>,
+}
+
+/// The width and height of the cells of a `DisplayItemIndex`, in pixels.
+static INDEX_CELL_SIZE: int = 256;
+
+/// A uniform grid over the extents of the items of a display list, used to find the items that
+/// intersect a tile or a point without visiting every item.
+pub struct DisplayItemIndex {
+    /// The extent of each item, as returned by `DisplayItem::extent()`.
+    priv extents: ~[Rect<Au>],
+    /// The top left corner of the grid.
+    priv origin: Point2D<Au>,
+    priv columns: uint,
+    priv rows: uint,
+    /// The indices of the items overlapping each cell, in row-major order.
+    priv cells: ~[~[uint]],
+}
+
+impl DisplayItemIndex {
+    pub fn new<E>(items: &[DisplayItem<E>]) -> DisplayItemIndex {
+        let extents: ~[Rect<Au>] = items.iter().map(|item| item.extent()).collect();
+
+        let mut grid_rect: Option<Rect<Au>> = None;
+        for extent in extents.iter() {
+            grid_rect = match grid_rect {
+                None => Some(*extent),
+                Some(grid_rect) => Some(grid_rect.union(extent)),
+            }
+        }
+        let grid_rect = grid_rect.unwrap_or(Au::zero_rect());
+
+        let cell_size = *Au::from_px(INDEX_CELL_SIZE);
+        let columns = (*grid_rect.size.width / cell_size) as uint + 1;
+        let rows = (*grid_rect.size.height / cell_size) as uint + 1;
+        let mut index = DisplayItemIndex {
+            extents: ~[],
+            origin: grid_rect.origin,
+            columns: columns,
+            rows: rows,
+            cells: vec::from_fn(columns * rows, |_| ~[]),
+        };
+
+        for (i, extent) in extents.iter().enumerate() {
+            let (first_column, last_column, first_row, last_row) = index.cells_for_rect(extent);
+            for row in range(first_row, last_row + 1) {
+                for column in range(first_column, last_column + 1) {
+                    index.cells[row * columns + column].push(i);
+                }
+            }
+        }
+        index.extents = extents;
+        index
+    }
+
+    /// Returns the columns and rows of the cells that the given rectangle overlaps, clamped to
+    /// the grid, as `(first_column, last_column, first_row, last_row)`.
+    fn cells_for_rect(&self, rect: &Rect<Au>) -> (uint, uint, uint, uint) {
+        let cell_size = *Au::from_px(INDEX_CELL_SIZE);
+        let cell = |position: Au, origin: Au, count: uint| -> uint {
+            let offset = *(position - origin);
+            if offset < 0 {
+                0
+            } else {
+                uint::min((offset / cell_size) as uint, count - 1)
+            }
+        };
+        let right = rect.origin.x + rect.size.width;
+        let bottom = rect.origin.y + rect.size.height;
+        (cell(rect.origin.x, self.origin.x, self.columns),
+         cell(right, self.origin.x, self.columns),
+         cell(rect.origin.y, self.origin.y, self.rows),
+         cell(bottom, self.origin.y, self.rows))
+    }
+
+    /// Returns the indices of the items whose extents intersect the given rectangle, in
+    /// increasing order.
+    pub fn items_intersecting(&self, rect: &Rect<Au>) -> ~[uint] {
+        let mut found = vec::from_elem(self.extents.len(), false);
+        let (first_column, last_column, first_row, last_row) = self.cells_for_rect(rect);
+        for row in range(first_row, last_row + 1) {
+            for column in range(first_column, last_column + 1) {
+                for &i in self.cells[row * self.columns + column].iter() {
+                    if !found[i] && self.extents[i].intersects(rect) {
+                        found[i] = true;
+                    }
+                }
+            }
+        }
+        found.iter().enumerate().filter_map(|(i, &found)| {
+            if found { Some(i) } else { None }
+        }).collect()
+    }
 }
 
 pub enum DisplayListIterator<'a,E> {
@@ -87,7 +226,25 @@ impl<E> DisplayList<E> {
     /// Creates a new display list.
     pub fn new() -> DisplayList<E> {
         DisplayList {
-            list: ~[]
+            list: ~[],
+            index: None,
+        }
+    }
+
+    /// Builds the index used to find the items in a region of the page.
+    pub fn build_index(&mut self) {
+        self.index = Some(DisplayItemIndex::new(self.list));
+    }
+
+    /// Returns the items whose extents intersect the given rectangle, in painting order.
+    pub fn items_intersecting<'a>(&'a self, rect: &Rect<Au>) -> ~[&'a DisplayItem<E>] {
+        match self.index {
+            Some(ref index) => {
+                index.items_intersecting(rect).iter().map(|&i| &self.list[i]).collect()
+            }
+            None => {
+                self.list.iter().filter(|item| item.extent().intersects(rect)).collect()
+            }
         }
     }
 
@@ -104,10 +261,11 @@ impl<E> DisplayList<E> {
         self.list.push(item)
     }
 
-    /// Draws the display list into the given render context.
-    pub fn draw_into_context(&self, render_context: &mut RenderContext) {
+    /// Draws the items of the display list that intersect the given tile into the given render
+    /// context.
+    pub fn draw_into_context(&self, render_context: &mut RenderContext, tile_rect: &Rect<Au>) {
         debug!("Beginning display list.");
-        for item in self.list.iter() {
+        for item in self.items_intersecting(tile_rect).iter() {
             // FIXME(Issue #150): crashes
             //debug!("drawing {}", *item);
             item.draw_into_context(render_context)
@@ -162,6 +320,9 @@ pub struct TextDisplayItem<E> {
     /// The color of the text.
     text_color: Color,
 
+    /// The extra space added after each whitespace character for `text-align: justify`.
+    justification: Au,
+
     /// A bitfield of flags for text display items.
     flags: TextDisplayItemFlags,
 
@@ -191,6 +352,13 @@ bitfield!(TextDisplayItemFlags, override_line_through, set_override_line_through
 pub struct ImageDisplayItem<E> {
     base: BaseDisplayItem<E>,
     image: Arc<~Image>,
+
+    /// The frames of the image, if it is animated. The frame shown at the time the item is drawn
+    /// is drawn instead of `image`.
+    animation: Option<Arc<~Animation>>,
+
+    /// The URL the image was loaded from.
+    image_url: Url,
 }
 
 /// Renders a border.
@@ -276,7 +444,8 @@ impl<E> DisplayItem<E> {
                                                 text.text_run.get(),
                                                 &text.range,
                                                 baseline_origin,
-                                                text.text_color);
+                                                text.text_color,
+                                                text.justification);
                 });
                 let width = text.base.bounds.size.width;
                 let underline_size = font_metrics.underline_size;
@@ -306,7 +475,11 @@ impl<E> DisplayItem<E> {
             ImageDisplayItemClass(ref image_item) => {
                 debug!("Drawing image at {:?}.", image_item.base.bounds);
 
-                render_context.draw_image(image_item.base.bounds, image_item.image.clone())
+                let image = match image_item.animation {
+                    Some(ref animation) => animation.get().frame_at(precise_time_ns()),
+                    None => image_item.image.clone(),
+                };
+                render_context.draw_image(image_item.base.bounds, image)
             }
 
             BorderDisplayItemClass(ref border) => {
@@ -342,6 +515,33 @@ impl<E> DisplayItem<E> {
         self.base().bounds
     }
 
+    /// Returns the area that this item and its children may paint into. Glyphs can overhang the
+    /// bounds of their text, so text items are treated as half their height larger on each side.
+    pub fn extent(&self) -> Rect<Au> {
+        match *self {
+            ClipDisplayItemClass(ref clip) => {
+                let mut extent = clip.base.bounds;
+                if !clip.need_clip {
+                    for child in clip.child_list.iter() {
+                        extent = extent.union(&child.extent());
+                    }
+                }
+                extent
+            }
+            TextDisplayItemClass(ref text) => {
+                let bounds = text.base.bounds;
+                let overhang = bounds.size.height.scale_by(0.5);
+                Rect(Point2D(bounds.origin.x - overhang, bounds.origin.y - overhang),
+                     Size2D(bounds.size.width + overhang * Au(2),
+                            bounds.size.height + overhang * Au(2)))
+            }
+            SolidColorDisplayItemClass(..) |
+            ImageDisplayItemClass(..) |
+            BorderDisplayItemClass(..) |
+            LineDisplayItemClass(..) => self.bounds(),
+        }
+    }
+
     pub fn children<'a>(&'a self) -> DisplayItemIterator<'a,E> {
         match *self {
             ClipDisplayItemClass(ref clip) => ParentDisplayItemIterator(clip.child_list.iter()),
@@ -364,16 +564,189 @@ impl<E> DisplayItem<E> {
             }
     }
 
+    /// Adds the bounds of this item and its children to `frame_times` if they are animated
+    /// images that will show a new frame after the given time.
+    fn collect_animated_image_frame_times(&self, time: u64, frame_times: &mut ~[(Rect<Au>, u64)]) {
+        match *self {
+            ImageDisplayItemClass(ref image_item) => {
+                for animation in image_item.animation.iter() {
+                    for next_frame_time in animation.get().next_frame_time(time).iter() {
+                        frame_times.push((image_item.base.bounds, *next_frame_time));
+                    }
+                }
+            }
+            SolidColorDisplayItemClass(..) |
+            TextDisplayItemClass(..) |
+            BorderDisplayItemClass(..) |
+            LineDisplayItemClass(..) |
+            ClipDisplayItemClass(..) => {}
+        }
+        for child in self.children() {
+            child.collect_animated_image_frame_times(time, frame_times);
+        }
+    }
+
+    /// Appends a line describing this item, and then its children, to the given string.
+    pub fn dump_into(&self, output: &mut ~str, level: uint) {
+        for _ in range(0, level) {
+            output.push_str("| ")
+        }
+        output.push_str("+ ");
+        output.push_str(self.debug_str());
+        output.push_char('\n');
+        for child in self.children() {
+            child.dump_into(output, level + 1);
+        }
+    }
+
     pub fn debug_str(&self) -> ~str {
-        let class = match *self {
-            SolidColorDisplayItemClass(_) => "SolidColor",
-            TextDisplayItemClass(_) => "Text",
-            ImageDisplayItemClass(_) => "Image",
-            BorderDisplayItemClass(_) => "Border",
-            LineDisplayItemClass(_) => "Line",
-            ClipDisplayItemClass(_) => "Clip",
-        };
-        format!("{} @ {:?}", class, self.base().bounds)
+        let bounds = geometry::rect_to_px_str(&self.base().bounds);
+        match *self {
+            SolidColorDisplayItemClass(ref solid_color) => {
+                format!("SolidColor {} color={}", bounds, color_debug_str(&solid_color.color))
+            }
+            TextDisplayItemClass(ref text) => {
+                let text_run = text.text_run.get();
+                let text_str = text_run.text.get().slice_chars(text.range.begin(),
+                                                               text.range.end());
+                format!("Text {} color={} text=\"{}\"",
+                        bounds,
+                        color_debug_str(&text.text_color),
+                        text_str.escape_default())
+            }
+            ImageDisplayItemClass(ref image) => {
+                match image.animation {
+                    Some(ref animation) => {
+                        format!("Image {} url={} frames={}",
+                                bounds,
+                                image.image_url.to_str(),
+                                animation.get().frames.len())
+                    }
+                    None => format!("Image {} url={}", bounds, image.image_url.to_str()),
+                }
+            }
+            BorderDisplayItemClass(ref border) => {
+                format!("Border {} widths=({},{},{},{}) colors=({},{},{},{})",
+                        bounds,
+                        geometry::to_frac_px(border.border.top),
+                        geometry::to_frac_px(border.border.right),
+                        geometry::to_frac_px(border.border.bottom),
+                        geometry::to_frac_px(border.border.left),
+                        color_debug_str(&border.color.top),
+                        color_debug_str(&border.color.right),
+                        color_debug_str(&border.color.bottom),
+                        color_debug_str(&border.color.left))
+            }
+            LineDisplayItemClass(ref line) => {
+                format!("Line {} color={}", bounds, color_debug_str(&line.color))
+            }
+            ClipDisplayItemClass(ref clip) => {
+                format!("Clip {} need_clip={}", bounds, clip.need_clip)
+            }
+        }
     }
 }
 
+/// Describes a color as 8-bit RGBA components, for debugging output.
+fn color_debug_str(color: &Color) -> ~str {
+    format!("rgba({},{},{},{})",
+            (color.r * 255.0).round() as u8,
+            (color.g * 255.0).round() as u8,
+            (color.b * 255.0).round() as u8,
+            (color.a * 255.0).round() as u8)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{BaseDisplayItem, ClipDisplayItem, ClipDisplayItemClass, DisplayItem};
+    use super::{DisplayItemIndex, DisplayList, DisplayListCollection};
+    use super::{SolidColorDisplayItem, SolidColorDisplayItemClass};
+    use color;
+    use geom::{Point2D, Rect, Size2D};
+    use servo_util::geometry::Au;
+
+    fn px_rect(x: int, y: int, width: int, height: int) -> Rect<Au> {
+        Rect(Point2D(Au::from_px(x), Au::from_px(y)),
+             Size2D(Au::from_px(width), Au::from_px(height)))
+    }
+
+    fn solid_color(bounds: Rect<Au>) -> DisplayItem<()> {
+        SolidColorDisplayItemClass(~SolidColorDisplayItem {
+            base: BaseDisplayItem {
+                bounds: bounds,
+                extra: (),
+            },
+            color: color::rgb(0, 0, 0),
+        })
+    }
+
+    #[test]
+    fn test_items_are_found_in_the_cells_they_overlap() {
+        let items = ~[
+            solid_color(px_rect(0, 0, 10, 10)),
+            solid_color(px_rect(600, 0, 10, 10)),
+            solid_color(px_rect(0, 600, 700, 10)),
+            solid_color(px_rect(250, 250, 10, 10)),
+        ];
+        let index = DisplayItemIndex::new(items);
+        assert_eq!(index.items_intersecting(&px_rect(0, 0, 20, 20)), ~[0]);
+        assert_eq!(index.items_intersecting(&px_rect(590, 0, 20, 20)), ~[1]);
+        // Items that span several cells are found once, from any of them.
+        assert_eq!(index.items_intersecting(&px_rect(300, 590, 20, 20)), ~[2]);
+        assert_eq!(index.items_intersecting(&px_rect(0, 0, 700, 700)), ~[0, 1, 2, 3]);
+        // Items in the same cell that don't intersect the rectangle are skipped.
+        assert_eq!(index.items_intersecting(&px_rect(100, 100, 20, 20)), ~[]);
+        // Rectangles outside the grid are clamped to its edge cells.
+        assert_eq!(index.items_intersecting(&px_rect(-50, -50, 60, 60)), ~[0]);
+        assert_eq!(index.items_intersecting(&px_rect(5000, 5000, 10, 10)), ~[]);
+    }
+
+    #[test]
+    fn test_an_empty_index_finds_nothing() {
+        let items: ~[DisplayItem<()>] = ~[];
+        let index = DisplayItemIndex::new(items);
+        assert_eq!(index.items_intersecting(&px_rect(0, 0, 100, 100)), ~[]);
+    }
+
+    #[test]
+    fn test_indexed_and_unindexed_lists_agree() {
+        let mut list = DisplayList::new();
+        list.list = ~[
+            solid_color(px_rect(0, 0, 300, 300)),
+            solid_color(px_rect(500, 500, 10, 10)),
+            solid_color(px_rect(280, 280, 40, 40)),
+        ];
+        let rect = px_rect(290, 290, 5, 5);
+        let unindexed: ~[Rect<Au>] = list.items_intersecting(&rect).iter().map(|item| {
+            item.bounds()
+        }).collect();
+        list.build_index();
+        let indexed: ~[Rect<Au>] = list.items_intersecting(&rect).iter().map(|item| {
+            item.bounds()
+        }).collect();
+        assert_eq!(indexed, unindexed);
+        assert_eq!(indexed, ~[px_rect(0, 0, 300, 300), px_rect(280, 280, 40, 40)]);
+    }
+
+    #[test]
+    fn test_dump_to_str() {
+        let clip = ClipDisplayItemClass(~ClipDisplayItem {
+            base: BaseDisplayItem {
+                bounds: px_rect(10, 20, 100, 50),
+                extra: (),
+            },
+            child_list: ~[solid_color(px_rect(10, 20, 5, 5))],
+            need_clip: true,
+        });
+        let mut list = DisplayList::new();
+        list.list = ~[solid_color(px_rect(0, 0, 800, 600)), clip];
+        let mut collection = DisplayListCollection::new();
+        collection.add_list(list);
+        assert_eq!(collection.dump_to_str(),
+                   ~"DisplayList 0\n" +
+                   "| + SolidColor (0,0 800x600) color=rgba(0,0,0,255)\n" +
+                   "| + Clip (10,20 100x50) need_clip=true\n" +
+                   "| | + SolidColor (10,20 5x5) color=rgba(0,0,0,255)\n");
+    }
+}
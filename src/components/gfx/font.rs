@@ -8,6 +8,7 @@ use azure::scaled_font::ScaledFont;
 use extra::arc::Arc;
 use geom::{Point2D, Rect, Size2D};
 use std::cast;
+use std::mem;
 use std::ptr;
 use std::str;
 use std::rc::Rc;
@@ -23,7 +24,8 @@ use platform::font_context::FontContextHandle;
 use platform::font::{FontHandle, FontTable};
 use render_context::RenderContext;
 use text::glyph::{GlyphStore, GlyphIndex};
-use text::shaping::ShaperMethods;
+use text::line_break;
+use text::shaping::{ShaperMethods, ShapingOptions};
 use text::{Shaper, TextRun};
 
 // FontHandle encapsulates access to the platform's font API,
@@ -162,13 +164,92 @@ impl FontGroup {
         self.fonts = ~[];
     }
 
-    pub fn create_textrun(&self, text: ~str, decoration: text_decoration::T) -> TextRun {
+    /// Creates the text runs for the given text. Each character is rendered with the first font
+    /// in the group that has a glyph for it. Characters that no font in the group covers use a
+    /// system fallback font found through the font context, or the first font of the group if
+    /// there is none. The text is split into a new run wherever the font changes.
+    pub fn create_textruns(&self,
+                           font_context: &mut FontContext,
+                           text: &str,
+                           decoration: text_decoration::T,
+                           options: &ShapingOptions)
+                           -> ~[TextRun] {
         assert!(self.fonts.len() > 0);
 
-        // TODO(Issue #177): Actually fall back through the FontGroup when a font is unsuitable.
-        self.fonts[0].borrow().with_mut(|font| {
-            TextRun::new(font, text.clone(), decoration)
-        })
+        // The fonts of the group, followed by any fallback fonts used by this text.
+        let mut fonts = self.fonts.clone();
+        let runs = split_runs_by_font(text, |ch| {
+            self.font_index_for_char(font_context, &mut fonts, ch)
+        });
+        runs.move_iter().map(|(font_index, begin, end)| {
+            let slice = text.slice(begin, end).to_owned();
+            fonts[font_index].borrow().with_mut(|font| {
+                TextRun::new(font, slice, decoration, options)
+            })
+        }).collect()
+    }
+
+    /// Returns the index into `fonts` of the font to render the given character with, appending
+    /// a fallback font to `fonts` if needed.
+    fn font_index_for_char(&self,
+                           font_context: &mut FontContext,
+                           fonts: &mut ~[Rc<RefCell<Font>>],
+                           ch: char)
+                           -> uint {
+        for (i, font) in fonts.iter().enumerate() {
+            if font.borrow().with(|font| font.glyph_index(ch).is_some()) {
+                return i
+            }
+        }
+
+        match font_context.get_fallback_font_for_char(ch, &self.style) {
+            Some(fallback) => {
+                let descriptor = fallback.borrow().with(|font| font.get_descriptor());
+                for (i, font) in fonts.iter().enumerate() {
+                    if font.borrow().with(|font| font.get_descriptor()) == descriptor {
+                        return i
+                    }
+                }
+                fonts.push(fallback);
+                fonts.len() - 1
+            }
+            None => 0,
+        }
+    }
+}
+
+/// Splits text into runs that each use one font, given the index of the font to render each
+/// character with. Returns the font index and the byte range of each run; empty text makes one
+/// empty run in the first font.
+fn split_runs_by_font(text: &str, font_index_for_char: |char| -> uint) -> ~[(uint, uint, uint)] {
+    let mut runs = ~[];
+    let mut current_font = None;
+    let mut byte_last_boundary = 0;
+    for (byte_i, ch) in text.char_indices() {
+        let font_index = match current_font {
+            Some(index) if continues_run(ch) => index,
+            _ => font_index_for_char(ch),
+        };
+        match current_font {
+            Some(current_index) if current_index != font_index => {
+                runs.push((current_index, byte_last_boundary, byte_i));
+                byte_last_boundary = byte_i;
+            }
+            _ => {}
+        }
+        current_font = Some(font_index);
+    }
+    runs.push((current_font.unwrap_or(0), byte_last_boundary, text.len()));
+    runs
+}
+
+/// Returns true if the given character stays in the run of the preceding character whatever its
+/// font coverage. This holds for whitespace, so that runs are not split needlessly, and for
+/// combining marks, so that they are shaped with their base.
+fn continues_run(ch: char) -> bool {
+    match line_break::line_break_class(ch) {
+        line_break::SP | line_break::CM | line_break::ZWJ => true,
+        _ => ch == '\t' || ch == '\n',
     }
 }
 
@@ -211,7 +292,7 @@ pub struct Font {
     style: UsedFontStyle,
     metrics: FontMetrics,
     backend: BackendType,
-    shape_cache: HashCache<~str, Arc<GlyphStore>>,
+    shape_cache: HashCache<(~str, ShapingOptions), Arc<GlyphStore>>,
     glyph_advance_cache: HashCache<u32, FractionalPixel>,
 }
 
@@ -342,7 +423,8 @@ impl Font {
                               run: &~TextRun,
                               range: &Range,
                               baseline_origin: Point2D<Au>,
-                              color: Color) {
+                              color: Color,
+                              justification: Au) {
         use std::libc::types::common::c99::{uint16_t, uint32_t};
         use azure::{struct__AzDrawOptions,
                     struct__AzGlyph,
@@ -361,25 +443,37 @@ impl Font {
             fields: 0x0200 as uint16_t
         };
 
-        let mut origin = baseline_origin.clone();
-        let mut azglyphs = ~[];
-        azglyphs.reserve(range.length());
-
+        // Gather the glyphs in logical order, along with the extra space that follows each.
+        let mut glyph_entries = ~[];
         for (glyphs, _offset, slice_range) in run.iter_slices_for_range(range) {
+            // Distribute the extra space for justified text among the whitespace characters.
+            let extra_advance = if glyphs.is_whitespace() { justification } else { Au(0) };
             for (_i, glyph) in glyphs.iter_glyphs_for_char_range(&slice_range) {
-                let glyph_advance = glyph.advance();
-                let glyph_offset = glyph.offset().unwrap_or(Au::zero_point());
-
-                let azglyph = struct__AzGlyph {
-                    mIndex: glyph.index() as uint32_t,
-                    mPosition: struct__AzPoint {
-                        x: (origin.x + glyph_offset.x).to_nearest_px() as AzFloat,
-                        y: (origin.y + glyph_offset.y).to_nearest_px() as AzFloat
-                    }
-                };
-                origin = Point2D(origin.x + glyph_advance, origin.y);
-                azglyphs.push(azglyph)
+                glyph_entries.push((glyph.index(),
+                                    glyph.advance() + extra_advance,
+                                    glyph.offset().unwrap_or(Au::zero_point())));
+            }
+        }
+
+        // Right-to-left runs are drawn in visual order.
+        if run.rtl {
+            glyph_entries.reverse();
+        }
+
+        let mut origin = baseline_origin.clone();
+        let mut azglyphs = ~[];
+        azglyphs.reserve(glyph_entries.len());
+
+        for &(glyph_index, glyph_advance, glyph_offset) in glyph_entries.iter() {
+            let azglyph = struct__AzGlyph {
+                mIndex: glyph_index as uint32_t,
+                mPosition: struct__AzPoint {
+                    x: (origin.x + glyph_offset.x).to_nearest_px() as AzFloat,
+                    y: (origin.y + glyph_offset.y).to_nearest_px() as AzFloat
+                }
             };
+            origin = Point2D(origin.x + glyph_advance, origin.y);
+            azglyphs.push(azglyph)
         }
 
         let azglyph_buf_len = azglyphs.len();
@@ -402,8 +496,6 @@ impl Font {
     }
 
     pub fn measure_text(&self, run: &TextRun, range: &Range) -> RunMetrics {
-        // TODO(Issue #199): alter advance direction for RTL
-        // TODO(Issue #98): using inter-char and inter-word spacing settings  when measuring text
         let mut advance = Au(0);
         for (glyphs, _offset, slice_range) in run.iter_slices_for_range(range) {
             for (_i, glyph) in glyphs.iter_glyphs_for_char_range(&slice_range) {
@@ -424,13 +516,15 @@ impl Font {
         RunMetrics::new(advance, self.metrics.ascent, self.metrics.descent)
     }
 
-    pub fn shape_text(&mut self, text: ~str, is_whitespace: bool) -> Arc<GlyphStore> {
+    pub fn shape_text(&mut self, text: ~str, options: &ShapingOptions, is_whitespace: bool)
+                      -> Arc<GlyphStore> {
 
         //FIXME (ksh8281)
         self.make_shaper();
-        self.shape_cache.find_or_create(&text, |txt| {
-            let mut glyphs = GlyphStore::new(text.char_len(), is_whitespace);
-            self.shaper.get_ref().shape_text(*txt, &mut glyphs);
+        self.shape_cache.find_or_create(&(text.clone(), options.clone()), |key| {
+            let (ref txt, ref options) = *key;
+            let mut glyphs = GlyphStore::new(txt.char_len(), is_whitespace);
+            self.shaper.get_ref().shape_text(*txt, options, &mut glyphs);
             Arc::new(glyphs)
         })
     }
@@ -451,6 +545,18 @@ impl Font {
             }
         })
     }
+
+    /// Returns an estimate of the heap memory used by the shaping and glyph advance caches of
+    /// this font, for the memory profiler.
+    pub fn heap_size_of_caches(&self) -> uint {
+        let mut size = 0;
+        for (&(ref text, _), glyphs) in self.shape_cache.iter() {
+            size += mem::size_of::<((~str, ShapingOptions), Arc<GlyphStore>)>() + text.len() +
+                glyphs.get().heap_size();
+        }
+        size + self.glyph_advance_cache.iter().count() *
+            mem::size_of::<(u32, FractionalPixel)>()
+    }
 }
 
 /*fn should_destruct_on_fail_without_leaking() {
@@ -522,3 +628,42 @@ fn should_be_able_to_create_instances_in_multiple_threads() {
 }
 
 */
+
+#[cfg(test)]
+mod tests {
+    use super::{continues_run, split_runs_by_font};
+
+    /// Renders ideographs with the second font and everything else with the first.
+    fn font_index(ch: char) -> uint {
+        if ch >= '\u4e00' && ch <= '\u9fff' { 1 } else { 0 }
+    }
+
+    #[test]
+    fn test_split_runs_where_the_font_changes() {
+        assert_eq!(split_runs_by_font("ab\u4e00\u4e01c", |ch| font_index(ch)),
+                   ~[(0, 0, 2), (1, 2, 8), (0, 8, 9)]);
+        assert_eq!(split_runs_by_font("abc", |ch| font_index(ch)), ~[(0, 0, 3)]);
+        assert_eq!(split_runs_by_font("", |ch| font_index(ch)), ~[(0, 0, 0)]);
+    }
+
+    #[test]
+    fn test_whitespace_and_marks_stay_in_the_preceding_run() {
+        assert_eq!(split_runs_by_font("\u4e00 a", |ch| font_index(ch)),
+                   ~[(1, 0, 4), (0, 4, 5)]);
+        assert_eq!(split_runs_by_font("\u4e00\u0301a", |ch| font_index(ch)),
+                   ~[(1, 0, 5), (0, 5, 6)]);
+        // Leading whitespace picks a font of its own.
+        assert_eq!(split_runs_by_font(" \u4e00", |ch| font_index(ch)), ~[(0, 0, 1), (1, 1, 4)]);
+    }
+
+    #[test]
+    fn test_continues_run() {
+        assert!(continues_run(' '));
+        assert!(continues_run('\t'));
+        assert!(continues_run('\n'));
+        assert!(continues_run('\u0301'));
+        assert!(continues_run('\u200d'));
+        assert!(!continues_run('a'));
+        assert!(!continues_run('\u4e00'));
+    }
+}
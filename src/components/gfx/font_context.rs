@@ -12,6 +12,8 @@ use azure::azure_hl::BackendType;
 use servo_util::cache::{Cache, LRUCache};
 use servo_util::time::ProfilerChan;
 use std::hashmap::HashMap;
+use std::iter::AdditiveIterator;
+use std::mem;
 
 use std::rc::Rc;
 use std::cell::RefCell;
@@ -40,6 +42,9 @@ pub struct FontContext {
     handle: FontContextHandle,
     backend: BackendType,
     generic_fonts: HashMap<~str,~str>,
+    /// The system font family chosen to render each character that was missing from the fonts
+    /// of its font group, or `None` if no installed font covers it.
+    fallback_families: HashMap<char, Option<~str>>,
     profiler_chan: ProfilerChan,
 }
 
@@ -67,10 +72,20 @@ impl FontContext {
             handle: handle,
             backend: info.backend,
             generic_fonts: generic_fonts,
+            fallback_families: HashMap::new(),
             profiler_chan: info.profiler_chan.clone(),
         }
     }
 
+    /// Returns an estimate of the heap memory used by the fonts cached by this context, for the
+    /// memory profiler. Font groups share their fonts with the instance cache, so only the
+    /// instance cache is counted.
+    pub fn heap_size_of_font_caches(&self) -> uint {
+        self.instance_cache.iter().map(|&(_, ref font)| {
+            mem::size_of::<Font>() + font.borrow().with(|font| font.heap_size_of_caches())
+        }).sum()
+    }
+
     pub fn get_resolved_font_for_style(&mut self, style: &SpecifiedFontStyle)
                                        -> Rc<RefCell<FontGroup>> {
         match self.group_cache.find(style) {
@@ -107,6 +122,50 @@ impl FontContext {
         }
     }
 
+    /// Returns a font in the given style that can render the given character, for characters
+    /// that none of the fonts of a font group can render. The font family chosen for each
+    /// character is cached.
+    pub fn get_fallback_font_for_char(&mut self, codepoint: char, style: &SpecifiedFontStyle)
+                                      -> Option<Rc<RefCell<Font>>> {
+        let family = match self.fallback_families.find(&codepoint) {
+            Some(family) => {
+                debug!("fallback family cache hit");
+                family.clone()
+            }
+            None => {
+                debug!("fallback family cache miss");
+                let family = match self.font_list {
+                    Some(ref font_list) => font_list.find_fallback_family_for_char(codepoint),
+                    None => None,
+                };
+                self.fallback_families.insert(codepoint, family.clone());
+                family
+            }
+        };
+
+        let font_desc = match family {
+            Some(ref family) => self.find_font_descriptor_in_family(family, style),
+            None => None,
+        };
+        match font_desc {
+            Some(ref font_desc) => self.get_font_by_descriptor(font_desc).ok(),
+            None => None,
+        }
+    }
+
+    fn find_font_descriptor_in_family(&mut self, family: &~str, style: &SpecifiedFontStyle)
+                                      -> Option<FontDescriptor> {
+        match self.font_list {
+            Some(ref mut font_list) => {
+                font_list.find_font_in_family(family, style).map(|font_entry| {
+                    let font_id = SelectorPlatformIdentifier(font_entry.handle.face_identifier());
+                    FontDescriptor::new((*style).clone(), font_id)
+                })
+            }
+            None => None,
+        }
+    }
+
     fn transform_family(&self, family: &~str) -> ~str {
         debug!("(transform family) searching for `{:s}`", family.as_slice());
         match self.generic_fonts.find(family) {
@@ -126,23 +185,7 @@ impl FontContext {
             debug!("(create font group) transformed family is `{:s}`", transformed_family_name);
             let mut found = false;
 
-            let result = match self.font_list {
-                Some(ref mut fl) => {
-                    let font_in_family = fl.find_font_in_family(&transformed_family_name, style);
-                    match font_in_family {
-                        Some(font_entry) => {
-                            let font_id =
-                                SelectorPlatformIdentifier(font_entry.handle.face_identifier());
-                            let font_desc = FontDescriptor::new((*style).clone(), font_id);
-                            Some(font_desc)
-                        },
-                        None => {
-                            None
-                        }
-                    }
-                }
-                None => None,
-            };
+            let result = self.find_font_descriptor_in_family(&transformed_family_name, style);
 
             match result {
                 Some(ref result) => {
@@ -161,27 +204,7 @@ impl FontContext {
         if fonts.len() == 0 {
             let last_resort = FontList::get_last_resort_font_families();
             for family in last_resort.iter() {
-                let font_desc = match self.font_list {
-                    Some(ref mut font_list) => {
-                        let font_desc = {
-                            let font_entry = font_list.find_font_in_family(family, style);
-                            match font_entry {
-                                Some(v) => {
-                                    let font_id =
-                                        SelectorPlatformIdentifier(v.handle.face_identifier());
-                                    Some(FontDescriptor::new((*style).clone(), font_id))
-                                },
-                                None => {
-                                    None
-                                }
-                            }
-                        };
-                        font_desc
-                    },
-                    None => {
-                        None
-                    }
-                };
+                let font_desc = self.find_font_descriptor_in_family(family, style);
 
                 match font_desc {
                     Some(ref fd) => {
@@ -18,6 +18,7 @@ pub type FontFamilyMap = HashMap<~str, FontFamily>;
 trait FontListHandleMethods {
     fn get_available_families(&self, fctx: &FontContextHandle) -> FontFamilyMap;
     fn load_variations_for_family(&self, family: &mut FontFamily);
+    fn get_fallback_family_for_char(&self, codepoint: char) -> Option<~str>;
     fn get_last_resort_font_families() -> ~[~str];
 }
 
@@ -75,6 +76,11 @@ impl FontList {
         }
     }
 
+    /// Returns the name of an installed font family that can render the given character.
+    pub fn find_fallback_family_for_char(&self, codepoint: char) -> Option<~str> {
+        self.handle.get_fallback_family_for_char(codepoint)
+    }
+
     pub fn get_last_resort_font_families() -> ~[~str] {
         let last_resort = FontListHandle::get_last_resort_font_families();
         last_resort
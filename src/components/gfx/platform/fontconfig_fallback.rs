@@ -0,0 +1,99 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Per-character font fallback through fontconfig, shared by the Linux and Android font lists.
+
+use fontconfig::fontconfig::{
+    FcChar8, FcChar32, FcCharSet, FcPattern, FcResultMatch, FcResultNoMatch, FcMatchPattern
+};
+use fontconfig::fontconfig::{
+    FcConfigGetCurrent, FcConfigSubstitute, FcDefaultSubstitute, FcFontMatch, FcPatternCreate,
+    FcPatternDestroy, FcPatternAddCharSet, FcPatternGetCharSet, FcPatternGetString,
+    FcCharSetCreate, FcCharSetAddChar, FcCharSetDestroy, FcCharSetHasChar
+};
+
+use std::libc::c_char;
+use std::ptr;
+use std::str;
+
+/// Asks fontconfig for an installed font family that has a glyph for the given character.
+/// Returns `None` if no installed font covers it.
+pub fn get_fallback_family_for_char(codepoint: char) -> Option<~str> {
+    unsafe {
+        let config = FcConfigGetCurrent();
+        let wrapper = AutoPattern { pattern: FcPatternCreate() };
+        let pattern = wrapper.pattern;
+        let charset = AutoCharSet { charset: FcCharSetCreate() };
+        if FcCharSetAddChar(charset.charset, codepoint as FcChar32) != 1 {
+            debug!("adding codepoint to charset failed");
+            return None;
+        }
+        let res = "charset".to_c_str().with_ref(|FC_CHARSET| {
+            FcPatternAddCharSet(pattern, FC_CHARSET, charset.charset)
+        });
+        if res != 1 {
+            debug!("adding charset to pattern failed");
+            return None;
+        }
+
+        if FcConfigSubstitute(config, pattern, FcMatchPattern) != 1 {
+            debug!("substitution failed");
+            return None;
+        }
+        FcDefaultSubstitute(pattern);
+        let result = FcResultNoMatch;
+        let result_wrapper = AutoPattern { pattern: FcFontMatch(config, pattern, &result) };
+        let result_pattern = result_wrapper.pattern;
+        if result != FcResultMatch || result_pattern.is_null() {
+            debug!("obtaining match to pattern failed");
+            return None;
+        }
+
+        // The best match is not guaranteed to cover the character, since fontconfig weighs
+        // the other pattern elements too.
+        let matched_charset: *FcCharSet = ptr::null();
+        let res = "charset".to_c_str().with_ref(|FC_CHARSET| {
+            FcPatternGetCharSet(result_pattern, FC_CHARSET, 0, &matched_charset)
+        });
+        if res != FcResultMatch ||
+                FcCharSetHasChar(matched_charset, codepoint as FcChar32) == 0 {
+            debug!("no installed font covers {:?}", codepoint);
+            return None;
+        }
+
+        let family: *FcChar8 = ptr::null();
+        let res = "family".to_c_str().with_ref(|FC_FAMILY| {
+            FcPatternGetString(result_pattern, FC_FAMILY, 0, &family)
+        });
+        if res != FcResultMatch {
+            debug!("getting family name for font failed");
+            return None;
+        }
+        Some(str::raw::from_c_str(family as *c_char))
+    }
+}
+
+struct AutoPattern {
+    pattern: *FcPattern
+}
+
+impl Drop for AutoPattern {
+    fn drop(&mut self) {
+        unsafe {
+            FcPatternDestroy(self.pattern);
+        }
+    }
+}
+
+struct AutoCharSet {
+    charset: *FcCharSet
+}
+
+impl Drop for AutoCharSet {
+    fn drop(&mut self) {
+        unsafe {
+            FcCharSetDestroy(self.charset);
+        }
+    }
+}
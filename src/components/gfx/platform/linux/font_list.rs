@@ -24,6 +24,7 @@ use font::{FontHandleMethods, UsedFontStyle};
 use font_list::{FontEntry, FontFamily, FontFamilyMap};
 use platform::font::FontHandle;
 use platform::font_context::FontContextHandle;
+use platform::fontconfig_fallback;
 
 use std::hashmap::HashMap;
 use std::libc;
@@ -129,6 +130,10 @@ impl FontListHandle {
         }
     }
 
+    pub fn get_fallback_family_for_char(&self, codepoint: char) -> Option<~str> {
+        fontconfig_fallback::get_fallback_family_for_char(codepoint)
+    }
+
     pub fn get_last_resort_font_families() -> ~[~str] {
         ~[~"Arial"]
     }
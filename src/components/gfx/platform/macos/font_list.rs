@@ -7,12 +7,23 @@ use font_list::{FontEntry, FontFamily, FontFamilyMap};
 use platform::macos::font::FontHandle;
 use platform::macos::font_context::FontContextHandle;
 
-use core_foundation::base::TCFType;
+use core_foundation::base::{CFIndex, CFRange, TCFType};
 use core_foundation::string::{CFString, CFStringRef};
+use core_text::font::{CTFont, CTFontRef};
 use core_text::font_descriptor::{CTFontDescriptor, CTFontDescriptorRef};
 use core_text;
 use std::cast;
 use std::hashmap::HashMap;
+use std::str;
+
+/// The family whose cascade list is searched for fonts that cover characters missing from the
+/// fonts of a font group.
+static FALLBACK_BASE_FAMILY: &'static str = "Helvetica";
+
+extern {
+    fn CTFontCreateForString(currentFont: CTFontRef, string: CFStringRef, range: CFRange)
+                             -> CTFontRef;
+}
 
 pub struct FontListHandle {
     fctx: FontContextHandle,
@@ -58,6 +69,36 @@ impl FontListHandle {
         }
     }
 
+    /// Returns an installed font family that has a glyph for the given character, as chosen by
+    /// Core Text from the cascade list of the system font.
+    pub fn get_fallback_family_for_char(&self, codepoint: char) -> Option<~str> {
+        let base_font = match core_text::font::new_from_name(FALLBACK_BASE_FAMILY, 12.0) {
+            Ok(font) => font,
+            Err(()) => return None,
+        };
+        let text = str::from_char(codepoint);
+        let string = CFString::new(text);
+        let range = CFRange::init(0, text.to_utf16().len() as CFIndex);
+        let font: CTFont = unsafe {
+            let font = CTFontCreateForString(base_font.as_concrete_TypeRef(),
+                                             string.as_concrete_TypeRef(),
+                                             range);
+            if font.is_null() {
+                return None;
+            }
+            TCFType::wrap_under_create_rule(font)
+        };
+
+        // Core Text returns the last resort font, which draws placeholder glyphs, when no
+        // installed font covers the character.
+        let family = font.family_name();
+        if family.as_slice() == "LastResort" {
+            debug!("no installed font covers {:?}", codepoint);
+            return None;
+        }
+        Some(family)
+    }
+
     pub fn get_last_resort_font_families() -> ~[~str] {
         ~[~"Arial Unicode MS",~"Arial"]
     }
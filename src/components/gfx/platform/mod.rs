@@ -6,6 +6,9 @@
 #[cfg(target_os="macos")] pub use platform::macos::{font, font_context, font_list};
 #[cfg(target_os="android")] pub use platform::android::{font, font_context, font_list};
 
+#[cfg(target_os="linux")] #[cfg(target_os="android")]
+pub mod fontconfig_fallback;
+
 #[cfg(target_os="linux")]
 pub mod linux {
     pub mod font;
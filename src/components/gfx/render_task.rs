@@ -6,17 +6,22 @@
 
 use azure::azure_hl::{B8G8R8A8, Color, DrawTarget, StolenGLResources};
 use azure::AzFloat;
+use extra::time::precise_time_ns;
 use geom::matrix2d::Matrix2D;
+use geom::point::Point2D;
 use geom::rect::Rect;
 use geom::size::Size2D;
 use layers::platform::surface::{NativePaintingGraphicsContext, NativeSurface};
 use layers::platform::surface::{NativeSurfaceMethods};
 use layers;
-use servo_msg::compositor_msg::{Epoch, IdleRenderState, LayerBuffer, LayerBufferSet};
-use servo_msg::compositor_msg::{RenderListener, RenderingRenderState};
+use servo_msg::compositor_msg::{AnimatedRect, Epoch, IdleRenderState, LayerBuffer};
+use servo_msg::compositor_msg::{LayerBufferSet};
+use servo_msg::compositor_msg::{RenderListener, RenderingRenderState, SoftwareLayerBuffer};
 use servo_msg::constellation_msg::{ConstellationChan, PipelineId, RendererReadyMsg};
 use servo_msg::constellation_msg::{Failure, FailureMsg};
 use servo_msg::platform::surface::NativeSurfaceAzureMethods;
+use servo_util::geometry;
+use servo_util::memory::{MemoryReport, MemoryReporter, MemoryReportsChan};
 use servo_util::opts::Opts;
 use servo_util::time::{ProfilerChan, profile};
 use servo_util::time;
@@ -24,6 +29,7 @@ use servo_util::task::send_on_failure;
 
 use std::comm::{Chan, Port, SharedChan};
 use std::task;
+use std::vec;
 use extra::arc::Arc;
 
 use buffer_map::BufferMap;
@@ -43,6 +49,8 @@ pub enum Msg<T> {
     UnusedBufferMsg(~[~LayerBuffer]),
     PaintPermissionGranted,
     PaintPermissionRevoked,
+    /// Asks for the memory used by the font caches and the unused layer buffers.
+    CollectMemoryReportsMsg(MemoryReportsChan),
     ExitMsg(Option<Chan<()>>),
 }
 
@@ -95,11 +103,145 @@ impl<T: Send> RenderChan<T> {
     }
 }
 
+impl<T: Send> MemoryReporter for RenderChan<T> {
+    fn collect_reports(&self, reports_chan: MemoryReportsChan) -> bool {
+        self.try_send(CollectMemoryReportsMsg(reports_chan))
+    }
+}
+
 /// If we're using GPU rendering, this provides the metadata needed to create a GL context that
 /// is compatible with that of the main thread.
 enum GraphicsContext {
     CpuGraphicsContext,
     GpuGraphicsContext,
+    /// CPU rendering into memory, for compositors without graphics metadata (that is, the
+    /// headless compositor). Tiles are sent to the compositor as `SoftwareLayerBuffer`s.
+    SoftwareGraphicsContext,
+}
+
+/// Messages to a render worker.
+enum WorkerMsg<T> {
+    /// Rasterizes a tile and sends its pixels back along with its index in the request.
+    RenderTileMsg(TileRequest<T>),
+    /// Tells the worker to exit.
+    ExitWorkerMsg,
+}
+
+/// A tile for a render worker to rasterize.
+struct TileRequest<T> {
+    /// The index of the tile within its `ReRenderMsg`, so that results can be put back in order.
+    index: uint,
+    tile: BufferRequest,
+    scale: f32,
+    display_list_collection: Arc<DisplayListCollection<T>>,
+    result_chan: SharedChan<(uint, ~[u8])>,
+}
+
+/// Spawns a task that rasterizes tiles on the CPU with its own font context.
+fn spawn_render_worker<T:Send+Freeze>(opts: Opts, profiler_chan: ProfilerChan)
+                                      -> Chan<WorkerMsg<T>> {
+    let (port, chan) = Chan::new();
+    let mut builder = task::task();
+    builder.name("RenderWorker");
+    builder.spawn(proc() {
+        let mut font_ctx = ~FontContext::new(FontContextInfo {
+            backend: opts.render_backend.clone(),
+            needs_font_list: false,
+            profiler_chan: profiler_chan.clone(),
+        });
+
+        loop {
+            match port.recv() {
+                RenderTileMsg(request) => {
+                    let TileRequest {
+                        index: index,
+                        tile: tile,
+                        scale: scale,
+                        display_list_collection: display_list_collection,
+                        result_chan: result_chan,
+                    } = request;
+
+                    let size = Size2D(tile.screen_rect.size.width as i32,
+                                      tile.screen_rect.size.height as i32);
+                    let draw_target = DrawTarget::new(opts.render_backend, size, B8G8R8A8);
+                    render_tile(&draw_target,
+                                &mut font_ctx,
+                                &opts,
+                                &tile,
+                                scale,
+                                display_list_collection.get(),
+                                profiler_chan.clone());
+
+                    let data = draw_target.snapshot().get_data_surface().with_data(|data| {
+                        data.to_owned()
+                    });
+                    result_chan.send((index, data));
+                }
+                ExitWorkerMsg => break,
+            }
+        }
+    });
+    chan
+}
+
+/// Receives the pixels of `count` tiles from the render workers, which may finish them in any
+/// order, and returns them in the order the tiles were requested.
+fn collect_tiles_in_order(result_port: &Port<(uint, ~[u8])>, count: uint) -> ~[~[u8]] {
+    let mut results = vec::from_fn(count, |_| None);
+    for _ in range(0, count) {
+        let (index, data) = result_port.recv();
+        results[index] = Some(data);
+    }
+
+    results.move_iter().map(|data| {
+        data.expect("render worker didn't return a tile")
+    }).collect()
+}
+
+/// Wraps the pixels of a tile rendered into memory for the compositor.
+fn software_tile(tile: &BufferRequest, scale: f32, data: ~[u8]) -> ~SoftwareLayerBuffer {
+    ~SoftwareLayerBuffer {
+        pixels: data,
+        rect: tile.page_rect,
+        screen_pos: tile.screen_rect,
+        resolution: scale,
+        stride: tile.screen_rect.size.width,
+    }
+}
+
+/// Draws the display lists into the part of the draw target covered by the given tile.
+fn render_tile<T>(draw_target: &DrawTarget,
+                  font_ctx: &mut ~FontContext,
+                  opts: &Opts,
+                  tile: &BufferRequest,
+                  scale: f32,
+                  display_list_collection: &DisplayListCollection<T>,
+                  profiler_chan: ProfilerChan) {
+    // Build the render context.
+    let mut ctx = RenderContext {
+        draw_target: draw_target,
+        font_ctx: font_ctx,
+        opts: opts,
+        page_rect: tile.page_rect,
+        screen_rect: tile.screen_rect,
+    };
+
+    // Apply the translation to render the tile we want.
+    let matrix: Matrix2D<AzFloat> = Matrix2D::identity();
+    let matrix = matrix.scale(scale as AzFloat, scale as AzFloat);
+    let matrix = matrix.translate(-(tile.page_rect.origin.x) as AzFloat,
+                                  -(tile.page_rect.origin.y) as AzFloat);
+
+    ctx.draw_target.set_transform(&matrix);
+
+    // Clear the buffer.
+    ctx.clear();
+
+    // Draw the display list.
+    profile(time::RenderingDrawingCategory, profiler_chan, || {
+        display_list_collection.draw_lists_into_context(&mut ctx);
+        ctx.draw_target.flush();
+    });
 }
 
 pub struct RenderTask<C,T> {
@@ -130,6 +272,13 @@ pub struct RenderTask<C,T> {
 
     /// A data structure to store unused LayerBuffers
     buffer_map: BufferMap<~LayerBuffer>,
+
+    /// Channels to the tasks that rasterize tiles in parallel. Empty if tiles are rendered
+    /// sequentially on this task, which is always the case for GPU rendering.
+    workers: ~[Chan<WorkerMsg<T>>],
+
+    /// Whether the compositor was last told about any animated images.
+    has_animated_rects: bool,
 }
 
 // If we implement this as a function, we get borrowck errors from borrowing
@@ -157,7 +306,25 @@ impl<C: RenderListener + Send,T:Send+Freeze> RenderTask<C,T> {
             { // Ensures RenderTask and graphics context are destroyed before shutdown msg
                 let native_graphics_context = compositor.get_graphics_metadata().map(
                     |md| NativePaintingGraphicsContext::from_metadata(&md));
-                let cpu_painting = opts.cpu_painting;
+                let graphics_context = if native_graphics_context.is_none() {
+                    SoftwareGraphicsContext
+                } else if opts.cpu_painting {
+                    CpuGraphicsContext
+                } else {
+                    GpuGraphicsContext
+                };
+
+                let parallel = match graphics_context {
+                    GpuGraphicsContext => false,
+                    CpuGraphicsContext | SoftwareGraphicsContext => opts.n_render_threads > 1,
+                };
+                let workers = if parallel {
+                    vec::from_fn(opts.n_render_threads, |_| {
+                        spawn_render_worker(opts.clone(), profiler_chan.clone())
+                    })
+                } else {
+                    ~[]
+                };
 
                 // FIXME: rust/#5967
                 let mut render_task = RenderTask {
@@ -173,11 +340,7 @@ impl<C: RenderListener + Send,T:Send+Freeze> RenderTask<C,T> {
                     opts: opts,
                     profiler_chan: profiler_chan,
 
-                    graphics_context: if cpu_painting {
-                        CpuGraphicsContext
-                    } else {
-                        GpuGraphicsContext
-                    },
+                    graphics_context: graphics_context,
 
                     native_graphics_context: native_graphics_context,
 
@@ -186,10 +349,16 @@ impl<C: RenderListener + Send,T:Send+Freeze> RenderTask<C,T> {
                     paint_permission: false,
                     epoch: Epoch(0),
                     buffer_map: BufferMap::new(10000000),
+                    workers: workers,
+                    has_animated_rects: false,
                 };
 
                 render_task.start();
 
+                for worker in render_task.workers.iter() {
+                    worker.send(ExitWorkerMsg);
+                }
+
                 // Destroy all the buffers.
                 render_task.native_graphics_context.as_ref().map(
                     |ctx| render_task.buffer_map.clear(ctx));
@@ -241,6 +410,16 @@ impl<C: RenderListener + Send,T:Send+Freeze> RenderTask<C,T> {
                 PaintPermissionRevoked => {
                     self.paint_permission = false;
                 }
+                CollectMemoryReportsMsg(reports_chan) => {
+                    // The render workers have font contexts of their own, which aren't counted.
+                    let PipelineId(id) = self.id;
+                    reports_chan.try_send(~[
+                        MemoryReport::new(format!("pipeline-{}/render/font-caches", id),
+                                          self.font_ctx.heap_size_of_font_caches()),
+                        MemoryReport::new(format!("pipeline-{}/render/unused-buffers", id),
+                                          self.buffer_map.mem),
+                    ]);
+                }
                 ExitMsg(response_ch) => {
                     debug!("render_task: exitmsg response send");
                     response_ch.map(|ch| ch.send(()));
@@ -251,28 +430,42 @@ impl<C: RenderListener + Send,T:Send+Freeze> RenderTask<C,T> {
     }
 
     fn render(&mut self, tiles: ~[BufferRequest], scale: f32) {
-        let render_layer;
-        match self.render_layer {
-            Some(ref r_layer) => {
-                render_layer = r_layer;
-            }
-            _ => return, // nothing to do
-        }
+        let display_list_collection = match self.render_layer {
+            Some(ref render_layer) => render_layer.display_list_collection.clone(),
+            None => return, // nothing to do
+        };
 
         self.compositor.set_render_state(RenderingRenderState);
+        let render_time = precise_time_ns();
         time::profile(time::RenderingCategory, self.profiler_chan.clone(), || {
             // FIXME: Try not to create a new array here.
             let mut new_buffers = ~[];
+            let mut software_buffers = ~[];
 
             // Divide up the layer into tiles.
             time::profile(time::RenderingPrepBuffCategory, self.profiler_chan.clone(), || {
+                if self.workers.len() > 0 {
+                    let tile_data = self.render_tiles_in_parallel(tiles,
+                                                                  scale,
+                                                                  &display_list_collection);
+                    for (tile, data) in tiles.iter().zip(tile_data.move_iter()) {
+                        match self.graphics_context {
+                            SoftwareGraphicsContext => {
+                                software_buffers.push(software_tile(tile, scale, data))
+                            }
+                            _ => new_buffers.push(self.upload_cpu_tile(tile, scale, data)),
+                        }
+                    }
+                    return
+                }
+
                 for tile in tiles.iter() {
                     let width = tile.screen_rect.size.width;
                     let height = tile.screen_rect.size.height;
 
                     let size = Size2D(width as i32, height as i32);
                     let draw_target = match self.graphics_context {
-                        CpuGraphicsContext => {
+                        CpuGraphicsContext | SoftwareGraphicsContext => {
                             DrawTarget::new(self.opts.render_backend, size, B8G8R8A8)
                         }
                         GpuGraphicsContext => {
@@ -288,33 +481,13 @@ impl<C: RenderListener + Send,T:Send+Freeze> RenderTask<C,T> {
                         }
                     };
 
-                    {
-                        // Build the render context.
-                        let mut ctx = RenderContext {
-                            draw_target: &draw_target,
-                            font_ctx: &mut self.font_ctx,
-                            opts: &self.opts,
-                            page_rect: tile.page_rect,
-                            screen_rect: tile.screen_rect,
-                        };
-
-                        // Apply the translation to render the tile we want.
-                        let matrix: Matrix2D<AzFloat> = Matrix2D::identity();
-                        let matrix = matrix.scale(scale as AzFloat, scale as AzFloat);
-                        let matrix = matrix.translate(-(tile.page_rect.origin.x) as AzFloat,
-                                                      -(tile.page_rect.origin.y) as AzFloat);
-                        
-                        ctx.draw_target.set_transform(&matrix);
-                        
-                        // Clear the buffer.
-                        ctx.clear();
-                        
-                        // Draw the display list.
-                        profile(time::RenderingDrawingCategory, self.profiler_chan.clone(), || {
-                            render_layer.display_list_collection.get().draw_lists_into_context(&mut ctx);
-                            ctx.draw_target.flush();
-                        });
-                    }
+                    render_tile(&draw_target,
+                                &mut self.font_ctx,
+                                &self.opts,
+                                tile,
+                                scale,
+                                display_list_collection.get(),
+                                self.profiler_chan.clone());
 
                     // Extract the texture from the draw target and place it into its slot in the
                     // buffer. If using CPU rendering, upload it first.
@@ -322,43 +495,17 @@ impl<C: RenderListener + Send,T:Send+Freeze> RenderTask<C,T> {
                     // FIXME(pcwalton): We should supply the texture and native surface *to* the
                     // draw target in GPU rendering mode, so that it doesn't have to recreate it.
                     let buffer = match self.graphics_context {
+                        SoftwareGraphicsContext => {
+                            let data = draw_target.snapshot().get_data_surface().with_data(|data| {
+                                data.to_owned()
+                            });
+                            software_buffers.push(software_tile(tile, scale, data));
+                            continue
+                        }
                         CpuGraphicsContext => {
-                            let buffer = match self.buffer_map.find(tile.screen_rect.size) {
-                                Some(buffer) => {
-                                    let mut buffer = buffer;
-                                    buffer.rect = tile.page_rect;
-                                    buffer.screen_pos = tile.screen_rect;
-                                    buffer.resolution = scale;
-                                    buffer.native_surface.mark_wont_leak();
-                                    buffer
-                                }
-                                None => {
-                                    // Create an empty native surface. We mark it as not leaking
-                                    // in case it dies in transit to the compositor task.
-                                    let mut native_surface: NativeSurface =
-                                        layers::platform::surface::NativeSurfaceMethods::new(
-                                            native_graphics_context!(self),
-                                            Size2D(width as i32, height as i32),
-                                            width as i32 * 4);
-                                    native_surface.mark_wont_leak();
-
-                                    ~LayerBuffer {
-                                        native_surface: native_surface,
-                                        rect: tile.page_rect,
-                                        screen_pos: tile.screen_rect,
-                                        resolution: scale,
-                                        stride: (width * 4) as uint
-                                    }
-                                }
-                            };
-
                             draw_target.snapshot().get_data_surface().with_data(|data| {
-                                buffer.native_surface.upload(native_graphics_context!(self), data);
-                                debug!("RENDERER uploading to native surface {:d}",
-                                       buffer.native_surface.get_id() as int);
-                            });
-
-                            buffer
+                                self.upload_cpu_tile(tile, scale, data)
+                            })
                         }
                         GpuGraphicsContext => {
                             draw_target.make_current();
@@ -386,13 +533,20 @@ impl<C: RenderListener + Send,T:Send+Freeze> RenderTask<C,T> {
                 }
             });
 
-            let layer_buffer_set = ~LayerBufferSet {
-                buffers: new_buffers,
-            };
-
             debug!("render_task: returning surface");
             if self.paint_permission {
-                self.compositor.paint(self.id, layer_buffer_set, self.epoch);
+                match self.graphics_context {
+                    SoftwareGraphicsContext => {
+                        self.compositor.paint_software(self.id, software_buffers, self.epoch);
+                    }
+                    CpuGraphicsContext | GpuGraphicsContext => {
+                        let layer_buffer_set = ~LayerBufferSet {
+                            buffers: new_buffers,
+                        };
+                        self.compositor.paint(self.id, layer_buffer_set, self.epoch);
+                    }
+                }
+                self.send_animated_rects(display_list_collection.get(), render_time);
             } else {
                 debug!("render_task: RendererReadyMsg send");
                 self.constellation_chan.send(RendererReadyMsg(self.id));
@@ -400,5 +554,120 @@ impl<C: RenderListener + Send,T:Send+Freeze> RenderTask<C,T> {
             self.compositor.set_render_state(IdleRenderState);
         })
     }
+
+    /// Tells the compositor when the animated images in the display list show their next frames,
+    /// so that it can ask for them to be rendered again.
+    fn send_animated_rects(&mut self,
+                           display_list_collection: &DisplayListCollection<T>,
+                           time: u64) {
+        let frame_times = display_list_collection.animated_image_frame_times(time);
+        if frame_times.is_empty() && !self.has_animated_rects {
+            return
+        }
+        self.has_animated_rects = !frame_times.is_empty();
+
+        let animated_rects = frame_times.move_iter().map(|(bounds, next_frame_time)| {
+            AnimatedRect {
+                rect: Rect(Point2D(geometry::to_frac_px(bounds.origin.x) as f32,
+                                   geometry::to_frac_px(bounds.origin.y) as f32),
+                           Size2D(geometry::to_frac_px(bounds.size.width) as f32,
+                                  geometry::to_frac_px(bounds.size.height) as f32)),
+                next_frame_time: next_frame_time,
+            }
+        }).collect();
+        self.compositor.set_layer_animated_rects(self.id, animated_rects);
+    }
+
+    /// Rasterizes the tiles on the render workers and returns their pixels in the order the
+    /// tiles were requested.
+    fn render_tiles_in_parallel(&mut self,
+                                tiles: &[BufferRequest],
+                                scale: f32,
+                                display_list_collection: &Arc<DisplayListCollection<T>>)
+                                -> ~[~[u8]] {
+        let (result_port, result_chan) = SharedChan::new();
+        for (index, tile) in tiles.iter().enumerate() {
+            let worker = &self.workers[index % self.workers.len()];
+            worker.send(RenderTileMsg(TileRequest {
+                index: index,
+                tile: tile.clone(),
+                scale: scale,
+                display_list_collection: display_list_collection.clone(),
+                result_chan: result_chan.clone(),
+            }));
+        }
+
+        collect_tiles_in_order(&result_port, tiles.len())
+    }
+
+    /// Uploads the pixels of a tile rendered on the CPU to a native surface, reusing an unused
+    /// buffer of the same size if there is one.
+    fn upload_cpu_tile(&mut self, tile: &BufferRequest, scale: f32, data: &[u8])
+                       -> ~LayerBuffer {
+        let width = tile.screen_rect.size.width;
+        let height = tile.screen_rect.size.height;
+
+        let buffer = match self.buffer_map.find(tile.screen_rect.size) {
+            Some(buffer) => {
+                let mut buffer = buffer;
+                buffer.rect = tile.page_rect;
+                buffer.screen_pos = tile.screen_rect;
+                buffer.resolution = scale;
+                buffer.native_surface.mark_wont_leak();
+                buffer
+            }
+            None => {
+                // Create an empty native surface. We mark it as not leaking
+                // in case it dies in transit to the compositor task.
+                let mut native_surface: NativeSurface =
+                    layers::platform::surface::NativeSurfaceMethods::new(
+                        native_graphics_context!(self),
+                        Size2D(width as i32, height as i32),
+                        width as i32 * 4);
+                native_surface.mark_wont_leak();
+
+                ~LayerBuffer {
+                    native_surface: native_surface,
+                    rect: tile.page_rect,
+                    screen_pos: tile.screen_rect,
+                    resolution: scale,
+                    stride: (width * 4) as uint
+                }
+            }
+        };
+
+        buffer.native_surface.upload(native_graphics_context!(self), data);
+        debug!("RENDERER uploading to native surface {:d}",
+               buffer.native_surface.get_id() as int);
+        buffer
+    }
+
 }
 
+#[cfg(test)]
+mod tests {
+    use super::collect_tiles_in_order;
+    use std::comm::SharedChan;
+    use std::task;
+
+    #[test]
+    fn test_tiles_are_returned_in_request_order() {
+        let (result_port, result_chan) = SharedChan::new();
+        for &index in [2u, 0, 1].iter() {
+            let result_chan = result_chan.clone();
+            task::spawn(proc() {
+                result_chan.send((index, ~[index as u8]));
+            });
+        }
+        assert_eq!(collect_tiles_in_order(&result_port, 3), ~[~[0u8], ~[1], ~[2]]);
+    }
+
+    #[test]
+    #[should_fail]
+    fn test_a_tile_returned_twice_is_an_error() {
+        let (result_port, result_chan) = SharedChan::new();
+        result_chan.send((0u, ~[0u8]));
+        result_chan.send((0u, ~[0u8]));
+        collect_tiles_in_order(&result_port, 2);
+    }
+}
@@ -0,0 +1,340 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! An implementation of the Unicode Bidirectional Algorithm (UAX #9).
+//!
+//! Explicit embeddings and overrides are expressed through CSS (`direction` and `unicode-bidi`)
+//! rather than through the explicit formatting characters, so this implementation resolves the
+//! levels of a single run of text at a given embedding level (rules W1-W7, N1-N2, I1-I2 and L1)
+//! and reorders resolved levels into visual order (rule L2). The explicit formatting characters
+//! themselves are treated as boundary neutrals.
+
+use std::vec;
+
+/// The bidirectional character types of UAX #9, Table 4.
+#[deriving(Clone, Eq)]
+pub enum BidiClass {
+    /// Left-to-right.
+    L,
+    /// Right-to-left.
+    R,
+    /// Right-to-left Arabic.
+    AL,
+    /// European number.
+    EN,
+    /// European number separator.
+    ES,
+    /// European number terminator.
+    ET,
+    /// Arabic number.
+    AN,
+    /// Common number separator.
+    CS,
+    /// Nonspacing mark.
+    NSM,
+    /// Boundary neutral.
+    BN,
+    /// Paragraph separator.
+    B,
+    /// Segment separator.
+    S,
+    /// Whitespace.
+    WS,
+    /// Other neutrals.
+    ON,
+}
+
+/// An embedding level. Even levels are left-to-right and odd levels are right-to-left.
+pub type Level = u8;
+
+/// Returns true if text at the given level is laid out right-to-left.
+#[inline]
+pub fn is_rtl(level: Level) -> bool {
+    level & 1 == 1
+}
+
+/// Returns the embedding level that text with the given direction gets when it is embedded at
+/// `base_level`: the least level greater than `base_level` with the right parity (rules X2-X5).
+pub fn embedding_level(base_level: Level, rtl: bool) -> Level {
+    if is_rtl(base_level) == rtl {
+        base_level + 2
+    } else {
+        base_level + 1
+    }
+}
+
+/// Returns the bidirectional character type of the given character.
+///
+/// FIXME: This covers the scripts and punctuation that matter in practice rather than the full
+/// Unicode Character Database.
+pub fn bidi_class(ch: char) -> BidiClass {
+    match ch {
+        '0'..'9' | '\u00b2' | '\u00b3' | '\u00b9' | '\u06f0'..'\u06f9' | '\u2070'..'\u2079' |
+        '\uff10'..'\uff19' => EN,
+        '+' | '-' | '\u207a' | '\u207b' | '\ufe62' | '\ufe63' | '\uff0b' | '\uff0d' => ES,
+        '#' | '$' | '%' | '\u00a2'..'\u00a5' | '\u00b0' | '\u00b1' | '\u066a' |
+        '\u2030'..'\u2034' | '\u20a0'..'\u20cf' => ET,
+        ',' | '.' | '/' | ':' | '\u00a0' | '\u060c' | '\u202f' | '\u2044' | '\ufe50' | '\ufe52' |
+        '\ufe55' | '\uff0c' | '\uff0e' | '\uff0f' | '\uff1a' => CS,
+        '\u0660'..'\u0669' | '\u066b' | '\u066c' | '\u0600'..'\u0605' => AN,
+        '\t' | '\x0b' | '\x1f' => S,
+        '\n' | '\r' | '\x1c'..'\x1e' | '\x85' | '\u2029' => B,
+        ' ' | '\x0c' | '\u1680' | '\u2000'..'\u200a' | '\u2028' | '\u205f' | '\u3000' => WS,
+        '\x00'..'\x08' | '\x0e'..'\x1b' | '\x7f'..'\x84' | '\x86'..'\x9f' | '\u00ad' |
+        '\u200b'..'\u200d' | '\u202a'..'\u202e' | '\u2060'..'\u2064' | '\ufeff' => BN,
+        '\u200e' => L,
+        '\u200f' => R,
+        '\u0300'..'\u036f' | '\u0483'..'\u0489' | '\u0591'..'\u05bd' | '\u05bf' | '\u05c1' |
+        '\u05c2' | '\u05c4' | '\u05c5' | '\u05c7' | '\u0610'..'\u061a' | '\u064b'..'\u065f' |
+        '\u0670' | '\u06d6'..'\u06dc' | '\u06df'..'\u06e4' | '\u06e7' | '\u06e8' |
+        '\u06ea'..'\u06ed' | '\u20d0'..'\u20f0' | '\ufe00'..'\ufe0f' | '\ufe20'..'\ufe2f' => NSM,
+        '\u0590'..'\u05ff' | '\u07c0'..'\u085f' | '\ufb1d'..'\ufb4f' |
+        '\U00010800'..'\U00010fff' | '\U0001e800'..'\U0001edff' => R,
+        '\u0600'..'\u07bf' | '\u0860'..'\u08ff' | '\ufb50'..'\ufdff' | '\ufe70'..'\ufefe' |
+        '\U0001ee00'..'\U0001eeff' => AL,
+        '!' | '"' | '&' | '\'' | '(' | ')' | '*' | ';' | '<' | '=' | '>' | '?' | '@' | '[' |
+        '\\' | ']' | '^' | '_' | '`' | '{' | '|' | '}' | '~' | '\u00a1' | '\u00a6'..'\u00a9' |
+        '\u00ab' | '\u00ac' | '\u00ae' | '\u00af' | '\u00b4' | '\u00b6'..'\u00b8' |
+        '\u00bb'..'\u00bf' | '\u00d7' | '\u00f7' | '\u2010'..'\u2027' | '\u2035'..'\u205e' |
+        '\u2190'..'\u2bff' | '\u3001'..'\u3004' | '\u3008'..'\u3020' | '\ufe30'..'\ufe4f' => ON,
+        _ => L,
+    }
+}
+
+/// Returns true if the given text contains any characters that are strongly right-to-left or are
+/// Arabic numbers. Text without such characters resolves entirely to the embedding level when
+/// that level is left-to-right.
+pub fn has_rtl_characters(text: &str) -> bool {
+    text.chars().any(|ch| {
+        match bidi_class(ch) {
+            R | AL | AN => true,
+            _ => false,
+        }
+    })
+}
+
+/// The direction of the embedding level, as a strong type.
+fn embedding_direction(level: Level) -> BidiClass {
+    if is_rtl(level) { R } else { L }
+}
+
+fn is_neutral(class: BidiClass) -> bool {
+    match class {
+        B | S | WS | ON | BN => true,
+        _ => false,
+    }
+}
+
+/// Resolves the embedding level of each character of the given text, which is embedded at
+/// `embedding_level`. The result has one entry per character, not per byte.
+pub fn resolve_levels(text: &str, embedding_level: Level) -> ~[Level] {
+    let original: ~[BidiClass] = text.chars().map(bidi_class).collect();
+    let mut classes = original.clone();
+    let len = classes.len();
+    let sos = embedding_direction(embedding_level);
+
+    // X9 and W1: Boundary neutrals and nonspacing marks take the type of the previous character,
+    // or of the start of the sequence.
+    for i in range(0, len) {
+        if classes[i] == NSM || classes[i] == BN {
+            classes[i] = if i == 0 { sos } else { classes[i - 1] };
+        }
+    }
+
+    // W2: European numbers after Arabic letters become Arabic numbers. W3: Arabic letters become
+    // right-to-left.
+    let mut last_strong = sos;
+    for i in range(0, len) {
+        match classes[i] {
+            L | R | AL => last_strong = classes[i],
+            EN if last_strong == AL => classes[i] = AN,
+            _ => {}
+        }
+    }
+    for i in range(0, len) {
+        if classes[i] == AL {
+            classes[i] = R;
+        }
+    }
+
+    // W4: A single separator between two numbers of the same type takes that type.
+    for i in range(1, if len > 0 { len - 1 } else { 0 }) {
+        let (before, after) = (classes[i - 1], classes[i + 1]);
+        match classes[i] {
+            ES if before == EN && after == EN => classes[i] = EN,
+            CS if before == EN && after == EN => classes[i] = EN,
+            CS if before == AN && after == AN => classes[i] = AN,
+            _ => {}
+        }
+    }
+
+    // W5: A sequence of European terminators adjacent to a European number becomes part of it.
+    let mut i = 0;
+    while i < len {
+        if classes[i] != ET {
+            i += 1;
+            continue
+        }
+        let start = i;
+        while i < len && classes[i] == ET {
+            i += 1;
+        }
+        let adjacent_to_number = (start > 0 && classes[start - 1] == EN) ||
+            (i < len && classes[i] == EN);
+        if adjacent_to_number {
+            for j in range(start, i) {
+                classes[j] = EN;
+            }
+        }
+    }
+
+    // W6: Remaining separators and terminators become other neutrals.
+    for i in range(0, len) {
+        match classes[i] {
+            ES | ET | CS => classes[i] = ON,
+            _ => {}
+        }
+    }
+
+    // W7: European numbers after left-to-right text become left-to-right.
+    let mut last_strong = sos;
+    for i in range(0, len) {
+        match classes[i] {
+            L | R => last_strong = classes[i],
+            EN if last_strong == L => classes[i] = L,
+            _ => {}
+        }
+    }
+
+    // N1 and N2: Sequences of neutrals take the direction of the surrounding text if both sides
+    // agree, and the embedding direction otherwise. Numbers count as right-to-left here.
+    let mut i = 0;
+    while i < len {
+        if !is_neutral(classes[i]) {
+            i += 1;
+            continue
+        }
+        let start = i;
+        while i < len && is_neutral(classes[i]) {
+            i += 1;
+        }
+        let before = if start == 0 { sos } else { strong_direction(classes[start - 1]) };
+        let after = if i == len { sos } else { strong_direction(classes[i]) };
+        let resolved = if before == after { before } else { sos };
+        for j in range(start, i) {
+            classes[j] = resolved;
+        }
+    }
+
+    // I1 and I2: Resolve implicit levels.
+    let mut levels = vec::from_elem(len, embedding_level);
+    for i in range(0, len) {
+        levels[i] = match (is_rtl(embedding_level), classes[i]) {
+            (false, R) => embedding_level + 1,
+            (false, AN) | (false, EN) => embedding_level + 2,
+            (true, L) | (true, AN) | (true, EN) => embedding_level + 1,
+            _ => embedding_level,
+        };
+    }
+
+    // L1: Separators, and whitespace preceding them or the end of the text, are reset to the
+    // embedding level.
+    let mut trailing = true;
+    for i in range(0, len).invert() {
+        match original[i] {
+            B | S => {
+                levels[i] = embedding_level;
+                trailing = true;
+            }
+            WS | BN if trailing => levels[i] = embedding_level,
+            _ => trailing = false,
+        }
+    }
+
+    levels
+}
+
+/// Maps resolved types to the strong direction they count as for rules N1 and N2.
+fn strong_direction(class: BidiClass) -> BidiClass {
+    match class {
+        L => L,
+        _ => R,
+    }
+}
+
+/// Returns the indices of the given levels in visual order, from left to right, per rule L2:
+/// from the highest level down to the lowest odd level, every maximal sequence at that level or
+/// higher is reversed.
+pub fn visual_order(levels: &[Level]) -> ~[uint] {
+    let mut order = vec::from_fn(levels.len(), |i| i);
+    if levels.len() == 0 {
+        return order
+    }
+
+    let highest = *levels.iter().max().unwrap();
+    let lowest_odd = match levels.iter().filter(|level| is_rtl(**level)).min() {
+        Some(level) => *level,
+        None => return order,
+    };
+
+    let mut level = highest;
+    while level >= lowest_odd {
+        let mut i = 0;
+        while i < order.len() {
+            if levels[order[i]] < level {
+                i += 1;
+                continue
+            }
+            let start = i;
+            while i < order.len() && levels[order[i]] >= level {
+                i += 1;
+            }
+            order.mut_slice(start, i).reverse();
+        }
+        level -= 1;
+    }
+    order
+}
+
+#[test]
+fn test_ltr_text_resolves_to_embedding_level() {
+    assert_eq!(resolve_levels("foo bar 123.", 0), ~[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    assert!(!has_rtl_characters("foo bar 123."));
+}
+
+#[test]
+fn test_hebrew_in_ltr_paragraph() {
+    // "ab \u05d0\u05d1 cd": the space between the Hebrew and Latin words takes the embedding
+    // direction.
+    assert_eq!(resolve_levels("ab \u05d0\u05d1 cd", 0), ~[0, 0, 0, 1, 1, 0, 0, 0]);
+    assert!(has_rtl_characters("\u05d0"));
+}
+
+#[test]
+fn test_numbers_in_rtl_paragraph() {
+    assert_eq!(resolve_levels("\u05d0 12", 1), ~[1, 1, 2, 2]);
+    assert_eq!(resolve_levels("\u0627 12", 1), ~[1, 1, 2, 2]);
+    assert_eq!(resolve_levels("ab", 1), ~[2, 2]);
+}
+
+#[test]
+fn test_trailing_whitespace_is_reset() {
+    assert_eq!(resolve_levels("\u05d0\u05d1 ", 0), ~[1, 1, 0]);
+}
+
+#[test]
+fn test_embedding_level() {
+    assert_eq!(embedding_level(0, true), 1);
+    assert_eq!(embedding_level(0, false), 2);
+    assert_eq!(embedding_level(1, false), 2);
+    assert_eq!(embedding_level(1, true), 3);
+}
+
+#[test]
+fn test_visual_order() {
+    assert_eq!(visual_order([0, 0, 0]), ~[0, 1, 2]);
+    assert_eq!(visual_order([1, 1, 1]), ~[2, 1, 0]);
+    assert_eq!(visual_order([0, 1, 1, 0]), ~[0, 2, 1, 3]);
+    assert_eq!(visual_order([1, 2, 2, 1]), ~[3, 1, 2, 0]);
+}
@@ -13,6 +13,7 @@ use std::u16;
 use std::vec;
 use std::util;
 use std::iter;
+use std::mem;
 use geom::point::Point2D;
 
 /// GlyphEntry is a port of Gecko's CompressedGlyph scheme for storing glyph data compactly.
@@ -254,7 +255,7 @@ impl GlyphEntry {
 #[deriving(Clone)]
 struct DetailedGlyph {
     index: GlyphIndex,
-    // glyph's advance, in the text's direction (RTL or RTL)
+    // glyph's advance, in the text's direction (LTR or RTL)
     advance: Au,
     // glyph's offset from the font's em-box (from top-left)
     offset: Point2D<Au>
@@ -316,6 +317,11 @@ impl<'a> DetailedGlyphStore {
         }
     }
 
+    fn heap_size(&self) -> uint {
+        self.detail_buffer.len() * mem::size_of::<DetailedGlyph>() +
+            self.detail_lookup.len() * mem::size_of::<DetailedGlyphRecord>()
+    }
+
     fn add_detailed_glyphs_for_entry(&mut self, entry_offset: uint, glyphs: &[DetailedGlyph]) {
         let entry = DetailedGlyphRecord {
             entry_offset: entry_offset,
@@ -523,6 +529,11 @@ impl<'a> GlyphStore {
         self.entry_buffer.len()
     }
 
+    /// Returns the heap memory used by the glyph data, for the memory profiler.
+    pub fn heap_size(&self) -> uint {
+        self.entry_buffer.len() * mem::size_of::<GlyphEntry>() + self.detail_store.heap_size()
+    }
+
     pub fn is_whitespace(&self) -> bool {
         self.is_whitespace
     }
@@ -0,0 +1,279 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Line break opportunities, per the Unicode Line Breaking Algorithm (UAX #14).
+//!
+//! This implements the pair rules LB4-LB31 on a subset of the line breaking classes. Classes that
+//! need dictionaries or tailoring are resolved as UAX #14 section 6.1 suggests: complex-context
+//! characters (SA) are treated as alphabetic, conditional Japanese starters (CJ) as nonstarters,
+//! and Hangul syllables as ideographs.
+
+use std::vec;
+
+/// The line breaking classes of UAX #14, Table 1.
+#[deriving(Clone, Eq)]
+pub enum LineBreakClass {
+    /// Mandatory break.
+    BK,
+    /// Carriage return.
+    CR,
+    /// Line feed.
+    LF,
+    /// Next line.
+    NL,
+    /// Space.
+    SP,
+    /// Zero-width space.
+    ZW,
+    /// Non-breaking ("glue").
+    GL,
+    /// Combining mark.
+    CM,
+    /// Zero-width joiner.
+    ZWJ,
+    /// Word joiner.
+    WJ,
+    /// Break opportunity after.
+    BA,
+    /// Break opportunity before.
+    BB,
+    /// Hyphen.
+    HY,
+    /// Break opportunity before and after.
+    B2,
+    /// Close punctuation.
+    CL,
+    /// Close parenthesis.
+    CP,
+    /// Open punctuation.
+    OP,
+    /// Quotation.
+    QU,
+    /// Exclamation or interrogation.
+    EX,
+    /// Infix numeric separator.
+    IS,
+    /// Nonstarter.
+    NS,
+    /// Symbols allowing break after.
+    SY,
+    /// Inseparable.
+    IN,
+    /// Numeric.
+    NU,
+    /// Prefix numeric.
+    PR,
+    /// Postfix numeric.
+    PO,
+    /// Ordinary alphabetic and symbol characters.
+    AL,
+    /// Ideographic.
+    ID,
+}
+
+/// Returns the line breaking class of the given character.
+///
+/// FIXME: Like `bidi::bidi_class`, this covers the characters that matter in practice rather
+/// than the full Unicode Character Database.
+pub fn line_break_class(ch: char) -> LineBreakClass {
+    match ch {
+        '\n' => LF,
+        '\r' => CR,
+        '\x0b' | '\x0c' | '\u2028' | '\u2029' => BK,
+        '\x85' => NL,
+        ' ' => SP,
+        '\u200b' => ZW,
+        '\u00a0' | '\u034f' | '\u2007' | '\u2011' | '\u202f' | '\u0f0c' => GL,
+        '\u200d' => ZWJ,
+        '\u2060' | '\ufeff' => WJ,
+        '\u0300'..'\u036f' | '\u0483'..'\u0489' | '\u0591'..'\u05bd' | '\u0610'..'\u061a' |
+        '\u064b'..'\u065f' | '\u200c' | '\u20d0'..'\u20f0' | '\ufe00'..'\ufe0f' |
+        '\ufe20'..'\ufe2f' | '\x00'..'\x08' | '\x0e'..'\x1f' => CM,
+        '\t' | '|' | '\u00ad' | '\u058a' | '\u2000'..'\u2006' | '\u2008'..'\u200a' | '\u2010' |
+        '\u2012' | '\u2013' | '\u3000' => BA,
+        '\u00b4' | '\u02c8' | '\u02cc' | '\u02df' => BB,
+        '-' => HY,
+        '\u2014' => B2,
+        '}' | '\u3001' | '\u3002' | '\u3009' | '\u300b' | '\u300d' | '\u300f' | '\u3011' |
+        '\u3015' | '\u3017' | '\u3019' | '\u301b' | '\u301e' | '\u301f' | '\ufe50' |
+        '\ufe52' | '\uff0c' | '\uff0e' | '\uff5d' | '\uff60' | '\uff61' | '\uff63' |
+        '\uff64' => CL,
+        ')' | ']' | '\uff09' | '\uff3d' => CP,
+        '(' | '[' | '{' | '\u00a1' | '\u00bf' | '\u201a' | '\u201e' | '\u2045' | '\u207d' |
+        '\u208d' | '\u3008' | '\u300a' | '\u300c' | '\u300e' | '\u3010' | '\u3014' | '\u3016' |
+        '\u3018' | '\u301a' | '\u301d' | '\uff08' | '\uff3b' | '\uff5b' | '\uff5f' |
+        '\uff62' => OP,
+        '"' | '\'' | '\u00ab' | '\u00bb' | '\u2018' | '\u2019' | '\u201b' | '\u201c' |
+        '\u201d' | '\u201f' | '\u2039' | '\u203a' => QU,
+        '!' | '?' | '\u05c6' | '\u061b' | '\u061f' | '\uff01' | '\uff1f' => EX,
+        ',' | '.' | ':' | ';' | '\u037e' | '\u0589' | '\u060c' | '\u060d' | '\u2044' |
+        '\ufe10' | '\ufe13' | '\ufe14' => IS,
+        '/' => SY,
+        '\u2024'..'\u2026' | '\ufe19' => IN,
+        '0'..'9' | '\u0660'..'\u0669' | '\u06f0'..'\u06f9' | '\u0966'..'\u096f' => NU,
+        '$' | '+' | '\\' | '\u00a3'..'\u00a5' | '\u00b1' | '\u20a0'..'\u20b5' | '\u2116' |
+        '\ufe69' | '\uff04' | '\uffe1' | '\uffe5' | '\uffe6' => PR,
+        '%' | '\u00a2' | '\u00b0' | '\u2030'..'\u2037' | '\u2103' | '\u2109' | '\ufe6a' |
+        '\uff05' | '\uffe0' => PO,
+        '\u203c' | '\u203d' | '\u2047'..'\u2049' | '\u3005' | '\u303b' | '\u3041' | '\u3043' |
+        '\u3045' | '\u3047' | '\u3049' | '\u3063' | '\u3083' | '\u3085' | '\u3087' | '\u308e' |
+        '\u3095' | '\u3096' | '\u309b'..'\u309e' | '\u30a0' | '\u30a1' | '\u30a3' | '\u30a5' |
+        '\u30a7' | '\u30a9' | '\u30c3' | '\u30e3' | '\u30e5' | '\u30e7' | '\u30ee' | '\u30f5' |
+        '\u30f6' | '\u30fb'..'\u30fe' | '\u31f0'..'\u31ff' | '\uff1a' | '\uff1b' |
+        '\uff67'..'\uff70' => NS,
+        '\u1100'..'\u115f' | '\u2e80'..'\u2fff' | '\u3003' | '\u3004' | '\u3006' | '\u3007' |
+        '\u3012' | '\u3013' | '\u3020'..'\u3029' | '\u3030'..'\u303a' | '\u303c'..'\u303f' |
+        '\u3040'..'\u309f' | '\u30a2'..'\u30fa' | '\u30ff'..'\u31ef' | '\u3200'..'\u4dbf' |
+        '\u4e00'..'\u9fff' | '\ua000'..'\ua4cf' | '\uac00'..'\ud7a3' | '\uf900'..'\ufaff' |
+        '\ufe30'..'\ufe4f' | '\uff00'..'\uff5f' | '\uffe2'..'\uffe4' |
+        '\U0001f000'..'\U0001faff' | '\U00020000'..'\U0003fffd' => ID,
+        _ => AL,
+    }
+}
+
+/// Returns, for each character of the given text, whether a line may be broken before it.
+/// Mandatory breaks are reported as opportunities too. The first character never has a break
+/// opportunity before it (LB2).
+pub fn break_opportunities(text: &str) -> ~[bool] {
+    let classes: ~[LineBreakClass] = text.chars().map(line_break_class).collect();
+    let mut opportunities = vec::from_elem(classes.len(), false);
+
+    // The class of the character before the current position, after applying LB9 and LB10, and
+    // the class of the last character before any spaces, for the rules that look past spaces.
+    let mut before = match classes.head_opt() {
+        Some(&CM) | Some(&ZWJ) => AL,
+        Some(&class) => class,
+        None => return opportunities,
+    };
+    let mut before_spaces = before;
+
+    for i in range(1, classes.len()) {
+        let after = classes[i];
+        opportunities[i] = can_break_between(before, before_spaces, after);
+
+        // LB9: Combining marks and joiners take the class of the character they attach to,
+        // unless that character is a space or a break. LB10: Otherwise they are alphabetic.
+        let attaches = match after {
+            CM | ZWJ => true,
+            _ => false,
+        };
+        if attaches {
+            match before {
+                BK | CR | LF | NL | SP | ZW => before = AL,
+                _ => {}
+            }
+        } else {
+            before = after;
+        }
+        if before != SP {
+            before_spaces = before;
+        }
+    }
+
+    opportunities
+}
+
+/// Applies the pair rules of UAX #14 to decide whether a break is allowed between a character
+/// of class `before` and one of class `after`. `before_spaces` is the class of the last
+/// non-space character, for the rules of the form `X SP* x`.
+fn can_break_between(before: LineBreakClass,
+                     before_spaces: LineBreakClass,
+                     after: LineBreakClass)
+                     -> bool {
+    match (before, after) {
+        // LB4, LB5: Always break after hard line breaks, but not within CR LF.
+        (CR, LF) => false,
+        (BK, _) | (CR, _) | (LF, _) | (NL, _) => true,
+        // LB6: Do not break before hard line breaks.
+        (_, BK) | (_, CR) | (_, LF) | (_, NL) => false,
+        // LB7: Do not break before spaces or zero width space.
+        (_, SP) | (_, ZW) => false,
+        // LB8: Break after zero width space, even if spaces follow.
+        (ZW, _) => true,
+        _ if before == SP && before_spaces == ZW => true,
+        // LB9: Do not break before combining marks or joiners.
+        (_, CM) | (_, ZWJ) => false,
+        // LB11: Do not break before or after word joiners.
+        (_, WJ) | (WJ, _) => false,
+        // LB12: Do not break after non-breaking characters.
+        (GL, _) => false,
+        // LB12a: Do not break before non-breaking characters, except after spaces and hyphens.
+        (SP, GL) | (BA, GL) | (HY, GL) => true,
+        (_, GL) => false,
+        // LB13: Do not break before closing punctuation, even after spaces.
+        (_, CL) | (_, CP) | (_, EX) | (_, IS) | (_, SY) => false,
+        // LB14: Do not break after opening punctuation, even after spaces.
+        _ if before_spaces == OP => false,
+        // LB15: Do not break within a quotation followed by opening punctuation.
+        (_, OP) if before_spaces == QU => false,
+        // LB16: Do not break between closing punctuation and a nonstarter.
+        (_, NS) if before_spaces == CL || before_spaces == CP => false,
+        // LB17: Do not break within em dashes, even after spaces.
+        (_, B2) if before_spaces == B2 => false,
+        // LB18: Break after spaces.
+        (SP, _) => true,
+        // LB19: Do not break before or after quotation marks.
+        (_, QU) | (QU, _) => false,
+        // LB21: Do not break before hyphen-like characters and nonstarters, or after acute
+        // accents and similar.
+        (_, BA) | (_, HY) | (_, NS) | (BB, _) => false,
+        // LB22: Do not break before ellipses.
+        (_, IN) => false,
+        // LB23: Do not break between letters and digits.
+        (AL, NU) | (NU, AL) => false,
+        // LB23a: Do not break between numeric prefixes and ideographs, or ideographs and
+        // numeric postfixes.
+        (PR, ID) | (ID, PO) => false,
+        // LB24: Do not break between numeric prefixes or postfixes and letters.
+        (PR, AL) | (PO, AL) | (AL, PR) | (AL, PO) => false,
+        // LB25: Do not break within numbers.
+        (CL, PO) | (CP, PO) | (CL, PR) | (CP, PR) | (NU, PO) | (NU, PR) | (PO, OP) |
+        (PO, NU) | (PR, OP) | (PR, NU) | (HY, NU) | (IS, NU) | (NU, NU) | (SY, NU) => false,
+        // LB28: Do not break between alphabetics.
+        (AL, AL) => false,
+        // LB29: Do not break between numeric punctuation and alphabetics.
+        (IS, AL) => false,
+        // LB30: Do not break between letters, numbers, or ordinary symbols and parentheses.
+        (AL, OP) | (NU, OP) | (CP, AL) | (CP, NU) => false,
+        // LB31: Break everywhere else.
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+fn break_positions(text: &str) -> ~[uint] {
+    break_opportunities(text).iter().enumerate().filter_map(|(i, &can_break)| {
+        if can_break { Some(i) } else { None }
+    }).collect()
+}
+
+#[test]
+fn test_break_after_spaces() {
+    assert_eq!(break_positions("foo bar  baz"), ~[4, 9]);
+    assert_eq!(break_positions("foo\u00a0bar"), ~[]);
+}
+
+#[test]
+fn test_break_after_hyphens_and_slashes() {
+    assert_eq!(break_positions("well-known"), ~[5]);
+    assert_eq!(break_positions("http://a.org/b"), ~[7, 13]);
+    assert_eq!(break_positions("-5 and 1-2"), ~[3, 7]);
+}
+
+#[test]
+fn test_break_at_soft_hyphen() {
+    assert_eq!(break_positions("hy\u00adphen"), ~[3]);
+}
+
+#[test]
+fn test_break_between_ideographs() {
+    // The ideographic full stop must not start a line.
+    assert_eq!(break_positions("\u4f60\u597d\u3002"), ~[1]);
+}
+
+#[test]
+fn test_no_break_inside_punctuation() {
+    assert_eq!(break_positions("(foo) bar!"), ~[6]);
+    assert_eq!(break_positions("$100.00"), ~[]);
+}
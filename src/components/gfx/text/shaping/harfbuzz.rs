@@ -7,17 +7,18 @@ extern mod harfbuzz;
 use font::{Font, FontHandleMethods, FontTableMethods, FontTableTag};
 use platform::font::FontTable;
 use text::glyph::{GlyphStore, GlyphIndex, GlyphData};
-use text::shaping::ShaperMethods;
+use text::shaping::{ShaperMethods, ShapingOptions};
 use text::util::{float_to_fixed, fixed_to_float};
 
 use geom::Point2D;
-use harfbuzz::{HB_MEMORY_MODE_READONLY, HB_DIRECTION_LTR};
+use harfbuzz::{HB_MEMORY_MODE_READONLY, HB_DIRECTION_LTR, HB_DIRECTION_RTL};
 use harfbuzz::{hb_blob_create, hb_face_create_for_tables};
 use harfbuzz::{hb_blob_t};
 use harfbuzz::{hb_bool_t};
 use harfbuzz::{hb_buffer_add_utf8};
 use harfbuzz::{hb_buffer_destroy};
 use harfbuzz::{hb_buffer_get_glyph_positions};
+use harfbuzz::{hb_buffer_reverse};
 use harfbuzz::{hb_buffer_set_direction};
 use harfbuzz::{hb_face_destroy};
 use harfbuzz::{hb_face_t, hb_font_t};
@@ -201,10 +202,11 @@ impl Shaper {
 impl ShaperMethods for Shaper {
     /// Calculate the layout metrics associated with the given text when rendered in a specific
     /// font.
-    fn shape_text(&self, text: &str, glyphs: &mut GlyphStore) {
+    fn shape_text(&self, text: &str, options: &ShapingOptions, glyphs: &mut GlyphStore) {
         unsafe {
             let hb_buffer: *hb_buffer_t = hb_buffer_create();
-            hb_buffer_set_direction(hb_buffer, HB_DIRECTION_LTR);
+            let direction = if options.rtl { HB_DIRECTION_RTL } else { HB_DIRECTION_LTR };
+            hb_buffer_set_direction(hb_buffer, direction);
 
             // Using as_imm_buf because it never does a copy - we don't need the trailing null
             hb_buffer_add_utf8(hb_buffer,
@@ -214,14 +216,26 @@ impl ShaperMethods for Shaper {
                                text.len() as c_int);
 
             hb_shape(self.hb_font, hb_buffer, null(), 0);
-            self.save_glyph_results(text, glyphs, hb_buffer);
+
+            // HarfBuzz returns right-to-left glyphs in visual order. The glyph store is indexed
+            // by character, so put them back into logical order; they are reversed again when
+            // drawn.
+            if options.rtl {
+                hb_buffer_reverse(hb_buffer);
+            }
+
+            self.save_glyph_results(text, options, glyphs, hb_buffer);
             hb_buffer_destroy(hb_buffer);
         }
     }
 }
 
 impl Shaper {
-    fn save_glyph_results(&self, text: &str, glyphs: &mut GlyphStore, buffer: *hb_buffer_t) {
+    fn save_glyph_results(&self,
+                          text: &str,
+                          options: &ShapingOptions,
+                          glyphs: &mut GlyphStore,
+                          buffer: *hb_buffer_t) {
         let glyph_data = ShapedGlyphData::new(buffer);
         let glyph_count = glyph_data.len();
         let byte_max = text.len();
@@ -395,6 +409,14 @@ impl Shaper {
             let end = covered_byte_span.end(); // FIXME: borrow checker workaround
             covered_byte_span.extend_to(num::min(end, byte_max));
 
+            // `letter-spacing` and `word-spacing` are applied per cluster, keyed off the first
+            // character of the cluster.
+            let extra_advance = if covered_byte_span.begin() < byte_max {
+                options.extra_advance_for_char(text.char_at(covered_byte_span.begin()))
+            } else {
+                Au(0)
+            };
+
             // fast path: 1-to-1 mapping of single char and single glyph.
             if glyph_span.length() == 1 {
                 // TODO(Issue #214): cluster ranges need to be computed before
@@ -403,8 +425,9 @@ impl Shaper {
                 // (i.e., pretend there are no combining character sequences).
                 // 1-to-1 mapping of character to glyph also treated as ligature start.
                 let shape = glyph_data.get_entry_for_glyph(glyph_span.begin(), &mut y_pos);
+                let advance = shape.advance + extra_advance;
                 let data = GlyphData::new(shape.codepoint,
-                                          shape.advance,
+                                          advance,
                                           shape.offset,
                                           false,
                                           true,
@@ -416,8 +439,14 @@ impl Shaper {
 
                 for glyph_i in glyph_span.eachi() {
                     let shape = glyph_data.get_entry_for_glyph(glyph_i, &mut y_pos);
+                    // Letter and word spacing go after the last glyph of the cluster.
+                    let advance = if glyph_i == glyph_span.end() - 1 {
+                        shape.advance + extra_advance
+                    } else {
+                        shape.advance
+                    };
                     datas.push(GlyphData::new(shape.codepoint,
-                                              shape.advance,
+                                              advance,
                                               shape.offset,
                                               false, // not missing
                                               true,  // treat as cluster start
@@ -7,13 +7,92 @@
 ///
 /// Currently, only harfbuzz bindings are implemented.
 
+use servo_util::geometry::Au;
+use std::to_bytes::Cb;
 use text::glyph::GlyphStore;
 
 pub use Shaper = text::shaping::harfbuzz::Shaper;
 
 pub mod harfbuzz;
 
+/// Extra spacing to apply while shaping, as given by the CSS `letter-spacing` and `word-spacing`
+/// properties, and the direction of the text.
+#[deriving(Clone, Eq)]
+pub struct ShapingOptions {
+    /// Spacing added after every character cluster, or `None` for `letter-spacing: normal`.
+    letter_spacing: Option<Au>,
+    /// Spacing added to every word-separator character.
+    word_spacing: Au,
+    /// True if the text is shaped right-to-left.
+    rtl: bool,
+}
+
+impl ShapingOptions {
+    pub fn new(letter_spacing: Option<Au>, word_spacing: Au) -> ShapingOptions {
+        ShapingOptions {
+            letter_spacing: letter_spacing,
+            word_spacing: word_spacing,
+            rtl: false,
+        }
+    }
+
+    /// Options that shape text with the font's own advances.
+    pub fn normal() -> ShapingOptions {
+        ShapingOptions::new(None, Au(0))
+    }
+
+    /// Returns the extra advance to add to a cluster that begins with the given character.
+    #[inline]
+    pub fn extra_advance_for_char(&self, ch: char) -> Au {
+        let letter_spacing = self.letter_spacing.unwrap_or(Au(0));
+        if is_word_separator(ch) {
+            letter_spacing + self.word_spacing
+        } else {
+            letter_spacing
+        }
+    }
+}
+
+impl IterBytes for ShapingOptions {
+    fn iter_bytes(&self, lsb0: bool, f: Cb) -> bool {
+        let letter_spacing = self.letter_spacing.map(|spacing| *spacing);
+        letter_spacing.iter_bytes(lsb0, |x| f(x)) &&
+            (*self.word_spacing).iter_bytes(lsb0, |x| f(x)) &&
+            self.rtl.iter_bytes(lsb0, |x| f(x))
+    }
+}
+
+/// Returns true if `word-spacing` applies to the given character. See CSS Text Level 3 § 8.1.
+pub fn is_word_separator(ch: char) -> bool {
+    match ch {
+        ' ' | '\u00a0' | '\u1361' | '\U00010100' | '\U00010101' | '\U0001039f' => true,
+        _ => false,
+    }
+}
+
 pub trait ShaperMethods {
-    fn shape_text(&self, text: &str, glyphs: &mut GlyphStore);
+    fn shape_text(&self, text: &str, options: &ShapingOptions, glyphs: &mut GlyphStore);
 }
 
+#[cfg(test)]
+mod tests {
+    use super::{ShapingOptions, is_word_separator};
+    use servo_util::geometry::Au;
+
+    #[test]
+    fn test_normal_spacing_adds_nothing() {
+        let options = ShapingOptions::normal();
+        assert_eq!(options.extra_advance_for_char('a'), Au(0));
+        assert_eq!(options.extra_advance_for_char(' '), Au(0));
+    }
+
+    #[test]
+    fn test_word_spacing_applies_to_word_separators_only() {
+        let options = ShapingOptions::new(Some(Au(2)), Au(5));
+        assert_eq!(options.extra_advance_for_char('a'), Au(2));
+        assert_eq!(options.extra_advance_for_char(' '), Au(7));
+        assert_eq!(options.extra_advance_for_char('\u00a0'), Au(7));
+        assert!(!is_word_separator('\t'));
+        assert!(!is_word_separator('\u3000'));
+    }
+}
@@ -9,6 +9,8 @@ use servo_util::range::Range;
 use std::vec::VecIterator;
 use style::computed_values::text_decoration;
 use text::glyph::GlyphStore;
+use text::line_break;
+use text::shaping::ShapingOptions;
 
 /// A text run.
 #[deriving(Clone)]
@@ -19,6 +21,8 @@ pub struct TextRun {
     font_style: FontStyle,
     decoration: text_decoration::T,
     glyphs: Arc<~[Arc<GlyphStore>]>,
+    /// True if this run was shaped right-to-left. Its glyphs are still stored in logical order.
+    rtl: bool,
 }
 
 pub struct SliceIterator<'a> {
@@ -96,8 +100,12 @@ impl<'a> Iterator<Range> for LineIterator<'a> {
 }
 
 impl<'a> TextRun {
-    pub fn new(font: &mut Font, text: ~str, decoration: text_decoration::T) -> TextRun {
-        let glyphs = TextRun::break_and_shape(font, text);
+    pub fn new(font: &mut Font,
+               text: ~str,
+               decoration: text_decoration::T,
+               options: &ShapingOptions)
+               -> TextRun {
+        let glyphs = TextRun::break_and_shape(font, text, options);
 
         let run = TextRun {
             text: Arc::new(text),
@@ -106,6 +114,7 @@ impl<'a> TextRun {
             font_descriptor: font.get_descriptor(),
             decoration: decoration,
             glyphs: Arc::new(glyphs),
+            rtl: options.rtl,
         };
         return run;
     }
@@ -113,56 +122,53 @@ impl<'a> TextRun {
     pub fn teardown(&self) {
     }
 
-    pub fn break_and_shape(font: &mut Font, text: &str) -> ~[Arc<GlyphStore>] {
-        // TODO(Issue #230): do a better job. See Gecko's LineBreaker.
+    /// Splits the text into slices at line break opportunities and shapes each of them.
+    /// Whitespace is always kept in slices of its own, so that it can be trimmed at the ends of
+    /// lines; this means a break is also allowed after spaces that UAX #14 would attach to
+    /// following punctuation.
+    pub fn break_and_shape(font: &mut Font, text: &str, options: &ShapingOptions)
+                           -> ~[Arc<GlyphStore>] {
+        let opportunities = line_break::break_opportunities(text);
 
         let mut glyphs = ~[];
-        let mut byte_i = 0u;
         let mut cur_slice_is_whitespace = false;
         let mut byte_last_boundary = 0;
-        while byte_i < text.len() {
-            let range = text.char_range_at(byte_i);
-            let ch = range.ch;
-            let next = range.next;
-
-            // Slices alternate between whitespace and non-whitespace,
-            // representing line break opportunities.
-            let can_break_before = if cur_slice_is_whitespace {
-                match ch {
-                    ' ' | '\t' | '\n' => false,
-                    _ => {
-                        cur_slice_is_whitespace = false;
-                        true
-                    }
-                }
+        let mut prev_ch = None;
+        for (char_i, (byte_i, ch)) in text.char_indices().enumerate() {
+            let is_whitespace = match ch {
+                ' ' | '\t' | '\n' => true,
+                _ => false,
+            };
+
+            // Slices end wherever the text switches between whitespace and non-whitespace, and
+            // at every break opportunity within non-whitespace text.
+            //
+            // FIXME: Soft hyphens are break opportunities too, but they are skipped until a
+            // hyphen can be drawn at the end of a line broken there.
+            let can_break_before = if is_whitespace != cur_slice_is_whitespace {
+                true
             } else {
-                match ch {
-                    ' ' | '\t' | '\n' => {
-                        cur_slice_is_whitespace = true;
-                        true
-                    },
-                    _ => false
-                }
+                !is_whitespace && opportunities[char_i] && prev_ch != Some('\u00ad')
             };
 
-            // Create a glyph store for this slice if it's nonempty.
+            // Create a glyph store for the previous slice if it's nonempty.
             if can_break_before && byte_i > byte_last_boundary {
                 let slice = text.slice(byte_last_boundary, byte_i).to_owned();
                 debug!("creating glyph store for slice {} (ws? {}), {} - {} in run {}",
-                        slice, !cur_slice_is_whitespace, byte_last_boundary, byte_i, text);
-                glyphs.push(font.shape_text(slice, !cur_slice_is_whitespace));
+                        slice, cur_slice_is_whitespace, byte_last_boundary, byte_i, text);
+                glyphs.push(font.shape_text(slice, options, cur_slice_is_whitespace));
                 byte_last_boundary = byte_i;
             }
-
-            byte_i = next;
+            cur_slice_is_whitespace = is_whitespace;
+            prev_ch = Some(ch);
         }
 
         // Create a glyph store for the final slice if it's nonempty.
-        if byte_i > byte_last_boundary {
+        if text.len() > byte_last_boundary {
             let slice = text.slice_from(byte_last_boundary).to_owned();
             debug!("creating glyph store for final slice {} (ws? {}), {} - {} in run {}",
                 slice, cur_slice_is_whitespace, byte_last_boundary, text.len(), text);
-            glyphs.push(font.shape_text(slice, cur_slice_is_whitespace));
+            glyphs.push(font.shape_text(slice, options, cur_slice_is_whitespace));
         }
 
         glyphs
@@ -185,9 +191,19 @@ impl<'a> TextRun {
         true
     }
 
+    /// Returns the number of whitespace characters in the given range. Each of these is an
+    /// opportunity to expand the text for `text-align: justify`.
+    pub fn justification_opportunities_for_range(&self, range: &Range) -> uint {
+        let mut count = 0;
+        for (glyphs, _, slice_range) in self.iter_slices_for_range(range) {
+            if glyphs.is_whitespace() {
+                count += slice_range.length();
+            }
+        }
+        count
+    }
+
     pub fn metrics_for_range(&self, range: &Range) -> RunMetrics {
-        // TODO(Issue #199): alter advance direction for RTL
-        // TODO(Issue #98): using inter-char and inter-word spacing settings  when measuring text
         let mut advance = Au(0);
         for (glyphs, _offset, slice_range) in self.iter_slices_for_range(range) {
             for (_i, glyph) in glyphs.iter_glyphs_for_char_range(&slice_range) {
@@ -207,9 +223,9 @@ impl<'a> TextRun {
     pub fn min_width_for_range(&self, range: &Range) -> Au {
         let mut max_piece_width = Au(0);
         debug!("iterating outer range {:?}", range);
-        for (_, offset, slice_range) in self.iter_slices_for_range(range) {
+        for (glyphs, offset, slice_range) in self.iter_slices_for_range(range) {
             debug!("iterated on {:?}[{:?}]", offset, slice_range);
-            let metrics = self.metrics_for_range(&slice_range);
+            let metrics = self.metrics_for_slice(glyphs, &slice_range);
             max_piece_width = Au::max(max_piece_width, metrics.advance_width);
         }
         max_piece_width
@@ -12,13 +12,13 @@ use windowing::{WindowEvent, WindowMethods,
                 WindowNavigateMsg,
                 IdleWindowEvent, RefreshWindowEvent, ResizeWindowEvent, LoadUrlWindowEvent,
                 MouseWindowEventClass, MouseWindowMoveEventClass,ScrollWindowEvent, ZoomWindowEvent, NavigationWindowEvent,
-                FinishedWindowEvent, QuitWindowEvent,
+                FinishedWindowEvent, QuitWindowEvent, PrintMemoryReportsWindowEvent,
                 MouseWindowEvent, MouseWindowClickEvent, MouseWindowMouseDownEvent, MouseWindowMouseUpEvent};
 
 
 use azure::azure_hl::{SourceSurfaceMethods, Color};
 use azure::azure_hl;
-use extra::time::precise_time_s;
+use extra::time::{precise_time_ns, precise_time_s};
 use geom::matrix::identity;
 use geom::point::Point2D;
 use geom::rect::Rect;
@@ -30,15 +30,19 @@ use layers::rendergl::RenderContext;
 use layers::scene::Scene;
 use opengles::gl2;
 use png;
-use servo_msg::compositor_msg::{Blank, Epoch, FinishedLoading, IdleRenderState, LayerBufferSet, ReadyState, RenderState};
+use servo_msg::compositor_msg::{AnimatedRect, Blank, Epoch, FinishedLoading, IdleRenderState, LayerBufferSet, ReadyState, RenderState};
 use servo_msg::constellation_msg::{ConstellationChan, ExitMsg, NavigateMsg, ResizedWindowMsg, LoadUrlMsg, PipelineId};
+use servo_msg::constellation_msg::{FrameTickMsg, SetDevicePixelRatioMsg};
 use servo_msg::constellation_msg;
+use servo_util::memory::MemoryProfilerChan;
+use servo_util::memory;
 use servo_util::opts::Opts;
 use servo_util::time::{profile, ProfilerChan, Timer};
 use servo_util::{time, url};
 use std::comm::Port;
 use std::num::Orderable;
 use std::path::Path;
+use std::util;
 
 //FIXME: switch to std::rc when we upgrade Rust
 use layers::temp_rc::Rc;
@@ -89,6 +93,12 @@ pub struct IOCompositor {
     /// The time of the last zoom action has started.
     zoom_time: f64,
 
+    /// The number of device pixels per CSS pixel that the constellation was last told about.
+    device_pixel_ratio: f32,
+
+    /// The time, in nanoseconds, at which the constellation was last told about a frame.
+    last_frame_tick: u64,
+
     /// Current display/reflow status of the page
     ready_state: ReadyState,
 
@@ -109,8 +119,14 @@ pub struct IOCompositor {
     /// The channel on which messages can be sent to the profiler.
     profiler_chan: ProfilerChan,
 
+    /// The channel on which messages can be sent to the memory profiler.
+    memory_profiler_chan: MemoryProfilerChan,
+
     /// Pending scroll to fragment event, if any 
-    fragment_point: Option<Point2D<f32>>
+    fragment_point: Option<Point2D<f32>>,
+
+    /// The animated images of each layer, which are rendered again when they show a new frame.
+    animated_rects: ~[(PipelineId, AnimatedRect)],
 }
 
 impl IOCompositor {
@@ -119,7 +135,8 @@ impl IOCompositor {
                opts: Opts,
                port: Port<Msg>,
                constellation_chan: ConstellationChan,
-               profiler_chan: ProfilerChan) -> IOCompositor {
+               profiler_chan: ProfilerChan,
+               memory_profiler_chan: MemoryProfilerChan) -> IOCompositor {
         let window: rc::Rc<Window> = WindowMethods::new(app);
 
         // Create an initial layer tree.
@@ -145,12 +162,16 @@ impl IOCompositor {
             world_zoom: 1f32,
             zoom_action: false,
             zoom_time: 0f64,
+            device_pixel_ratio: 1f32,
+            last_frame_tick: 0,
             ready_state: Blank,
             load_complete: false,
             compositor_layer: None,
             constellation_chan: constellation_chan,
             profiler_chan: profiler_chan,
-            fragment_point: None
+            memory_profiler_chan: memory_profiler_chan,
+            fragment_point: None,
+            animated_rects: ~[],
         }
     }
 
@@ -158,12 +179,14 @@ impl IOCompositor {
                   opts: Opts,
                   port: Port<Msg>,
                   constellation_chan: ConstellationChan,
-                  profiler_chan: ProfilerChan) {
+                  profiler_chan: ProfilerChan,
+                  memory_profiler_chan: MemoryProfilerChan) {
         let mut compositor = IOCompositor::new(app,
                                                opts,
                                                port,
                                                constellation_chan,
-                                               profiler_chan);
+                                               profiler_chan,
+                                               memory_profiler_chan);
 
         // Starts the compositor, which listens for messages on the specified port.
         compositor.run();
@@ -172,6 +195,7 @@ impl IOCompositor {
     fn run (&mut self) {
         // Tell the constellation about the initial window size.
         self.constellation_chan.send(ResizedWindowMsg(self.window_size));
+        self.update_device_pixel_ratio();
 
         // Enter the main event loop.
         while !self.done {
@@ -195,14 +219,20 @@ impl IOCompositor {
                 self.composite();
             }
 
+            // Let pages run their animation frame callbacks before the next frame.
+            self.send_frame_tick();
+
             Timer::sleep(10);
 
             // If a pinch-zoom happened recently, ask for tiles at the new resolution
             if self.zoom_action && precise_time_s() - self.zoom_time > 0.3 {
                 self.zoom_action = false;
                 self.ask_for_tiles();
+                self.update_device_pixel_ratio();
             }
 
+            self.invalidate_animated_rects();
+
         }
 
         // Clear out the compositor layers so that painting tasks can destroy the buffers.
@@ -215,8 +245,9 @@ impl IOCompositor {
         // another task from finishing (i.e. SetIds)
         while self.port.try_recv().is_some() {}
 
-        // Tell the profiler to shut down.
+        // Tell the profilers to shut down.
         self.profiler_chan.send(time::ExitMsg);
+        self.memory_profiler_chan.send(memory::ExitMsg);
     }
 
     fn handle_message(&mut self) {
@@ -278,10 +309,18 @@ impl IOCompositor {
                     self.paint(id, new_layer_buffer_set, epoch);
                 }
 
+                (Some(PaintSoftware(..)), false) => {
+                    fail!("compositor: software tiles are only sent to the headless compositor")
+                }
+
                 (Some(InvalidateRect(id, rect)), false) => {
                     self.invalidate_rect(id, rect);
                 }
 
+                (Some(SetAnimatedRects(id, animated_rects)), false) => {
+                    self.set_animated_rects(id, animated_rects);
+                }
+
                 (Some(ScrollFragmentPoint(id, point)), false) => {
                     self.scroll_fragment_to_point(id, point);
                 }
@@ -290,6 +329,17 @@ impl IOCompositor {
                     self.load_complete = true;
                 }
 
+                // Frame ticks are sent before every frame regardless.
+                (Some(SetAnimationFrameCallbacksPending(..)), false) => { }
+
+                (Some(CollectMemoryReports(reports_chan)), false) => {
+                    let mut reports = ~[];
+                    for layer in self.compositor_layer.iter() {
+                        layer.collect_memory_reports(&mut reports);
+                    }
+                    reports_chan.try_send(reports);
+                }
+
                 // When we are shutting_down, we need to avoid performing operations
                 // such as Paint that may crash because we have begun tearing down
                 // the rest of our resources.
@@ -351,6 +401,7 @@ impl IOCompositor {
         let window_size = Size2D(window_size.width as uint,
                                  window_size.height as uint);
         new_constellation_chan.send(ResizedWindowMsg(window_size));
+        new_constellation_chan.send(SetDevicePixelRatioMsg(self.device_pixel_ratio));
 
         self.constellation_chan = new_constellation_chan;
     }
@@ -427,6 +478,7 @@ impl IOCompositor {
     }
 
     fn delete_layer(&mut self, id: PipelineId) {
+        self.animated_rects.retain(|&(animated_id, _)| animated_id != id);
         let ask: bool = match self.compositor_layer {
             Some(ref mut layer) => {
                 assert!(layer.delete(&self.graphics_context, id));
@@ -468,6 +520,42 @@ impl IOCompositor {
         // it wishes.
     }
 
+    fn set_animated_rects(&mut self, id: PipelineId, animated_rects: ~[AnimatedRect]) {
+        self.animated_rects.retain(|&(animated_id, _)| animated_id != id);
+        for animated_rect in animated_rects.move_iter() {
+            self.animated_rects.push((id, animated_rect));
+        }
+    }
+
+    /// Asks for the animated images that are due to show a new frame to be rendered again. Only
+    /// the rects of the images are invalidated. The renderer sends their next frame times once it
+    /// has rendered them, so images that are scrolled out of view stop animating.
+    fn invalidate_animated_rects(&mut self) {
+        if self.animated_rects.is_empty() {
+            return
+        }
+
+        let now = precise_time_ns();
+        let mut invalidated = false;
+        let animated_rects = util::replace(&mut self.animated_rects, ~[]);
+        for (id, animated_rect) in animated_rects.move_iter() {
+            if animated_rect.next_frame_time > now {
+                self.animated_rects.push((id, animated_rect));
+                continue
+            }
+            match self.compositor_layer {
+                Some(ref mut layer) => {
+                    invalidated = layer.invalidate_rect(id, animated_rect.rect) || invalidated;
+                }
+                None => {}
+            }
+        }
+
+        if invalidated {
+            self.ask_for_tiles();
+        }
+    }
+
     fn invalidate_rect(&mut self, id: PipelineId, rect: Rect<uint>) {
         let ask: bool = match self.compositor_layer {
             Some(ref mut layer) => {
@@ -561,6 +649,10 @@ impl IOCompositor {
                 self.constellation_chan.send(ExitMsg);
                 self.shutting_down = true;
             }
+
+            PrintMemoryReportsWindowEvent => {
+                self.memory_profiler_chan.send(memory::PrintMsg);
+            }
         }
     }
 
@@ -734,6 +826,30 @@ impl IOCompositor {
         }
     }
 
+    /// Tells the constellation that a new frame is starting, at most once per
+    /// `FRAME_INTERVAL_NS`, so that it can forward the tick to visible pipelines.
+    fn send_frame_tick(&mut self) {
+        if !self.composite_ready || self.shutting_down {
+            return;
+        }
+        let now = precise_time_ns();
+        if now - self.last_frame_tick < FRAME_INTERVAL_NS {
+            return;
+        }
+        self.last_frame_tick = now;
+        self.constellation_chan.send(FrameTickMsg(now));
+    }
+
+    /// Tells the constellation how many device pixels each CSS pixel is drawn at, if that has
+    /// changed, so that images are decoded at the size they are drawn at.
+    fn update_device_pixel_ratio(&mut self) {
+        let device_pixel_ratio = self.window.borrow().hidpi_factor() * self.world_zoom;
+        if device_pixel_ratio != self.device_pixel_ratio {
+            self.device_pixel_ratio = device_pixel_ratio;
+            self.constellation_chan.send(SetDevicePixelRatioMsg(device_pixel_ratio));
+        }
+    }
+
     fn recomposite_if(&mut self, result: bool) {
         self.recomposite = result || self.recomposite;
     }
@@ -22,6 +22,7 @@ use script::dom::event::{ClickEvent, MouseDownEvent, MouseMoveEvent, MouseUpEven
 use script::script_task::SendEventMsg;
 use servo_msg::compositor_msg::{LayerBuffer, LayerBufferSet, Epoch, Tile};
 use servo_msg::constellation_msg::PipelineId;
+use servo_util::memory::MemoryReport;
 //FIXME: switch to std::rc when we upgrade Rust
 use layers::temp_rc::Rc;
 //use std::rc::Rc;
@@ -763,6 +764,21 @@ impl CompositorLayer {
         }
     }
 
+    /// Adds a report of the memory used by the tiles of this layer and of each of its descendants,
+    /// for the memory profiler.
+    pub fn collect_memory_reports(&self, reports: &mut ~[MemoryReport]) {
+        let tile_mem = match self.quadtree {
+            NoTree(..) => 0,
+            Tree(ref quadtree) => quadtree.get_mem(),
+        };
+        let PipelineId(id) = self.pipeline.id;
+        reports.push(MemoryReport::new(format!("pipeline-{}/compositor/tiles", id), tile_mem));
+
+        for kid in self.children.iter() {
+            kid.child.collect_memory_reports(reports)
+        }
+    }
+
     /// Destroys all tiles of all layers, including children, *without* sending them back to the
     /// renderer. You must call this only when the render task is destined to be going down;
     /// otherwise, you will leak tiles.
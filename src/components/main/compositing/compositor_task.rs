@@ -13,9 +13,11 @@ use geom::point::Point2D;
 use geom::rect::Rect;
 use geom::size::Size2D;
 use layers::platform::surface::{NativeCompositingGraphicsContext, NativeGraphicsMetadata};
-use servo_msg::compositor_msg::{Epoch, RenderListener, LayerBufferSet, RenderState, ReadyState};
-use servo_msg::compositor_msg::{ScriptListener, Tile};
+use servo_msg::compositor_msg::{AnimatedRect, Epoch, RenderListener, LayerBufferSet};
+use servo_msg::compositor_msg::{RenderState, ReadyState};
+use servo_msg::compositor_msg::{ScriptListener, SoftwareLayerBuffer, Tile};
 use servo_msg::constellation_msg::{ConstellationChan, PipelineId};
+use servo_util::memory::{MemoryProfilerChan, MemoryReporter, MemoryReportsChan};
 use servo_util::opts::Opts;
 use servo_util::time::ProfilerChan;
 use std::comm::{Chan, SharedChan, Port};
@@ -26,6 +28,9 @@ use extra::url::Url;
 #[cfg(target_os="linux")]
 use azure::azure_hl;
 
+/// The interval between frame ticks sent to the constellation: roughly 60 frames per second.
+pub static FRAME_INTERVAL_NS: u64 = 16666667;
+
 mod quadtree;
 mod compositor_layer;
 
@@ -54,6 +59,10 @@ impl ScriptListener for CompositorChan {
 	    self.chan.send(ScrollFragmentPoint(id, point));
     }
 
+    fn set_animation_frame_callbacks_pending(&self, id: PipelineId, pending: bool) {
+        self.chan.send(SetAnimationFrameCallbacksPending(id, pending));
+    }
+
     fn close(&self) {
         let (port, chan) = Chan::new();
         self.chan.send(Exit(chan));
@@ -77,6 +86,10 @@ impl RenderListener for CompositorChan {
         self.chan.send(Paint(id, layer_buffer_set, epoch))
     }
 
+    fn paint_software(&self, id: PipelineId, buffers: ~[~SoftwareLayerBuffer], epoch: Epoch) {
+        self.chan.send(PaintSoftware(id, buffers, epoch))
+    }
+
     fn new_layer(&self, id: PipelineId, page_size: Size2D<uint>) {
         let Size2D { width, height } = page_size;
         self.chan.send(NewLayer(id, Size2D(width as f32, height as f32)))
@@ -102,6 +115,10 @@ impl RenderListener for CompositorChan {
     fn set_render_state(&self, render_state: RenderState) {
         self.chan.send(ChangeRenderState(render_state))
     }
+
+    fn set_layer_animated_rects(&self, id: PipelineId, animated_rects: ~[AnimatedRect]) {
+        self.chan.send(SetAnimatedRects(id, animated_rects))
+    }
 }
 
 impl CompositorChan {
@@ -117,6 +134,12 @@ impl CompositorChan {
         self.chan.send(msg);
     }
 }
+
+impl MemoryReporter for CompositorChan {
+    fn collect_reports(&self, reports_chan: MemoryReportsChan) -> bool {
+        self.chan.try_send(CollectMemoryReports(reports_chan))
+    }
+}
 /// Messages from the painting task and the constellation task to the compositor task.
 pub enum Msg {
     /// Requests that the compositor shut down.
@@ -131,7 +154,8 @@ pub enum Msg {
     /// to create surfaces that the compositor can see. On Linux this is the X display; on Mac this
     /// is the pixel format.
     ///
-    /// The headless compositor returns `None`.
+    /// The headless compositor returns `None`, which makes the renderer send `PaintSoftware`
+    /// messages instead of `Paint` messages.
     GetGraphicsMetadata(Chan<Option<NativeGraphicsMetadata>>),
 
     /// Alerts the compositor that there is a new layer to be rendered.
@@ -144,10 +168,19 @@ pub enum Msg {
     DeleteLayer(PipelineId),
     /// Invalidate a rect for a given layer
     InvalidateRect(PipelineId, Rect<uint>),
+    /// Replaces the animated images of the given layer, whose rects are rendered again whenever
+    /// the images show a new frame.
+    SetAnimatedRects(PipelineId, ~[AnimatedRect]),
     /// Scroll a page in a window
     ScrollFragmentPoint(PipelineId, Point2D<f32>),
+    /// Tells the compositor whether the given page has animation frame callbacks waiting for the
+    /// next frame tick. Only the headless compositor, which doesn't tick otherwise, needs this.
+    SetAnimationFrameCallbacksPending(PipelineId, bool),
     /// Requests that the compositor paint the given layer buffer set for the given page size.
     Paint(PipelineId, ~LayerBufferSet, Epoch),
+    /// Requests that the compositor paint the given tiles rendered into memory. Only sent to
+    /// compositors without graphics metadata.
+    PaintSoftware(PipelineId, ~[~SoftwareLayerBuffer], Epoch),
     /// Alerts the compositor to the current status of page loading.
     ChangeReadyState(ReadyState),
     /// Alerts the compositor to the current status of rendering.
@@ -159,6 +192,9 @@ pub enum Msg {
 
     /// The load of a page for a given URL has completed.
     LoadComplete(PipelineId, Url),
+
+    /// Asks for the memory used by the tiles held by the compositor.
+    CollectMemoryReports(MemoryReportsChan),
 }
 
 pub enum CompositorMode {
@@ -198,7 +234,8 @@ impl CompositorTask {
     pub fn create(opts: Opts,
                   port: Port<Msg>,
                   constellation_chan: ConstellationChan,
-                  profiler_chan: ProfilerChan) {
+                  profiler_chan: ProfilerChan,
+                  memory_profiler_chan: MemoryProfilerChan) {
 
         let compositor = CompositorTask::new(opts.headless);
 
@@ -208,12 +245,15 @@ impl CompositorTask {
                                                  opts,
                                                  port,
                                                  constellation_chan.clone(),
-                                                 profiler_chan)
+                                                 profiler_chan,
+                                                 memory_profiler_chan)
             }
             Headless => {
-                headless::NullCompositor::create(port,
-                                                 constellation_chan.clone(),
-                                                 profiler_chan)
+                headless::HeadlessCompositor::create(opts,
+                                                     port,
+                                                     constellation_chan.clone(),
+                                                     profiler_chan,
+                                                     memory_profiler_chan)
             }
         };
     }
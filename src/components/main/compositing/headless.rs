@@ -3,52 +3,130 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use compositing::*;
+use pipeline::CompositionPipeline;
 
+use azure::azure_hl::Color;
+use geom::point::Point2D;
+use geom::rect::Rect;
 use geom::size::Size2D;
-use servo_msg::constellation_msg::{ConstellationChan, ExitMsg, ResizedWindowMsg};
-use std::comm::Port;
-use servo_util::time::ProfilerChan;
+use gfx::color;
+use gfx::render_task::{BufferRequest, ReRenderMsg};
+use png;
+use servo_msg::compositor_msg::{Blank, Epoch, FinishedLoading, ReadyState, SoftwareLayerBuffer};
+use servo_msg::constellation_msg::{ConstellationChan, ExitMsg, FrameTickMsg, PipelineId};
+use servo_msg::constellation_msg::ResizedWindowMsg;
+use servo_util::memory::{MemoryProfilerChan, MemoryReport};
+use servo_util::memory;
+use servo_util::opts::Opts;
+use servo_util::time::{ProfilerChan, Timer};
 use servo_util::time;
+use extra::time::precise_time_ns;
+use std::cmp;
+use std::comm::Port;
+use std::hashmap::HashSet;
+use std::os;
+use std::path::Path;
+use std::vec;
 
 /// Starts the compositor, which listens for messages on the specified port.
 ///
-/// This is the null compositor which doesn't draw anything to the screen.
-/// It's intended for headless testing.
-pub struct NullCompositor {
+/// This is the headless compositor, which doesn't need a window or a GPU. If an output file was
+/// requested (`-o`), it asks the renderer for tiles covering the viewport, assembles them into a
+/// framebuffer in memory, and writes that to the output file once the page has loaded. Otherwise
+/// it doesn't draw anything, which is useful for benchmarking.
+pub struct HeadlessCompositor {
     /// The port on which we receive messages.
     port: Port<Msg>,
+
+    opts: Opts,
+
+    /// The channel on which messages can be sent to the constellation.
+    constellation_chan: ConstellationChan,
+
+    /// The pipeline of the root frame, once the constellation has told us about it.
+    pipeline: Option<CompositionPipeline>,
+
+    /// The size of the root layer's page, in pixels.
+    page_size: Size2D<f32>,
+
+    /// The epoch of the root layer's current page size. Tiles from older epochs are ignored.
+    epoch: Epoch,
+
+    /// True if the framebuffer holds the tiles requested last.
+    painted: bool,
+
+    /// The number of tile requests for the current epoch that the renderer hasn't answered yet.
+    pending_tile_requests: uint,
+
+    /// The color to draw where nothing has been rendered.
+    unrendered_color: Color,
+
+    /// The viewport contents, as rows of R8G8B8A8 pixels with premultiplied alpha.
+    framebuffer: ~[u8],
+
+    ready_state: ReadyState,
+    load_complete: bool,
+
+    /// The pipelines that have animation frame callbacks waiting for a frame tick.
+    animating_pipelines: HashSet<PipelineId>,
+
+    /// The time at which the last frame tick was sent, in nanoseconds.
+    last_frame_tick: u64,
+
+    /// Whether we have asked the constellation to shut down.
+    shutting_down: bool,
 }
 
-impl NullCompositor {
-    fn new(port: Port<Msg>) -> NullCompositor {
-        NullCompositor {
+impl HeadlessCompositor {
+    fn new(opts: Opts, port: Port<Msg>, constellation_chan: ConstellationChan)
+           -> HeadlessCompositor {
+        let viewport_size = opts.headless_viewport_size;
+        HeadlessCompositor {
             port: port,
+            opts: opts,
+            constellation_chan: constellation_chan,
+            pipeline: None,
+            page_size: Size2D(0f32, 0f32),
+            epoch: Epoch(0),
+            painted: false,
+            pending_tile_requests: 0,
+            unrendered_color: color::rgba(1.0, 1.0, 1.0, 1.0),
+            framebuffer: vec::from_elem(viewport_size.width * viewport_size.height * 4, 0u8),
+            ready_state: Blank,
+            load_complete: false,
+            animating_pipelines: HashSet::new(),
+            last_frame_tick: 0,
+            shutting_down: false,
         }
     }
 
-    pub fn create(port: Port<Msg>,
+    pub fn create(opts: Opts,
+                  port: Port<Msg>,
                   constellation_chan: ConstellationChan,
-                  profiler_chan: ProfilerChan) {
-        let compositor = NullCompositor::new(port);
+                  profiler_chan: ProfilerChan,
+                  memory_profiler_chan: MemoryProfilerChan) {
+        let mut compositor = HeadlessCompositor::new(opts, port, constellation_chan.clone());
 
-        // Tell the constellation about the initial fake size.
-        constellation_chan.send(ResizedWindowMsg(Size2D(640u, 480u)));
-        compositor.handle_message(constellation_chan);
+        // Tell the constellation about the size of the viewport.
+        constellation_chan.send(ResizedWindowMsg(compositor.opts.headless_viewport_size));
+        compositor.handle_message();
 
         // Drain compositor port, sometimes messages contain channels that are blocking
         // another task from finishing (i.e. SetIds)
         while compositor.port.try_recv().is_some() {}
 
         profiler_chan.send(time::ExitMsg);
+        memory_profiler_chan.send(memory::ExitMsg);
     }
 
-    fn handle_message(&self, constellation_chan: ConstellationChan) {
+    fn handle_message(&mut self) {
         loop {
-            match self.port.recv() {
+            match self.recv_message() {
                 Exit(chan) => {
                     debug!("shutting down the constellation");
-                    constellation_chan.send(ExitMsg);
+                    self.constellation_chan.send(ExitMsg);
                     chan.send(());
+                    self.shutting_down = true;
                 }
 
                 ShutdownComplete => {
@@ -60,19 +138,298 @@ impl NullCompositor {
                     chan.send(None);
                 }
 
-                SetIds(_, response_chan, _) => {
+                SetIds(frame_tree, response_chan, _) => {
+                    self.pipeline = Some(frame_tree.pipeline.clone());
                     response_chan.send(());
                 }
 
+                SetLayerPageSize(id, new_size, epoch) => {
+                    if self.is_root_pipeline(id) {
+                        self.page_size = new_size;
+                        self.epoch = epoch;
+                        // The renderer drops the requests made for older epochs.
+                        self.pending_tile_requests = 0;
+                        self.request_tiles();
+                    }
+                }
+
+                InvalidateRect(id, _) => {
+                    if self.is_root_pipeline(id) {
+                        self.request_tiles();
+                    }
+                }
+
+                PaintSoftware(id, buffers, epoch) => {
+                    if self.is_root_pipeline(id) && self.epoch == epoch {
+                        self.paint(buffers);
+                        if self.pending_tile_requests > 0 {
+                            self.pending_tile_requests -= 1;
+                        }
+                        self.painted = self.pending_tile_requests == 0;
+                        self.write_output_file_if_ready();
+                    }
+                }
+
+                SetUnRenderedColor(id, color) => {
+                    if self.is_root_pipeline(id) {
+                        self.unrendered_color = color;
+                    }
+                }
+
+                ChangeReadyState(ready_state) => {
+                    self.ready_state = ready_state;
+                    self.write_output_file_if_ready();
+                }
+
+                LoadComplete(..) => {
+                    self.load_complete = true;
+                    self.write_output_file_if_ready();
+                }
+
+                SetAnimationFrameCallbacksPending(id, pending) => {
+                    if pending {
+                        self.animating_pipelines.insert(id);
+                    } else {
+                        self.animating_pipelines.remove(&id);
+                    }
+                }
+
+                CollectMemoryReports(reports_chan) => {
+                    reports_chan.try_send(~[
+                        MemoryReport::new(~"compositor/framebuffer", self.framebuffer.len()),
+                    ]);
+                }
+
                 // Explicitly list ignored messages so that when we add a new one,
                 // we'll notice and think about whether it needs a response, like
                 // SetIds.
 
-                NewLayer(..) | SetLayerPageSize(..) | SetLayerClipRect(..) | DeleteLayer(..) |
-                Paint(..) | InvalidateRect(..) | ChangeReadyState(..) | ChangeRenderState(..)|
-                ScrollFragmentPoint(..) | SetUnRenderedColor(..) | LoadComplete(..)
+                NewLayer(..) | SetLayerClipRect(..) | DeleteLayer(..) | Paint(..) |
+                ChangeRenderState(..) | ScrollFragmentPoint(..) | SetAnimatedRects(..)
                     => ()
             }
         }
     }
+
+    /// Waits for the next message. There is no window to draw frames for, so while pages have
+    /// animation frame callbacks pending, this sends the frame ticks that the windowed
+    /// compositor would send before each frame in the meantime.
+    fn recv_message(&mut self) -> Msg {
+        loop {
+            if self.animating_pipelines.is_empty() || self.shutting_down {
+                return self.port.recv();
+            }
+            match self.port.try_recv() {
+                Some(msg) => return msg,
+                None => (),
+            }
+
+            let now = precise_time_ns();
+            let next_frame_tick = self.last_frame_tick + FRAME_INTERVAL_NS;
+            if now < next_frame_tick {
+                Timer::sleep(cmp::max((next_frame_tick - now) / 1000000, 1));
+                continue;
+            }
+            self.last_frame_tick = now;
+            self.constellation_chan.send(FrameTickMsg(now));
+        }
+    }
+
+    fn is_root_pipeline(&self, id: PipelineId) -> bool {
+        match self.pipeline {
+            Some(ref pipeline) => pipeline.id == id,
+            None => false,
+        }
+    }
+
+    /// Asks the renderer for the tiles covering the part of the page within the viewport. Nothing
+    /// is rendered if there is no output file to write.
+    fn request_tiles(&mut self) {
+        // The framebuffer is out of date until the requested tiles arrive, whether the page was
+        // laid out again or only repainted.
+        self.painted = false;
+
+        if self.opts.output_file.is_none() || self.pipeline.is_none() || self.shutting_down {
+            return
+        }
+
+        let viewport_size = self.opts.headless_viewport_size;
+        let width = cmp::min(viewport_size.width, self.page_size.width.ceil() as uint);
+        let height = cmp::min(viewport_size.height, self.page_size.height.ceil() as uint);
+        let tiles = tile_rects(width, height, self.opts.tile_size);
+        let requests: ~[BufferRequest] = tiles.move_iter().map(|rect| {
+            BufferRequest(rect, Rect(Point2D(rect.origin.x as f32, rect.origin.y as f32),
+                                     Size2D(rect.size.width as f32, rect.size.height as f32)))
+        }).collect();
+
+        if requests.len() > 0 {
+            let render_chan = &self.pipeline.get_ref().render_chan;
+            render_chan.try_send(ReRenderMsg(requests, 1f32, self.epoch));
+            self.pending_tile_requests += 1;
+        } else if self.pending_tile_requests == 0 {
+            // The page is empty, so there is nothing to wait for.
+            self.painted = true;
+            self.write_output_file_if_ready();
+        }
+    }
+
+    /// Copies the given tiles into the framebuffer.
+    fn paint(&mut self, buffers: ~[~SoftwareLayerBuffer]) {
+        for buffer in buffers.iter() {
+            copy_tile(self.framebuffer.as_mut_slice(), self.opts.headless_viewport_size, &**buffer);
+        }
+    }
+
+    /// Writes the framebuffer to the output file and shuts down once the page has finished
+    /// loading and its tiles have been painted.
+    fn write_output_file_if_ready(&mut self) {
+        if !self.painted || !self.load_complete || self.ready_state != FinishedLoading ||
+                self.shutting_down {
+            return
+        }
+        let path = match self.opts.output_file {
+            Some(ref output_file) => from_str::<Path>(*output_file).unwrap(),
+            None => return,
+        };
+
+        let viewport_size = self.opts.headless_viewport_size;
+        let img = png::Image {
+            width: viewport_size.width as u32,
+            height: viewport_size.height as u32,
+            color_type: png::RGB8,
+            pixels: composite_over_color(self.framebuffer.as_slice(), &self.unrendered_color),
+        };
+        match png::store_png(&img, &path) {
+            Ok(()) => {}
+            Err(error) => {
+                error!("failed to write the output file {}: {}", path.display(), error);
+                os::set_exit_status(1);
+            }
+        }
+
+        debug!("shutting down the constellation after generating an output file");
+        self.constellation_chan.send(ExitMsg);
+        self.shutting_down = true;
+    }
+}
+
+/// Divides the given area, starting at the origin, into tiles of at most `tile_size` pixels
+/// square, in rows from the top left.
+fn tile_rects(width: uint, height: uint, tile_size: uint) -> ~[Rect<uint>] {
+    let mut rects = ~[];
+    let mut y = 0;
+    while y < height {
+        let tile_height = cmp::min(tile_size, height - y);
+        let mut x = 0;
+        while x < width {
+            let tile_width = cmp::min(tile_size, width - x);
+            rects.push(Rect(Point2D(x, y), Size2D(tile_width, tile_height)));
+            x += tile_size;
+        }
+        y += tile_size;
+    }
+    rects
+}
+
+/// Copies the B8G8R8A8 pixels of a tile into an R8G8B8A8 framebuffer of the given size, clipping
+/// the tile to the framebuffer.
+fn copy_tile(framebuffer: &mut [u8], size: Size2D<uint>, buffer: &SoftwareLayerBuffer) {
+    let origin = buffer.screen_pos.origin;
+    let tile_size = buffer.screen_pos.size;
+    let width = cmp::min(tile_size.width, size.width - cmp::min(origin.x, size.width));
+    let height = cmp::min(tile_size.height, size.height - cmp::min(origin.y, size.height));
+    for row in range(0, height) {
+        for column in range(0, width) {
+            let src = (row * buffer.stride + column) * 4;
+            let dst = ((origin.y + row) * size.width + origin.x + column) * 4;
+            framebuffer[dst] = buffer.pixels[src + 2];
+            framebuffer[dst + 1] = buffer.pixels[src + 1];
+            framebuffer[dst + 2] = buffer.pixels[src];
+            framebuffer[dst + 3] = buffer.pixels[src + 3];
+        }
+    }
+}
+
+/// Composites R8G8B8A8 pixels with premultiplied alpha over an opaque color, returning R8G8B8
+/// pixels.
+fn composite_over_color(framebuffer: &[u8], color: &Color) -> ~[u8] {
+    let background = [
+        (color.r * 255.0) as uint,
+        (color.g * 255.0) as uint,
+        (color.b * 255.0) as uint,
+    ];
+    let pixel_count = framebuffer.len() / 4;
+    let mut pixels = vec::with_capacity(pixel_count * 3);
+    for i in range(0, pixel_count) {
+        let alpha = framebuffer[i * 4 + 3] as uint;
+        for channel in range(0, 3) {
+            let value = framebuffer[i * 4 + channel] as uint +
+                background[channel] * (255 - alpha) / 255;
+            pixels.push(cmp::min(value, 255) as u8);
+        }
+    }
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{composite_over_color, copy_tile, tile_rects};
+
+    use geom::point::Point2D;
+    use geom::rect::Rect;
+    use geom::size::Size2D;
+    use gfx::color;
+    use servo_msg::compositor_msg::SoftwareLayerBuffer;
+    use std::vec;
+
+    fn tile(x: uint, y: uint, width: uint, height: uint, pixels: ~[u8]) -> SoftwareLayerBuffer {
+        SoftwareLayerBuffer {
+            pixels: pixels,
+            rect: Rect(Point2D(x as f32, y as f32), Size2D(width as f32, height as f32)),
+            screen_pos: Rect(Point2D(x, y), Size2D(width, height)),
+            resolution: 1.0,
+            stride: width,
+        }
+    }
+
+    #[test]
+    fn test_tile_rects_cover_the_area() {
+        assert_eq!(tile_rects(5, 3, 4), ~[
+            Rect(Point2D(0u, 0u), Size2D(4u, 3u)),
+            Rect(Point2D(4u, 0u), Size2D(1u, 3u)),
+        ]);
+        assert_eq!(tile_rects(4, 8, 4), ~[
+            Rect(Point2D(0u, 0u), Size2D(4u, 4u)),
+            Rect(Point2D(0u, 4u), Size2D(4u, 4u)),
+        ]);
+        assert_eq!(tile_rects(0, 8, 4), ~[]);
+    }
+
+    #[test]
+    fn test_copy_tile_swaps_red_and_blue() {
+        let mut framebuffer = vec::from_elem(2 * 2 * 4, 0u8);
+        copy_tile(framebuffer.as_mut_slice(), Size2D(2u, 2u), &tile(1, 1, 1, 1, ~[1, 2, 3, 4]));
+        assert_eq!(framebuffer, ~[0, 0, 0, 0,  0, 0, 0, 0,
+                                  0, 0, 0, 0,  3, 2, 1, 4]);
+    }
+
+    #[test]
+    fn test_copy_tile_clips_to_the_framebuffer() {
+        let mut framebuffer = vec::from_elem(2 * 1 * 4, 0u8);
+        let overhanging = tile(1, 0, 2, 2, ~[1, 2, 3, 4,  5, 6, 7, 8,
+                                             9, 9, 9, 9,  9, 9, 9, 9]);
+        copy_tile(framebuffer.as_mut_slice(), Size2D(2u, 1u), &overhanging);
+        assert_eq!(framebuffer, ~[0, 0, 0, 0,  3, 2, 1, 4]);
+        copy_tile(framebuffer.as_mut_slice(), Size2D(2u, 1u), &tile(4, 4, 1, 1, ~[9, 9, 9, 9]));
+        assert_eq!(framebuffer, ~[0, 0, 0, 0,  3, 2, 1, 4]);
+    }
+
+    #[test]
+    fn test_composite_over_color() {
+        let white = color::rgba(1.0, 1.0, 1.0, 1.0);
+        // Transparent, opaque red, and half-transparent green.
+        let framebuffer = [0u8, 0, 0, 0,  255, 0, 0, 255,  0, 128, 0, 128];
+        assert_eq!(composite_over_color(framebuffer, &white),
+                   ~[255, 255, 255,  255, 0, 0,  127, 255, 127]);
+    }
 }
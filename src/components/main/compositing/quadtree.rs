@@ -99,6 +99,11 @@ impl<T: Tile> Quadtree<T> {
         }
     }
 
+    /// Returns the combined memory of all the tiles in the tree, as given by `Tile::get_mem`.
+    pub fn get_mem(&self) -> uint {
+        self.root.tile_mem
+    }
+
     /// Add a tile associated with a given pixel position and scale.
     /// If the tile pushes the total memory over its maximum, tiles will be removed
     /// until total memory is below the maximum again. These tiles are returned.
@@ -712,6 +717,7 @@ pub fn test() {
     q.add_tile_pixel(0, 2, 2f32, T{a: 4});
     q.add_tile_pixel(2, 2, 2f32, T{a: 5});
     assert!(q.root.tile_mem == 4);
+    assert!(q.get_mem() == 4);
 
     let (request, _) = q.get_tile_rects_pixel(Rect(Point2D(0, 0), Size2D(2, 2)), 2f32);
     assert!(request.is_empty());
@@ -9,17 +9,26 @@ use geom::rect::Rect;
 use geom::size::Size2D;
 use gfx::render_task;
 use pipeline::{Pipeline, CompositionPipeline};
-use script::script_task::{ResizeMsg, ResizeInactiveMsg, ExitPipelineMsg};
+use script::script_task::{AnimationTickMsg, DevicePixelRatioMsg, DevtoolsControlMsg, ResizeMsg};
+use script::script_task::ResizeInactiveMsg;
+use script::script_task::{ExitPipelineMsg, PopStateMsg, StorageEventMsg};
+use script::script_task::ScriptMemoryReporter;
 use script::layout_interface;
-use servo_msg::constellation_msg::{ConstellationChan, ExitMsg, FailureMsg, Failure, FrameRectMsg};
+use servo_msg::constellation_msg::{ConstellationChan, DevtoolsMsg, ExitMsg, FailureMsg, Failure};
+use servo_msg::constellation_msg::{FrameRectMsg, FrameTickMsg, GetPipelinesMsg};
 use servo_msg::constellation_msg::{IFrameSandboxState, IFrameUnsandboxed, InitLoadUrlMsg};
 use servo_msg::constellation_msg::{LoadCompleteMsg, LoadIframeUrlMsg, LoadUrlMsg, Msg, NavigateMsg};
 use servo_msg::constellation_msg::{NavigationType, PipelineId, RendererReadyMsg, ResizedWindowMsg};
-use servo_msg::constellation_msg::SubpageId;
+use servo_msg::constellation_msg::{PushStateMsg, ReplaceStateMsg, SessionHistoryState, SubpageId};
+use servo_msg::constellation_msg::{SetDevicePixelRatioMsg, StorageChange, StorageChangedMsg};
 use servo_msg::constellation_msg;
 use servo_net::image_cache_task::{ImageCacheTask, ImageCacheTaskClient};
 use servo_net::resource_task::ResourceTask;
 use servo_net::resource_task;
+use servo_net::storage_task::StorageTask;
+use servo_net::storage_task;
+use servo_util::memory::{MemoryProfilerChan, MemoryReporter, RegisterReporterMsg};
+use servo_util::memory::UnregisterReporterMsg;
 use servo_util::opts::Opts;
 use servo_util::time::ProfilerChan;
 use servo_util::url::parse_url;
@@ -32,6 +41,7 @@ use layers::temp_rc::Rc;
 use std::util::replace;
 use std::io;
 use std::libc;
+use std::ptr;
 
 /// Maintains the pipelines and navigation context and grants permission to composite
 pub struct Constellation {
@@ -40,16 +50,26 @@ pub struct Constellation {
     compositor_chan: CompositorChan,
     resource_task: ResourceTask,
     image_cache_task: ImageCacheTask,
+    storage_task: StorageTask,
     pipelines: HashMap<PipelineId, Rc<Pipeline>>,
     navigation_context: NavigationContext,
     priv next_pipeline_id: PipelineId,
     pending_frames: ~[FrameChange],
     pending_sizes: HashMap<(PipelineId, SubpageId), Rect<f32>>,
     profiler_chan: ProfilerChan,
+    memory_profiler_chan: MemoryProfilerChan,
     window_size: Size2D<uint>,
+    /// The number of device pixels that each CSS pixel is drawn at.
+    device_pixel_ratio: f32,
+    /// The number of frame ticks received from the compositor, used to throttle the ticks
+    /// sent to pipelines that are not visible.
+    frame_ticks: uint,
     opts: Opts,
 }
 
+/// Pipelines that are not visible only receive one in this many frame ticks.
+static BACKGROUND_TICK_DIVISOR: uint = 60;
+
 /// Stores the Id of the outermost frame's pipeline, along with a vector of children frames
 struct FrameTree {
     pipeline: RefCell<Rc<Pipeline>>,
@@ -218,11 +238,31 @@ struct FrameChange {
     navigation_type: NavigationType,
 }
 
+/// An entry in the session history. Entries added by `history.pushState` share the frame tree
+/// of the entry they were pushed from, and record the pipeline that pushed them.
+#[deriving(Clone)]
+struct SessionHistoryEntry {
+    frame_tree: Rc<FrameTree>,
+    state: Option<(PipelineId, SessionHistoryState)>,
+}
+
+impl SessionHistoryEntry {
+    fn new(frame_tree: Rc<FrameTree>) -> SessionHistoryEntry {
+        SessionHistoryEntry {
+            frame_tree: frame_tree,
+            state: None,
+        }
+    }
+}
+
 /// Stores the Id's of the pipelines previous and next in the browser's history
 struct NavigationContext {
-    previous: ~[Rc<FrameTree>],
-    next: ~[Rc<FrameTree>],
+    previous: ~[SessionHistoryEntry],
+    next: ~[SessionHistoryEntry],
     current: Option<Rc<FrameTree>>,
+    /// The state of the current entry, if it was added by `history.pushState` or given a state
+    /// by `history.replaceState`.
+    current_state: Option<(PipelineId, SessionHistoryState)>,
 }
 
 impl NavigationContext {
@@ -231,6 +271,7 @@ impl NavigationContext {
             previous: ~[],
             next: ~[],
             current: None,
+            current_state: None,
         }
     }
 
@@ -238,17 +279,63 @@ impl NavigationContext {
      * when it is known that there exists either a previous page or a next page. */
 
     pub fn back(&mut self) -> Rc<FrameTree> {
-        self.next.push(self.current.take_unwrap());
+        let current = self.take_current();
+        self.next.push(current);
         let prev = self.previous.pop();
-        self.current = Some(prev.clone());
-        prev
+        self.set_current(prev)
     }
 
     pub fn forward(&mut self) -> Rc<FrameTree> {
-        self.previous.push(self.current.take_unwrap());
+        let current = self.take_current();
+        self.previous.push(current);
         let next = self.next.pop();
-        self.current = Some(next.clone());
-        next
+        self.set_current(next)
+    }
+
+    fn take_current(&mut self) -> SessionHistoryEntry {
+        SessionHistoryEntry {
+            frame_tree: self.current.take_unwrap(),
+            state: self.current_state.take(),
+        }
+    }
+
+    fn set_current(&mut self, entry: SessionHistoryEntry) -> Rc<FrameTree> {
+        self.current = Some(entry.frame_tree.clone());
+        self.current_state = entry.state;
+        entry.frame_tree
+    }
+
+    /// Returns true if the entry one step in the given direction belongs to the current
+    /// document, so that traversing to it doesn't load anything.
+    pub fn is_same_document(&self, direction: constellation_msg::NavigationDirection) -> bool {
+        let destination = match direction {
+            constellation_msg::Forward => self.next.last_opt(),
+            constellation_msg::Back => self.previous.last_opt(),
+        };
+        match (destination, &self.current) {
+            (Some(destination), &Some(ref current)) => {
+                ptr::to_unsafe_ptr(destination.frame_tree.borrow()) ==
+                    ptr::to_unsafe_ptr(current.borrow())
+            }
+            _ => false,
+        }
+    }
+
+    /// Adds a same-document entry after the current one, evicting all the next entries.
+    /// Returns the evicted frame trees.
+    pub fn push_state(&mut self, pipeline_id: PipelineId, state: SessionHistoryState)
+                      -> ~[Rc<FrameTree>] {
+        let frame_tree = self.current.get_ref().clone();
+        let evicted = self.evict_next();
+        let current = self.take_current();
+        self.previous.push(current);
+        self.current = Some(frame_tree);
+        self.current_state = Some((pipeline_id, state));
+        evicted
+    }
+
+    pub fn replace_state(&mut self, pipeline_id: PipelineId, state: SessionHistoryState) {
+        self.current_state = Some((pipeline_id, state));
     }
 
     /// Loads a new set of page frames, returning all evicted frame trees
@@ -258,32 +345,48 @@ impl NavigationContext {
             let tmp = frame_tree.borrow().pipeline.borrow();
             tmp.get().borrow().id
         });
-        let evicted = replace(&mut self.next, ~[]);
+        let evicted = self.evict_next();
         if self.current.is_some() {
-            self.previous.push(self.current.take_unwrap());
+            let current = self.take_current();
+            self.previous.push(current);
         }
         self.current = Some(frame_tree.clone());
         evicted
     }
 
+    /// Removes all the next entries, returning their frame trees. Consecutive entries of the
+    /// same document are only returned once.
+    fn evict_next(&mut self) -> ~[Rc<FrameTree>] {
+        let mut evicted: ~[Rc<FrameTree>] = ~[];
+        for entry in replace(&mut self.next, ~[]).move_iter() {
+            let is_duplicate = evicted.last_opt().map_default(false, |last| {
+                ptr::to_unsafe_ptr(last.borrow()) == ptr::to_unsafe_ptr(entry.frame_tree.borrow())
+            });
+            if !is_duplicate {
+                evicted.push(entry.frame_tree);
+            }
+        }
+        evicted
+    }
+
     /// Returns the frame trees whose keys are pipeline_id.
     pub fn find_all(&mut self, pipeline_id: PipelineId) -> ~[Rc<FrameTree>] {
         let from_current = self.current.iter().filter_map(|frame_tree| {
             frame_tree.borrow().find(pipeline_id)
         });
-        let from_next = self.next.iter().filter_map(|frame_tree| {
-            frame_tree.borrow().find(pipeline_id)
+        let from_next = self.next.iter().filter_map(|entry| {
+            entry.frame_tree.borrow().find(pipeline_id)
         });
-        let from_prev = self.previous.iter().filter_map(|frame_tree| {
-            frame_tree.borrow().find(pipeline_id)
+        let from_prev = self.previous.iter().filter_map(|entry| {
+            entry.frame_tree.borrow().find(pipeline_id)
         });
         from_prev.chain(from_current).chain(from_next).collect()
     }
 
     pub fn contains(&mut self, pipeline_id: PipelineId) -> bool {
         let from_current = self.current.iter();
-        let from_next = self.next.iter();
-        let from_prev = self.previous.iter();
+        let from_next = self.next.iter().map(|entry| &entry.frame_tree);
+        let from_prev = self.previous.iter().map(|entry| &entry.frame_tree);
 
         let mut all_contained = from_prev.chain(from_current).chain(from_next);
         all_contained.any(|frame_tree| {
@@ -297,7 +400,9 @@ impl Constellation {
                  opts: &Opts,
                  resource_task: ResourceTask,
                  image_cache_task: ImageCacheTask,
-                 profiler_chan: ProfilerChan)
+                 storage_task: StorageTask,
+                 profiler_chan: ProfilerChan,
+                 memory_profiler_chan: MemoryProfilerChan)
                  -> ConstellationChan {
         let (constellation_port, constellation_chan) = ConstellationChan::new();
         let constellation_chan_clone = constellation_chan.clone();
@@ -309,13 +414,17 @@ impl Constellation {
                 compositor_chan: compositor_chan,
                 resource_task: resource_task,
                 image_cache_task: image_cache_task,
+                storage_task: storage_task,
                 pipelines: HashMap::new(),
                 navigation_context: NavigationContext::new(),
                 next_pipeline_id: PipelineId(0),
                 pending_frames: ~[],
                 pending_sizes: HashMap::new(),
                 profiler_chan: profiler_chan,
+                memory_profiler_chan: memory_profiler_chan,
                 window_size: Size2D(800u, 600u),
+                device_pixel_ratio: 1.0,
+                frame_ticks: 0,
                 opts: opts_clone,
             };
             constellation.run();
@@ -407,16 +516,68 @@ impl Constellation {
                 debug!("constellation got window resize message");
                 self.handle_resized_window_msg(new_size);
             }
+            SetDevicePixelRatioMsg(device_pixel_ratio) => {
+                debug!("constellation got device pixel ratio {}", device_pixel_ratio);
+                self.handle_set_device_pixel_ratio_msg(device_pixel_ratio);
+            }
+            GetPipelinesMsg(reply_chan) => {
+                debug!("constellation got pipeline list request");
+                self.handle_get_pipelines_msg(reply_chan);
+            }
+            DevtoolsMsg(pipeline_id, msg) => {
+                debug!("constellation got devtools message for {:?}", pipeline_id);
+                match self.pipelines.find(&pipeline_id) {
+                    Some(pipeline) => {
+                        let script_chan = &pipeline.borrow().script_chan;
+                        script_chan.try_send(DevtoolsControlMsg(pipeline_id, msg));
+                    }
+                    // Dropping the message hangs up on the devtools server.
+                    None => debug!("devtools message sent to nonexistent pipeline"),
+                }
+            }
+            // A page added or replaced a same-document session history entry. Pages that aren't
+            // displayed yet have no entry of their own to attach the state to.
+            PushStateMsg(pipeline_id, state) => {
+                debug!("constellation got push state message");
+                if self.is_current_pipeline(pipeline_id) {
+                    let evicted = self.navigation_context.push_state(pipeline_id, state);
+                    self.handle_evicted_frames(evicted);
+                }
+            }
+            ReplaceStateMsg(pipeline_id, state) => {
+                debug!("constellation got replace state message");
+                if self.is_current_pipeline(pipeline_id) {
+                    self.navigation_context.replace_state(pipeline_id, state);
+                }
+            }
+            StorageChangedMsg(pipeline_id, change) => {
+                debug!("constellation got storage changed message");
+                self.handle_storage_changed_msg(pipeline_id, change);
+            }
+            // The compositor is about to draw a frame.
+            FrameTickMsg(frame_time) => {
+                self.handle_frame_tick_msg(frame_time);
+            }
         }
         true
     }
 
+    fn handle_get_pipelines_msg(&self, reply_chan: Chan<~[(PipelineId, Option<Url>)]>) {
+        let mut pipelines: ~[(PipelineId, Option<Url>)] = self.pipelines.iter().map(|(id, p)| {
+            let url = p.borrow().url.borrow();
+            (*id, url.get().clone())
+        }).collect();
+        pipelines.sort_by(|&(PipelineId(a), _), &(PipelineId(b), _)| a.cmp(&b));
+        reply_chan.try_send(pipelines);
+    }
+
     fn handle_exit(&self) {
         for (_id, ref pipeline) in self.pipelines.iter() {
             pipeline.borrow().exit();
         }
         self.image_cache_task.exit();
         self.resource_task.send(resource_task::Exit);
+        self.storage_task.send(storage_task::Exit);
         self.compositor_chan.send(ShutdownComplete);
     }
 
@@ -441,6 +602,7 @@ impl Constellation {
         old_pipeline.borrow().render_chan.try_send(render_task::ExitMsg(None));
         old_pipeline.borrow().layout_chan.try_send(layout_interface::ExitNowMsg);
         self.pipelines.remove(&pipeline_id);
+        self.unregister_memory_reporters(pipeline_id);
 
         let new_id = self.get_next_pipeline_id();
         let pipeline = Pipeline::create(new_id,
@@ -449,10 +611,12 @@ impl Constellation {
                                         self.compositor_chan.clone(),
                                         self.image_cache_task.clone(),
                                         self.resource_task.clone(),
+                                        self.storage_task.clone(),
                                         self.profiler_chan.clone(),
                                         self.window_size,
                                         self.opts.clone());
         let url = parse_url("about:failure", None);
+        self.send_device_pixel_ratio(&pipeline);
         pipeline.load(url);
 
         let pipeline_wrapped = Rc::new(pipeline);
@@ -466,6 +630,7 @@ impl Constellation {
             navigation_type: constellation_msg::Navigate,
         });
 
+        self.register_memory_reporters(pipeline_wrapped.borrow());
         self.pipelines.insert(pipeline_id, pipeline_wrapped);
     }
 
@@ -476,9 +641,11 @@ impl Constellation {
                                         self.compositor_chan.clone(),
                                         self.image_cache_task.clone(),
                                         self.resource_task.clone(),
+                                        self.storage_task.clone(),
                                         self.profiler_chan.clone(),
                                         self.window_size,
                                         self.opts.clone());
+        self.send_device_pixel_ratio(&pipeline);
         pipeline.load(url);
         let pipeline_wrapped = Rc::new(pipeline);
 
@@ -491,6 +658,7 @@ impl Constellation {
             }),
             navigation_type: constellation_msg::Load,
         });
+        self.register_memory_reporters(pipeline_wrapped.borrow());
         self.pipelines.insert(pipeline_wrapped.borrow().id, pipeline_wrapped);
     }
 
@@ -623,11 +791,13 @@ impl Constellation {
                              self.compositor_chan.clone(),
                              self.image_cache_task.clone(),
                              self.resource_task.clone(),
+                             self.storage_task.clone(),
                              self.profiler_chan.clone(),
                              self.window_size,
                              self.opts.clone())
         };
 
+        self.send_device_pixel_ratio(&pipeline);
         debug!("Constellation: sending load msg to pipeline {:?}", pipeline.id);
         pipeline.load(url);
         let pipeline_wrapped = Rc::new(pipeline);
@@ -644,6 +814,7 @@ impl Constellation {
                 rect: rect,
             });
         }
+        self.register_memory_reporters(pipeline_wrapped.borrow());
         self.pipelines.insert(pipeline_wrapped.borrow().id, pipeline_wrapped);
     }
 
@@ -682,10 +853,12 @@ impl Constellation {
                                         self.compositor_chan.clone(),
                                         self.image_cache_task.clone(),
                                         self.resource_task.clone(),
+                                        self.storage_task.clone(),
                                         self.profiler_chan.clone(),
                                         self.window_size,
                                         self.opts.clone());
 
+        self.send_device_pixel_ratio(&pipeline);
         pipeline.load(url);
         let pipeline_wrapped = Rc::new(pipeline);
 
@@ -698,12 +871,18 @@ impl Constellation {
             }),
             navigation_type: constellation_msg::Load,
         });
+        self.register_memory_reporters(pipeline_wrapped.borrow());
         self.pipelines.insert(pipeline_wrapped.borrow().id, pipeline_wrapped);
     }
 
     fn handle_navigate_msg(&mut self, direction: constellation_msg::NavigationDirection) {
         debug!("received message to navigate {:?}", direction);
 
+        if self.navigation_context.is_same_document(direction) {
+            self.handle_same_document_navigation(direction);
+            return;
+        }
+
         // TODO(tkuehn): what is the "critical point" beyond which pending frames
         // should not be cleared? Currently, the behavior is that forward/back
         // navigation always has navigation priority, and after that new page loading is
@@ -748,6 +927,60 @@ impl Constellation {
 
     }
 
+    /// Moves between two entries of the same document, then tells the pipeline that created
+    /// them to fire a `popstate` event.
+    fn handle_same_document_navigation(&mut self,
+                                       direction: constellation_msg::NavigationDirection) {
+        let old_state = self.navigation_context.current_state.clone();
+        match direction {
+            constellation_msg::Forward => { self.navigation_context.forward(); }
+            constellation_msg::Back => { self.navigation_context.back(); }
+        }
+        let new_state = self.navigation_context.current_state.clone();
+
+        // Returning to an entry without state restores the document that pushed the entry
+        // we just left.
+        let (pipeline_id, state) = match (new_state, old_state) {
+            (Some((pipeline_id, state)), _) => (pipeline_id, Some(state)),
+            (None, Some((pipeline_id, _))) => (pipeline_id, None),
+            (None, None) => return,
+        };
+        let pipeline = match self.pipelines.find(&pipeline_id) {
+            Some(pipeline) => pipeline.clone(),
+            None => return,
+        };
+        let pipeline = pipeline.borrow();
+        let (data, url) = match state {
+            Some(state) => (state.data, state.url),
+            None => {
+                let url = pipeline.url.borrow();
+                match url.get().clone() {
+                    Some(url) => (None, url),
+                    None => return,
+                }
+            }
+        };
+        pipeline.script_chan.try_send(PopStateMsg(pipeline_id, data, url));
+    }
+
+    /// Tells every pipeline but the one that made a storage change about it. Pipelines of other
+    /// origins ignore the change.
+    fn handle_storage_changed_msg(&self, source_id: PipelineId, change: StorageChange) {
+        for (id, pipeline) in self.pipelines.iter() {
+            if *id != source_id {
+                let script_chan = &pipeline.borrow().script_chan;
+                script_chan.try_send(StorageEventMsg(*id, change.clone()));
+            }
+        }
+    }
+
+    /// Returns true if the pipeline belongs to the frame tree currently being displayed.
+    fn is_current_pipeline(&self, pipeline_id: PipelineId) -> bool {
+        self.current_frame().as_ref().map_default(false, |frame_tree| {
+            frame_tree.borrow().contains(pipeline_id)
+        })
+    }
+
     fn handle_renderer_ready_msg(&mut self, pipeline_id: PipelineId) {
         debug!("Renderer {:?} ready to send paint msg", pipeline_id);
         // This message could originate from a pipeline in the navigation context or
@@ -874,10 +1107,10 @@ impl Constellation {
             pipeline.script_chan.try_send(ResizeMsg(pipeline.id, new_size));
             already_seen.insert(pipeline.id);
         }
-        for frame_tree in self.navigation_context.previous.iter()
+        for entry in self.navigation_context.previous.iter()
             .chain(self.navigation_context.next.iter()) {
             // NOTE: work around borrowchk issues
-            let tmp = frame_tree.borrow().pipeline.borrow();
+            let tmp = entry.frame_tree.borrow().pipeline.borrow();
             let pipeline = &tmp.get().borrow();
             if !already_seen.contains(&pipeline.id) {
                 debug!("constellation sending resize message to inactive frame");
@@ -904,6 +1137,77 @@ impl Constellation {
         self.window_size = new_size;
     }
 
+    fn handle_set_device_pixel_ratio_msg(&mut self, device_pixel_ratio: f32) {
+        self.device_pixel_ratio = device_pixel_ratio;
+        for (_, pipeline) in self.pipelines.iter() {
+            let pipeline = pipeline.borrow();
+            pipeline.script_chan.try_send(DevicePixelRatioMsg(pipeline.id, device_pixel_ratio));
+        }
+    }
+
+    /// Tells a new pipeline how many device pixels each CSS pixel is drawn at, before it loads
+    /// anything, unless it is the default of one.
+    fn send_device_pixel_ratio(&self, pipeline: &Pipeline) {
+        if self.device_pixel_ratio != 1.0 {
+            pipeline.script_chan.send(DevicePixelRatioMsg(pipeline.id, self.device_pixel_ratio));
+        }
+    }
+
+    /// Forwards a frame tick to the script task of every visible pipeline. Pipelines in the
+    /// session history are throttled to a fraction of the compositor's frame rate.
+    fn handle_frame_tick_msg(&mut self, frame_time: u64) {
+        let mut already_seen = HashSet::new();
+        for frame_tree in self.current_frame().iter() {
+            for frame in frame_tree.borrow().iter() {
+                // NOTE: work around borrowchk issues
+                let tmp = frame.borrow().pipeline.borrow();
+                let pipeline = tmp.get().borrow();
+                pipeline.script_chan.try_send(AnimationTickMsg(pipeline.id, frame_time));
+                already_seen.insert(pipeline.id);
+            }
+        }
+
+        self.frame_ticks += 1;
+        if self.frame_ticks % BACKGROUND_TICK_DIVISOR != 0 {
+            return;
+        }
+        for entry in self.navigation_context.previous.iter()
+            .chain(self.navigation_context.next.iter()) {
+            for frame in entry.frame_tree.borrow().iter() {
+                // NOTE: work around borrowchk issues
+                let tmp = frame.borrow().pipeline.borrow();
+                let pipeline = tmp.get().borrow();
+                if !already_seen.contains(&pipeline.id) {
+                    pipeline.script_chan.try_send(AnimationTickMsg(pipeline.id, frame_time));
+                    already_seen.insert(pipeline.id);
+                }
+            }
+        }
+    }
+
+    /// Registers memory reporters for the script, layout, and render tasks of a new pipeline.
+    fn register_memory_reporters(&self, pipeline: &Pipeline) {
+        let PipelineId(id) = pipeline.id;
+        let reporters = ~[
+            ("script", ~ScriptMemoryReporter::new(pipeline.script_chan.clone(), pipeline.id)
+                as ~MemoryReporter:Send),
+            ("layout", ~pipeline.layout_chan.clone() as ~MemoryReporter:Send),
+            ("render", ~pipeline.render_chan.clone() as ~MemoryReporter:Send),
+        ];
+        for (task, reporter) in reporters.move_iter() {
+            let name = format!("pipeline-{}-{}", id, task);
+            self.memory_profiler_chan.try_send(RegisterReporterMsg(name, reporter));
+        }
+    }
+
+    fn unregister_memory_reporters(&self, pipeline_id: PipelineId) {
+        let PipelineId(id) = pipeline_id;
+        for task in ["script", "layout", "render"].iter() {
+            let name = format!("pipeline-{}-{}", id, *task);
+            self.memory_profiler_chan.try_send(UnregisterReporterMsg(name));
+        }
+    }
+
     // Close all pipelines at and beneath a given frame
     fn close_pipelines(&mut self, frame_tree: Rc<FrameTree>) {
         // TODO(tkuehn): should only exit once per unique script task,
@@ -914,6 +1218,7 @@ impl Constellation {
             let pipeline = tmp.get().borrow();
             pipeline.exit();
             self.pipelines.remove(&pipeline.id);
+            self.unregister_memory_reporters(pipeline.id);
         }
     }
 
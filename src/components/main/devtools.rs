@@ -0,0 +1,349 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A server that lets tools outside of Servo inspect the running pages over a TCP connection on
+//! localhost. It is started with `--devtools PORT`.
+//!
+//! The protocol is line-based: every request and every reply is a JSON object on a line of its
+//! own, with a `type` field saying what it is. The requests are:
+//!
+//! * `{"type": "listPipelines"}`, answered with
+//!   `{"type": "pipelines", "pipelines": [{"id": 0, "url": "..."}, ...]}`.
+//! * `{"type": "getDocument", "pipeline": 0}`, answered with
+//!   `{"type": "document", "pipeline": 0, "html": "..."}`.
+//! * `{"type": "evaluateJS", "pipeline": 0, "script": "..."}`, answered with
+//!   `{"type": "evaluationResult", "pipeline": 0, "result": "..."}`.
+//! * `{"type": "watchConsole", "pipeline": 0}`, which turns the connection into a stream of
+//!   `{"type": "consoleMessage", "pipeline": 0, "level": "log", "message": "..."}`, one per call
+//!   to a `console` method of the page. No further requests are read from a streaming
+//!   connection, so clients should open another connection for them.
+//!
+//! Requests that fail are answered with `{"type": "error", "message": "..."}`.
+
+use extra::json::{Json, List, Number, Object, String};
+use extra::json;
+use extra::treemap::TreeMap;
+use servo_msg::constellation_msg::{ConstellationChan, DevtoolsMsg, GetPipelinesMsg, PipelineId};
+use servo_msg::devtools_msg::{EvaluateJS, GetDocumentHTML, WantConsoleMessages};
+use servo_util::io::result;
+use servo_util::task::spawn_named;
+use std::comm::SharedChan;
+use std::io::net::ip::{Ipv4Addr, SocketAddr};
+use std::io::net::tcp::{TcpListener, TcpStream};
+use std::io::{Acceptor, BufferedStream, Listener};
+use std::io;
+
+/// Starts the devtools server, which listens for clients on the given port of localhost.
+pub fn start_server(port: u16, constellation_chan: ConstellationChan) {
+    spawn_named("devtools", proc() {
+        run_server(port, constellation_chan)
+    });
+}
+
+fn run_server(port: u16, constellation_chan: ConstellationChan) {
+    // ignore_io_error causes us to get None instead of a task failure.
+    let _guard = io::ignore_io_error();
+    let address = SocketAddr {
+        ip: Ipv4Addr(127, 0, 0, 1),
+        port: port,
+    };
+    let mut acceptor = match TcpListener::bind(address).and_then(|listener| listener.listen()) {
+        Some(acceptor) => acceptor,
+        None => {
+            error!("devtools: couldn't listen on port {}", port);
+            return
+        }
+    };
+    debug!("devtools: listening on port {}", port);
+
+    for stream in acceptor.incoming() {
+        match stream {
+            Some(stream) => {
+                let constellation_chan = constellation_chan.clone();
+                spawn_named("devtools client", proc() {
+                    handle_client(stream, constellation_chan)
+                });
+            }
+            None => {
+                error!("devtools: couldn't accept a client; no longer listening");
+                break
+            }
+        }
+    }
+}
+
+/// A request read from a client.
+enum Request {
+    /// A `watchConsole` request, which the connection is handed over to.
+    WatchConsoleRequest(PipelineId),
+    /// Any other request, with its type and the whole request object.
+    OtherRequest(~str, ~json::Object),
+}
+
+/// Parses a line read from a client into a request, or into the message of the error to reply
+/// with.
+fn parse_request(line: &str) -> Result<Request, ~str> {
+    let request = match json::from_str(line) {
+        Ok(Object(request)) => request,
+        _ => return Err(~"the request is not a JSON object"),
+    };
+    let request_type = match request.find(&~"type") {
+        Some(&String(ref request_type)) if request_type.len() > 0 => request_type.clone(),
+        _ => return Err(~"the request has no type"),
+    };
+    match request_type.as_slice() {
+        "watchConsole" => pipeline_id_field(request).map(|id| WatchConsoleRequest(id)),
+        _ => Ok(OtherRequest(request_type, request)),
+    }
+}
+
+/// Answers the requests of a client until it disconnects.
+fn handle_client(stream: TcpStream, constellation_chan: ConstellationChan) {
+    let _guard = io::ignore_io_error();
+    let mut stream = BufferedStream::new(stream);
+    loop {
+        let line = match stream.read_line() {
+            Some(line) => line,
+            None => return,
+        };
+
+        let reply = match parse_request(line) {
+            Ok(WatchConsoleRequest(pipeline_id)) => {
+                return watch_console(stream, &constellation_chan, pipeline_id)
+            }
+            Ok(OtherRequest(request_type, request)) => {
+                handle_request(request_type.as_slice(), request, &constellation_chan)
+            }
+            Err(message) => error_reply(message),
+        };
+
+        if !write_reply(&mut stream, &reply) {
+            return
+        }
+    }
+}
+
+/// Answers a request other than `watchConsole`.
+fn handle_request(request_type: &str,
+                  request: &json::Object,
+                  constellation_chan: &ConstellationChan)
+                  -> Json {
+    match request_type {
+        "listPipelines" => {
+            let (port, chan) = Chan::new();
+            constellation_chan.send(GetPipelinesMsg(chan));
+            let pipelines = port.recv().move_iter().map(|(PipelineId(id), url)| {
+                let mut pipeline = ~TreeMap::new();
+                pipeline.insert(~"id", Number(id as f64));
+                pipeline.insert(~"url", match url {
+                    Some(url) => String(url.to_str()),
+                    None => json::Null,
+                });
+                Object(pipeline)
+            }).collect();
+            make_reply("pipelines", ~[(~"pipelines", List(pipelines))])
+        }
+        "getDocument" => {
+            let pipeline_id = match pipeline_id_field(request) {
+                Ok(pipeline_id) => pipeline_id,
+                Err(message) => return error_reply(message),
+            };
+            let (port, chan) = Chan::new();
+            constellation_chan.send(DevtoolsMsg(pipeline_id, GetDocumentHTML(chan)));
+            match port.recv_opt() {
+                Some(html) => {
+                    make_reply("document", ~[(~"pipeline", pipeline_id_to_json(pipeline_id)),
+                                             (~"html", String(html))])
+                }
+                None => no_such_pipeline(pipeline_id),
+            }
+        }
+        "evaluateJS" => {
+            let pipeline_id = match pipeline_id_field(request) {
+                Ok(pipeline_id) => pipeline_id,
+                Err(message) => return error_reply(message),
+            };
+            let script = match request.find(&~"script") {
+                Some(&String(ref script)) => script.clone(),
+                _ => return error_reply(~"the request has no script"),
+            };
+            let (port, chan) = Chan::new();
+            constellation_chan.send(DevtoolsMsg(pipeline_id, EvaluateJS(script, chan)));
+            match port.recv_opt() {
+                Some(Ok(result)) => {
+                    let pipeline = pipeline_id_to_json(pipeline_id);
+                    make_reply("evaluationResult", ~[(~"pipeline", pipeline),
+                                                     (~"result", String(result))])
+                }
+                Some(Err(message)) => error_reply(message),
+                None => no_such_pipeline(pipeline_id),
+            }
+        }
+        _ => error_reply(format!("unknown request type: {}", request_type)),
+    }
+}
+
+/// Streams the console messages of the given pipeline to the client until it disconnects or the
+/// pipeline goes away.
+fn watch_console(mut stream: BufferedStream<TcpStream>,
+                 constellation_chan: &ConstellationChan,
+                 pipeline_id: PipelineId) {
+    let (port, chan) = SharedChan::new();
+    constellation_chan.send(DevtoolsMsg(pipeline_id, WantConsoleMessages(chan)));
+    loop {
+        let reply = match port.recv_opt() {
+            Some(message) => {
+                let pipeline = pipeline_id_to_json(message.pipeline_id);
+                let level = String(message.level.name().to_owned());
+                make_reply("consoleMessage", ~[(~"pipeline", pipeline),
+                                               (~"level", level),
+                                               (~"message", String(message.message))])
+            }
+            None => {
+                write_reply(&mut stream, &no_such_pipeline(pipeline_id));
+                return
+            }
+        };
+        if !write_reply(&mut stream, &reply) {
+            return
+        }
+    }
+}
+
+fn pipeline_id_field(request: &json::Object) -> Result<PipelineId, ~str> {
+    match request.find(&~"pipeline") {
+        Some(&Number(id)) if id >= 0.0 => Ok(PipelineId(id as uint)),
+        _ => Err(~"the request has no pipeline"),
+    }
+}
+
+fn pipeline_id_to_json(pipeline_id: PipelineId) -> Json {
+    let PipelineId(id) = pipeline_id;
+    Number(id as f64)
+}
+
+/// Builds a reply of the given type with the given fields.
+fn make_reply(reply_type: &str, fields: ~[(~str, Json)]) -> Json {
+    let mut object = ~TreeMap::new();
+    object.insert(~"type", String(reply_type.to_owned()));
+    for (name, value) in fields.move_iter() {
+        object.insert(name, value);
+    }
+    Object(object)
+}
+
+fn error_reply(message: ~str) -> Json {
+    make_reply("error", ~[(~"message", String(message))])
+}
+
+fn no_such_pipeline(pipeline_id: PipelineId) -> Json {
+    let PipelineId(id) = pipeline_id;
+    error_reply(format!("there is no pipeline {}", id))
+}
+
+/// Writes a reply on a line of its own. Returns false if the client has disconnected.
+fn write_reply(stream: &mut BufferedStream<TcpStream>, reply: &Json) -> bool {
+    result(|| {
+        stream.write_line(reply.to_str());
+        stream.flush();
+    }).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OtherRequest, WatchConsoleRequest, handle_request, parse_request};
+
+    use extra::json::{List, Number, Object, String};
+    use extra::json;
+    use extra::url::Url;
+    use servo_msg::constellation_msg::{ConstellationChan, DevtoolsMsg, GetPipelinesMsg};
+    use servo_msg::constellation_msg::PipelineId;
+    use std::task;
+
+    #[test]
+    fn test_parse_request() {
+        match parse_request("{\"type\": \"getDocument\", \"pipeline\": 2}") {
+            Ok(OtherRequest(request_type, request)) => {
+                assert_eq!(request_type, ~"getDocument");
+                assert_eq!(request.find(&~"pipeline"), Some(&Number(2.0)));
+            }
+            _ => fail!("expected a getDocument request"),
+        }
+        match parse_request("{\"type\": \"watchConsole\", \"pipeline\": 3}") {
+            Ok(WatchConsoleRequest(PipelineId(3))) => {}
+            _ => fail!("expected a watchConsole request for pipeline 3"),
+        }
+    }
+
+    #[test]
+    fn test_parse_invalid_requests() {
+        let error = |line: &str| {
+            match parse_request(line) {
+                Err(message) => message,
+                Ok(..) => fail!("expected {} to be rejected", line),
+            }
+        };
+        assert_eq!(error("listPipelines"), ~"the request is not a JSON object");
+        assert_eq!(error("[\"listPipelines\"]"), ~"the request is not a JSON object");
+        assert_eq!(error("{\"pipeline\": 0}"), ~"the request has no type");
+        assert_eq!(error("{\"type\": \"\"}"), ~"the request has no type");
+        assert_eq!(error("{\"type\": \"watchConsole\"}"), ~"the request has no pipeline");
+        assert_eq!(error("{\"type\": \"watchConsole\", \"pipeline\": -1}"),
+                   ~"the request has no pipeline");
+    }
+
+    /// Answers a request with a constellation that knows about a single pipeline, and returns
+    /// the reply as a string.
+    fn reply_to(line: &str) -> ~str {
+        let (port, constellation_chan) = ConstellationChan::new();
+        task::spawn(proc() {
+            loop {
+                match port.recv_opt() {
+                    Some(GetPipelinesMsg(chan)) => {
+                        let url = from_str::<Url>("http://example.com/").unwrap();
+                        chan.send(~[(PipelineId(0), Some(url))]);
+                    }
+                    // The script task of an unknown pipeline never answers.
+                    Some(DevtoolsMsg(..)) => {}
+                    Some(_) => fail!("unexpected message"),
+                    None => break,
+                }
+            }
+        });
+        match parse_request(line) {
+            Ok(OtherRequest(request_type, request)) => {
+                handle_request(request_type.as_slice(), request, &constellation_chan).to_str()
+            }
+            _ => fail!("expected a request"),
+        }
+    }
+
+    #[test]
+    fn test_list_pipelines() {
+        let reply = json::from_str(reply_to("{\"type\": \"listPipelines\"}")).unwrap();
+        let reply = match reply {
+            Object(reply) => reply,
+            _ => fail!("expected an object"),
+        };
+        assert_eq!(reply.find(&~"type"), Some(&String(~"pipelines")));
+        match reply.find(&~"pipelines") {
+            Some(&List(ref pipelines)) => {
+                assert_eq!(pipelines.len(), 1);
+                assert_eq!(pipelines[0].to_str(),
+                           ~"{\"id\":0,\"url\":\"http://example.com/\"}");
+            }
+            _ => fail!("expected a list of pipelines"),
+        }
+    }
+
+    #[test]
+    fn test_unknown_requests_and_pipelines() {
+        assert_eq!(reply_to("{\"type\": \"frobnicate\"}"),
+                   ~"{\"message\":\"unknown request type: frobnicate\",\"type\":\"error\"}");
+        assert_eq!(reply_to("{\"type\": \"getDocument\", \"pipeline\": 7}"),
+                   ~"{\"message\":\"there is no pipeline 7\",\"type\":\"error\"}");
+        assert_eq!(reply_to("{\"type\": \"evaluateJS\", \"pipeline\": 0}"),
+                   ~"{\"message\":\"the request has no script\",\"type\":\"error\"}");
+    }
+}
@@ -22,9 +22,10 @@ use layout::flow::{mut_base, PreorderFlowTraversal, PostorderFlowTraversal, Muta
 use layout::flow;
 use layout::model::{MaybeAuto, Specified, Auto, specified_or_none, specified};
 use layout::wrapper::ThreadSafeLayoutNode;
-use style::computed_values::{position};
+use style::computed_values::{direction, position};
 
 use std::cell::RefCell;
+use std::mem;
 use geom::{Point2D, Rect, Size2D};
 use gfx::display_list::{DisplayListCollection, DisplayList};
 use servo_util::geometry::Au;
@@ -1327,6 +1328,10 @@ impl Flow for BlockFlow {
 
         // FIXME(ksh8281): avoid copy
         let flags_info = self.base.flags_info.clone();
+        let is_rtl = match self.box_ {
+            Some(ref box_) => box_.style().InheritedBox.get().direction == direction::rtl,
+            None => false,
+        };
         for kid in self.base.child_iter() {
             assert!(kid.is_block_flow() || kid.is_inline_flow());
 
@@ -1341,6 +1346,7 @@ impl Flow for BlockFlow {
             // Width of kid flow is our content width
             child_base.position.size.width = content_width;
             child_base.flags_info.flags.set_inorder(has_inorder_children);
+            child_base.flags_info.flags.set_parent_is_rtl(is_rtl);
 
             if !child_base.flags_info.flags.inorder() {
                 child_base.floats = Floats::new();
@@ -1489,6 +1495,15 @@ impl Flow for BlockFlow {
         }
     }
 
+    fn dump_boxes(&self) -> ~[~str] {
+        self.box_.iter().map(|box_| box_.dump_str()).collect()
+    }
+
+    fn heap_size(&self) -> uint {
+        let boxes_size = self.box_.as_ref().map_or(0, |box_| box_.heap_size_of_contents());
+        mem::size_of::<BlockFlow>() + boxes_size
+    }
+
     fn debug_str(&self) -> ~str {
         let txt = if self.is_float() {
             ~"FloatFlow: "
@@ -1513,6 +1528,11 @@ struct WidthConstraintInput {
     right: MaybeAuto,
     available_width: Au,
     static_x_offset: Au,
+    /// The distance from the right edge of the containing block to the right margin edge of the
+    /// hypothetical box, the counterpart of `static_x_offset` for 'rtl' parent flows.
+    static_right_offset: Au,
+    /// True if the parent flow has `direction: rtl`.
+    parent_is_rtl: bool,
 }
 
 impl WidthConstraintInput {
@@ -1522,7 +1542,9 @@ impl WidthConstraintInput {
            left: MaybeAuto,
            right: MaybeAuto,
            available_width: Au,
-           static_x_offset: Au)
+           static_x_offset: Au,
+           static_right_offset: Au,
+           parent_is_rtl: bool)
            -> WidthConstraintInput {
         WidthConstraintInput {
             computed_width: computed_width,
@@ -1532,6 +1554,8 @@ impl WidthConstraintInput {
             right: right,
             available_width: available_width,
             static_x_offset: static_x_offset,
+            static_right_offset: static_right_offset,
+            parent_is_rtl: parent_is_rtl,
         }
     }
 }
@@ -1608,13 +1632,20 @@ trait WidthAndMarginsComputer {
                 (MaybeAuto::from_style(style.PositionOffsets.get().left, containing_block_width),
                  MaybeAuto::from_style(style.PositionOffsets.get().right, containing_block_width));
             let available_width = containing_block_width - box_.noncontent_width();
+
+            // The hypothetical box is as wide as the content box of the parent flow.
+            let static_x_offset = block.static_x_offset();
+            let static_right_offset = containing_block_width -
+                (static_x_offset + parent_flow_width);
             return WidthConstraintInput::new(computed_width,
                                              margin_left,
                                              margin_right,
                                              left,
                                              right,
                                              available_width,
-                                             block.static_x_offset());
+                                             static_x_offset,
+                                             static_right_offset,
+                                             block.base.flags_info.flags.parent_is_rtl());
         }
         fail!("Block doesn't have a principal box")
     }
@@ -1722,6 +1753,7 @@ trait WidthAndMarginsComputer {
                                                                             input.left_margin,
                                                                             input.right_margin,
                                                                             input.available_width);
+        let parent_is_rtl = input.parent_is_rtl;
 
         // If width is not 'auto', and width + margins > available_width, all
         // 'auto' margins are treated as 0.
@@ -1746,7 +1778,8 @@ trait WidthAndMarginsComputer {
             // If direction is ltr, ignore the specified right margin and
             // solve for it.
             // If it is rtl, ignore the specified left margin.
-            // FIXME(eatkinson): this assumes the direction is ltr
+            (Specified(_margin_l), Specified(width), Specified(margin_r)) if parent_is_rtl =>
+                (available_width - (width + margin_r), width, margin_r),
             (Specified(margin_l), Specified(width), Specified(_margin_r)) =>
                 (margin_l, width, available_width - (margin_l + width )),
 
@@ -1810,18 +1843,35 @@ impl WidthAndMarginsComputer for AbsoluteNonReplaced {
             right,
             available_width,
             static_x_offset,
+            static_right_offset,
+            parent_is_rtl,
         } = input;
 
-        // TODO: Check for direction of parent flow (NOT Containing Block)
-        // when right-to-left is implemented.
-        // Assume direction is 'ltr' for now
+        // The static position and over-constrained cases are resolved according to the
+        // direction of the parent flow (NOT the Containing Block).
 
         // Distance from the left edge of the Absolute Containing Block to the
         // left margin edge of a hypothetical box that would have been the
         // first box of the element.
         let static_position_left = static_x_offset;
+        // Distance from the right edge of the Absolute Containing Block to the right margin edge
+        // of the same hypothetical box.
+        let static_position_right = static_right_offset;
 
         let (left, right, width, margin_left, margin_right) = match (left, right, computed_width) {
+            (Auto, Auto, Auto) if parent_is_rtl => {
+                let margin_l = left_margin.specified_or_zero();
+                let margin_r = right_margin.specified_or_zero();
+                let right = static_position_right;
+                // Now it is the same situation as right Specified and left
+                // and width Auto.
+
+                // Set left to zero to calculate width
+                let width = block.get_shrink_to_fit_width(
+                    available_width - (right + margin_l + margin_r));
+                let sum = right + width + margin_l + margin_r;
+                (available_width - sum, right, width, margin_l, margin_r)
+            }
             (Auto, Auto, Auto) => {
                 let margin_l = left_margin.specified_or_zero();
                 let margin_r = right_margin.specified_or_zero();
@@ -1839,9 +1889,11 @@ impl WidthAndMarginsComputer for AbsoluteNonReplaced {
                 match (left_margin, right_margin) {
                     (Auto, Auto) => {
                         let total_margin_val = (available_width - left - right - width);
-                        if total_margin_val < Au(0) {
+                        if total_margin_val < Au(0) && parent_is_rtl {
+                            // margin-right becomes 0 because direction is 'rtl'.
+                            (left, right, width, total_margin_val, Au(0))
+                        } else if total_margin_val < Au(0) {
                             // margin-left becomes 0 because direction is 'ltr'.
-                            // TODO: Handle 'rtl' when it is implemented.
                             (left, right, width, Au(0), total_margin_val)
                         } else {
                             // Equal margins
@@ -1858,10 +1910,15 @@ impl WidthAndMarginsComputer for AbsoluteNonReplaced {
                         let sum = left + right + width + margin_r;
                         (left, right, width, available_width - sum, margin_r)
                     }
+                    (Specified(margin_l), Specified(margin_r)) if parent_is_rtl => {
+                        // Values are over-constrained.
+                        // Ignore value for 'left' cos direction is 'rtl'.
+                        let sum = right + width + margin_l + margin_r;
+                        (available_width - sum, right, width, margin_l, margin_r)
+                    }
                     (Specified(margin_l), Specified(margin_r)) => {
                         // Values are over-constrained.
                         // Ignore value for 'right' cos direction is 'ltr'.
-                        // TODO: Handle 'rtl' when it is implemented.
                         let sum = left + width + margin_l + margin_r;
                         (left, available_width - sum, width, margin_l, margin_r)
                     }
@@ -1910,11 +1967,18 @@ impl WidthAndMarginsComputer for AbsoluteNonReplaced {
                 (available_width - sum, right, width, margin_l, margin_r)
             }
 
+            (Auto, Auto, Specified(width)) if parent_is_rtl => {
+                let margin_l = left_margin.specified_or_zero();
+                let margin_r = right_margin.specified_or_zero();
+                // Setting 'right' to static position because direction is 'rtl'.
+                let right = static_position_right;
+                let sum = right + width + margin_l + margin_r;
+                (available_width - sum, right, width, margin_l, margin_r)
+            }
             (Auto, Auto, Specified(width)) => {
                 let margin_l = left_margin.specified_or_zero();
                 let margin_r = right_margin.specified_or_zero();
                 // Setting 'left' to static position because direction is 'ltr'.
-                // TODO: Handle 'rtl' when it is implemented.
                 let left = static_position_left;
                 let sum = left + width + margin_l + margin_r;
                 (left, available_width - sum, width, margin_l, margin_r)
@@ -1958,12 +2022,12 @@ impl WidthAndMarginsComputer for AbsoluteReplaced {
             right,
             available_width,
             static_x_offset,
+            static_right_offset,
+            parent_is_rtl,
         } = input;
-        // TODO: Check for direction of static-position Containing Block (aka
-        // parent flow, _not_ the actual Containing Block) when right-to-left
-        // is implemented
-        // Assume direction is 'ltr' for now
-        // TODO: Handle all the cases for 'rtl' direction.
+        // The static position and over-constrained cases are resolved according to the
+        // direction of the static-position Containing Block (aka parent flow, _not_ the actual
+        // Containing Block).
 
         let width = match computed_width {
             Specified(w) => w,
@@ -1976,8 +2040,18 @@ impl WidthAndMarginsComputer for AbsoluteReplaced {
         // left margin edge of a hypothetical box that would have been the
         // first box of the element.
         let static_position_left = static_x_offset;
+        // Distance from the right edge of the Absolute Containing Block to the right margin edge
+        // of the same hypothetical box.
+        let static_position_right = static_right_offset;
 
         let (left, right, width, margin_left, margin_right) = match (left, right) {
+            (Auto, Auto) if parent_is_rtl => {
+                let right = static_position_right;
+                let margin_l = left_margin.specified_or_zero();
+                let margin_r = right_margin.specified_or_zero();
+                let sum = right + width + margin_l + margin_r;
+                (available_width - sum, right, width, margin_l, margin_r)
+            }
             (Auto, Auto) => {
                 let left = static_position_left;
                 let margin_l = left_margin.specified_or_zero();
@@ -2002,7 +2076,10 @@ impl WidthAndMarginsComputer for AbsoluteReplaced {
                 match (left_margin, right_margin) {
                     (Auto, Auto) => {
                         let total_margin_val = (available_width - left - right - width);
-                        if total_margin_val < Au(0) {
+                        if total_margin_val < Au(0) && parent_is_rtl {
+                            // margin-right becomes 0 because direction is 'rtl'.
+                            (left, right, width, total_margin_val, Au(0))
+                        } else if total_margin_val < Au(0) {
                             // margin-left becomes 0 because direction is 'ltr'.
                             (left, right, width, Au(0), total_margin_val)
                         } else {
@@ -2020,6 +2097,12 @@ impl WidthAndMarginsComputer for AbsoluteReplaced {
                         let sum = left + right + width + margin_r;
                         (left, right, width, available_width - sum, margin_r)
                     }
+                    (Specified(margin_l), Specified(margin_r)) if parent_is_rtl => {
+                        // Values are over-constrained.
+                        // Ignore value for 'left' cos direction is 'rtl'.
+                        let sum = right + width + margin_l + margin_r;
+                        (available_width - sum, right, width, margin_l, margin_r)
+                    }
                     (Specified(margin_l), Specified(margin_r)) => {
                         // Values are over-constrained.
                         // Ignore value for 'right' cos direction is 'ltr'.
@@ -14,7 +14,9 @@ use gfx::display_list::{ImageDisplayItem, ImageDisplayItemClass};
 use gfx::display_list::{SolidColorDisplayItem, SolidColorDisplayItemClass, TextDisplayItem};
 use gfx::display_list::{TextDisplayItemClass, TextDisplayItemFlags, ClipDisplayItem};
 use gfx::display_list::{ClipDisplayItemClass, DisplayListCollection};
-use gfx::font::FontStyle;
+use gfx::font::{FontMetrics, FontStyle};
+use gfx::text::bidi::Level;
+use gfx::text::shaping::ShapingOptions;
 use gfx::text::text_run::TextRun;
 use servo_msg::constellation_msg::{FrameRectMsg, PipelineId, SubpageId};
 use servo_net::image::holder::ImageHolder;
@@ -28,11 +30,13 @@ use servo_util::str::is_whitespace;
 use std::cast;
 use std::cell::RefCell;
 use std::cmp::ApproxEq;
+use std::mem;
 use std::num::Zero;
 use style::{ComputedValues, TElement, TNode};
 use style::computed_values::{LengthOrPercentage, LengthOrPercentageOrAuto, overflow, LPA_Auto};
-use style::computed_values::{border_style, clear, font_family, line_height, position};
-use style::computed_values::{text_align, text_decoration, vertical_align, visibility, white_space};
+use style::computed_values::{border_style, clear, font_variant, overflow_wrap};
+use style::computed_values::{position, text_align, text_decoration, text_transform};
+use style::computed_values::{vertical_align, visibility, white_space};
 
 use css::node_style::StyledNode;
 use layout::construct::FlowConstructor;
@@ -42,6 +46,7 @@ use layout::floats::{ClearBoth, ClearLeft, ClearRight, ClearType};
 use layout::flow::{Flow, FlowFlagsInfo};
 use layout::flow;
 use layout::model::{MaybeAuto, specified, Auto, Specified};
+use layout::text;
 use layout::util::OpaqueNode;
 use layout::wrapper::{TLayoutNode, ThreadSafeLayoutNode};
 
@@ -123,13 +128,15 @@ pub struct ImageBoxInfo {
 }
 
 impl ImageBoxInfo {
-    /// Creates a new image box from the given URL and local image cache.
+    /// Creates a new image box from the given URL and local image cache. The image is drawn at
+    /// `device_pixel_ratio` device pixels per CSS pixel.
     ///
     /// FIXME(pcwalton): The fact that image boxes store the cache in the box makes little sense to
     /// me.
     pub fn new(node: &ThreadSafeLayoutNode,
                image_url: Url,
-               local_image_cache: MutexArc<LocalImageCache>)
+               local_image_cache: MutexArc<LocalImageCache>,
+               device_pixel_ratio: f32)
                -> ImageBoxInfo {
         fn convert_length(node: &ThreadSafeLayoutNode, name: &str) -> Option<Au> {
             node.with_element(|element| {
@@ -140,12 +147,33 @@ impl ImageBoxInfo {
             })
         }
 
+        let dom_width = convert_length(node, "width");
+        let dom_height = convert_length(node, "height");
+
+        // If the image is sized only by its width and height attributes, its intrinsic size is
+        // never used, so a large image can be shrunk to the size it is drawn at, in device
+        // pixels, when decoded.
+        let target_size = {
+            let box_style = node.style().get().Box.get();
+            let to_device_px = |length: Au| {
+                (geometry::to_frac_px(length) * (device_pixel_ratio as f64)).ceil() as uint
+            };
+            match (box_style.width, box_style.height, dom_width, dom_height) {
+                (LPA_Auto, LPA_Auto, Some(width), Some(height)) => {
+                    Some(Size2D(to_device_px(width), to_device_px(height)))
+                }
+                _ => None,
+            }
+        };
+
         ImageBoxInfo {
-            image: RefCell::new(ImageHolder::new(image_url, local_image_cache)),
+            image: RefCell::new(ImageHolder::new_with_target_size(image_url,
+                                                                  local_image_cache,
+                                                                  target_size)),
             computed_width: RefCell::new(None),
             computed_height: RefCell::new(None),
-            dom_width: convert_length(node,"width"),
-            dom_height: convert_length(node,"height"),
+            dom_width: dom_width,
+            dom_height: dom_height,
         }
     }
 
@@ -236,14 +264,24 @@ pub struct ScannedTextBoxInfo {
 
     /// The range within the above text run that this represents.
     range: Range,
+
+    /// The extra space added after each whitespace character in this box for
+    /// `text-align: justify`. Set during line layout.
+    justification: Au,
+
+    /// The resolved bidirectional embedding level of the text.
+    bidi_level: Level,
 }
 
 impl ScannedTextBoxInfo {
-    /// Creates the information specific to a scanned text box from a range and a text run.
-    pub fn new(run: Arc<~TextRun>, range: Range) -> ScannedTextBoxInfo {
+    /// Creates the information specific to a scanned text box from a range, a text run, and the
+    /// bidirectional embedding level of the text.
+    pub fn new(run: Arc<~TextRun>, range: Range, bidi_level: Level) -> ScannedTextBoxInfo {
         ScannedTextBoxInfo {
             run: run,
             range: range,
+            justification: Au(0),
+            bidi_level: bidi_level,
         }
     }
 }
@@ -541,12 +579,9 @@ impl Box {
             self.border.get().left + self.border.get().right
     }
 
-    pub fn calculate_line_height(&self, font_size: Au) -> Au {
-        match self.line_height() {
-            line_height::Normal => font_size.scale_by(1.14),
-            line_height::Number(l) => font_size.scale_by(l),
-            line_height::Length(l) => l
-        }
+    /// Returns the used `line-height` of this box, given the metrics of its first available font.
+    pub fn calculate_line_height(&self, font_metrics: &FontMetrics) -> Au {
+        text::line_height_from_style(self.style(), font_metrics)
     }
 
     /// Populates the box model border parameters from the given computed style.
@@ -717,27 +752,7 @@ impl Box {
     /// FIXME(pcwalton): This should not be necessary; just make the font part of style sharable
     /// with the display list somehow. (Perhaps we should use an ARC.)
     pub fn font_style(&self) -> FontStyle {
-        let my_style = self.style();
-
-        debug!("(font style) start");
-
-        // FIXME: Too much allocation here.
-        let font_families = my_style.Font.get().font_family.map(|family| {
-            match *family {
-                font_family::FamilyName(ref name) => (*name).clone(),
-            }
-        });
-        debug!("(font style) font families: `{:?}`", font_families);
-
-        let font_size = my_style.Font.get().font_size.to_f64().unwrap() / 60.0;
-        debug!("(font style) font size: `{:f}px`", font_size);
-
-        FontStyle {
-            pt_size: font_size,
-            weight: my_style.Font.get().font_weight,
-            style: my_style.Font.get().font_style,
-            families: font_families,
-        }
+        text::computed_style_to_font_style(self.style())
     }
 
     #[inline(always)]
@@ -751,10 +766,6 @@ impl Box {
         self.style().InheritedText.get().text_align
     }
 
-    pub fn line_height(&self) -> line_height::T {
-        self.style().InheritedBox.get().line_height
-    }
-
     pub fn vertical_align(&self) -> vertical_align::T {
         self.style().Box.get().vertical_align
     }
@@ -763,6 +774,24 @@ impl Box {
         self.style().InheritedText.get().white_space
     }
 
+    pub fn overflow_wrap(&self) -> overflow_wrap::T {
+        self.style().InheritedText.get().overflow_wrap
+    }
+
+    pub fn text_transform(&self) -> text_transform::T {
+        self.style().InheritedText.get().text_transform
+    }
+
+    pub fn font_variant(&self) -> font_variant::T {
+        self.style().Font.get().font_variant
+    }
+
+    /// Returns the `letter-spacing` and `word-spacing` to apply when shaping this box's text.
+    pub fn shaping_options(&self) -> ShapingOptions {
+        let text_style = self.style().InheritedText.get();
+        ShapingOptions::new(text_style.letter_spacing, text_style.word_spacing)
+    }
+
     /// Returns the text decoration of this box, according to the style of the nearest ancestor
     /// element.
     ///
@@ -927,6 +956,7 @@ impl Box {
                 match holder.get_image() {
                     Some(image) => {
                         debug!("(building display list) building background image");
+                        let animation = holder.get_animation();
 
                         // Place the image into the display list.
                         lists.with_mut(|lists| {
@@ -936,6 +966,8 @@ impl Box {
                                     extra: ExtraDisplayListData::new(self),
                                 },
                                 image: image.clone(),
+                                animation: animation.clone(),
+                                image_url: image_url.clone(),
                             };
                             lists.lists[index].append_item(ImageDisplayItemClass(image_display_item));
                         });
@@ -1081,6 +1113,15 @@ impl Box {
                                     - self.noncontent_inline_left()
                                     - self.noncontent_inline_right();
 
+                // The box is as tall as its line height, but the text is drawn in the content
+                // area, which is centered within it by splitting the leading in half.
+                let font_metrics = &text_box.run.get().font_metrics;
+                let content_area_height = font_metrics.ascent + font_metrics.descent;
+                let half_leading = (self.calculate_line_height(font_metrics) -
+                                    content_area_height).scale_by(0.5);
+                bounds.origin.y = bounds.origin.y + half_leading;
+                bounds.size.height = content_area_height;
+
                 // Create the text box.
                 lists.with_mut(|lists| {
                     let text_display_item = ~TextDisplayItem {
@@ -1091,6 +1132,7 @@ impl Box {
                         text_run: text_box.run.clone(),
                         range: text_box.range,
                         text_color: text_color,
+                        justification: text_box.justification,
                         overline_color: flow_flags.overline_color(text_color),
                         underline_color: flow_flags.underline_color(text_color),
                         line_through_color: flow_flags.line_through_color(text_color),
@@ -1125,7 +1167,8 @@ impl Box {
                     // Draw a rectangle representing the baselines.
                     let ascent = text_box.run.get().metrics_for_range(
                         &text_box.range).ascent;
-                    let baseline = Rect(absolute_box_bounds.origin + Point2D(Au(0), ascent),
+                    let baseline = Rect(absolute_box_bounds.origin +
+                                        Point2D(Au(0), half_leading + ascent),
                                         Size2D(absolute_box_bounds.size.width, Au(0)));
 
                     lists.with_mut(|lists| {
@@ -1190,6 +1233,7 @@ impl Box {
                 match image_ref.get().get_image() {
                     Some(image) => {
                         debug!("(building display list) building image box");
+                        let animation = image_ref.get().get_animation();
 
                         // Place the image into the display list.
                         lists.with_mut(|lists| {
@@ -1199,6 +1243,8 @@ impl Box {
                                     extra: ExtraDisplayListData::new(self),
                                 },
                                 image: image.clone(),
+                                animation: animation.clone(),
+                                image_url: image_ref.get().url.clone(),
                             };
                             lists.lists[index].append_item(ImageDisplayItemClass(image_display_item));
                         });
@@ -1303,11 +1349,8 @@ impl Box {
                 image_box_info.computed_height()
             }
             ScannedTextBox(ref text_box_info) => {
-                // Compute the height based on the line-height and font size.
-                let (range, run) = (&text_box_info.range, &text_box_info.run);
-                let text_bounds = run.get().metrics_for_range(range).bounding_box;
-                let em_size = text_bounds.size.height;
-                self.calculate_line_height(em_size)
+                // Compute the height based on the line-height and font metrics.
+                self.calculate_line_height(&text_box_info.run.get().font_metrics)
             }
             UnscannedTextBox(_) => fail!("Unscanned text boxes should have been scanned by now!"),
         }
@@ -1334,7 +1377,9 @@ impl Box {
 
                 // Left box is for left text of first founded new-line character.
                 let left_box = {
-                    let new_text_box_info = ScannedTextBoxInfo::new(text_box_info.run.clone(), left_range);
+                    let new_text_box_info = ScannedTextBoxInfo::new(text_box_info.run.clone(),
+                                                                    left_range,
+                                                                    text_box_info.bidi_level);
                     let new_metrics = new_text_box_info.run.get().metrics_for_range(&left_range);
                     let mut new_box = self.transform(new_metrics.bounding_box.size, ScannedTextBox(new_text_box_info));
                     new_box.new_line_pos = ~[];
@@ -1343,7 +1388,9 @@ impl Box {
 
                 // Right box is for right text of first founded new-line character.
                 let right_box = if right_range.length() > 0 {
-                    let new_text_box_info = ScannedTextBoxInfo::new(text_box_info.run.clone(), right_range);
+                    let new_text_box_info = ScannedTextBoxInfo::new(text_box_info.run.clone(),
+                                                                    right_range,
+                                                                    text_box_info.bidi_level);
                     let new_metrics = new_text_box_info.run.get().metrics_for_range(&right_range);
                     let mut new_box = self.transform(new_metrics.bounding_box.size, ScannedTextBox(new_text_box_info));
                     new_box.new_line_pos = new_line_pos;
@@ -1415,6 +1462,29 @@ impl Box {
                                 debug!("split_to_width: case=skipping trimmable trailing \
                                         whitespace");
                             }
+                        } else if starts_line && left_range.length() == 0 &&
+                                self.overflow_wrap() == overflow_wrap::break_word {
+                            // Nothing fits on this line, so break inside the word. At least one
+                            // character is placed on the line so that layout makes progress.
+                            debug!("split_to_width: case=breaking word for overflow-wrap");
+                            let mut fitting_char_count = 0;
+                            for char_i in range(slice_range.begin(), slice_range.end()) {
+                                let char_metrics = text_box_info.run.get().metrics_for_slice(
+                                    glyphs, &Range::new(char_i, 1));
+                                let char_advance = char_metrics.advance_width;
+                                if fitting_char_count > 0 && char_advance > remaining_width {
+                                    break
+                                }
+                                remaining_width = remaining_width - char_advance;
+                                fitting_char_count += 1;
+                            }
+                            left_range = Range::new(slice_begin, fitting_char_count);
+
+                            let break_pos = slice_begin + fitting_char_count;
+                            if break_pos < text_box_info.range.end() {
+                                let right_range_end = text_box_info.range.end() - break_pos;
+                                right_range = Some(Range::new(break_pos, right_range_end));
+                            }
                         } else if slice_begin < text_box_info.range.end() {
                             // There are still some things left over at the end of the line. Create
                             // the right chunk.
@@ -1433,7 +1503,9 @@ impl Box {
                 }
 
                 let left_box = if left_range.length() > 0 {
-                    let new_text_box_info = ScannedTextBoxInfo::new(text_box_info.run.clone(), left_range);
+                    let new_text_box_info = ScannedTextBoxInfo::new(text_box_info.run.clone(),
+                                                                    left_range,
+                                                                    text_box_info.bidi_level);
                     let mut new_metrics = new_text_box_info.run.get().metrics_for_range(&left_range);
                     new_metrics.bounding_box.size.height = self.border_box.get().size.height;
                     Some(self.transform(new_metrics.bounding_box.size,
@@ -1443,7 +1515,9 @@ impl Box {
                 };
 
                 let right_box = right_range.map_default(None, |range: Range| {
-                    let new_text_box_info = ScannedTextBoxInfo::new(text_box_info.run.clone(), range);
+                    let new_text_box_info = ScannedTextBoxInfo::new(text_box_info.run.clone(),
+                                                                    range,
+                                                                    text_box_info.bidi_level);
                     let mut new_metrics = new_text_box_info.run.get().metrics_for_range(&range);
                     new_metrics.bounding_box.size.height = self.border_box.get().size.height;
                     Some(self.transform(new_metrics.bounding_box.size,
@@ -1572,11 +1646,18 @@ impl Box {
     }
 
     /// Returns true if this box can merge with another adjacent box or false otherwise.
+    ///
+    /// Small-caps boxes are never merged, since the text run scanner splits them into several
+    /// runs with different font sizes.
     pub fn can_merge_with_box(&self, other: &Box) -> bool {
         match (&self.specific, &other.specific) {
             (&UnscannedTextBox(_), &UnscannedTextBox(_)) => {
                 self.font_style() == other.font_style() &&
-                    self.text_decoration() == other.text_decoration()
+                    self.text_decoration() == other.text_decoration() &&
+                    self.text_transform() == other.text_transform() &&
+                    self.shaping_options() == other.shaping_options() &&
+                    self.font_variant() == font_variant::normal &&
+                    other.font_variant() == font_variant::normal
             }
             _ => false,
         }
@@ -1612,6 +1693,45 @@ impl Box {
                 self.side_offsets_debug_string("m", self.margin.get()))
     }
 
+    /// Returns a string describing this box, its position, and its content, for the flow tree
+    /// dump.
+    pub fn dump_str(&self) -> ~str {
+        let mut output = format!("{} border_box={}",
+                                 self.debug_str(),
+                                 geometry::rect_to_px_str(&self.border_box.get()));
+        match self.specific {
+            ScannedTextBox(ref text_box_info) => {
+                let text_run = text_box_info.run.get();
+                let text = text_run.text.get().slice_chars(text_box_info.range.begin(),
+                                                           text_box_info.range.end());
+                output.push_str(format!(" text=\"{}\"", text.escape_default()))
+            }
+            ImageBox(ref image_box_info) => {
+                let image_holder = image_box_info.image.borrow();
+                output.push_str(format!(" url={}", image_holder.get().url.to_str()))
+            }
+            GenericBox | IframeBox(_) | UnscannedTextBox(_) => {}
+        }
+        output
+    }
+
+    /// Returns an estimate of the heap memory owned by this box, not counting the box itself.
+    /// Text runs are shared between the boxes split from them, so they are not counted here.
+    pub fn heap_size_of_contents(&self) -> uint {
+        let inline_info = self.inline_info.borrow();
+        let inline_info_size = match *inline_info.get() {
+            Some(ref inline_info) => {
+                inline_info.parent_info.len() * mem::size_of::<InlineParentInfo>()
+            }
+            None => 0,
+        };
+        let specific_size = match self.specific {
+            UnscannedTextBox(ref text_box_info) => text_box_info.text.len(),
+            GenericBox | ImageBox(_) | IframeBox(_) | ScannedTextBox(_) => 0,
+        };
+        inline_info_size + specific_size
+    }
+
     /// A helper function to return a debug string describing the side offsets for one of the rect
     /// box model properties (border, padding, or margin).
     fn side_offsets_debug_string(&self, name: &str, value: SideOffsets2D<Au>) -> ~str {
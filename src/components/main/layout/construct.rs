@@ -273,7 +273,10 @@ impl<'a> FlowConstructor<'a> {
             Some(url) => {
                 // FIXME(pcwalton): The fact that image boxes store the cache within them makes
                 // little sense to me.
-                ImageBox(ImageBoxInfo::new(node, url, self.layout_context.image_cache.clone()))
+                ImageBox(ImageBoxInfo::new(node,
+                                           url,
+                                           self.layout_context.image_cache.clone(),
+                                           self.layout_context.device_pixel_ratio))
             }
         }
     }
@@ -306,7 +309,9 @@ impl<'a> FlowConstructor<'a> {
             return
         }
 
-        let mut inline_flow = ~InlineFlow::from_boxes((*node).clone(), boxes) as ~Flow;
+        let mut inline_flow = ~InlineFlow::from_boxes((*node).clone(), boxes);
+        inline_flow.compute_minimum_ascent_and_depth(self.font_context(), node.style().get());
+        let mut inline_flow = inline_flow as ~Flow;
         TextRunScanner::new().scan_for_runs(self.font_context(), inline_flow);
         inline_flow.finish(self.layout_context);
 
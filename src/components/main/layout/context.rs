@@ -44,6 +44,9 @@ pub struct LayoutContext {
     /// The current screen size.
     screen_size: Size2D<Au>,
 
+    /// The number of device pixels that each CSS pixel is drawn at.
+    device_pixel_ratio: f32,
+
     /// A channel up to the constellation.
     constellation_chan: ConstellationChan,
 
@@ -48,6 +48,7 @@ use layout::display_list_builder::ToGfxColor;
 use gfx::color::Color;
 use servo_util::smallvec::{SmallVec, SmallVec0};
 use servo_util::geometry::Au;
+use servo_util::geometry;
 use std::cast;
 use std::cell::RefCell;
 use std::sync::atomics::Relaxed;
@@ -181,6 +182,17 @@ pub trait Flow {
     fn debug_str(&self) -> ~str {
         ~"???"
     }
+
+    /// Returns a description of each box of this flow, for the flow tree dump.
+    fn dump_boxes(&self) -> ~[~str] {
+        ~[]
+    }
+
+    /// Returns an estimate of the heap memory used by this flow and its boxes, not counting its
+    /// children, for the memory profiler.
+    fn heap_size(&self) -> uint {
+        0
+    }
 }
 
 // Base access
@@ -242,6 +254,16 @@ pub trait ImmutableFlowUtils {
 
     /// Dumps the flow tree for debugging, with a prefix to indicate that we're at the given level.
     fn dump_with_level(self, level: uint);
+
+    /// Returns a description of this flow, its boxes, and its descendants, one per line, in a
+    /// format meant to be diffed between runs.
+    fn dump_to_str(self) -> ~str;
+
+    /// Appends the description of this flow tree to the given string, indented to the given level.
+    fn dump_into(self, output: &mut ~str, level: uint);
+
+    /// Returns an estimate of the heap memory used by this flow and its descendants.
+    fn heap_size_of_tree(self) -> uint;
 }
 
 pub trait MutableFlowUtils {
@@ -527,6 +549,10 @@ bitfield!(FlowFlags, override_overline, set_override_overline, 0b0000_0100)
 // NB: If you update this, you need to update TEXT_DECORATION_OVERRIDE_BITMASK.
 bitfield!(FlowFlags, override_line_through, set_override_line_through, 0b0000_1000)
 
+// Whether the parent of this flow has `direction: rtl`. This determines how over-constrained
+// width equations are resolved (CSS 2.1 § 10.3).
+bitfield!(FlowFlags, parent_is_rtl, set_parent_is_rtl, 0b0100_0000)
+
 // The text alignment for this flow.
 impl FlowFlags {
     #[inline]
@@ -809,6 +835,51 @@ impl<'a> ImmutableFlowUtils for &'a Flow {
             kid.dump_with_level(level + 1)
         }
     }
+
+    /// Returns a description of this flow, its boxes, and its descendants, one per line.
+    fn dump_to_str(self) -> ~str {
+        let mut output = ~"";
+        self.dump_into(&mut output, 0);
+        output
+    }
+
+    /// Appends the description of this flow tree to the given string, indented to the given level.
+    fn dump_into(self, output: &mut ~str, level: uint) {
+        let mut indent = ~"";
+        for _ in range(0, level) {
+            indent.push_str("| ")
+        }
+
+        let class_name = match self.class() {
+            BlockFlowClass if self.is_root() => "RootFlow",
+            BlockFlowClass if self.is_float() => "FloatFlow",
+            BlockFlowClass => "BlockFlow",
+            InlineFlowClass => "InlineFlow",
+        };
+        let flow_base = base(self);
+        output.push_str(format!("{}+ {} position={} overflow={} min_width={} pref_width={}\n",
+                                indent,
+                                class_name,
+                                geometry::rect_to_px_str(&flow_base.position),
+                                geometry::rect_to_px_str(&flow_base.overflow),
+                                geometry::to_frac_px(flow_base.min_width),
+                                geometry::to_frac_px(flow_base.pref_width)));
+        for box_str in self.dump_boxes().iter() {
+            output.push_str(format!("{}|   - {}\n", indent, *box_str));
+        }
+
+        for kid in imm_child_iter(self) {
+            kid.dump_into(output, level + 1)
+        }
+    }
+
+    fn heap_size_of_tree(self) -> uint {
+        let mut size = self.heap_size();
+        for kid in imm_child_iter(self) {
+            size += kid.heap_size_of_tree()
+        }
+        size
+    }
 }
 
 impl<'a> MutableFlowUtils for &'a mut Flow {
@@ -134,7 +134,10 @@ pub fn compute_damage(old: &ComputedValues, new: &ComputedValues) -> RestyleDama
           Padding.padding_top, Padding.padding_right, Padding.padding_bottom, Padding.padding_left,
           Box.position, Box.width, Box.height, Box.float, Box.display,
           Font.font_family, Font.font_size, Font.font_style, Font.font_weight,
-          InheritedText.text_align, Text.text_decoration, InheritedBox.line_height ]);
+          Font.font_variant, InheritedText.text_align, InheritedText.letter_spacing,
+          InheritedText.word_spacing, InheritedText.text_indent, InheritedText.text_transform,
+          InheritedText.overflow_wrap, Text.text_decoration, Text.unicode_bidi,
+          InheritedBox.line_height, InheritedBox.direction ]);
 
     // FIXME: test somehow that we checked every CSS property
 
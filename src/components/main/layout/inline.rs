@@ -10,6 +10,8 @@ use layout::display_list_builder::{DisplayListBuilder, ExtraDisplayListData};
 use layout::floats::{FloatLeft, Floats, PlacementInfo};
 use layout::flow::{BaseFlow, FlowClass, Flow, InlineFlowClass};
 use layout::flow;
+use layout::model;
+use layout::text;
 use layout::util::ElementMapping;
 use layout::wrapper::ThreadSafeLayoutNode;
 
@@ -17,12 +19,20 @@ use extra::container::Deque;
 use extra::ringbuf::RingBuf;
 use geom::{Point2D, Rect, Size2D};
 use gfx::display_list::DisplayListCollection;
+use gfx::font::FontMetrics;
+use gfx::font_context::FontContext;
+use gfx::text::bidi::Level;
+use gfx::text::bidi;
 use servo_util::geometry::Au;
 use servo_util::range::Range;
 use std::cell::RefCell;
+use std::iter::AdditiveIterator;
+use std::mem;
 use std::u16;
 use std::util;
-use style::computed_values::{text_align, vertical_align, white_space};
+use style::ComputedValues;
+use style::computed_values::{LengthOrPercentage, direction, text_align, vertical_align};
+use style::computed_values::white_space;
 
 /// Lineboxes are represented as offsets into the child list, rather than
 /// as an object that "owns" boxes. Choosing a different set of line
@@ -118,7 +128,7 @@ impl LineboxScanner {
 
             let box_was_appended = match cur_box.white_space() {
                 white_space::normal => self.try_append_to_line(cur_box, flow),
-                white_space::pre => self.try_append_to_line_by_new_line(cur_box),
+                white_space::pre => self.try_append_to_line_by_new_line(cur_box, flow),
             };
 
             if !box_was_appended {
@@ -171,6 +181,16 @@ impl LineboxScanner {
         }
     }
 
+    /// Returns the indentation of the pending line, which is nonzero only for the first line of
+    /// the flow (CSS 2.1 § 16.1).
+    fn indentation_for_pending_line(&self, flow: &InlineFlow) -> Au {
+        if self.lines.len() == 0 {
+            flow.first_line_indentation
+        } else {
+            Au(0)
+        }
+    }
+
     /// Computes the position of a line that has only the provided box. Returns the bounding rect
     /// of the line's green zone (whose origin coincides with the line's origin) and the actual
     /// width of the first box after splitting.
@@ -199,7 +219,12 @@ impl LineboxScanner {
             kind: FloatLeft,
         };
 
-        let line_bounds = self.floats.place_between_floats(&info);
+        let mut line_bounds = self.floats.place_between_floats(&info);
+
+        // `text-indent` narrows the first line from the start side.
+        let indentation = self.indentation_for_pending_line(flow);
+        line_bounds.origin.x = line_bounds.origin.x + indentation;
+        line_bounds.size.width = line_bounds.size.width - indentation;
 
         debug!("LineboxScanner: found position for line: {} using placement_info: {:?}",
                line_bounds,
@@ -253,7 +278,9 @@ impl LineboxScanner {
                 };
 
                 info.size.width = actual_box_width;
-                let new_bounds = self.floats.place_between_floats(&info);
+                let mut new_bounds = self.floats.place_between_floats(&info);
+                new_bounds.origin.x = new_bounds.origin.x + indentation;
+                new_bounds.size.width = new_bounds.size.width - indentation;
 
                 debug!("LineboxScanner: case=new line position: {}", new_bounds);
                 return (new_bounds, actual_box_width);
@@ -308,7 +335,11 @@ impl LineboxScanner {
         false
     }
 
-    fn try_append_to_line_by_new_line(&mut self, in_box: Box) -> bool {
+    fn try_append_to_line_by_new_line(&mut self, in_box: Box, flow: &InlineFlow) -> bool {
+        if self.pending_line.range.length() == 0 {
+            self.pending_line.bounds.origin.x = self.indentation_for_pending_line(flow);
+        }
+
         if in_box.new_line_pos.len() == 0 {
             // In case of box does not include new-line character
             self.push_box_to_line(in_box);
@@ -445,6 +476,44 @@ impl LineboxScanner {
     }
 }
 
+/// The vertical extents of an inline box relative to its baseline, per CSS 2.1 § 10.8.1.
+pub struct InlineMetrics {
+    /// The distance from the top of the box's line-height area to the baseline.
+    height_above_baseline: Au,
+    /// The distance from the baseline to the bottom of the box's line-height area.
+    depth_below_baseline: Au,
+    /// The ascent of the font, i.e. the distance from the top of the content area to the baseline.
+    ascent: Au,
+}
+
+impl InlineMetrics {
+    /// Calculates the inline metrics of a box with the given font metrics and used line height.
+    /// The leading (the difference between the line height and the height of the content area)
+    /// is split in half and added above and below the content area.
+    pub fn from_font_metrics(font_metrics: &FontMetrics, line_height: Au) -> InlineMetrics {
+        let leading = line_height - (font_metrics.ascent + font_metrics.descent);
+        let height_above_baseline = font_metrics.ascent + leading.scale_by(0.5);
+        InlineMetrics {
+            height_above_baseline: height_above_baseline,
+            depth_below_baseline: line_height - height_above_baseline,
+            ascent: font_metrics.ascent,
+        }
+    }
+}
+
+/// Returns the extra space that each justification opportunity of a line gets: an even share of
+/// the line's slack for `text-align: justify`, except on the last line of a paragraph.
+fn justification_per_opportunity(slack_width: Au,
+                                 opportunities: uint,
+                                 linebox_align: text_align::T,
+                                 is_last_line: bool)
+                                 -> Au {
+    if linebox_align != text_align::justify || is_last_line || opportunities == 0 {
+        return Au(0)
+    }
+    slack_width.scale_by(1.0 / (opportunities as f64))
+}
+
 pub struct InlineFlow {
     /// Data common to all flows.
     base: BaseFlow,
@@ -461,18 +530,79 @@ pub struct InlineFlow {
     // must be well-nested, and are only related to the content of
     // boxes (not lines). Ranges are only kept for non-leaf elements.
     elems: ElementMapping,
+
+    /// The computed `text-indent` of the containing block.
+    text_indent: LengthOrPercentage,
+
+    /// The used indentation of the first line, resolved against the width of the flow during
+    /// width assignment.
+    first_line_indentation: Au,
+
+    /// The minimum height above the baseline of each line, as determined by the strut of the
+    /// containing block (CSS 2.1 § 10.8.1).
+    minimum_height_above_baseline: Au,
+
+    /// The minimum depth below the baseline of each line, as determined by the strut of the
+    /// containing block.
+    minimum_depth_below_baseline: Au,
+
+    /// The distance from the baseline to the top of the containing block's content area, used
+    /// for `vertical-align: text-top`.
+    parent_text_top: Au,
+
+    /// The distance from the baseline to the bottom of the containing block's content area, used
+    /// for `vertical-align: text-bottom`.
+    parent_text_bottom: Au,
+
+    /// The x-height of the containing block's font, used for `vertical-align: middle`.
+    parent_x_height: Au,
+
+    /// The bidirectional embedding level of the paragraph, from the `direction` of the
+    /// containing block.
+    paragraph_level: Level,
 }
 
 impl InlineFlow {
     pub fn from_boxes(node: ThreadSafeLayoutNode, boxes: ~[Box]) -> InlineFlow {
+        let text_indent = node.style().get().InheritedText.get().text_indent;
+        let paragraph_level = match node.style().get().InheritedBox.get().direction {
+            direction::ltr => 0,
+            direction::rtl => 1,
+        };
         InlineFlow {
             base: BaseFlow::new(node),
             boxes: boxes,
             lines: ~[],
             elems: ElementMapping::new(),
+            text_indent: text_indent,
+            first_line_indentation: Au(0),
+            minimum_height_above_baseline: Au(0),
+            minimum_depth_below_baseline: Au(0),
+            parent_text_top: Au(0),
+            parent_text_bottom: Au(0),
+            parent_x_height: Au(0),
+            paragraph_level: paragraph_level,
         }
     }
 
+    /// Computes the strut of this flow and the font metrics of the containing block from the
+    /// style of the containing block. This must be called during flow construction, since the
+    /// font context is not available during parallel layout.
+    pub fn compute_minimum_ascent_and_depth(&mut self,
+                                            font_context: &mut FontContext,
+                                            style: &ComputedValues) {
+        let font_style = text::computed_style_to_font_style(style);
+        let font_metrics = text::font_metrics_for_style(font_context, &font_style);
+        let line_height = text::line_height_from_style(style, &font_metrics);
+        let inline_metrics = InlineMetrics::from_font_metrics(&font_metrics, line_height);
+
+        self.minimum_height_above_baseline = inline_metrics.height_above_baseline;
+        self.minimum_depth_below_baseline = inline_metrics.depth_below_baseline;
+        self.parent_text_top = font_metrics.ascent;
+        self.parent_text_bottom = font_metrics.descent;
+        self.parent_x_height = font_metrics.x_height;
+    }
+
     pub fn teardown(&mut self) {
         for box_ in self.boxes.iter() {
             box_.teardown();
@@ -516,90 +646,118 @@ impl InlineFlow {
     /// That is, if the box has a `top` or `bottom` value, true is returned.
     fn relative_offset_from_baseline(cur_box: &Box,
                                      ascent: Au,
+                                     line_height: Au,
                                      parent_text_top: Au,
                                      parent_text_bottom: Au,
+                                     parent_x_height: Au,
                                      top_from_base: &mut Au,
                                      bottom_from_base: &mut Au,
                                      biggest_top: &mut Au,
                                      biggest_bottom: &mut Au)
                                      -> (Au, bool) {
-        match cur_box.vertical_align() {
-            vertical_align::baseline => (-ascent, false),
+        // The distance by which the baseline of this box is raised above the baseline of the
+        // parent. Negative values lower the box.
+        let font_size = cur_box.style().Font.get().font_size;
+        let raise = match cur_box.vertical_align() {
+            vertical_align::baseline => Au(0),
             vertical_align::middle => {
-                // TODO: x-height value should be used from font info.
-                let xheight = Au::new(0);
-                (-(xheight + cur_box.content_height()).scale_by(0.5), false)
-            },
-            vertical_align::sub => {
-                // TODO: The proper position for subscripts should be used.
-                // Lower the baseline to the proper position for subscripts
-                let sub_offset = Au::new(0);
-                (sub_offset - ascent, false)
-            },
-            vertical_align::super_ => {
-                // TODO: The proper position for superscripts should be used.
-                // Raise the baseline to the proper position for superscripts
-                let super_offset = Au::new(0);
-                (-super_offset - ascent, false)
-            },
-            vertical_align::text_top => {
-                let box_height = *top_from_base + *bottom_from_base;
-                let prev_bottom_from_base = *bottom_from_base;
-                *top_from_base = parent_text_top;
-                *bottom_from_base = box_height - *top_from_base;
-                (*bottom_from_base - prev_bottom_from_base - ascent, false)
-            },
-            vertical_align::text_bottom => {
+                // Align the vertical midpoint of the box with the baseline of the parent plus
+                // half the x-height of the parent.
                 let box_height = *top_from_base + *bottom_from_base;
-                let prev_bottom_from_base = *bottom_from_base;
-                *bottom_from_base = parent_text_bottom;
-                *top_from_base = box_height - *bottom_from_base;
-                (*bottom_from_base - prev_bottom_from_base - ascent, false)
+                parent_x_height.scale_by(0.5) + box_height.scale_by(0.5) - *top_from_base
             },
+            // These are the offsets that Gecko and WebKit use in the absence of font-supplied
+            // subscript and superscript positions.
+            vertical_align::sub => -font_size.scale_by(0.2),
+            vertical_align::super_ => font_size.scale_by(1.0 / 3.0),
+            vertical_align::text_top => parent_text_top - *top_from_base,
+            vertical_align::text_bottom => *bottom_from_base - parent_text_bottom,
             vertical_align::top => {
                 if *biggest_top < (*top_from_base + *bottom_from_base) {
                     *biggest_top = *top_from_base + *bottom_from_base;
                 }
                 let offset_top = *top_from_base - ascent;
-                (offset_top, true)
+                return (offset_top, true)
             },
             vertical_align::bottom => {
                 if *biggest_bottom < (*top_from_base + *bottom_from_base) {
                     *biggest_bottom = *top_from_base + *bottom_from_base;
                 }
                 let offset_bottom = -(*bottom_from_base + ascent);
-                (offset_bottom, true)
+                return (offset_bottom, true)
             },
-            vertical_align::Length(length) => (-(length + ascent), false),
-            vertical_align::Percentage(p) => {
-                let pt_size = cur_box.font_style().pt_size;
-                let line_height = cur_box.calculate_line_height(Au::from_pt(pt_size));
-                let percent_offset = line_height.scale_by(p);
-                (-(percent_offset + ascent), false)
-            }
-        }
+            vertical_align::Length(length) => length,
+            vertical_align::Percentage(p) => line_height.scale_by(p),
+        };
+
+        *top_from_base = *top_from_base + raise;
+        *bottom_from_base = *bottom_from_base - raise;
+        (-(raise + ascent), false)
     }
 
-    /// Sets box X positions based on alignment for one line.
-    fn set_horizontal_box_positions(boxes: &[Box], line: &LineBox, linebox_align: text_align::T) {
+    /// Sets box X positions based on alignment for one line. Lines aligned with
+    /// `text-align: justify` have their slack distributed among the whitespace characters of their
+    /// text boxes, unless they are the last line of the flow. Boxes are placed in visual order,
+    /// per rule L2 of the Unicode Bidirectional Algorithm.
+    fn set_horizontal_box_positions(boxes: &mut [Box],
+                                    line: &LineBox,
+                                    linebox_align: text_align::T,
+                                    is_last_line: bool,
+                                    paragraph_level: Level) {
         // Figure out how much width we have.
         let slack_width = Au::max(Au(0), line.green_zone.width - line.bounds.size.width);
 
+        // Figure out how much extra space each justification opportunity gets, if any.
+        let mut opportunities = 0;
+        if linebox_align == text_align::justify && !is_last_line {
+            for i in line.range.eachi() {
+                match boxes[i].specific {
+                    ScannedTextBox(ref text_box) if boxes[i].white_space() == white_space::normal => {
+                        opportunities += text_box.run.get()
+                            .justification_opportunities_for_range(&text_box.range);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        let justification = justification_per_opportunity(slack_width,
+                                                          opportunities,
+                                                          linebox_align,
+                                                          is_last_line);
+
         // Set the box x positions based on that alignment.
         let mut offset_x = line.bounds.origin.x;
         offset_x = offset_x + match linebox_align {
-            // So sorry, but justified text is more complicated than shuffling linebox
-            // coordinates.
-            //
-            // TODO(burg, issue #213): Implement `text-align: justify`.
             text_align::left | text_align::justify => Au(0),
             text_align::center => slack_width.scale_by(0.5),
             text_align::right => slack_width,
         };
 
-        for i in line.range.eachi() {
-            let box_ = &boxes[i];
-            let size = box_.border_box.get().size;
+        // Reorder the boxes of the line by their embedding levels. Boxes other than text are at
+        // the level of the paragraph.
+        let levels: ~[Level] = line.range.eachi().map(|i| {
+            match boxes[i].specific {
+                ScannedTextBox(ref text_box) => text_box.bidi_level,
+                _ => paragraph_level,
+            }
+        }).collect();
+        let visual_order = bidi::visual_order(levels);
+
+        for &logical_index in visual_order.iter() {
+            let box_ = &mut boxes[line.range.begin() + logical_index];
+            let mut size = box_.border_box.get().size;
+            let justify_box = box_.white_space() == white_space::normal;
+            match box_.specific {
+                ScannedTextBox(ref mut text_box) => {
+                    // Reset any justification left over from a previous layout.
+                    text_box.justification = if justify_box { justification } else { Au(0) };
+                    let opportunities = text_box.run.get()
+                        .justification_opportunities_for_range(&text_box.range);
+                    size.width = size.width +
+                        text_box.justification.scale_by(opportunities as f64);
+                }
+                _ => {}
+            }
             box_.border_box.set(Rect(Point2D(offset_x, box_.border_box.get().origin.y), size));
             offset_x = offset_x + size.width;
         }
@@ -654,6 +812,9 @@ impl Flow for InlineFlow {
 
         debug!("InlineFlow::assign_widths: floats in: {:?}", self.base.floats);
 
+        self.first_line_indentation = model::specified(self.text_indent,
+                                                       self.base.position.size.width);
+
         {
             let this = &mut *self;
             for box_ in this.boxes.iter() {
@@ -688,12 +849,6 @@ impl Flow for InlineFlow {
 
         // Divide the boxes into lines.
         //
-        // TODO(#226): Get the CSS `line-height` property from the containing block's style to
-        // determine minimum linebox height.
-        //
-        // TODO(#226): Get the CSS `line-height` property from each non-replaced inline element to
-        // determine its height for computing linebox height.
-        //
         // TODO(pcwalton): Cache the linebox scanner?
         debug!("assign_height_inline: floats in: {:?}", self.base.floats);
         // assign height for inline boxes
@@ -711,16 +866,23 @@ impl Flow for InlineFlow {
         let text_align = self.base.flags_info.flags.text_align();
 
         // Now, go through each line and lay out the boxes inside.
-        for line in self.lines.mut_iter() {
+        let line_count = self.lines.len();
+        for (line_index, line) in self.lines.mut_iter().enumerate() {
             // Lay out boxes horizontally.
-            InlineFlow::set_horizontal_box_positions(self.boxes, line, text_align);
+            InlineFlow::set_horizontal_box_positions(self.boxes,
+                                                     line,
+                                                     text_align,
+                                                     line_index + 1 == line_count,
+                                                     self.paragraph_level);
 
             // Set the top y position of the current linebox.
             // `line_height_offset` is updated at the end of the previous loop.
             line.bounds.origin.y = line.bounds.origin.y + line_height_offset;
 
-            // Calculate the distance from baseline to the top and bottom of the linebox.
-            let (mut topmost, mut bottommost) = (Au(0), Au(0));
+            // Calculate the distance from baseline to the top and bottom of the linebox. Every
+            // line is at least as tall as the strut of the containing block.
+            let (mut topmost, mut bottommost) = (self.minimum_height_above_baseline,
+                                                 self.minimum_depth_below_baseline);
             // Calculate the biggest height among boxes with 'top' and 'bottom' values
             // respectively.
             let (mut biggest_top, mut biggest_bottom) = (Au(0), Au(0));
@@ -729,6 +891,10 @@ impl Flow for InlineFlow {
                 let cur_box = &self.boxes[box_i];
                 let top = cur_box.noncontent_top();
 
+                // The line height of replaced and generic boxes is taken from the strut.
+                let mut line_height = self.minimum_height_above_baseline +
+                    self.minimum_depth_below_baseline;
+
                 // FIXME(pcwalton): Move into `box.rs` like the rest of box-specific layout code?
                 let (top_from_base, bottom_from_base, ascent) = match cur_box.specific {
                     ImageBox(_) => {
@@ -744,23 +910,15 @@ impl Flow for InlineFlow {
                         (height, Au::new(0), ascent)
                     },
                     ScannedTextBox(ref text_box) => {
-                        let range = &text_box.range;
-                        let run = &text_box.run;
-
-                        // Compute the height based on the line-height and font size
-                        let text_bounds = run.get().metrics_for_range(range).bounding_box;
-                        let em_size = text_bounds.size.height;
-                        let line_height = cur_box.calculate_line_height(em_size);
-
-                        // Find the top and bottom of the content area.
-                        // Those are used in text-top and text-bottom value of 'vertical-align'
-                        let text_ascent = text_box.run.get().font_metrics.ascent;
-
-                        // Offset from the top of the box is 1/2 of the leading + ascent
-                        let text_offset = text_ascent + (line_height - em_size).scale_by(0.5);
-                        text_bounds.translate(&Point2D(cur_box.border_box.get().origin.x, Au(0)));
-
-                        (text_offset, line_height - text_offset, text_ascent)
+                        // Compute the height based on the line-height and the metrics of the
+                        // font. The top of the box is the top of its line-height area.
+                        let font_metrics = &text_box.run.get().font_metrics;
+                        line_height = cur_box.calculate_line_height(font_metrics);
+                        let inline_metrics = InlineMetrics::from_font_metrics(font_metrics,
+                                                                              line_height);
+                        (inline_metrics.height_above_baseline,
+                         inline_metrics.depth_below_baseline,
+                         inline_metrics.height_above_baseline)
                     },
                     GenericBox | IframeBox(_) => {
                         let height = cur_box.border_box.get().size.height;
@@ -775,24 +933,12 @@ impl Flow for InlineFlow {
                 let mut bottom_from_base = bottom_from_base;
 
                 // To calculate text-top and text-bottom value of 'vertical-align',
-                //  we should find the top and bottom of the content area of parent box.
+                // we use the top and bottom of the content area of the containing block.
                 // The content area is defined in:
                 //      http://www.w3.org/TR/CSS2/visudet.html#inline-non-replaced
                 //
-                // TODO: We should extract em-box info from the font size of the parent and
-                // calculate the distances from the baseline to the top and the bottom of the
-                // parent's content area.
-
-                // We should calculate the distance from baseline to the top of parent's content
-                // area. But for now we assume it's the font size.
-                //
-                // The spec does not state which font to use. Previous versions of the code used
-                // the parent's font; this code uses the current font.
-                let parent_text_top = cur_box.style().Font.get().font_size;
-
-                // We should calculate the distance from baseline to the bottom of the parent's
-                // content area. But for now we assume it's zero.
-                let parent_text_bottom = Au::new(0);
+                // FIXME: Nested inline elements should use the content area of their parent
+                // element rather than that of the containing block.
 
                 // Calculate a relative offset from the baseline.
                 //
@@ -802,8 +948,10 @@ impl Flow for InlineFlow {
                 let (offset, no_update_flag) =
                     InlineFlow::relative_offset_from_baseline(cur_box,
                                                               ascent,
-                                                              parent_text_top,
-                                                              parent_text_bottom,
+                                                              line_height,
+                                                              self.parent_text_top,
+                                                              self.parent_text_bottom,
+                                                              self.parent_x_height,
                                                               &mut top_from_base,
                                                               &mut bottom_from_base,
                                                               &mut biggest_top,
@@ -895,7 +1043,74 @@ impl Flow for InlineFlow {
         }
     }
 
+    fn dump_boxes(&self) -> ~[~str] {
+        self.boxes.iter().map(|box_| box_.dump_str()).collect()
+    }
+
+    fn heap_size(&self) -> uint {
+        let boxes_size = self.boxes.iter().map(|box_| {
+            mem::size_of::<Box>() + box_.heap_size_of_contents()
+        }).sum();
+        mem::size_of::<InlineFlow>() + boxes_size + self.lines.len() * mem::size_of::<LineBox>()
+    }
+
     fn debug_str(&self) -> ~str {
         ~"InlineFlow: " + self.boxes.map(|s| s.debug_str()).connect(", ")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{InlineMetrics, justification_per_opportunity};
+    use gfx::font::FontMetrics;
+    use servo_util::geometry::Au;
+    use style::computed_values::text_align;
+
+    fn font_metrics(ascent: Au, descent: Au) -> FontMetrics {
+        FontMetrics {
+            underline_size: Au(0),
+            underline_offset: Au(0),
+            strikeout_size: Au(0),
+            strikeout_offset: Au(0),
+            leading: Au(0),
+            x_height: Au(0),
+            em_size: ascent + descent,
+            ascent: ascent,
+            descent: descent,
+            max_advance: Au(0),
+        }
+    }
+
+    #[test]
+    fn test_justification_shares_the_slack() {
+        let slack = Au::from_px(30);
+        assert_eq!(justification_per_opportunity(slack, 3, text_align::justify, false),
+                   Au::from_px(10));
+        assert_eq!(justification_per_opportunity(slack, 0, text_align::justify, false), Au(0));
+    }
+
+    #[test]
+    fn test_no_justification_on_last_line_or_other_alignments() {
+        let slack = Au::from_px(30);
+        assert_eq!(justification_per_opportunity(slack, 3, text_align::justify, true), Au(0));
+        assert_eq!(justification_per_opportunity(slack, 3, text_align::left, false), Au(0));
+        assert_eq!(justification_per_opportunity(slack, 3, text_align::center, false), Au(0));
+    }
+
+    #[test]
+    fn test_leading_is_split_around_the_content_area() {
+        let metrics = font_metrics(Au::from_px(12), Au::from_px(4));
+        let inline_metrics = InlineMetrics::from_font_metrics(&metrics, Au::from_px(20));
+        assert_eq!(inline_metrics.height_above_baseline, Au::from_px(14));
+        assert_eq!(inline_metrics.depth_below_baseline, Au::from_px(6));
+        assert_eq!(inline_metrics.ascent, Au::from_px(12));
+    }
+
+    #[test]
+    fn test_line_height_smaller_than_the_content_area() {
+        let metrics = font_metrics(Au::from_px(12), Au::from_px(4));
+        let inline_metrics = InlineMetrics::from_font_metrics(&metrics, Au::from_px(10));
+        assert_eq!(inline_metrics.height_above_baseline, Au::from_px(9));
+        assert_eq!(inline_metrics.depth_below_baseline, Au::from_px(1));
+    }
+}
@@ -35,7 +35,7 @@ use script::dom::bindings::js::JS;
 use script::dom::event::ReflowEvent;
 use script::dom::node::{ElementNodeTypeId, LayoutDataRef, Node};
 use script::dom::element::{HTMLBodyElementTypeId, HTMLHtmlElementTypeId};
-use script::layout_interface::{AddStylesheetMsg, ContentBoxQuery};
+use script::layout_interface::{AddStylesheetMsg, CollectMemoryReportsMsg, ContentBoxQuery};
 use script::layout_interface::{ContentBoxesQuery, ContentBoxesResponse, ExitNowMsg, LayoutQuery};
 use script::layout_interface::{HitTestQuery, ContentBoxResponse, HitTestResponse, MouseOverQuery, MouseOverResponse};
 use script::layout_interface::{ContentChangedDocumentDamage, LayoutChan, Msg, PrepareToExitMsg};
@@ -46,6 +46,7 @@ use servo_msg::constellation_msg::{ConstellationChan, PipelineId, Failure, Failu
 use servo_net::image_cache_task::{ImageCacheTask, ImageResponseMsg};
 use servo_net::local_image_cache::{ImageResponder, LocalImageCache};
 use servo_util::geometry::Au;
+use servo_util::memory::{MemoryReport, MemoryReportsChan};
 use servo_util::opts::Opts;
 use servo_util::time::{ProfilerChan, profile};
 use servo_util::time;
@@ -55,6 +56,8 @@ use std::cast::transmute;
 use std::cast;
 use std::cell::RefCell;
 use std::comm::Port;
+use std::io::File;
+use std::io;
 use std::ptr;
 use std::task;
 use std::util;
@@ -90,11 +93,18 @@ pub struct LayoutTask {
     /// The size of the viewport.
     screen_size: Size2D<Au>,
 
+    /// The number of device pixels that each CSS pixel is drawn at.
+    device_pixel_ratio: f32,
+
     /// A cached display list.
     display_list_collection: Option<Arc<DisplayListCollection<OpaqueNode>>>,
 
     stylist: ~Stylist,
 
+    /// The estimated heap size of the flow tree built by the last reflow, if the memory profiler
+    /// is enabled. The flow tree is destroyed at the end of each reflow, so it is measured then.
+    flow_tree_size: uint,
+
     /// The initial set of CSS values.
     initial_css_values: Arc<ComputedValues>,
 
@@ -304,9 +314,11 @@ impl LayoutTask {
             image_cache_task: image_cache_task.clone(),
             local_image_cache: local_image_cache,
             screen_size: screen_size,
+            device_pixel_ratio: 1.0,
 
             display_list_collection: None,
             stylist: ~new_stylist(),
+            flow_tree_size: 0,
             initial_css_values: Arc::new(style::initial_values()),
             parallel_traversal: parallel_traversal,
             profiler_chan: profiler_chan,
@@ -332,6 +344,7 @@ impl LayoutTask {
         LayoutContext {
             image_cache: self.local_image_cache.clone(),
             screen_size: self.screen_size.clone(),
+            device_pixel_ratio: self.device_pixel_ratio,
             constellation_chan: self.constellation_chan.clone(),
             layout_chan: self.chan.clone(),
             font_context_info: font_context_info,
@@ -363,6 +376,9 @@ impl LayoutTask {
                     self.handle_reap_layout_data(dead_layout_data)
                 }
             }
+            CollectMemoryReportsMsg(reports_chan) => {
+                self.handle_collect_memory_reports(reports_chan)
+            }
             PrepareToExitMsg(response_chan) => {
                 debug!("layout: PrepareToExitMsg received");
                 self.prepare_to_exit(response_chan);
@@ -378,6 +394,15 @@ impl LayoutTask {
         true
     }
 
+    /// Measures the flow tree of the last reflow and the selector maps for the memory profiler.
+    fn handle_collect_memory_reports(&self, reports_chan: MemoryReportsChan) {
+        let PipelineId(id) = self.id;
+        reports_chan.try_send(~[
+            MemoryReport::new(format!("pipeline-{}/layout/flow-tree", id), self.flow_tree_size),
+            MemoryReport::new(format!("pipeline-{}/layout/stylist", id), self.stylist.heap_size()),
+        ]);
+    }
+
     /// Enters a quiescent state in which no new messages except for `ReapLayoutDataMsg` will be
     /// processed until an `ExitNowMsg` is received. A pong is immediately sent on the given
     /// response channel.
@@ -390,6 +415,9 @@ impl LayoutTask {
                         self.handle_reap_layout_data(dead_layout_data)
                     }
                 }
+                // The memory profiler may not have heard that we are exiting yet. Dropping the
+                // channel tells it that there is nothing to report.
+                CollectMemoryReportsMsg(_) => {}
                 ExitNowMsg => {
                     debug!("layout task is exiting...");
                     self.exit_now();
@@ -525,6 +553,23 @@ impl LayoutTask {
     fn verify_flow_tree(&mut self, _: &mut ~Flow) {
     }
 
+    /// Writes a flow tree or display list dump to the file given with `--dump-file`, or to stdout
+    /// if there is none.
+    fn write_layout_dump(&self, title: &str, url: &Url, dump: &str) {
+        let output = format!("{} for {}\n{}", title, url.to_str(), dump);
+        match self.opts.dump_file {
+            None => print(output),
+            Some(ref path) => {
+                // ignore_io_error causes us to get None instead of a task failure.
+                let _guard = io::ignore_io_error();
+                match File::open_mode(&Path::new(path.as_slice()), io::Append, io::Write) {
+                    Some(ref mut file) => file.write(output.as_bytes()),
+                    None => error!("layout: couldn't open the dump file {:s}", *path),
+                }
+            }
+        }
+    }
+
     /// The high-level routine that performs layout tasks.
     fn handle_reflow(&mut self, data: &Reflow) {
         // FIXME: Isolate this transmutation into a "bridge" module.
@@ -559,6 +604,12 @@ impl LayoutTask {
         }
         self.screen_size = current_screen_size;
 
+        // Images are decoded at the size they are drawn at, so they have to be laid out again.
+        if self.device_pixel_ratio != data.device_pixel_ratio {
+            all_style_damage = true
+        }
+        self.device_pixel_ratio = data.device_pixel_ratio;
+
         // Create a layout context for use throughout the following passes.
         let mut layout_ctx = self.build_layout_context(node, &data.url);
 
@@ -590,7 +641,10 @@ impl LayoutTask {
                                                        None))
                 }
                 Some(ref mut traversal) => {
-                    parallel::recalc_style_for_subtree(node, &mut layout_ctx, traversal)
+                    parallel::recalc_style_for_subtree(node,
+                                                       self.profiler_chan.clone(),
+                                                       &mut layout_ctx,
+                                                       traversal)
                 }
             }
 
@@ -625,6 +679,14 @@ impl LayoutTask {
             }
         });
 
+        if self.opts.dump_flow_tree {
+            self.write_layout_dump("Flow tree", &data.url, layout_root.dump_to_str());
+        }
+
+        if self.opts.memory_profiler_period.is_some() {
+            self.flow_tree_size = layout_root.heap_size_of_tree();
+        }
+
         // Build the display list if necessary, and send it to the renderer.
         if data.goal == ReflowForDisplay {
             profile(time::LayoutDispListBuildCategory, self.profiler_chan.clone(), || {
@@ -641,7 +703,14 @@ impl LayoutTask {
                                                 root_abs_position,
                                                 &dirty, 0u, display_list_collection);
 
-                let display_list_collection = Arc::new(display_list_collection.unwrap());
+                let mut display_list_collection = display_list_collection.unwrap();
+                display_list_collection.build_indices();
+                if self.opts.dump_display_list {
+                    self.write_layout_dump("Display list",
+                                           &data.url,
+                                           display_list_collection.dump_to_str());
+                }
+                let display_list_collection = Arc::new(display_list_collection);
 
                 let mut color = color::rgba(255.0, 255.0, 255.0, 255.0);
 
@@ -746,12 +815,14 @@ impl LayoutTask {
                 reply_chan.send(ContentBoxesResponse(boxes))
             }
             HitTestQuery(_, point, reply_chan) => {
-                fn hit_test(x: Au, y: Au, list: &[DisplayItem<OpaqueNode>])
+                fn hit_test(x: Au, y: Au, list: &[&DisplayItem<OpaqueNode>])
                             -> Option<HitTestResponse> {
                     for item in list.rev_iter() {
-                        match *item {
+                        match **item {
                             ClipDisplayItemClass(ref cc) => {
-                                let ret = hit_test(x, y, cc.child_list);
+                                let children: ~[&DisplayItem<OpaqueNode>] =
+                                    cc.child_list.iter().collect();
+                                let ret = hit_test(x, y, children);
                                 if !ret.is_none() {
                                     return ret;
                                 }
@@ -761,7 +832,7 @@ impl LayoutTask {
                     }
 
                     for item in list.rev_iter() {
-                        match *item {
+                        match **item {
                             ClipDisplayItemClass(_) => continue,
                             _ => {}
                         }
@@ -781,10 +852,11 @@ impl LayoutTask {
                     let ret: Option<HitTestResponse> = None;
                     ret
                 }
+                let (x, y) = (Au::from_frac_px(point.x as f64),
+                              Au::from_frac_px(point.y as f64));
+                let point_rect = Rect(Point2D(x, y), Size2D(Au(1), Au(1)));
                 for display_list in self.display_list_collection.as_ref().unwrap().get().lists.rev_iter() {
-                    let (x, y) = (Au::from_frac_px(point.x as f64),
-                                  Au::from_frac_px(point.y as f64));
-                    let resp = hit_test(x,y,display_list.list);
+                    let resp = hit_test(x, y, display_list.items_intersecting(&point_rect));
                     if resp.is_some() {
                         reply_chan.send(Ok(resp.unwrap()));
                         return
@@ -794,11 +866,13 @@ impl LayoutTask {
 
             }
             MouseOverQuery(_, point, reply_chan) => {
-                fn mouse_over_test(x: Au, y: Au, list: &[DisplayItem<OpaqueNode>], result: &mut ~[UntrustedNodeAddress]) {
+                fn mouse_over_test(x: Au, y: Au, list: &[&DisplayItem<OpaqueNode>], result: &mut ~[UntrustedNodeAddress]) {
                     for item in list.rev_iter() {
-                        match *item {
+                        match **item {
                             ClipDisplayItemClass(ref cc) => {
-                                mouse_over_test(x, y, cc.child_list, result);
+                                let children: ~[&DisplayItem<OpaqueNode>] =
+                                    cc.child_list.iter().collect();
+                                mouse_over_test(x, y, children, result);
                             }
                             _ => {}
                         }
@@ -821,10 +895,14 @@ impl LayoutTask {
                 }
 
                 let mut mouse_over_list:~[UntrustedNodeAddress] = ~[];
+                let (x, y) = (Au::from_frac_px(point.x as f64),
+                              Au::from_frac_px(point.y as f64));
+                let point_rect = Rect(Point2D(x, y), Size2D(Au(1), Au(1)));
                 for display_list in self.display_list_collection.as_ref().unwrap().get().lists.rev_iter() {
-                    let (x, y) = (Au::from_frac_px(point.x as f64),
-                                  Au::from_frac_px(point.y as f64));
-                    mouse_over_test(x,y,display_list.list, &mut mouse_over_list);
+                    mouse_over_test(x,
+                                    y,
+                                    display_list.items_intersecting(&point_rect),
+                                    &mut mouse_over_list);
                 }
 
                 if mouse_over_list.is_empty() {
@@ -403,6 +403,7 @@ fn assign_heights_and_store_overflow(unsafe_flow: PaddedUnsafeFlow,
 }
 
 pub fn recalc_style_for_subtree(root_node: &LayoutNode,
+                                profiler_chan: ProfilerChan,
                                 layout_context: &mut LayoutContext,
                                 queue: &mut WorkQueue<*mut LayoutContext,UnsafeLayoutNode>) {
     unsafe {
@@ -415,7 +416,7 @@ pub fn recalc_style_for_subtree(root_node: &LayoutNode,
         data: layout_node_to_unsafe_layout_node(root_node),
     });
 
-    queue.run();
+    queue.run_traced(time::LayoutStyleRecalcCategory, &profiler_chan);
 
     queue.data = ptr::mut_null()
 }
@@ -428,14 +429,14 @@ pub fn traverse_flow_tree_preorder(root: &mut ~Flow,
         queue.data = cast::transmute(layout_context)
     }
 
-    profile(time::LayoutParallelWarmupCategory, profiler_chan, || {
+    profile(time::LayoutParallelWarmupCategory, profiler_chan.clone(), || {
         queue.push(WorkUnit {
             fun: assign_widths,
             data: UnsafeFlowConversions::from_flow(&mut_owned_flow_to_unsafe_flow(root)),
         })
     });
 
-    queue.run();
+    queue.run_traced(time::LayoutMainCategory, &profiler_chan);
 
     queue.data = ptr::mut_null()
 }
@@ -8,12 +8,23 @@ use layout::box_::{Box, ScannedTextBox, ScannedTextBoxInfo, UnscannedTextBox};
 use layout::flow::Flow;
 
 use extra::arc::Arc;
+use gfx::font::{FontMetrics, FontStyle};
 use gfx::font_context::FontContext;
+use gfx::text::bidi::Level;
+use gfx::text::bidi;
 use gfx::text::text_run::TextRun;
 use gfx::text::util::{CompressWhitespaceNewline, transform_text, CompressNone};
+use servo_util::geometry::Au;
 use servo_util::range::Range;
+use std::char;
+use std::str;
 use std::vec;
-use style::computed_values::white_space;
+use style::ComputedValues;
+use style::computed_values::{direction, font_family, font_variant, line_height, text_transform};
+use style::computed_values::{unicode_bidi, white_space};
+
+/// The size of synthesized small capitals relative to the font size of the element.
+static SMALL_CAPS_SCALE_FACTOR: f64 = 0.7;
 
 /// A stack-allocated object for scanning an inline flow into `TextRun`-containing `TextBox`es.
 pub struct TextRunScanner {
@@ -35,11 +46,13 @@ impl TextRunScanner {
 
         let mut last_whitespace = true;
         let mut out_boxes = ~[];
+        let paragraph_level = flow.as_immutable_inline().paragraph_level;
         for box_i in range(0, flow.as_immutable_inline().boxes.len()) {
             debug!("TextRunScanner: considering box: {:u}", box_i);
             if box_i > 0 && !can_coalesce_text_nodes(flow.as_immutable_inline().boxes,
                                                      box_i - 1,
-                                                     box_i) {
+                                                     box_i,
+                                                     paragraph_level) {
                 last_whitespace = self.flush_clump_to_list(font_context,
                                                            flow,
                                                            last_whitespace,
@@ -57,12 +70,19 @@ impl TextRunScanner {
         // Swap out the old and new box list of the flow.
         flow.as_inline().boxes = out_boxes;
 
-        // A helper function.
-        fn can_coalesce_text_nodes(boxes: &[Box], left_i: uint, right_i: uint) -> bool {
+        // A helper function. Only text that is entirely left-to-right is coalesced, since the
+        // resulting text run is shaped in a single direction.
+        fn can_coalesce_text_nodes(boxes: &[Box],
+                                   left_i: uint,
+                                   right_i: uint,
+                                   paragraph_level: Level)
+                                   -> bool {
             assert!(left_i < boxes.len());
             assert!(right_i > 0 && right_i < boxes.len());
             assert!(left_i != right_i);
-            boxes[left_i].can_merge_with_box(&boxes[right_i])
+            boxes[left_i].can_merge_with_box(&boxes[right_i]) &&
+                is_unidirectional_ltr(&boxes[left_i], paragraph_level) &&
+                is_unidirectional_ltr(&boxes[right_i], paragraph_level)
         }
     }
 
@@ -82,6 +102,7 @@ impl TextRunScanner {
                                out_boxes: &mut ~[Box])
                                -> bool {
         let inline = flow.as_inline();
+        let paragraph_level = inline.paragraph_level;
         let in_boxes = &mut inline.boxes;
 
         assert!(self.clump.length() > 0);
@@ -114,6 +135,7 @@ impl TextRunScanner {
 
                 let font_style = old_box.font_style();
                 let decoration = old_box.text_decoration();
+                let shaping_options = old_box.shaping_options();
 
                 // TODO(#115): Use the actual CSS `white-space` property of the relevant style.
                 let compression = match old_box.white_space() {
@@ -128,26 +150,83 @@ impl TextRunScanner {
                                                                     last_whitespace,
                                                                     &mut new_line_pos);
 
+                let transformed_text = apply_text_transform(transformed_text,
+                                                            old_box.text_transform(),
+                                                            last_whitespace);
                 new_whitespace = whitespace;
 
                 if transformed_text.len() > 0 {
-                    // TODO(#177): Text run creation must account for the renderability of text by
-                    // font group fonts. This is probably achieved by creating the font group above
-                    // and then letting `FontGroup` decide which `Font` to stick into the text run.
-                    let fontgroup = font_context.get_resolved_font_for_style(&font_style);
-                    let run = ~fontgroup.borrow().with(|fg| fg.create_textrun(transformed_text.clone(), decoration));
-
-                    debug!("TextRunScanner: pushing single text box in range: {} ({})",
-                           self.clump,
-                           *text);
-                    let range = Range::new(0, run.char_len());
-                    let new_metrics = run.metrics_for_range(&range);
-                    let new_text_box_info = ScannedTextBoxInfo::new(Arc::new(run), range);
-                    let mut new_box = old_box.transform(new_metrics.bounding_box.size,
-                                                    ScannedTextBox(new_text_box_info));
-                    new_box.new_line_pos = new_line_pos;
-                    out_boxes.push(new_box)
+                    // Resolve the bidirectional embedding levels of the text.
+                    let (embedding_level, is_override) = bidi_embedding_for_box(old_box,
+                                                                                paragraph_level);
+                    let levels = if is_override {
+                        vec::from_elem(transformed_text.char_len(), embedding_level)
+                    } else {
+                        bidi::resolve_levels(transformed_text, embedding_level)
+                    };
 
+                    // Synthesized small caps need one run per font size, and each directional
+                    // level needs its own run, so the box may be split into several boxes here.
+                    let small_caps = old_box.font_variant() == font_variant::small_caps;
+                    let segments = split_into_segments(transformed_text, small_caps, levels);
+
+                    // Each segment may be split further wherever its text needs a fallback font.
+                    let mut runs = ~[];
+                    for segment in segments.move_iter() {
+                        let TextSegment {
+                            text: segment_text,
+                            is_small: is_small,
+                            level: level,
+                        } = segment;
+
+                        let mut segment_options = shaping_options.clone();
+                        segment_options.rtl = bidi::is_rtl(level);
+
+                        let mut segment_font_style = font_style.clone();
+                        if is_small {
+                            segment_font_style.pt_size = segment_font_style.pt_size *
+                                SMALL_CAPS_SCALE_FACTOR;
+                        }
+
+                        let fontgroup =
+                            font_context.get_resolved_font_for_style(&segment_font_style);
+                        let segment_runs = fontgroup.borrow().with(|fg| {
+                            fg.create_textruns(font_context,
+                                               segment_text.as_slice(),
+                                               decoration,
+                                               &segment_options)
+                        });
+                        for run in segment_runs.move_iter() {
+                            runs.push((run, level));
+                        }
+                    }
+
+                    let run_count = runs.len();
+                    for (i, (run, level)) in runs.move_iter().enumerate() {
+                        debug!("TextRunScanner: pushing single text box in range: {} ({})",
+                               self.clump,
+                               *text);
+                        let run_new_line_pos = if run_count == 1 {
+                            new_line_pos.clone()
+                        } else {
+                            compute_new_line_positions(run.text.get().as_slice())
+                        };
+                        let range = Range::new(0, run.char_len());
+                        let new_metrics = run.metrics_for_range(&range);
+                        let new_text_box_info = ScannedTextBoxInfo::new(Arc::new(~run),
+                                                                        range,
+                                                                        level);
+                        let mut new_box = old_box.transform(new_metrics.bounding_box.size,
+                                                            ScannedTextBox(new_text_box_info));
+                        new_box.new_line_pos = run_new_line_pos;
+                        if i > 0 {
+                            new_box.clear_noncontent_inline_left();
+                        }
+                        if i + 1 < run_count {
+                            new_box.clear_noncontent_inline_right();
+                        }
+                        out_boxes.push(new_box)
+                    }
                 } else {
                     if self.clump.begin() + 1 < in_boxes.len() {
                         // if the this box has border,margin,padding of inline,
@@ -158,13 +237,12 @@ impl TextRunScanner {
                 }
             },
             (false, true) => {
-                // TODO(#177): Text run creation must account for the renderability of text by
-                // font group fonts. This is probably achieved by creating the font group above
-                // and then letting `FontGroup` decide which `Font` to stick into the text run.
                 let in_box = &in_boxes[self.clump.begin()];
                 let font_style = in_box.font_style();
                 let fontgroup = font_context.get_resolved_font_for_style(&font_style);
                 let decoration = in_box.text_decoration();
+                let shaping_options = in_box.shaping_options();
+                let text_transform = in_box.text_transform();
 
                 // TODO(#115): Use the actual CSS `white-space` property of the relevant style.
                 let compression = match in_box.white_space() {
@@ -198,6 +276,9 @@ impl TextRunScanner {
                                                                    &mut new_line_pos);
                     new_line_positions.push(NewLinePositions { new_line_pos: new_line_pos });
 
+                    let new_str = apply_text_transform(new_str,
+                                                       text_transform,
+                                                       last_whitespace_in_clump);
                     last_whitespace_in_clump = new_whitespace;
                     new_str
                 });
@@ -215,21 +296,33 @@ impl TextRunScanner {
                     char_total += added_chars;
                 }
 
-                // Now create the run.
+                // Now create the runs. The text is split into several runs if it needs fallback
+                // fonts.
                 // TextRuns contain a cycle which is usually resolved by the teardown
                 // sequence. If no clump takes ownership, however, it will leak.
                 let clump = self.clump;
-                let run = if clump.length() != 0 && run_str.len() > 0 {
-                    fontgroup.borrow().with(|fg| {
-                        fg.fonts[0].borrow().with_mut(|font| {
-                            Some(Arc::new(~TextRun::new(font, run_str.clone(), decoration)))
-                        })
-                    })
+                let runs: ~[Arc<~TextRun>] = if clump.length() != 0 && run_str.len() > 0 {
+                    let runs = fontgroup.borrow().with(|fg| {
+                        fg.create_textruns(font_context,
+                                           run_str.as_slice(),
+                                           decoration,
+                                           &shaping_options)
+                    });
+                    runs.move_iter().map(|run| Arc::new(~run)).collect()
                 } else {
-                    None
+                    ~[]
                 };
 
-                // Make new boxes with the run and adjusted text indices.
+                // The range of characters of the concatenated text covered by each run.
+                let mut run_ranges: ~[Range] = ~[];
+                let mut run_char_total = 0;
+                for run in runs.iter() {
+                    let run_char_len = run.get().char_len();
+                    run_ranges.push(Range::new(run_char_total, run_char_len));
+                    run_char_total += run_char_len;
+                }
+
+                // Make new boxes with the runs and adjusted text indices.
                 debug!("TextRunScanner: pushing box(es) in range: {}", self.clump);
                 for i in clump.eachi() {
                     let logical_offset = i - self.clump.begin();
@@ -246,12 +339,47 @@ impl TextRunScanner {
                         continue
                     }
 
-                    let new_text_box_info = ScannedTextBoxInfo::new(run.get_ref().clone(), range);
-                    let new_metrics = new_text_box_info.run.get().metrics_for_range(&range);
-                    let mut new_box = in_boxes[i].transform(new_metrics.bounding_box.size,
-                                                        ScannedTextBox(new_text_box_info));
-                    new_box.new_line_pos = new_line_positions[logical_offset].new_line_pos.clone();
-                    out_boxes.push(new_box)
+                    // A box whose text spans a font boundary becomes one box per run.
+                    let pieces: ~[(uint, Range)] = run_ranges.iter().enumerate().filter_map(
+                            |(run_index, run_range)| {
+                        let piece_range = range.intersect(run_range);
+                        if piece_range.is_empty() {
+                            None
+                        } else {
+                            Some((run_index, piece_range))
+                        }
+                    }).collect();
+                    let piece_count = pieces.len();
+
+                    for (j, &(run_index, piece_range)) in pieces.iter().enumerate() {
+                        let run = &runs[run_index];
+                        let mut run_range = piece_range;
+                        run_range.shift_by(-(run_ranges[run_index].begin() as int));
+
+                        // Coalesced text is always unidirectional left-to-right at the paragraph
+                        // level.
+                        let new_text_box_info = ScannedTextBoxInfo::new(run.clone(),
+                                                                        run_range,
+                                                                        paragraph_level);
+                        let new_metrics = run.get().metrics_for_range(&run_range);
+                        let mut new_box = in_boxes[i].transform(new_metrics.bounding_box.size,
+                                                            ScannedTextBox(new_text_box_info));
+                        if piece_count == 1 {
+                            new_box.new_line_pos =
+                                new_line_positions[logical_offset].new_line_pos.clone();
+                        } else {
+                            let piece_text = run.get().text.get().slice_chars(run_range.begin(),
+                                                                              run_range.end());
+                            new_box.new_line_pos = compute_new_line_positions(piece_text);
+                            if j > 0 {
+                                new_box.clear_noncontent_inline_left();
+                            }
+                            if j + 1 < piece_count {
+                                new_box.clear_noncontent_inline_right();
+                            }
+                        }
+                        out_boxes.push(new_box)
+                    }
                 }
             }
         } // End of match.
@@ -280,3 +408,221 @@ impl TextRunScanner {
         new_whitespace
     } // End of `flush_clump_to_list`.
 }
+
+/// Applies the CSS `text-transform` property to whitespace-compressed text. `starts_word` is true
+/// if the text begins a new word, which matters for `capitalize`.
+///
+/// Case mapping is done per character, so the character count (and hence new-line positions) is
+/// preserved.
+fn apply_text_transform(text: ~str, transform: text_transform::T, starts_word: bool) -> ~str {
+    match transform {
+        text_transform::none => text,
+        text_transform::uppercase => text.chars().map(|ch| char::to_uppercase(ch)).collect(),
+        text_transform::lowercase => text.chars().map(|ch| char::to_lowercase(ch)).collect(),
+        text_transform::capitalize => {
+            let mut out_str = str::with_capacity(text.len());
+            let mut at_word_start = starts_word;
+            for ch in text.chars() {
+                if ch.is_whitespace() {
+                    at_word_start = true;
+                    out_str.push_char(ch);
+                } else if ch.is_alphanumeric() {
+                    // The first letter of each word is uppercased; punctuation before it is
+                    // skipped over.
+                    out_str.push_char(if at_word_start { char::to_uppercase(ch) } else { ch });
+                    at_word_start = false;
+                } else {
+                    out_str.push_char(ch);
+                }
+            }
+            out_str
+        }
+    }
+}
+
+/// A piece of text that can be shaped as a single run.
+struct TextSegment {
+    text: ~str,
+    /// True if this segment consists of lowercase letters that are rendered as synthesized small
+    /// capitals.
+    is_small: bool,
+    /// The bidirectional embedding level of this segment.
+    level: Level,
+}
+
+/// Splits text into segments at changes of bidirectional level and, for synthesized
+/// `font-variant: small-caps`, at changes of case. Lowercase letters of small-caps text are
+/// uppercased and flagged so that they can be rendered at a reduced size.
+fn split_into_segments(text: &str, small_caps: bool, levels: &[Level]) -> ~[TextSegment] {
+    let mut segments = ~[];
+    let mut current = ~"";
+    let (mut current_is_small, mut current_level) = (false, 0);
+    for (i, ch) in text.chars().enumerate() {
+        let is_small = small_caps && ch.is_lowercase();
+        let level = levels[i];
+        if (is_small != current_is_small || level != current_level) && current.len() > 0 {
+            segments.push(TextSegment {
+                text: current,
+                is_small: current_is_small,
+                level: current_level,
+            });
+            current = ~"";
+        }
+        current_is_small = is_small;
+        current_level = level;
+        current.push_char(if is_small { char::to_uppercase(ch) } else { ch });
+    }
+    if current.len() > 0 {
+        segments.push(TextSegment {
+            text: current,
+            is_small: current_is_small,
+            level: current_level,
+        });
+    }
+    segments
+}
+
+/// Returns the embedding level of the text in the given box, and whether its direction is
+/// overridden, by applying the `unicode-bidi` values of the inline elements that contain it
+/// (outermost first) to the level of the paragraph.
+fn bidi_embedding_for_box(box_: &Box, paragraph_level: Level) -> (Level, bool) {
+    let mut level = paragraph_level;
+    let mut is_override = false;
+    box_.inline_info.with(|info| {
+        for info in info.iter() {
+            for parent_info in info.parent_info.rev_iter() {
+                let style = parent_info.style.get();
+                let rtl = style.InheritedBox.get().direction == direction::rtl;
+                match style.Text.get().unicode_bidi {
+                    unicode_bidi::normal => {}
+                    unicode_bidi::embed => {
+                        level = bidi::embedding_level(level, rtl);
+                        is_override = false;
+                    }
+                    unicode_bidi::bidi_override => {
+                        level = bidi::embedding_level(level, rtl);
+                        is_override = true;
+                    }
+                }
+            }
+        }
+    });
+    (level, is_override)
+}
+
+/// Returns true if the given box is not text, or is text that resolves entirely to a
+/// left-to-right paragraph level.
+fn is_unidirectional_ltr(box_: &Box, paragraph_level: Level) -> bool {
+    match box_.specific {
+        UnscannedTextBox(ref text_box_info) => {
+            !bidi::is_rtl(paragraph_level) &&
+                bidi_embedding_for_box(box_, paragraph_level) == (paragraph_level, false) &&
+                !bidi::has_rtl_characters(text_box_info.text)
+        }
+        _ => true,
+    }
+}
+
+/// Computes the relative new-line positions of a piece of already-transformed text, in the same
+/// format that `transform_text` produces.
+fn compute_new_line_positions(text: &str) -> ~[uint] {
+    let mut new_line_pos = ~[];
+    let mut new_line_index = 0;
+    for ch in text.chars() {
+        if ch == '\n' {
+            new_line_pos.push(new_line_index);
+            new_line_index = 0;
+        } else {
+            new_line_index += 1;
+        }
+    }
+    new_line_pos
+}
+
+/// Converts the computed font properties of the given style into a `FontStyle` suitable for font
+/// selection.
+pub fn computed_style_to_font_style(style: &ComputedValues) -> FontStyle {
+    debug!("(font style) start");
+
+    // FIXME: Too much allocation here.
+    let font_families = style.Font.get().font_family.map(|family| {
+        match *family {
+            font_family::FamilyName(ref name) => (*name).clone(),
+        }
+    });
+    debug!("(font style) font families: `{:?}`", font_families);
+
+    let font_size = style.Font.get().font_size.to_f64().unwrap() / 60.0;
+    debug!("(font style) font size: `{:f}px`", font_size);
+
+    FontStyle {
+        pt_size: font_size,
+        weight: style.Font.get().font_weight,
+        style: style.Font.get().font_style,
+        families: font_families,
+    }
+}
+
+/// Returns the metrics of the first available font for the given style.
+pub fn font_metrics_for_style(font_context: &mut FontContext, font_style: &FontStyle)
+                              -> FontMetrics {
+    let fontgroup = font_context.get_resolved_font_for_style(font_style);
+    fontgroup.borrow().with(|fontgroup| {
+        fontgroup.fonts[0].borrow().with(|font| font.metrics.clone())
+    })
+}
+
+/// Returns the used `line-height` for the given style. `normal` uses the ascent, descent, and
+/// line gap of the first available font, per CSS 2.1 § 10.8.1.
+pub fn line_height_from_style(style: &ComputedValues, metrics: &FontMetrics) -> Au {
+    let font_size = style.Font.get().font_size;
+    match style.InheritedBox.get().line_height {
+        line_height::Normal => metrics.ascent + metrics.descent + metrics.leading,
+        line_height::Number(l) => font_size.scale_by(l),
+        line_height::Length(l) => l,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_text_transform, split_into_segments};
+    use style::computed_values::text_transform;
+
+    #[test]
+    fn test_uppercase_and_lowercase() {
+        assert_eq!(apply_text_transform(~"Foo bar", text_transform::uppercase, true), ~"FOO BAR");
+        assert_eq!(apply_text_transform(~"Foo BAR", text_transform::lowercase, true), ~"foo bar");
+        assert_eq!(apply_text_transform(~"Foo bar", text_transform::none, true), ~"Foo bar");
+    }
+
+    #[test]
+    fn test_capitalize() {
+        assert_eq!(apply_text_transform(~"foo bar\nbaz", text_transform::capitalize, true),
+                   ~"Foo Bar\nBaz");
+        // Punctuation before the first letter of a word is skipped over.
+        assert_eq!(apply_text_transform(~"(foo) \"bar\"", text_transform::capitalize, true),
+                   ~"(Foo) \"Bar\"");
+        // Text that continues a word from the previous box is not capitalized.
+        assert_eq!(apply_text_transform(~"oo bar", text_transform::capitalize, false),
+                   ~"oo Bar");
+    }
+
+    #[test]
+    fn test_split_small_caps() {
+        let segments = split_into_segments("Foo BAR", true, [0, 0, 0, 0, 0, 0, 0]);
+        let texts: ~[~str] = segments.iter().map(|segment| segment.text.clone()).collect();
+        let smalls: ~[bool] = segments.iter().map(|segment| segment.is_small).collect();
+        assert_eq!(texts, ~[~"F", ~"OO", ~" BAR"]);
+        assert_eq!(smalls, ~[false, true, false]);
+    }
+
+    #[test]
+    fn test_split_at_level_changes() {
+        let segments = split_into_segments("ab \u05d0\u05d1", false, [0, 0, 0, 1, 1]);
+        let texts: ~[~str] = segments.iter().map(|segment| segment.text.clone()).collect();
+        let levels: ~[u8] = segments.iter().map(|segment| segment.level).collect();
+        assert_eq!(texts, ~[~"ab ", ~"\u05d0\u05d1"]);
+        assert_eq!(levels, ~[0, 1]);
+        assert!(segments.iter().all(|segment| !segment.is_small));
+    }
+}
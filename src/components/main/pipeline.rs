@@ -17,6 +17,7 @@ use script::script_task;
 use servo_msg::constellation_msg::{ConstellationChan, Failure, PipelineId, SubpageId};
 use servo_net::image_cache_task::ImageCacheTask;
 use servo_net::resource_task::ResourceTask;
+use servo_net::storage_task::StorageTask;
 use servo_util::opts::Opts;
 use servo_util::time::ProfilerChan;
 use std::cell::RefCell;
@@ -111,6 +112,7 @@ impl Pipeline {
                   compositor_chan: CompositorChan,
                   image_cache_task: ImageCacheTask,
                   resource_task: ResourceTask,
+                  storage_task: StorageTask,
                   profiler_chan: ProfilerChan,
                   window_size: Size2D<uint>,
                   opts: Opts)
@@ -142,6 +144,7 @@ impl Pipeline {
                            failure.clone(),
                            resource_task,
                            image_cache_task.clone(),
+                           storage_task,
                            window_size);
 
         RenderTask::create(id,
@@ -8,7 +8,7 @@ use windowing::{ApplicationMethods, WindowEvent, WindowMethods};
 use windowing::{IdleWindowEvent, ResizeWindowEvent, LoadUrlWindowEvent, MouseWindowEventClass,  MouseWindowMoveEventClass};
 use windowing::{ScrollWindowEvent, ZoomWindowEvent, NavigationWindowEvent, FinishedWindowEvent};
 use windowing::{QuitWindowEvent, MouseWindowClickEvent, MouseWindowMouseDownEvent, MouseWindowMouseUpEvent};
-use windowing::RefreshWindowEvent;
+use windowing::{PrintMemoryReportsWindowEvent, RefreshWindowEvent};
 use windowing::{Forward, Back};
 
 use alert::{Alert, AlertMethods};
@@ -279,6 +279,9 @@ impl Window {
         match key {
             glfw::KeyEscape => self.glfw_window.set_should_close(true),
             glfw::KeyL if mods.contains(glfw::Control) => self.load_url(), // Ctrl+L
+            glfw::KeyM if mods.contains(glfw::Control) => { // Ctrl+M
+                self.event_queue.with_mut(|queue| queue.push(PrintMemoryReportsWindowEvent));
+            }
             glfw::KeyEqual if mods.contains(glfw::Control) => { // Ctrl-+
                 self.event_queue.with_mut(|queue| queue.push(ZoomWindowEvent(1.1)));
             }
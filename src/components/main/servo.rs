@@ -47,10 +47,14 @@ use constellation::Constellation;
 use servo_msg::constellation_msg::InitLoadUrlMsg;
 
 #[cfg(not(test))]
-use servo_net::image_cache_task::{ImageCacheTask, SyncImageCacheTask};
+use servo_net::image_cache_task::{ImageCacheTask, SetByteBudget, SyncImageCacheTask};
 #[cfg(not(test))]
 use servo_net::resource_task::ResourceTask;
 #[cfg(not(test))]
+use servo_net::storage_task::StorageTask;
+#[cfg(not(test))]
+use servo_util::memory::{MemoryProfiler, MemoryReporter, RegisterReporterMsg};
+#[cfg(not(test))]
 use servo_util::time::Profiler;
 
 #[cfg(not(test))]
@@ -82,6 +86,7 @@ pub mod css {
 }
 
 pub mod constellation;
+pub mod devtools;
 pub mod pipeline;
 
 pub mod layout {
@@ -140,10 +145,14 @@ fn run(opts: opts::Opts) {
     let mut pool = green::SchedPool::new(green::PoolConfig::new());
 
     let (compositor_port, compositor_chan) = CompositorChan::new();
-    let profiler_chan = Profiler::create(opts.profiler_period);
+    let profiler_chan = Profiler::create(opts.profiler_period, opts.profiler_trace_file.clone());
+    let memory_profiler_chan = MemoryProfiler::create(opts.memory_profiler_period);
+    let compositor_reporter = ~compositor_chan.clone() as ~MemoryReporter:Send;
+    memory_profiler_chan.send(RegisterReporterMsg(~"compositor", compositor_reporter));
 
     let opts_clone = opts.clone();
     let profiler_chan_clone = profiler_chan.clone();
+    let memory_profiler_chan_clone = memory_profiler_chan.clone();
 
     let (result_port, result_chan) = Chan::new();
     pool.spawn(TaskOpts::new(), proc() {
@@ -158,11 +167,18 @@ fn run(opts: opts::Opts) {
             } else {
                 ImageCacheTask(resource_task.clone())
             };
+        image_cache_task.send(SetByteBudget(Some(opts.image_cache_size)));
+        let image_cache_reporter = ~image_cache_task.clone() as ~MemoryReporter:Send;
+        memory_profiler_chan_clone.send(RegisterReporterMsg(~"image-cache", image_cache_reporter));
+        let local_storage_dir = opts.local_storage_dir.as_ref().map(|dir| Path::new(dir.as_slice()));
+        let storage_task = StorageTask(local_storage_dir);
         let constellation_chan = Constellation::start(compositor_chan,
                                                       opts,
                                                       resource_task,
                                                       image_cache_task,
-                                                      profiler_chan_clone);
+                                                      storage_task,
+                                                      profiler_chan_clone,
+                                                      memory_profiler_chan_clone);
 
         // Send the URL command to the constellation.
         for filename in opts.urls.iter() {
@@ -184,11 +200,18 @@ fn run(opts: opts::Opts) {
 
     let constellation_chan = result_port.recv();
 
+    // Start the devtools server, if requested. Its sockets need the native runtime's I/O, so it
+    // is spawned from this task rather than from the pool.
+    for &port in opts.devtools_port.iter() {
+        devtools::start_server(port, constellation_chan.clone());
+    }
+
     debug!("preparing to enter main loop");
     CompositorTask::create(opts,
                            compositor_port,
                            constellation_chan,
-                           profiler_chan);
+                           profiler_chan,
+                           memory_profiler_chan);
 
     pool.shutdown();
 }
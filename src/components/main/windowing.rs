@@ -47,6 +47,8 @@ pub enum WindowEvent {
     FinishedWindowEvent,
     /// Sent when the user quits the application
     QuitWindowEvent,
+    /// Sent when the user asks for the memory reports to be printed (Ctrl+M).
+    PrintMemoryReportsWindowEvent,
 }
 
 /// Methods for an abstract Application.
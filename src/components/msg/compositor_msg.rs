@@ -46,6 +46,24 @@ impl LayerBufferSet {
     }
 }
 
+/// A tile rendered into memory on the CPU, for compositors that have no native graphics context.
+pub struct SoftwareLayerBuffer {
+    /// The pixels of the tile, in B8G8R8A8 format with premultiplied alpha.
+    pixels: ~[u8],
+
+    /// The rect in the containing RenderLayer that this represents.
+    rect: Rect<f32>,
+
+    /// The rect in pixels that will be drawn to the screen.
+    screen_pos: Rect<uint>,
+
+    /// The scale at which this tile is rendered
+    resolution: f32,
+
+    /// NB: stride is in pixels, like OpenGL GL_UNPACK_ROW_LENGTH.
+    stride: uint,
+}
+
 /// The status of the renderer.
 #[deriving(Eq, Clone)]
 pub enum RenderState {
@@ -75,6 +93,16 @@ impl Epoch {
     }
 }
 
+/// The part of a layer covered by an animated image, and the time at which the image shows its
+/// next frame. The compositor asks for the rect to be rendered again at that time.
+#[deriving(Clone)]
+pub struct AnimatedRect {
+    /// The rect, in page coordinates.
+    rect: Rect<f32>,
+    /// The time of the next frame, in nanoseconds.
+    next_frame_time: u64,
+}
+
 /// The interface used by the renderer to acquire draw targets for each render frame and
 /// submit them to be drawn to the display.
 pub trait RenderListener {
@@ -84,7 +112,10 @@ pub trait RenderListener {
     fn set_layer_clip_rect(&self, PipelineId, Rect<uint>);
     fn delete_layer(&self, PipelineId);
     fn paint(&self, id: PipelineId, layer_buffer_set: ~LayerBufferSet, Epoch);
+    fn paint_software(&self, id: PipelineId, buffers: ~[~SoftwareLayerBuffer], Epoch);
     fn set_render_state(&self, render_state: RenderState);
+    /// Replaces the animated images of the given layer.
+    fn set_layer_animated_rects(&self, PipelineId, ~[AnimatedRect]);
 }
 
 /// The interface used by the script task to tell the compositor to update its ready state,
@@ -93,6 +124,7 @@ pub trait ScriptListener : Clone {
     fn set_ready_state(&self, ReadyState);
     fn invalidate_rect(&self, PipelineId, Rect<uint>);
     fn scroll_fragment_point(&self, PipelineId, Point2D<f32>);
+    fn set_animation_frame_callbacks_pending(&self, PipelineId, bool);
     fn close(&self);
     fn dup(&self) -> ~ScriptListener;
 }
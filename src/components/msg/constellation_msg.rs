@@ -5,6 +5,7 @@
 //! The high-level interface from script to constellation. Using this abstract interface helps reduce
 /// coupling between these two components
 
+use devtools_msg::DevtoolScriptControlMsg;
 use extra::url::Url;
 use geom::rect::Rect;
 use geom::size::Size2D;
@@ -45,6 +46,45 @@ pub enum Msg {
     NavigateMsg(NavigationDirection),
     RendererReadyMsg(PipelineId),
     ResizedWindowMsg(Size2D<uint>),
+    /// Sets the number of device pixels that each CSS pixel is drawn at, which depends on the
+    /// pixel density of the monitor and on how far the window is zoomed in.
+    SetDevicePixelRatioMsg(f32),
+    /// Asks for the IDs and URLs of all the pipelines, for the devtools server.
+    GetPipelinesMsg(Chan<~[(PipelineId, Option<Url>)]>),
+    /// Forwards a request from the devtools server to the script task of the given pipeline.
+    DevtoolsMsg(PipelineId, DevtoolScriptControlMsg),
+    /// Sent by the compositor once per frame, with the frame time in nanoseconds, so that
+    /// pages can run their animation frame callbacks.
+    FrameTickMsg(u64),
+    /// Adds a same-document entry, created by `history.pushState`, to the session history.
+    PushStateMsg(PipelineId, SessionHistoryState),
+    /// Replaces the state of the current session history entry, for `history.replaceState`.
+    ReplaceStateMsg(PipelineId, SessionHistoryState),
+    /// A pipeline changed a storage area, so other pipelines of the same origin must fire a
+    /// `storage` event.
+    StorageChangedMsg(PipelineId, StorageChange),
+}
+
+/// A change that a pipeline made to its `localStorage` or `sessionStorage`.
+#[deriving(Clone)]
+pub struct StorageChange {
+    /// True for `localStorage`, false for `sessionStorage`.
+    is_local: bool,
+    /// The changed key, or `None` if the storage area was cleared.
+    key: Option<~str>,
+    old_value: Option<~str>,
+    new_value: Option<~str>,
+    /// The URL of the document that made the change.
+    url: Url,
+}
+
+/// The state that a page associated with a same-document session history entry.
+#[deriving(Clone)]
+pub struct SessionHistoryState {
+    /// The state object, serialized as JSON.
+    data: Option<~str>,
+    title: ~str,
+    url: Url,
 }
 
 /// Represents the two different ways to which a page can be navigated
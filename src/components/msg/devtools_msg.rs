@@ -0,0 +1,52 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The interface between the devtools server and the script tasks it inspects. Requests are
+//! routed to the right script task by the constellation.
+
+use constellation_msg::PipelineId;
+use std::comm::SharedChan;
+
+/// The method of the `console` object that produced a message.
+#[deriving(Clone, Eq)]
+pub enum ConsoleLevel {
+    LogLevel,
+    DebugLevel,
+    InfoLevel,
+    WarnLevel,
+    ErrorLevel,
+}
+
+impl ConsoleLevel {
+    /// The name of the `console` method for this level.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            LogLevel => "log",
+            DebugLevel => "debug",
+            InfoLevel => "info",
+            WarnLevel => "warn",
+            ErrorLevel => "error",
+        }
+    }
+}
+
+/// A call to one of the methods of the `console` object of a page.
+#[deriving(Clone)]
+pub struct ConsoleMessage {
+    pipeline_id: PipelineId,
+    level: ConsoleLevel,
+    message: ~str,
+}
+
+/// Requests from the devtools server to the script task that owns a pipeline.
+pub enum DevtoolScriptControlMsg {
+    /// Serializes the document of the pipeline as HTML.
+    GetDocumentHTML(Chan<~str>),
+    /// Evaluates a script in the global object of the pipeline. The reply is the completion value
+    /// converted to a string, or a description of the failure.
+    EvaluateJS(~str, Chan<Result<~str, ~str>>),
+    /// Sends every subsequent `console` message of the pipeline to the given channel, until the
+    /// receiving end hangs up.
+    WantConsoleMessages(SharedChan<ConsoleMessage>),
+}
@@ -20,6 +20,7 @@ extern mod io_surface;
 
 pub mod compositor_msg;
 pub mod constellation_msg;
+pub mod devtools_msg;
 
 pub mod platform {
     #[cfg(target_os="macos")]
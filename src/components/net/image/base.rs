@@ -2,7 +2,15 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use image::gif;
+use image::gif::GifDecoder;
+
+use extra::arc::Arc;
+use extra::time::precise_time_ns;
+use geom::size::Size2D;
+use std::cmp;
 use std::iter::range_step;
+use std::vec;
 use stb_image = stb_image::image;
 use png;
 
@@ -19,6 +27,72 @@ pub fn Image(width: u32, height: u32, color_type: png::ColorType, data: ~[u8]) -
     }
 }
 
+/// One frame of an animated image.
+pub struct ImageFrame {
+    image: Arc<~Image>,
+    /// How long the frame is shown for, in milliseconds.
+    delay: u64,
+}
+
+/// The frames of an animated image. Every frame covers the whole image.
+pub struct Animation {
+    frames: ~[ImageFrame],
+    /// The number of times the animation is played, or 0 to play it forever.
+    loop_count: uint,
+    /// The time at which the first frame was shown, in nanoseconds.
+    start_time: u64,
+}
+
+impl Animation {
+    /// Returns the index of the frame shown at the given time, and the time at which the frame
+    /// after it is shown, unless the animation has finished by then. Times are in nanoseconds.
+    fn frame_index_at(&self, time: u64) -> (uint, Option<u64>) {
+        let last_frame = self.frames.len() - 1;
+        let duration = self.frames.iter().fold(0, |duration, frame| duration + frame.delay);
+        if duration == 0 || time < self.start_time {
+            return (0, None)
+        }
+
+        let duration = duration * 1000000;
+        let iteration = (time - self.start_time) / duration;
+        if self.loop_count != 0 && iteration >= self.loop_count as u64 {
+            return (last_frame, None)
+        }
+
+        let mut frame_end = self.start_time + iteration * duration;
+        for (index, frame) in self.frames.iter().enumerate() {
+            frame_end += frame.delay * 1000000;
+            if time < frame_end {
+                let last_iteration = self.loop_count != 0 &&
+                    iteration + 1 == self.loop_count as u64;
+                if index == last_frame && last_iteration {
+                    return (index, None)
+                }
+                return (index, Some(frame_end))
+            }
+        }
+        (last_frame, None)
+    }
+
+    /// Returns the frame shown at the given time, in nanoseconds.
+    pub fn frame_at(&self, time: u64) -> Arc<~Image> {
+        let (index, _) = self.frame_index_at(time);
+        self.frames[index].image.clone()
+    }
+
+    /// Returns the time at which the next frame after the given time is shown, or `None` if the
+    /// animation has finished by then. Times are in nanoseconds.
+    pub fn next_frame_time(&self, time: u64) -> Option<u64> {
+        let (_, next_frame_time) = self.frame_index_at(time);
+        next_frame_time
+    }
+
+    /// The number of bytes of pixel data in all of the frames.
+    pub fn byte_size(&self) -> uint {
+        self.frames.iter().fold(0, |size, frame| size + frame.image.get().pixels.len())
+    }
+}
+
 static TEST_IMAGE: &'static [u8] = include_bin!("test.jpeg");
 
 pub fn test_image_bin() -> ~[u8] {
@@ -40,6 +114,44 @@ fn byte_swap(color_type: png::ColorType, data: &mut [u8]) {
     }
 }
 
+/// Shrinks an RGBA image by the largest whole factor that keeps it at least as large as `size`,
+/// averaging each block of pixels. Images that are less than twice as large as `size`, or that
+/// are not RGBA, are returned as they are.
+pub fn downscale_to_fit(image: Image, size: Size2D<uint>) -> Image {
+    match image.color_type {
+        png::RGBA8 => {}
+        _ => return image,
+    }
+    let (width, height) = (image.width as uint, image.height as uint);
+    if size.width == 0 || size.height == 0 {
+        return image
+    }
+    let factor = cmp::min(width / size.width, height / size.height);
+    if factor < 2 {
+        return image
+    }
+
+    let (new_width, new_height) = (width / factor, height / factor);
+    let mut pixels = vec::with_capacity(new_width * new_height * 4);
+    for y in range(0, new_height) {
+        for x in range(0, new_width) {
+            let mut sums = [0u, ..4];
+            for source_y in range(y * factor, (y + 1) * factor) {
+                for source_x in range(x * factor, (x + 1) * factor) {
+                    let pixel = (source_y * width + source_x) * 4;
+                    for component in range(0, 4) {
+                        sums[component] += image.pixels[pixel + component] as uint;
+                    }
+                }
+            }
+            for sum in sums.iter() {
+                pixels.push((*sum / (factor * factor)) as u8);
+            }
+        }
+    }
+    Image(new_width as u32, new_height as u32, png::RGBA8, pixels)
+}
+
 pub fn load_from_memory(buffer: &[u8]) -> Option<Image> {
     if png::is_png(buffer) {
         match png::load_png_from_memory(buffer) {
@@ -60,8 +172,202 @@ pub fn load_from_memory(buffer: &[u8]) -> Option<Image> {
                 byte_swap(png::RGBA8, image.data);
                 Some(Image(image.width as u32, image.height as u32, png::RGBA8, image.data))
             }
-            stb_image::ImageF32(_image) => fail!(~"HDR images not implemented"),
+            stb_image::ImageF32(image) => {
+                // HDR images are normally converted by stb_image; clamp any that are not.
+                assert!(image.depth == 4);
+                let mut data: ~[u8] = image.data.iter().map(|&component| {
+                    if component <= 0.0 {
+                        0u8
+                    } else if component >= 1.0 {
+                        255u8
+                    } else {
+                        (component * 255.0) as u8
+                    }
+                }).collect();
+                byte_swap(png::RGBA8, data);
+                Some(Image(image.width as u32, image.height as u32, png::RGBA8, data))
+            }
             stb_image::Error => None
         }
     }
 }
+
+/// Decodes every frame of an animated image. Returns `None` if the image is not a GIF or has a
+/// single frame, in which case `load_from_memory` should be used.
+pub fn load_animation_from_memory(buffer: &[u8]) -> Option<Animation> {
+    if !gif::is_gif(buffer) {
+        return None
+    }
+    gif::decode(buffer).and_then(|gif| {
+        if gif.frames.len() < 2 {
+            return None
+        }
+        let (width, height, loop_count) = (gif.width, gif.height, gif.loop_count);
+        // Each frame only holds the area that changed, so draw them in turn onto one screen.
+        let mut screen = vec::from_elem((width * height * 4) as uint, 0u8);
+        let mut frames = ~[];
+        for frame in gif.frames.iter() {
+            frame.draw(screen, width as uint);
+            let mut pixels = screen.clone();
+            byte_swap(png::RGBA8, pixels);
+            frames.push(ImageFrame {
+                image: Arc::new(~Image(width, height, png::RGBA8, pixels)),
+                delay: frame.delay,
+            });
+        }
+        Some(Animation {
+            frames: frames,
+            loop_count: loop_count,
+            start_time: precise_time_ns(),
+        })
+    })
+}
+
+/// Returns true if a `PartialImageDecoder` can show something of the image before all of it has
+/// arrived. Only GIFs are decoded progressively: other images, including interlaced PNGs, are
+/// shown once all of their data has arrived, and only the first frame of an APNG is ever shown.
+pub fn supports_partial_loading(buffer: &[u8]) -> bool {
+    gif::is_gif(buffer)
+}
+
+/// Decodes the first frame of an image while it downloads, so that the part of it that has
+/// arrived can be shown.
+pub struct PartialImageDecoder {
+    priv gif: GifDecoder,
+    /// The number of pixels of the first frame that had been decoded when it was last returned.
+    priv shown_pixels: uint,
+}
+
+impl PartialImageDecoder {
+    pub fn new() -> PartialImageDecoder {
+        PartialImageDecoder {
+            gif: GifDecoder::new(),
+            shown_pixels: 0,
+        }
+    }
+
+    /// Decodes the data that has arrived since the last call, given all of the data received so
+    /// far. Returns the part of the first frame that has been decoded if it has grown since the
+    /// last call. The rest of the image is transparent.
+    pub fn decode_more(&mut self, buffer: &[u8]) -> Option<Image> {
+        if !supports_partial_loading(buffer) || self.gif.first_frame_complete() {
+            return None
+        }
+        self.gif.decode_more(buffer);
+
+        let decoded_pixels = self.gif.first_frame_decoded_pixels();
+        if decoded_pixels == self.shown_pixels {
+            return None
+        }
+        self.shown_pixels = decoded_pixels;
+
+        let (width, height) = self.gif.dimensions();
+        self.gif.first_frame().map(|pixels| {
+            let mut pixels = pixels;
+            byte_swap(png::RGBA8, pixels);
+            Image(width, height, png::RGBA8, pixels)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::byte_swap;
+
+    use extra::arc::Arc;
+    use extra::test::BenchHarness;
+    use geom::size::Size2D;
+    use png;
+
+    fn animation(loop_count: uint) -> Animation {
+        let frame = |delay| {
+            ImageFrame {
+                image: Arc::new(~Image(1, 1, png::RGBA8, ~[0, 0, 0, 0])),
+                delay: delay,
+            }
+        };
+        Animation {
+            frames: ~[frame(100), frame(200)],
+            loop_count: loop_count,
+            start_time: 1000000000,
+        }
+    }
+
+    #[test]
+    fn should_show_each_frame_for_its_delay() {
+        let animation = animation(0);
+        assert_eq!(animation.frame_index_at(1050000000), (0, Some(1100000000)));
+        assert_eq!(animation.frame_index_at(1100000000), (1, Some(1300000000)));
+        assert_eq!(animation.frame_index_at(1350000000), (0, Some(1400000000)));
+    }
+
+    #[test]
+    fn should_stop_on_the_last_frame_after_the_last_loop() {
+        let animation = animation(1);
+        assert_eq!(animation.frame_index_at(1050000000), (0, Some(1100000000)));
+        assert_eq!(animation.frame_index_at(1150000000), (1, None));
+        assert_eq!(animation.frame_index_at(5000000000), (1, None));
+    }
+
+    #[test]
+    fn should_swap_red_and_blue() {
+        let mut pixels = ~[1u8, 2, 3, 4, 5, 6, 7, 8];
+        byte_swap(png::RGBA8, pixels);
+        assert_eq!(pixels, ~[3u8, 2, 1, 4, 7, 6, 5, 8]);
+    }
+
+    #[test]
+    fn should_swap_red_and_blue_in_unaligned_pixels() {
+        let mut pixels = ~[0u8, 1, 2, 3, 4, 5, 6, 7, 8];
+        byte_swap(png::RGBA8, pixels.mut_slice_from(1));
+        assert_eq!(pixels, ~[0u8, 3, 2, 1, 4, 7, 6, 5, 8]);
+    }
+
+    #[test]
+    fn should_downscale_by_averaging_blocks_of_pixels() {
+        let mut pixels = ~[];
+        for y in range(0u8, 4) {
+            for x in range(0u8, 4) {
+                pixels.push_all([x * 10, y * 10, 0, 255]);
+            }
+        }
+        let image = downscale_to_fit(Image(4, 4, png::RGBA8, pixels), Size2D(2, 2));
+        assert_eq!((image.width, image.height), (2, 2));
+        assert_eq!(image.pixels, ~[5u8, 5, 0, 255, 25, 5, 0, 255, 5, 25, 0, 255, 25, 25, 0, 255]);
+    }
+
+    #[test]
+    fn should_not_downscale_images_less_than_twice_as_large() {
+        let image = Image(3, 3, png::RGBA8, ~[0, ..36]);
+        let image = downscale_to_fit(image, Size2D(2, 2));
+        assert_eq!((image.width, image.height), (3, 3));
+    }
+
+    #[bench]
+    fn bench_load_test_image(bh: &mut BenchHarness) {
+        let data = test_image_bin();
+        bh.iter(|| {
+            load_from_memory(data);
+        });
+        bh.bytes = data.len() as u64;
+    }
+
+    #[bench]
+    fn bench_byte_swap_test_image(bh: &mut BenchHarness) {
+        let mut pixels = load_from_memory(test_image_bin()).unwrap().pixels;
+        bh.iter(|| byte_swap(png::RGBA8, pixels));
+        bh.bytes = pixels.len() as u64;
+    }
+
+    #[bench]
+    fn bench_downscale_test_image(bh: &mut BenchHarness) {
+        let image = load_from_memory(test_image_bin()).unwrap();
+        let size = Size2D(image.width as uint / 4, image.height as uint / 4);
+        bh.iter(|| {
+            let copy = Image(image.width, image.height, png::RGBA8, image.pixels.clone());
+            downscale_to_fit(copy, size);
+        });
+        bh.bytes = image.pixels.len() as u64;
+    }
+}
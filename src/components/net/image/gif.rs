@@ -0,0 +1,911 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A GIF decoder that handles animated and partially downloaded images.
+//!
+//! `stb_image` only decodes the first frame of a GIF, and only once the whole file has arrived.
+//! This decoder composites every frame onto the logical screen according to the frame's disposal
+//! method, and decodes as much of a truncated file as it can, so that images can be shown while
+//! they are still downloading.
+
+use std::cmp;
+use std::vec;
+
+/// The largest code an LZW-compressed GIF image may use.
+static MAX_LZW_CODE: uint = 4096;
+
+/// The largest logical screen we decode, in pixels. The dimensions come from the file, so a GIF
+/// of a few bytes could otherwise make us allocate gigabytes.
+static MAX_SCREEN_PIXELS: uint = 1 << 24;
+
+/// A rectangle of the logical screen, in pixels.
+#[deriving(Clone, Eq)]
+pub struct Area {
+    left: uint,
+    top: uint,
+    width: uint,
+    height: uint,
+}
+
+impl Area {
+    fn empty() -> Area {
+        Area {
+            left: 0,
+            top: 0,
+            width: 0,
+            height: 0,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// Returns the part of the area that is on a logical screen of the given size.
+    fn clip(&self, screen_width: uint, screen_height: uint) -> Area {
+        let right = cmp::min(self.left + self.width, screen_width);
+        let bottom = cmp::min(self.top + self.height, screen_height);
+        if self.left >= right || self.top >= bottom {
+            return Area::empty()
+        }
+        Area {
+            left: self.left,
+            top: self.top,
+            width: right - self.left,
+            height: bottom - self.top,
+        }
+    }
+
+    /// Returns the smallest area that contains both areas.
+    fn union(&self, other: &Area) -> Area {
+        if self.is_empty() {
+            return *other
+        }
+        if other.is_empty() {
+            return *self
+        }
+        let left = cmp::min(self.left, other.left);
+        let top = cmp::min(self.top, other.top);
+        Area {
+            left: left,
+            top: top,
+            width: cmp::max(self.left + self.width, other.left + other.width) - left,
+            height: cmp::max(self.top + self.height, other.top + other.height) - top,
+        }
+    }
+}
+
+/// One frame of a GIF. Only the area of the logical screen that differs from the previous frame
+/// is kept: the frames are drawn in turn onto a single logical screen to show the whole image.
+pub struct GifFrame {
+    /// The area of the logical screen that changed since the previous frame.
+    area: Area,
+    /// The pixels of the area, as RGBA.
+    pixels: ~[u8],
+    /// How long the frame is shown for, in milliseconds.
+    delay: u64,
+}
+
+impl GifFrame {
+    /// Draws the frame onto a logical screen that shows the previous frame, or nothing for the
+    /// first frame.
+    pub fn draw(&self, screen: &mut [u8], screen_width: uint) {
+        draw_area(screen, screen_width, &self.area, self.pixels);
+    }
+}
+
+/// Returns the pixels of an area of a logical screen.
+fn copy_area(screen: &[u8], screen_width: uint, area: &Area) -> ~[u8] {
+    let row_length = area.width * 4;
+    let mut pixels = vec::with_capacity(row_length * area.height);
+    for y in range(area.top, area.top + area.height) {
+        let start = (y * screen_width + area.left) * 4;
+        pixels.push_all(screen.slice(start, start + row_length));
+    }
+    pixels
+}
+
+/// Draws the pixels of an area, as returned by `copy_area`, onto a logical screen.
+fn draw_area(screen: &mut [u8], screen_width: uint, area: &Area, pixels: &[u8]) {
+    let row_length = area.width * 4;
+    for row in range(0, area.height) {
+        let start = ((area.top + row) * screen_width + area.left) * 4;
+        screen.mut_slice(start, start + row_length)
+              .copy_from(pixels.slice(row * row_length, (row + 1) * row_length));
+    }
+}
+
+/// A decoded GIF.
+pub struct Gif {
+    width: u32,
+    height: u32,
+    frames: ~[GifFrame],
+    /// The number of times the animation is played, or 0 to play it forever.
+    loop_count: uint,
+    /// False if the data ended before the trailer, in which case the last frame may only be
+    /// partly decoded. The pixels that have not arrived yet are transparent.
+    complete: bool,
+}
+
+/// The disposal methods of the graphic control extension, which say what happens to a frame's
+/// area of the logical screen before the next frame is drawn.
+#[deriving(Eq)]
+enum Disposal {
+    /// Leave the frame in place.
+    KeepFrame,
+    /// Clear the frame's area to transparent.
+    ClearFrame,
+    /// Restore the frame's area to what it was before the frame was drawn.
+    RestorePrevious,
+}
+
+/// The contents of a graphic control extension, which apply to the next frame.
+struct GraphicControl {
+    disposal: Disposal,
+    /// The frame delay, in hundredths of a second.
+    delay: u16,
+    transparent_index: Option<u8>,
+}
+
+impl GraphicControl {
+    fn default() -> GraphicControl {
+        GraphicControl {
+            disposal: KeepFrame,
+            delay: 0,
+            transparent_index: None,
+        }
+    }
+}
+
+/// Reads the little-endian fields of a GIF, returning `None` once the data runs out.
+struct Reader<'a> {
+    data: &'a [u8],
+    position: uint,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Option<u8> {
+        if self.position < self.data.len() {
+            self.position += 1;
+            Some(self.data[self.position - 1])
+        } else {
+            None
+        }
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let low = self.byte();
+        let high = self.byte();
+        match (low, high) {
+            (Some(low), Some(high)) => Some((low as u16) | ((high as u16) << 8)),
+            _ => None,
+        }
+    }
+
+    fn bytes(&mut self, length: uint) -> Option<&'a [u8]> {
+        if self.position + length <= self.data.len() {
+            self.position += length;
+            Some(self.data.slice(self.position - length, self.position))
+        } else {
+            None
+        }
+    }
+
+    /// Reads a sequence of data sub-blocks up to the block terminator. Returns their contents,
+    /// and whether the terminator was reached before the data ran out.
+    fn sub_blocks(&mut self) -> (~[u8], bool) {
+        let mut contents = ~[];
+        loop {
+            let length = match self.byte() {
+                Some(0) => return (contents, true),
+                Some(length) => length as uint,
+                None => return (contents, false),
+            };
+            let available = self.data.len() - self.position;
+            if length > available {
+                contents.push_all(self.data.slice_from(self.position));
+                self.position = self.data.len();
+                return (contents, false)
+            }
+            contents.push_all(self.bytes(length).unwrap());
+        }
+    }
+
+    /// Reads a color table with the given number of entries.
+    fn color_table(&mut self, entries: uint) -> Option<~[u8]> {
+        self.bytes(entries * 3).map(|table| table.to_owned())
+    }
+}
+
+pub fn is_gif(buffer: &[u8]) -> bool {
+    buffer.len() >= 6 &&
+        (buffer.slice(0, 6) == bytes!("GIF87a") || buffer.slice(0, 6) == bytes!("GIF89a"))
+}
+
+/// Decodes the frames of a GIF. Returns `None` if the data is not a GIF or does not contain any
+/// image data yet.
+pub fn decode(buffer: &[u8]) -> Option<Gif> {
+    let mut decoder = GifDecoder::new();
+    decoder.decode_more(buffer);
+    decoder.finish()
+}
+
+/// What a `GifDecoder` reads next.
+#[deriving(Eq)]
+enum DecoderState {
+    /// The header, the logical screen descriptor and the global color table.
+    ReadingHeader,
+    /// An extension, an image descriptor or the trailer.
+    ReadingBlock,
+    /// The image data sub-blocks of the frame being decoded.
+    ReadingImageData,
+    /// Nothing: the trailer has been read, or the data is corrupt from here on.
+    Finished,
+    /// Nothing: the data is not a GIF that can be shown.
+    Invalid,
+}
+
+/// Decodes a GIF as its data arrives. Each call to `decode_more` only reads the data that has
+/// arrived since the previous one, so that an image can be shown after every chunk of a download
+/// without decoding it from the start again.
+pub struct GifDecoder {
+    priv state: DecoderState,
+    /// The number of bytes of the data that have been read.
+    priv position: uint,
+    priv width: uint,
+    priv height: uint,
+    priv global_color_table: Option<~[u8]>,
+    /// The graphic control extension that applies to the next frame, or the current one.
+    priv graphic_control: GraphicControl,
+    /// The frame whose image data is being read.
+    priv frame: Option<FrameDecoder>,
+    /// The number of bytes of the current image data sub-block that have not been read yet.
+    priv sub_block_remaining: uint,
+    /// The logical screen, with the frames decoded so far drawn onto it.
+    priv canvas: ~[u8],
+    /// The area of the logical screen that the disposal of the last frame changed.
+    priv disposed_area: Area,
+    priv frames: ~[GifFrame],
+    priv loop_count: uint,
+    priv complete: bool,
+}
+
+impl GifDecoder {
+    pub fn new() -> GifDecoder {
+        GifDecoder {
+            state: ReadingHeader,
+            position: 0,
+            width: 0,
+            height: 0,
+            global_color_table: None,
+            graphic_control: GraphicControl::default(),
+            frame: None,
+            sub_block_remaining: 0,
+            canvas: ~[],
+            disposed_area: Area::empty(),
+            frames: ~[],
+            // Without a Netscape application extension, the animation is played once.
+            loop_count: 1,
+            complete: false,
+        }
+    }
+
+    /// Decodes the data that has arrived since the last call. `buffer` holds all of the data
+    /// received so far, including the part that has already been decoded.
+    pub fn decode_more(&mut self, buffer: &[u8]) {
+        loop {
+            let mut reader = Reader {
+                data: buffer,
+                position: self.position,
+            };
+            let read = match self.state {
+                ReadingHeader => self.read_header(&mut reader),
+                ReadingBlock => self.read_block(&mut reader),
+                ReadingImageData => self.read_image_data(&mut reader),
+                Finished | Invalid => false,
+            };
+            // Blocks are only read once all of their data has arrived.
+            if !read {
+                return
+            }
+            self.position = reader.position;
+        }
+    }
+
+    /// The width and height of the logical screen, or zero until the header has arrived.
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width as u32, self.height as u32)
+    }
+
+    /// True once the whole first frame has been decoded.
+    pub fn first_frame_complete(&self) -> bool {
+        !self.frames.is_empty()
+    }
+
+    /// The number of pixels of the first frame that have been decoded so far.
+    pub fn first_frame_decoded_pixels(&self) -> uint {
+        match (self.frames.is_empty(), &self.frame) {
+            (true, &Some(ref frame)) => frame.decoded_pixels,
+            (true, &None) => 0,
+            (false, _) => self.width * self.height,
+        }
+    }
+
+    /// Returns the logical screen with as much of the first frame drawn onto it as has been
+    /// decoded, as RGBA. The pixels that have not been decoded yet are transparent.
+    pub fn first_frame(&self) -> Option<~[u8]> {
+        if self.frames.is_empty() && self.frame.is_none() {
+            return None
+        }
+        let mut screen = vec::from_elem(self.width * self.height * 4, 0u8);
+        match self.frames.head_opt() {
+            Some(frame) => frame.draw(screen, self.width),
+            None => {
+                // Nothing but the first frame has been drawn onto the canvas yet.
+                let area = &self.frame.get_ref().area;
+                draw_area(screen, self.width, area, copy_area(self.canvas, self.width, area));
+            }
+        }
+        Some(screen)
+    }
+
+    /// Returns the frames decoded so far, including the part of the last one that has arrived.
+    /// Returns `None` if the data is not a GIF or does not contain any image data yet.
+    pub fn finish(mut self) -> Option<Gif> {
+        if self.state == Invalid {
+            return None
+        }
+        if self.frame.is_some() {
+            self.finish_frame();
+        }
+        if self.frames.is_empty() {
+            return None
+        }
+
+        Some(Gif {
+            width: self.width as u32,
+            height: self.height as u32,
+            frames: self.frames,
+            loop_count: self.loop_count,
+            complete: self.complete,
+        })
+    }
+
+    fn read_header(&mut self, reader: &mut Reader) -> bool {
+        if reader.data.len() < 6 {
+            return false
+        }
+        if !is_gif(reader.data) {
+            self.state = Invalid;
+            return true
+        }
+        reader.position = 6;
+
+        let (width, height, flags) = match (reader.u16(), reader.u16(), reader.byte()) {
+            (Some(width), Some(height), Some(flags)) => (width as uint, height as uint, flags),
+            _ => return false,
+        };
+        if width * height > MAX_SCREEN_PIXELS {
+            self.state = Invalid;
+            return true
+        }
+        // Skip the background color index and the pixel aspect ratio, which browsers ignore.
+        if reader.bytes(2).is_none() {
+            return false
+        }
+        if flags & 0x80 != 0 {
+            match reader.color_table(1 << ((flags & 0x07) + 1)) {
+                Some(table) => self.global_color_table = Some(table),
+                None => return false,
+            }
+        }
+
+        self.width = width;
+        self.height = height;
+        self.canvas = vec::from_elem(width * height * 4, 0u8);
+        self.state = ReadingBlock;
+        true
+    }
+
+    fn read_block(&mut self, reader: &mut Reader) -> bool {
+        match reader.byte() {
+            Some(0x3b) => {
+                // The trailer.
+                self.complete = true;
+                self.state = Finished;
+            }
+            Some(0x21) => {
+                let label = reader.byte();
+                let (contents, terminated) = reader.sub_blocks();
+                if !terminated {
+                    return false
+                }
+                match label {
+                    Some(0xf9) if contents.len() >= 4 => {
+                        self.graphic_control = GraphicControl {
+                            disposal: match (contents[0] >> 2) & 0x07 {
+                                2 => ClearFrame,
+                                3 => RestorePrevious,
+                                _ => KeepFrame,
+                            },
+                            delay: (contents[1] as u16) | ((contents[2] as u16) << 8),
+                            transparent_index: if contents[0] & 0x01 != 0 {
+                                Some(contents[3])
+                            } else {
+                                None
+                            },
+                        };
+                    }
+                    Some(0xff) if contents.len() >= 14 &&
+                            contents.slice(0, 11) == bytes!("NETSCAPE2.0") &&
+                            contents[11] == 1 => {
+                        self.loop_count = (contents[12] as uint) | ((contents[13] as uint) << 8);
+                    }
+                    _ => {}
+                }
+            }
+            Some(0x2c) => {
+                let descriptor = (reader.u16(), reader.u16(), reader.u16(), reader.u16(),
+                                  reader.byte());
+                let (left, top, width, height, flags) = match descriptor {
+                    (Some(left), Some(top), Some(width), Some(height), Some(flags)) => {
+                        (left as uint, top as uint, width as uint, height as uint, flags)
+                    }
+                    _ => return false,
+                };
+                let local_color_table = if flags & 0x80 != 0 {
+                    match reader.color_table(1 << ((flags & 0x07) + 1)) {
+                        Some(table) => Some(table),
+                        None => return false,
+                    }
+                } else {
+                    None
+                };
+                let color_table = match (local_color_table, &self.global_color_table) {
+                    (Some(table), _) => table,
+                    (None, &Some(ref table)) => table.clone(),
+                    (None, &None) => {
+                        self.state = Invalid;
+                        return true
+                    }
+                };
+                let min_code_size = match reader.byte() {
+                    Some(min_code_size) => min_code_size,
+                    None => return false,
+                };
+
+                let area = Area {
+                    left: left,
+                    top: top,
+                    width: width,
+                    height: height,
+                };
+                let screen_area = area.clip(self.width, self.height);
+                let previous = if self.graphic_control.disposal == RestorePrevious {
+                    Some(copy_area(self.canvas, self.width, &screen_area))
+                } else {
+                    None
+                };
+                self.frame = Some(FrameDecoder {
+                    left: left,
+                    top: top,
+                    width: width,
+                    height: height,
+                    area: screen_area,
+                    interlaced: flags & 0x40 != 0,
+                    color_table: color_table,
+                    transparent_index: self.graphic_control.transparent_index,
+                    lzw: LzwDecoder::new(min_code_size),
+                    decoded_pixels: 0,
+                    previous: previous,
+                });
+                self.sub_block_remaining = 0;
+                self.state = ReadingImageData;
+            }
+            // The data is corrupt from here on.
+            Some(_) => self.state = Finished,
+            None => return false,
+        }
+        true
+    }
+
+    /// Reads the next part of the current frame's image data, which may end in the middle of a
+    /// sub-block.
+    fn read_image_data(&mut self, reader: &mut Reader) -> bool {
+        if self.sub_block_remaining == 0 {
+            match reader.byte() {
+                Some(0) => {
+                    // The block terminator.
+                    self.finish_frame();
+                    self.state = ReadingBlock;
+                    return true
+                }
+                Some(length) => {
+                    self.sub_block_remaining = length as uint;
+                    return true
+                }
+                None => return false,
+            }
+        }
+
+        let available = cmp::min(self.sub_block_remaining, reader.data.len() - reader.position);
+        if available == 0 {
+            return false
+        }
+        let data = reader.bytes(available).unwrap();
+        self.sub_block_remaining -= available;
+        let (width, height) = (self.width, self.height);
+        self.frame.get_mut_ref().decode(data, self.canvas, width, height);
+        true
+    }
+
+    /// Adds the current frame to the decoded frames, and disposes of it.
+    fn finish_frame(&mut self) {
+        let frame = self.frame.take_unwrap();
+        // The frame differs from the previous one where the previous frame was disposed of, and
+        // where it has been drawn.
+        let changed_area = self.disposed_area.union(&frame.area);
+        self.frames.push(GifFrame {
+            area: changed_area,
+            pixels: copy_area(self.canvas, self.width, &changed_area),
+            delay: frame_delay(self.graphic_control.delay),
+        });
+
+        let width = self.width;
+        self.disposed_area = match self.graphic_control.disposal {
+            KeepFrame => Area::empty(),
+            ClearFrame => {
+                let cleared = vec::from_elem(frame.area.width * frame.area.height * 4, 0u8);
+                draw_area(self.canvas, width, &frame.area, cleared);
+                frame.area
+            }
+            RestorePrevious => {
+                draw_area(self.canvas, width, &frame.area, frame.previous.get_ref().as_slice());
+                frame.area
+            }
+        };
+        self.graphic_control = GraphicControl::default();
+    }
+}
+
+/// The state of decoding the image data of one frame.
+struct FrameDecoder {
+    left: uint,
+    top: uint,
+    width: uint,
+    height: uint,
+    /// The part of the frame that is on the logical screen.
+    area: Area,
+    interlaced: bool,
+    color_table: ~[u8],
+    transparent_index: Option<u8>,
+    lzw: LzwDecoder,
+    /// The number of the frame's pixels that have been decoded and drawn.
+    decoded_pixels: uint,
+    /// The frame's area of the logical screen before the frame was drawn, if it is restored
+    /// afterwards.
+    previous: Option<~[u8]>,
+}
+
+impl FrameDecoder {
+    /// Decompresses the next part of the frame's image data, and draws the pixels it holds onto
+    /// the logical screen.
+    fn decode(&mut self, data: &[u8], canvas: &mut [u8], canvas_width: uint,
+              canvas_height: uint) {
+        let mut indices = ~[];
+        let max_pixels = self.width * self.height - self.decoded_pixels;
+        self.lzw.decode(data, &mut indices, max_pixels);
+
+        for &index in indices.iter() {
+            let i = self.decoded_pixels;
+            self.decoded_pixels += 1;
+            if Some(index) == self.transparent_index {
+                continue
+            }
+            let row = i / self.width;
+            let row = if self.interlaced { interlaced_row(row, self.height) } else { row };
+            let (x, y) = (self.left + i % self.width, self.top + row);
+            let color = (index as uint) * 3;
+            if x >= canvas_width || y >= canvas_height || color + 2 >= self.color_table.len() {
+                continue
+            }
+            let pixel = (y * canvas_width + x) * 4;
+            canvas[pixel + 0] = self.color_table[color + 0];
+            canvas[pixel + 1] = self.color_table[color + 1];
+            canvas[pixel + 2] = self.color_table[color + 2];
+            canvas[pixel + 3] = 255;
+        }
+    }
+}
+
+/// Converts a frame delay in hundredths of a second to milliseconds. Like other browsers, we show
+/// frames with a delay of 10ms or less for 100ms, since many GIFs rely on it.
+fn frame_delay(delay: u16) -> u64 {
+    if delay <= 1 {
+        100
+    } else {
+        (delay as u64) * 10
+    }
+}
+
+/// Maps the index of a row in the order it is stored in an interlaced image to the row of the
+/// image it belongs to. The rows are stored in four passes: every eighth row starting from 0,
+/// every eighth row starting from 4, every fourth row starting from 2, and every other row
+/// starting from 1.
+fn interlaced_row(row: uint, height: uint) -> uint {
+    let passes = [(0u, 8u), (4, 8), (2, 4), (1, 2)];
+    let mut row = row;
+    for &(start, step) in passes.iter() {
+        let rows_in_pass = if height > start { (height - start + step - 1) / step } else { 0 };
+        if row < rows_in_pass {
+            return start + row * step
+        }
+        row -= rows_in_pass;
+    }
+    height
+}
+
+/// Decompresses the color indices of a frame, whose data may arrive in any number of pieces.
+struct LzwDecoder {
+    min_code_size: uint,
+    code_size: uint,
+    next_code: uint,
+    previous_code: Option<uint>,
+    /// Each code above the end of information code stands for the string of its prefix code
+    /// followed by its suffix.
+    prefixes: ~[u16],
+    suffixes: ~[u8],
+    /// The bits of the data that have been read but not decoded yet.
+    bits: u32,
+    bit_count: uint,
+    /// True once the end of information code has been read, or the data turned out to be
+    /// corrupt.
+    finished: bool,
+    string: ~[u8],
+}
+
+impl LzwDecoder {
+    fn new(min_code_size: u8) -> LzwDecoder {
+        let min_code_size = min_code_size as uint;
+        LzwDecoder {
+            min_code_size: min_code_size,
+            code_size: min_code_size + 1,
+            next_code: (1 << min_code_size) + 2,
+            previous_code: None,
+            prefixes: vec::from_elem(MAX_LZW_CODE, 0u16),
+            suffixes: vec::from_elem(MAX_LZW_CODE, 0u8),
+            bits: 0,
+            bit_count: 0,
+            finished: min_code_size < 1 || min_code_size > 11,
+            string: ~[],
+        }
+    }
+
+    /// Decompresses the next part of the data, adding the indices to `output`. Decoding stops at
+    /// the end of information code, once `max_pixels` indices have been added, or when the data
+    /// runs out, whichever comes first. The bits of a code that is split between two parts are
+    /// kept until the next call.
+    fn decode(&mut self, data: &[u8], output: &mut ~[u8], max_pixels: uint) {
+        let clear_code = 1u << self.min_code_size;
+        let end_code = clear_code + 1;
+        let mut position = 0;
+
+        while !self.finished && output.len() < max_pixels {
+            while self.bit_count < self.code_size {
+                if position >= data.len() {
+                    return
+                }
+                self.bits |= (data[position] as u32) << self.bit_count;
+                self.bit_count += 8;
+                position += 1;
+            }
+            let code = (self.bits & ((1 << self.code_size) - 1)) as uint;
+            self.bits >>= self.code_size;
+            self.bit_count -= self.code_size;
+
+            if code == clear_code {
+                self.code_size = self.min_code_size + 1;
+                self.next_code = end_code + 1;
+                self.previous_code = None;
+                continue
+            }
+            if code == end_code {
+                self.finished = true;
+                break
+            }
+
+            let previous = match self.previous_code {
+                None => {
+                    if code > clear_code {
+                        self.finished = true;
+                        break
+                    }
+                    output.push(code as u8);
+                    self.previous_code = Some(code);
+                    continue
+                }
+                Some(previous) => previous,
+            };
+
+            // Find the string for the code. The only code that may not be in the table yet is the
+            // next one, whose string is the previous string followed by its own first byte.
+            let known_code = if code < self.next_code {
+                code
+            } else if code == self.next_code {
+                previous
+            } else {
+                self.finished = true;
+                break
+            };
+            self.string.truncate(0);
+            let mut current = known_code;
+            while current > end_code {
+                self.string.push(self.suffixes[current]);
+                current = self.prefixes[current] as uint;
+            }
+            self.string.push(current as u8);
+            let first_byte = current as u8;
+            self.string.reverse();
+            if code == self.next_code {
+                self.string.push(first_byte);
+            }
+            output.push_all(self.string);
+
+            if self.next_code < MAX_LZW_CODE {
+                self.prefixes[self.next_code] = previous as u16;
+                self.suffixes[self.next_code] = first_byte;
+                self.next_code += 1;
+                if self.next_code == (1 << self.code_size) && self.code_size < 12 {
+                    self.code_size += 1;
+                }
+            }
+            self.previous_code = Some(code);
+        }
+
+        if output.len() > max_pixels {
+            output.truncate(max_pixels);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::vec;
+
+    /// A 2x2 GIF that loops forever between two frames: red and blue pixels in a checkerboard for
+    /// 100ms, then the opposite checkerboard for 200ms.
+    static ANIMATED_GIF: &'static [u8] = &[
+        0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x02, 0x00, 0x02, 0x00, 0x80, 0x00, 0x00,
+        0xff, 0x00, 0x00, 0x00, 0x00, 0xff,
+        0x21, 0xff, 0x0b, 0x4e, 0x45, 0x54, 0x53, 0x43, 0x41, 0x50, 0x45, 0x32, 0x2e, 0x30,
+        0x03, 0x01, 0x00, 0x00, 0x00,
+        0x21, 0xf9, 0x04, 0x00, 0x0a, 0x00, 0x00, 0x00,
+        0x2c, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x02, 0x00, 0x00,
+        0x02, 0x03, 0x44, 0x02, 0x05, 0x00,
+        0x21, 0xf9, 0x04, 0x00, 0x14, 0x00, 0x00, 0x00,
+        0x2c, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x02, 0x00, 0x00,
+        0x02, 0x03, 0x0c, 0x10, 0x05, 0x00,
+        0x3b,
+    ];
+
+    static RED: &'static [u8] = &[0xff, 0x00, 0x00, 0xff];
+    static BLUE: &'static [u8] = &[0x00, 0x00, 0xff, 0xff];
+
+    fn pixels(colors: &[&[u8]]) -> ~[u8] {
+        let mut pixels = ~[];
+        for color in colors.iter() {
+            pixels.push_all(*color);
+        }
+        pixels
+    }
+
+    /// Draws each frame in turn, and returns the whole logical screen after each one.
+    fn screens(gif: &Gif) -> ~[~[u8]] {
+        let mut screen = vec::from_elem((gif.width * gif.height * 4) as uint, 0u8);
+        let mut screens = ~[];
+        for frame in gif.frames.iter() {
+            frame.draw(screen, gif.width as uint);
+            screens.push(screen.clone());
+        }
+        screens
+    }
+
+    #[test]
+    fn should_decode_every_frame_of_an_animated_gif() {
+        let gif = decode(ANIMATED_GIF).unwrap();
+        assert_eq!((gif.width, gif.height), (2, 2));
+        assert_eq!(gif.loop_count, 0);
+        assert!(gif.complete);
+        assert_eq!(gif.frames.len(), 2);
+        assert_eq!(gif.frames[0].delay, 100);
+        assert_eq!(gif.frames[1].delay, 200);
+        let screens = screens(&gif);
+        assert_eq!(screens[0], pixels([RED, BLUE, BLUE, RED]));
+        assert_eq!(screens[1], pixels([BLUE, RED, RED, BLUE]));
+    }
+
+    #[test]
+    fn should_decode_the_frames_of_a_truncated_gif() {
+        // Cut the data off in the middle of the second frame's image data.
+        let gif = decode(ANIMATED_GIF.slice_to(ANIMATED_GIF.len() - 4)).unwrap();
+        assert!(!gif.complete);
+        assert_eq!(gif.frames.len(), 2);
+        assert_eq!(screens(&gif)[0], pixels([RED, BLUE, BLUE, RED]));
+    }
+
+    #[test]
+    fn should_decode_a_gif_that_arrives_a_byte_at_a_time() {
+        let mut decoder = GifDecoder::new();
+        for length in range(1, ANIMATED_GIF.len() + 1) {
+            decoder.decode_more(ANIMATED_GIF.slice_to(length));
+        }
+        let gif = decoder.finish().unwrap();
+        assert!(gif.complete);
+        assert_eq!(gif.frames.len(), 2);
+        let screens = screens(&gif);
+        assert_eq!(screens[0], pixels([RED, BLUE, BLUE, RED]));
+        assert_eq!(screens[1], pixels([BLUE, RED, RED, BLUE]));
+    }
+
+    #[test]
+    fn should_show_the_first_frame_once_its_image_data_arrives() {
+        let mut decoder = GifDecoder::new();
+        decoder.decode_more(ANIMATED_GIF.slice_to(40));
+        assert!(decoder.first_frame().is_none());
+        assert_eq!(decoder.first_frame_decoded_pixels(), 0);
+
+        // The first frame's image data ends at byte 62.
+        decoder.decode_more(ANIMATED_GIF.slice_to(62));
+        assert!(decoder.first_frame_complete());
+        assert_eq!(decoder.first_frame_decoded_pixels(), 4);
+        assert_eq!(decoder.first_frame().unwrap(), pixels([RED, BLUE, BLUE, RED]));
+    }
+
+    #[test]
+    fn should_not_decode_a_gif_without_image_data() {
+        assert!(decode(ANIMATED_GIF.slice_to(40)).is_none());
+        assert!(decode(bytes!("GIF89")).is_none());
+    }
+
+    #[test]
+    fn should_not_decode_a_gif_with_a_huge_logical_screen() {
+        let mut data = ANIMATED_GIF.to_owned();
+        for i in range(6u, 10) {
+            data[i] = 0xff;
+        }
+        let mut decoder = GifDecoder::new();
+        decoder.decode_more(data);
+        assert_eq!(decoder.dimensions(), (0, 0));
+        assert!(decoder.first_frame().is_none());
+        assert!(decoder.finish().is_none());
+    }
+
+    #[test]
+    fn should_only_keep_the_area_of_a_frame_that_changed() {
+        // The first frame of the GIF above, followed by a frame that turns the top right pixel
+        // red.
+        let mut data = ANIMATED_GIF.slice_to(62).to_owned();
+        data.push_all([
+            0x21, 0xf9, 0x04, 0x00, 0x14, 0x00, 0x00, 0x00,
+            0x2c, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00,
+            0x02, 0x02, 0x44, 0x01, 0x00,
+            0x3b,
+        ]);
+        let gif = decode(data).unwrap();
+        assert_eq!(gif.frames.len(), 2);
+        assert!(gif.frames[1].area == Area { left: 1, top: 0, width: 1, height: 1 });
+        assert_eq!(gif.frames[1].pixels, RED.to_owned());
+        assert_eq!(screens(&gif)[1], pixels([RED, RED, BLUE, RED]));
+
+        // When the first frame is cleared once it has been shown, the area it covered changes
+        // too.
+        data[41] = 0x08;
+        let gif = decode(data).unwrap();
+        assert!(gif.frames[1].area == Area { left: 0, top: 0, width: 2, height: 2 });
+        let clear: &[u8] = &[0x00, 0x00, 0x00, 0x00];
+        assert_eq!(screens(&gif)[1], pixels([clear, RED, clear, clear]));
+    }
+}
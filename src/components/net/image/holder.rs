@@ -2,8 +2,8 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use image::base::Image;
-use image_cache_task::{ImageReady, ImageNotReady, ImageFailed};
+use image::base::{Animation, Image};
+use image_cache_task::{ImageReady, ImagePartiallyReady, ImageNotReady, ImageFailed};
 use local_image_cache::LocalImageCache;
 
 use extra::arc::{Arc, MutexArc};
@@ -21,16 +21,27 @@ use std::util;
 pub struct ImageHolder {
     url: Url,
     image: Option<Arc<~Image>>,
+    animation: Option<Arc<~Animation>>,
     cached_size: Size2D<int>,
     local_image_cache: MutexArc<LocalImageCache>,
 }
 
 impl ImageHolder {
     pub fn new(url: Url, local_image_cache: MutexArc<LocalImageCache>) -> ImageHolder {
+        ImageHolder::new_with_target_size(url, local_image_cache, None)
+    }
+
+    /// Creates a holder for an image that will be drawn no larger than `target_size`, if one is
+    /// given, so that a large image can be shrunk when it is decoded.
+    pub fn new_with_target_size(url: Url,
+                                local_image_cache: MutexArc<LocalImageCache>,
+                                target_size: Option<Size2D<uint>>)
+                                -> ImageHolder {
         debug!("ImageHolder::new() {}", url.to_str());
         let holder = ImageHolder {
             url: url,
             image: None,
+            animation: None,
             cached_size: Size2D(0,0),
             local_image_cache: local_image_cache.clone(),
         };
@@ -43,7 +54,10 @@ impl ImageHolder {
         unsafe {
             holder.local_image_cache.unsafe_access(|local_image_cache| {
                 local_image_cache.prefetch(&holder.url);
-                local_image_cache.decode(&holder.url);
+                match target_size {
+                    Some(size) => local_image_cache.decode_to_size(&holder.url, size),
+                    None => local_image_cache.decode(&holder.url),
+                }
             });
         }
 
@@ -82,8 +96,14 @@ impl ImageHolder {
                 })
             };
             match port.recv() {
-                ImageReady(image) => {
+                ImageReady(image, animation) => {
                     self.image = Some(image);
+                    self.animation = animation;
+                }
+                ImagePartiallyReady(image) => {
+                    // Show what has arrived so far, but ask for the rest next time
+                    debug!("image partially loaded for {:s}", self.url.to_str());
+                    return Some(image);
                 }
                 ImageNotReady => {
                     debug!("image not ready for {:s}", self.url.to_str());
@@ -101,5 +121,11 @@ impl ImageHolder {
 
         return result;
     }
+
+    /// Returns the frames of the image if it is animated. `get_image` returns the first frame.
+    pub fn get_animation(&mut self) -> Option<Arc<~Animation>> {
+        self.get_image();
+        self.animation.clone()
+    }
 }
 
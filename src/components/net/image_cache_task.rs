@@ -2,16 +2,23 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use image::base::{Image, load_from_memory};
+use image::base::{Animation, Image, downscale_to_fit, load_animation_from_memory};
+use image::base::{PartialImageDecoder, load_from_memory, supports_partial_loading};
 use resource_task;
 use resource_task::ResourceTask;
+use servo_util::memory::{MemoryReport, MemoryReporter, MemoryReportsChan};
+use servo_util::task::spawn_named;
 use servo_util::url::{UrlMap, url_map};
 
+use geom::size::Size2D;
+use std::cmp;
+use std::ptr;
 use std::comm::{Chan, Port, SharedChan};
 use std::task::spawn;
 use std::to_str::ToStr;
 use std::util::replace;
 use std::result;
+use std::vec;
 use extra::arc::{Arc,MutexArc};
 use extra::url::Url;
 use extra::serialize::{Encoder, Encodable};
@@ -29,9 +36,20 @@ pub enum Msg {
     /// Tell the cache to decode an image. Must be posted before GetImage/WaitForImage
     Decode(Url),
 
-    /// Used by the decoder tasks to post decoded images back to the cache
+    /// Tell the cache to decode an image that will be drawn no larger than the given size, in
+    /// device pixels. Images much larger than that are shrunk when they are decoded, so that only
+    /// the small image is kept. Must be posted before GetImage/WaitForImage
+    DecodeToSize(Url, Size2D<uint>),
+
+    /// Used by the decoder tasks to post decoded images, and their frames if they are animated,
+    /// back to the cache
+    // FIXME: make this priv after visibility rules change
+    StoreImage(Url, Option<Arc<~Image>>, Option<Arc<~Animation>>),
+
+    /// Used by the prefetch tasks to post back the part of an image that has been decoded
+    /// while the rest of it is still downloading
     // FIXME: make this priv after visibility rules change
-    StoreImage(Url, Option<Arc<~Image>>),
+    StorePartialImage(Url, Arc<~Image>),
 
     /// Request an Image object for a URL. If the image is not is not immediately
     /// available then ImageNotReady is returned.
@@ -40,6 +58,17 @@ pub enum Msg {
     /// Wait for an image to become available (or fail to load).
     WaitForImage(Url, Chan<ImageResponseMsg>),
 
+    /// Wait for more of an image to be decoded while it downloads, or for the whole image to
+    /// become available (or fail to load). Used by clients that show partially loaded images.
+    WaitForImageProgress(Url, Chan<ImageResponseMsg>),
+
+    /// Send the memory used by the fetched and decoded images to the memory profiler
+    ReportMemory(MemoryReportsChan),
+
+    /// Set the number of bytes of fetched and decoded image data above which the least
+    /// recently used images are evicted. `None`, the default, keeps every image.
+    SetByteBudget(Option<uint>),
+
     /// Clients must wait for a response before shutting down the ResourceTask
     Exit(Chan<()>),
 
@@ -54,20 +83,39 @@ pub enum Msg {
 
 #[deriving(Clone)]
 pub enum ImageResponseMsg {
-    ImageReady(Arc<~Image>),
+    ImageReady(Arc<~Image>, Option<Arc<~Animation>>),
+    /// The image is still downloading; this is the part of it that has been decoded so far.
+    ImagePartiallyReady(Arc<~Image>),
     ImageNotReady,
     ImageFailed
 }
 
+/// Returns true if the two handles point to the same image, rather than to equal ones.
+fn same_arc<T: Freeze + Send>(a: &Arc<T>, b: &Arc<T>) -> bool {
+    ptr::to_unsafe_ptr(a.get()) == ptr::to_unsafe_ptr(b.get())
+}
+
 impl Eq for ImageResponseMsg {
     fn eq(&self, other: &ImageResponseMsg) -> bool {
-        // FIXME: Bad copies
-        match (self.clone(), other.clone()) {
-            (ImageReady(..), ImageReady(..)) => fail!(~"unimplemented comparison"),
-            (ImageNotReady, ImageNotReady) => true,
-            (ImageFailed, ImageFailed) => true,
+        match (self, other) {
+            (&ImageReady(ref image, ref animation),
+             &ImageReady(ref other_image, ref other_animation)) => {
+                same_arc(image, other_image) && match (animation, other_animation) {
+                    (&Some(ref animation), &Some(ref other_animation)) => {
+                        same_arc(animation, other_animation)
+                    }
+                    (&None, &None) => true,
+                    (&Some(..), &None) | (&None, &Some(..)) => false,
+                }
+            }
+            (&ImagePartiallyReady(ref image), &ImagePartiallyReady(ref other_image)) => {
+                same_arc(image, other_image)
+            }
+            (&ImageNotReady, &ImageNotReady) => true,
+            (&ImageFailed, &ImageFailed) => true,
 
-            (ImageReady(..), _) | (ImageNotReady, _) | (ImageFailed, _) => false
+            (&ImageReady(..), _) | (&ImagePartiallyReady(..), _) | (&ImageNotReady, _) |
+            (&ImageFailed, _) => false
         }
     }
 
@@ -88,17 +136,32 @@ impl<S: Encoder> Encodable<S> for ImageCacheTask {
 
 type DecoderFactory = fn() -> proc(&[u8]) -> Option<Image>;
 
+/// The number of tasks that decode images.
+static DECODER_TASK_COUNT: uint = 4;
+
 pub fn ImageCacheTask(resource_task: ResourceTask) -> ImageCacheTask {
     let (port, chan) = SharedChan::new();
     let chan_clone = chan.clone();
 
     spawn(proc() {
+        let decoders = vec::from_fn(DECODER_TASK_COUNT, |_| spawn_decoder(chan_clone.clone()));
         let mut cache = ImageCache {
             resource_task: resource_task.clone(),
             port: port,
             chan: chan_clone,
+            decoders: decoders,
+            next_decoder: 0,
+            decode_sizes: url_map(),
+            decoded_sizes: url_map(),
             state_map: url_map(),
             wait_map: url_map(),
+            progress_map: url_map(),
+            partial_map: url_map(),
+            previous_map: url_map(),
+            last_used: url_map(),
+            use_counter: 0,
+            bytes_used: 0,
+            byte_budget: None,
             need_exit: None
         };
         cache.run();
@@ -144,10 +207,33 @@ struct ImageCache {
     port: Port<Msg>,
     /// A copy of the shared chan to give to child tasks
     chan: SharedChan<Msg>,
+    /// Channels to the tasks that decode images, which are given images in turn
+    decoders: ~[Chan<DecoderMsg>],
+    /// The index in `decoders` of the task that decodes the next image
+    next_decoder: uint,
+    /// The largest size each image has been asked to be decoded at, or `None` for full size
+    decode_sizes: UrlMap<Option<Size2D<uint>>>,
+    /// The size each decoded image was shrunk to fit, or `None` if it was decoded at full size
+    decoded_sizes: UrlMap<Option<Size2D<uint>>>,
     /// The state of processsing an image for a URL
     state_map: UrlMap<ImageState>,
     /// List of clients waiting on a WaitForImage response
     wait_map: UrlMap<MutexArc<~[Chan<ImageResponseMsg>]>>,
+    /// List of clients waiting on a WaitForImageProgress response
+    progress_map: UrlMap<~[Chan<ImageResponseMsg>]>,
+    /// The part of each image being downloaded or decoded that can be shown already
+    partial_map: UrlMap<Arc<~Image>>,
+    /// The image shown while each image that was decoded too small is decoded again at a larger
+    /// size
+    previous_map: UrlMap<Arc<~Image>>,
+    /// The value of `use_counter` when each image was last stored or requested
+    last_used: UrlMap<uint>,
+    /// A counter that is incremented every time an image is used
+    use_counter: uint,
+    /// The number of bytes of fetched and decoded image data in `state_map`
+    bytes_used: uint,
+    /// The number of bytes above which images are evicted, if any
+    byte_budget: Option<uint>,
     need_exit: Option<Chan<()>>,
 }
 
@@ -157,8 +243,23 @@ enum ImageState {
     Prefetching(AfterPrefetch),
     Prefetched(~[u8]),
     Decoding,
-    Decoded(Arc<~Image>),
-    Failed
+    Decoded(Arc<~Image>, Option<Arc<~Animation>>),
+    Failed,
+    /// The image was fetched, but its data was evicted to keep the cache within its byte budget.
+    /// It is fetched and decoded again when it is next requested.
+    Evicted,
+}
+
+impl ImageState {
+    /// The number of bytes of image data held in this state.
+    fn byte_size(&self) -> uint {
+        match *self {
+            Prefetched(ref data) => data.len(),
+            Decoded(_, Some(ref animation)) => animation.get().byte_size(),
+            Decoded(ref image, None) => image.get().pixels.len(),
+            Init | Prefetching(..) | Decoding | Failed | Evicted => 0,
+        }
+    }
 }
 
 #[deriving(Clone)]
@@ -187,19 +288,29 @@ impl ImageCache {
 
                     self.store_prefetched_image_data(url, data);
                 }
-                Decode(url) => self.decode(url),
-                StoreImage(url, image) => {
+                Decode(url) => self.decode(url, None),
+                DecodeToSize(url, size) => self.decode(url, Some(size)),
+                StoreImage(url, image, animation) => {
                     store_chan.map(|chan| {
                         chan.send(());
                     });
                     store_chan = None;
 
-                    self.store_image(url, image)
+                    self.store_image(url, image, animation)
                 }
+                StorePartialImage(url, image) => self.store_partial_image(url, image),
                 GetImage(url, response) => self.get_image(url, response),
                 WaitForImage(url, response) => {
                     self.wait_for_image(url, response)
                 }
+                WaitForImageProgress(url, response) => {
+                    self.wait_for_image_progress(url, response)
+                }
+                ReportMemory(reports_chan) => self.report_memory(reports_chan),
+                SetByteBudget(byte_budget) => {
+                    self.byte_budget = byte_budget;
+                    self.evict_over_budget(None);
+                }
                 WaitForStore(chan) => store_chan = Some(chan),
                 WaitForStorePrefetched(chan) => store_prefetched_chan = Some(chan),
                 Exit(response) => {
@@ -220,7 +331,7 @@ impl ImageCache {
                         Prefetching(..) => can_exit = false,
                         Decoding => can_exit = false,
 
-                        Init | Prefetched(..) | Decoded(..) | Failed => ()
+                        Init | Prefetched(..) | Decoded(..) | Failed | Evicted => ()
                     }
                 }
 
@@ -236,6 +347,25 @@ impl ImageCache {
         }
     }
 
+    fn report_memory(&self, reports_chan: MemoryReportsChan) {
+        let mut encoded_size = 0;
+        let mut decoded_size = 0;
+        for (_, state) in self.state_map.iter() {
+            match *state {
+                Prefetched(..) => encoded_size += state.byte_size(),
+                Decoded(..) => decoded_size += state.byte_size(),
+                Init | Prefetching(..) | Decoding | Failed | Evicted => ()
+            }
+        }
+        let partial_size = self.partial_map.iter().chain(self.previous_map.iter()).fold(0,
+                |size, (_, image)| size + image.get().pixels.len());
+        reports_chan.try_send(~[
+            MemoryReport::new(~"images/encoded", encoded_size),
+            MemoryReport::new(~"images/decoded", decoded_size),
+            MemoryReport::new(~"images/partial", partial_size),
+        ]);
+    }
+
     fn get_state(&self, url: Url) -> ImageState {
         match self.state_map.find(&url) {
             Some(state) => state.clone(),
@@ -244,33 +374,87 @@ impl ImageCache {
     }
 
     fn set_state(&mut self, url: Url, state: ImageState) {
-        self.state_map.insert(url, state);
+        let new_size = state.byte_size();
+        let old_size = match self.state_map.swap(url, state) {
+            Some(old_state) => old_state.byte_size(),
+            None => 0,
+        };
+        self.bytes_used = self.bytes_used - old_size + new_size;
     }
 
-    fn prefetch(&mut self, url: Url) {
-        match self.get_state(url.clone()) {
-            Init => {
-                let to_cache = self.chan.clone();
-                let resource_task = self.resource_task.clone();
-                let url_clone = url.clone();
-
-                spawn(proc() {
-                    let url = url_clone;
-                    debug!("image_cache_task: started fetch for {:s}", url.to_str());
+    /// Records that the image was just used, so that it is the last to be evicted.
+    fn touch(&mut self, url: &Url) {
+        self.use_counter += 1;
+        self.last_used.insert(url.clone(), self.use_counter);
+    }
 
-                    let image = load_image_data(url.clone(), resource_task.clone());
+    /// Evicts the least recently used images until the cache is within its byte budget. The image
+    /// given in `keep` was just used and is never evicted, so that an image larger than the whole
+    /// budget stays available to the client that asked for it.
+    fn evict_over_budget(&mut self, keep: Option<&Url>) {
+        let byte_budget = match self.byte_budget {
+            Some(byte_budget) => byte_budget,
+            None => return,
+        };
 
-                    let result = if image.is_ok() {
-                        Ok(image.unwrap())
-                    } else {
-                        Err(())
-                    };
-                    to_cache.send(StorePrefetchedImageData(url.clone(), result));
-                    debug!("image_cache_task: ended fetch for {:s}", (url.clone()).to_str());
-                });
+        while self.bytes_used > byte_budget {
+            let mut victim: Option<(Url, uint)> = None;
+            for (url, state) in self.state_map.iter() {
+                if state.byte_size() == 0 || keep == Some(url) {
+                    continue
+                }
+                let last_used = self.last_used.find(url).map_or(0, |&last_used| last_used);
+                match victim {
+                    Some((_, victim_last_used)) if victim_last_used <= last_used => {}
+                    _ => victim = Some((url.clone(), last_used)),
+                }
+            }
 
-                self.set_state(url, Prefetching(DoNotDecode));
+            match victim {
+                Some((url, _)) => {
+                    debug!("image_cache_task: evicting {:s}", url.to_str());
+                    self.set_state(url, Evicted);
+                }
+                None => break,
             }
+        }
+    }
+
+    /// Starts fetching the image data. The data is decoded once it arrives if `next_step` is
+    /// `DoDecode`.
+    fn fetch(&mut self, url: Url, next_step: AfterPrefetch) {
+        let to_cache = self.chan.clone();
+        let resource_task = self.resource_task.clone();
+        let url_clone = url.clone();
+
+        spawn(proc() {
+            let url = url_clone;
+            debug!("image_cache_task: started fetch for {:s}", url.to_str());
+
+            let mut partial_decoder = PartialImageDecoder::new();
+            let image = load_image_data(url.clone(), resource_task.clone(), |data| {
+                match partial_decoder.decode_more(data) {
+                    Some(image) => to_cache.send(StorePartialImage(url.clone(),
+                                                                   Arc::new(~image))),
+                    None => {}
+                }
+            });
+
+            let result = if image.is_ok() {
+                Ok(image.unwrap())
+            } else {
+                Err(())
+            };
+            to_cache.send(StorePrefetchedImageData(url.clone(), result));
+            debug!("image_cache_task: ended fetch for {:s}", (url.clone()).to_str());
+        });
+
+        self.set_state(url, Prefetching(next_step));
+    }
+
+    fn prefetch(&mut self, url: Url) {
+        match self.get_state(url.clone()) {
+            Init | Evicted => self.fetch(url, DoNotDecode),
 
             Prefetching(..) | Prefetched(..) | Decoding | Decoded(..) | Failed => {
                 // We've already begun working on this image
@@ -284,14 +468,16 @@ impl ImageCache {
             match data {
               Ok(data) => {
                 self.set_state(url.clone(), Prefetched(data));
+                self.touch(&url);
+                self.evict_over_budget(Some(&url));
                 match next_step {
-                  DoDecode => self.decode(url),
+                  DoDecode => self.start_decode(url),
                   _ => ()
                 }
               }
               Err(..) => {
-                self.set_state(url.clone(), Failed);
-                self.purge_waiters(url, || ImageFailed);
+                self.partial_map.remove(&url);
+                self.fail(url);
               }
             }
           }
@@ -300,13 +486,32 @@ impl ImageCache {
           | Prefetched(..)
           | Decoding
           | Decoded(..)
-          | Failed => {
+          | Failed
+          | Evicted => {
             fail!(~"wrong state for storing prefetched image")
           }
         }
     }
 
-    fn decode(&mut self, url: Url) {
+    /// Records the size at which an image is needed, or `None` if it is needed at full size, and
+    /// decodes it once it has been fetched.
+    fn decode(&mut self, url: Url, size: Option<Size2D<uint>>) {
+        let needed_size = match self.decode_sizes.find(&url) {
+            Some(&needed_size) => larger_decode_size(needed_size, size),
+            None => size,
+        };
+        self.decode_sizes.insert(url.clone(), needed_size);
+        self.start_decode(url)
+    }
+
+    /// Returns false if the image was shrunk to fit a smaller size than it is now needed at.
+    fn decoded_large_enough(&self, url: &Url) -> bool {
+        let decoded_size = self.decoded_sizes.find(url).map_or(None, |&size| size);
+        let needed_size = self.decode_sizes.find(url).map_or(None, |&size| size);
+        larger_decode_size(decoded_size, needed_size) == decoded_size
+    }
+
+    fn start_decode(&mut self, url: Url) {
         match self.get_state(url.clone()) {
             Init => fail!(~"decoding image before prefetch"),
 
@@ -319,45 +524,78 @@ impl ImageCache {
                 // We don't have the data yet, but the decode request is queued up
             }
 
+            Evicted => {
+                // The data has to be fetched again before it can be decoded
+                self.fetch(url, DoDecode)
+            }
+
             Prefetched(data) => {
-                let to_cache = self.chan.clone();
-                let url_clone = url.clone();
+                let size = self.decode_sizes.find(&url).map_or(None, |&size| size);
+                self.decoded_sizes.insert(url.clone(), size);
 
-                spawn(proc() {
-                    let url = url_clone;
-                    debug!("image_cache_task: started image decode for {:s}", url.to_str());
-                    let image = load_from_memory(data);
-                    let image = if image.is_some() {
-                        Some(Arc::new(~image.unwrap()))
-                    } else {
-                        None
-                    };
-                    to_cache.send(StoreImage(url.clone(), image));
-                    debug!("image_cache_task: ended image decode for {:s}", url.to_str());
-                });
+                let decoder = self.next_decoder % self.decoders.len();
+                self.next_decoder += 1;
+                self.decoders[decoder].send(DecodeImageMsg(url.clone(), data, size));
 
                 self.set_state(url, Decoding);
             }
 
+            Decoded(image, _) if !self.decoded_large_enough(&url) => {
+                // The image is needed at a larger size than it was decoded at
+                self.decode_again(url, image)
+            }
+
             Decoding | Decoded(..) | Failed => {
                 // We've already begun decoding
             }
         }
     }
 
-    fn store_image(&mut self, url: Url, image: Option<Arc<~Image>>) {
+    /// Fetches and decodes an image again, because it is now needed at a larger size than it was
+    /// decoded at. The smaller image is shown until the larger one is ready.
+    fn decode_again(&mut self, url: Url, image: Arc<~Image>) {
+        self.previous_map.insert(url.clone(), image);
+        self.fetch(url, DoDecode)
+    }
+
+    /// Records that an image failed to load, unless it is being decoded again at a larger size,
+    /// in which case the smaller image is kept.
+    fn fail(&mut self, url: Url) {
+        match self.previous_map.pop(&url) {
+            Some(image) => {
+                self.set_state(url.clone(), Decoded(image.clone(), None));
+                self.purge_waiters(url, || ImageReady(image.clone(), None));
+            }
+            None => {
+                self.set_state(url.clone(), Failed);
+                self.purge_waiters(url, || ImageFailed);
+            }
+        }
+    }
+
+    fn store_image(&mut self,
+                   url: Url,
+                   image: Option<Arc<~Image>>,
+                   animation: Option<Arc<~Animation>>) {
+        self.partial_map.remove(&url);
 
         match self.get_state(url.clone()) {
           Decoding => {
             match image {
               Some(image) => {
-                self.set_state(url.clone(), Decoded(image.clone()));
-                self.purge_waiters(url, || ImageReady(image.clone()) );
-              }
-              None => {
-                self.set_state(url.clone(), Failed);
-                self.purge_waiters(url, || ImageFailed );
+                self.previous_map.remove(&url);
+                self.set_state(url.clone(), Decoded(image.clone(), animation.clone()));
+                self.touch(&url);
+                self.evict_over_budget(Some(&url));
+                self.purge_waiters(url.clone(), || ImageReady(image.clone(), animation.clone()) );
+
+                // If the image has been asked for at a larger size since it began decoding,
+                // decode it again
+                if !self.decoded_large_enough(&url) {
+                    self.decode_again(url, image);
+                }
               }
+              None => self.fail(url),
             }
           }
 
@@ -365,13 +603,55 @@ impl ImageCache {
           | Prefetching(..)
           | Prefetched(..)
           | Decoded(..)
-          | Failed => {
+          | Failed
+          | Evicted => {
             fail!(~"incorrect state in store_image")
           }
         }
 
     }
 
+    fn store_partial_image(&mut self, url: Url, image: Arc<~Image>) {
+        match self.get_state(url.clone()) {
+            Prefetching(DoDecode) if !self.previous_map.contains_key(&url) => {
+                // Show the new part of the image to the clients that are waiting for it. Clients
+                // waiting for the whole image keep waiting.
+                self.partial_map.insert(url.clone(), image.clone());
+                match self.progress_map.pop(&url) {
+                    Some(waiters) => {
+                        for response in waiters.iter() {
+                            response.send(ImagePartiallyReady(image.clone()));
+                        }
+                    }
+                    None => ()
+                }
+            }
+
+            Prefetching(DoDecode) => {
+                // The image decoded at a smaller size is shown until all of it has arrived
+            }
+
+            Init
+            | Prefetching(DoNotDecode)
+            | Prefetched(..)
+            | Decoding
+            | Decoded(..)
+            | Failed
+            | Evicted => {
+                // Nobody has asked to see the image yet, or all of it has already arrived
+            }
+        }
+    }
+
+    /// Returns the image decoded at a smaller size while the image is decoded again, or else the
+    /// part of the image that has been decoded while it downloads, if any.
+    fn partial_response(&self, url: &Url) -> ImageResponseMsg {
+        match self.previous_map.find(url).or(self.partial_map.find(url)) {
+            Some(image) => ImagePartiallyReady(image.clone()),
+            None => ImageNotReady,
+        }
+    }
+
     fn purge_waiters(&mut self, url: Url, f: || -> ImageResponseMsg) {
         match self.wait_map.pop(&url) {
             Some(waiters) => {
@@ -385,25 +665,45 @@ impl ImageCache {
             }
             None => ()
         }
+        match self.progress_map.pop(&url) {
+            Some(waiters) => {
+                for response in waiters.iter() {
+                    response.send(f());
+                }
+            }
+            None => ()
+        }
     }
 
-    fn get_image(&self, url: Url, response: Chan<ImageResponseMsg>) {
+    fn get_image(&mut self, url: Url, response: Chan<ImageResponseMsg>) {
+        self.touch(&url);
         match self.get_state(url.clone()) {
             Init => fail!(~"request for image before prefetch"),
-            Prefetching(DoDecode) => response.send(ImageNotReady),
+            Prefetching(DoDecode) => response.send(self.partial_response(&url)),
             Prefetching(DoNotDecode) | Prefetched(..) => fail!(~"request for image before decode"),
-            Decoding => response.send(ImageNotReady),
-            Decoded(image) => response.send(ImageReady(image.clone())),
+            Decoding => response.send(self.partial_response(&url)),
+            Decoded(image, animation) => response.send(ImageReady(image, animation)),
             Failed => response.send(ImageFailed),
+            Evicted => {
+                self.fetch(url, DoDecode);
+                response.send(ImageNotReady)
+            }
         }
     }
 
     fn wait_for_image(&mut self, url: Url, response: Chan<ImageResponseMsg>) {
+        self.touch(&url);
         match self.get_state(url.clone()) {
             Init => fail!(~"request for image before prefetch"),
 
             Prefetching(DoNotDecode) | Prefetched(..) => fail!(~"request for image before decode"),
 
+            Evicted => {
+                // Fetch the image again and wait for it like any other image being decoded
+                self.fetch(url.clone(), DoDecode);
+                self.wait_for_image(url, response)
+            }
+
             Prefetching(DoDecode) | Decoding => {
                 // We don't have this image yet
                 if self.wait_map.contains_key(&url) {
@@ -417,8 +717,8 @@ impl ImageCache {
                 }
             }
 
-            Decoded(image) => {
-                response.send(ImageReady(image.clone()));
+            Decoded(image, animation) => {
+                response.send(ImageReady(image, animation));
             }
 
             Failed => {
@@ -427,9 +727,84 @@ impl ImageCache {
         }
     }
 
+    fn wait_for_image_progress(&mut self, url: Url, response: Chan<ImageResponseMsg>) {
+        match self.get_state(url.clone()) {
+            Evicted => {
+                self.fetch(url.clone(), DoDecode);
+                self.wait_for_image_progress(url, response)
+            }
+
+            Prefetching(DoDecode) | Decoding => {
+                // More of the image may be shown before all of it is available
+                self.touch(&url);
+                self.progress_map.find_or_insert_with(url, |_| ~[]).push(response);
+            }
+
+            Init | Prefetching(DoNotDecode) | Prefetched(..) | Decoded(..) | Failed => {
+                self.wait_for_image(url, response)
+            }
+        }
+    }
 }
 
 
+/// Returns the larger of two sizes at which an image is needed, where `None` means full size.
+fn larger_decode_size(a: Option<Size2D<uint>>, b: Option<Size2D<uint>>) -> Option<Size2D<uint>> {
+    match (a, b) {
+        (Some(a), Some(b)) => {
+            Some(Size2D(cmp::max(a.width, b.width), cmp::max(a.height, b.height)))
+        }
+        (None, _) | (_, None) => None,
+    }
+}
+
+/// Messages to a decoder task.
+enum DecoderMsg {
+    /// Decodes the image data, shrinking still images to fit the given size, and posts the
+    /// result back to the cache.
+    DecodeImageMsg(Url, ~[u8], Option<Size2D<uint>>),
+}
+
+/// Spawns a task that decodes images. It exits once the cache drops its channel.
+fn spawn_decoder(to_cache: SharedChan<Msg>) -> Chan<DecoderMsg> {
+    let (port, chan) = Chan::new();
+    spawn_named("ImageDecoder", proc() {
+        loop {
+            match port.recv_opt() {
+                Some(DecodeImageMsg(url, data, size)) => {
+                    debug!("image_cache_task: started image decode for {:s}", url.to_str());
+                    let (image, animation) = decode_image(data, size);
+                    to_cache.send(StoreImage(url.clone(), image, animation));
+                    debug!("image_cache_task: ended image decode for {:s}", url.to_str());
+                }
+                None => break,
+            }
+        }
+    });
+    chan
+}
+
+/// Decodes an image, along with its frames if it is animated. Animated images are always kept at
+/// full size.
+fn decode_image(data: &[u8], size: Option<Size2D<uint>>)
+                -> (Option<Arc<~Image>>, Option<Arc<~Animation>>) {
+    match load_animation_from_memory(data) {
+        Some(animation) => {
+            let first_frame = animation.frames[0].image.clone();
+            (Some(first_frame), Some(Arc::new(~animation)))
+        }
+        None => {
+            let image = load_from_memory(data).map(|image| {
+                match size {
+                    Some(size) => downscale_to_fit(image, size),
+                    None => image,
+                }
+            });
+            (image.map(|image| Arc::new(~image)), None)
+        }
+    }
+}
+
 trait ImageCacheTaskClient {
     fn exit(&self);
 }
@@ -462,17 +837,37 @@ impl ImageCacheTask {
     }
 }
 
-fn load_image_data(url: Url, resource_task: ResourceTask) -> Result<~[u8], ()> {
+impl MemoryReporter for ImageCacheTask {
+    fn collect_reports(&self, reports_chan: MemoryReportsChan) -> bool {
+        self.chan.try_send(ReportMemory(reports_chan))
+    }
+}
+
+/// The number of bytes that have to arrive before more of a partially downloaded image is decoded
+/// and shown.
+static PARTIAL_DECODE_INTERVAL: uint = 16 * 1024;
+
+/// Fetches the data of an image. If the image can be shown before all of it has arrived,
+/// `on_partial_data` is called with the data received so far every `PARTIAL_DECODE_INTERVAL`
+/// bytes.
+fn load_image_data(url: Url, resource_task: ResourceTask, on_partial_data: |&[u8]|)
+                   -> Result<~[u8], ()> {
     let (response_port, response_chan) = Chan::new();
     resource_task.send(resource_task::Load(url, response_chan));
 
     let mut image_data = ~[];
+    let mut partial_decode_size = PARTIAL_DECODE_INTERVAL;
 
     let progress_port = response_port.recv().progress_port;
     loop {
         match progress_port.recv() {
             resource_task::Payload(data) => {
                 image_data.push_all(data);
+                if image_data.len() >= partial_decode_size &&
+                        supports_partial_loading(image_data) {
+                    on_partial_data(image_data.as_slice());
+                    partial_decode_size = image_data.len() + PARTIAL_DECODE_INTERVAL;
+                }
             }
             resource_task::Done(result::Ok(..)) => {
                 return Ok(image_data);
@@ -494,6 +889,8 @@ mod tests {
     use image::base::test_image_bin;
     use util::spawn_listener;
     use servo_util::url::parse_url;
+    use extra::url::Url;
+    use geom::size::Size2D;
 
     fn mock_resource_task(on_load: proc(resource: SharedChan<resource_task::ProgressMsg>)) -> ResourceTask {
         spawn_listener("mock_resource_task", proc(port: Port<resource_task::ControlMsg>) {
@@ -617,7 +1014,7 @@ mod tests {
         let (response_port, response_chan) = Chan::new();
         image_cache_task.send(GetImage(url, response_chan));
         match response_port.recv() {
-          ImageReady(_) => (),
+          ImageReady(..) => (),
           _ => fail!("bleh")
         }
 
@@ -647,7 +1044,7 @@ mod tests {
             let (response_port, response_chan) = Chan::new();
             image_cache_task.send(GetImage(url.clone(), response_chan));
             match response_port.recv() {
-              ImageReady(_) => (),
+              ImageReady(..) => (),
               _ => fail!("bleh")
             }
         }
@@ -933,6 +1330,66 @@ mod tests {
         mock_resource_task.send(resource_task::Exit);
     }
 
+    /// A 1x1 GIF followed by a comment long enough for the image to be shown before the rest of
+    /// its data, the trailer, arrives.
+    fn partial_gif_bin() -> ~[u8] {
+        let mut data = ~[
+            0x47u8, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00,
+            0xff, 0x00, 0x00, 0x00, 0x00, 0xff,
+            0x2c, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00,
+            0x02, 0x02, 0x44, 0x01, 0x00,
+            0x21, 0xfe,
+        ];
+        for _ in range(0, 100) {
+            data.push(0xff);
+            data.push_all([0x20u8, ..255]);
+        }
+        data.push(0x00);
+        data
+    }
+
+    #[test]
+    fn should_only_send_partial_images_to_clients_waiting_for_progress() {
+        let (start_port, start_chan) = Chan::new();
+        let (wait_port, wait_chan) = Chan::new();
+
+        let mock_resource_task = mock_resource_task(proc(response) {
+            start_port.recv();
+            response.send(resource_task::Payload(partial_gif_bin()));
+            wait_port.recv();
+            response.send(resource_task::Payload(~[0x3b]));
+            response.send(resource_task::Done(Ok(())));
+        });
+
+        let image_cache_task = ImageCacheTask(mock_resource_task.clone());
+        let url = parse_url("file", None);
+
+        image_cache_task.send(Prefetch(url.clone()));
+        image_cache_task.send(Decode(url.clone()));
+
+        let (image_port, image_chan) = Chan::new();
+        image_cache_task.send(WaitForImage(url.clone(), image_chan));
+        let (progress_port, progress_chan) = Chan::new();
+        image_cache_task.send(WaitForImageProgress(url, progress_chan));
+        start_chan.send(());
+
+        match progress_port.recv() {
+          ImagePartiallyReady(..) => (),
+          _ => fail!("expected the part of the image that has arrived")
+        }
+        // The clients waiting for the whole image are not sent the part of it that has arrived
+        assert!(image_port.try_recv().is_none());
+
+        wait_chan.send(());
+        match image_port.recv() {
+          ImageReady(..) => (),
+          _ => fail!("expected the decoded image")
+        }
+
+        image_cache_task.exit();
+        mock_resource_task.send(resource_task::Exit);
+    }
+
     #[test]
     fn sync_cache_should_wait_for_images() {
         let mock_resource_task = mock_resource_task(proc(response) {
@@ -949,11 +1406,191 @@ mod tests {
         let (response_port, response_chan) = Chan::new();
         image_cache_task.send(GetImage(url, response_chan));
         match response_port.recv() {
-          ImageReady(_) => (),
+          ImageReady(..) => (),
+          _ => fail!("bleh")
+        }
+
+        image_cache_task.exit();
+        mock_resource_task.send(resource_task::Exit);
+    }
+
+    #[test]
+    fn should_show_the_smaller_image_while_decoding_it_again_at_a_larger_size() {
+        let (wait_port, wait_chan) = Chan::new();
+
+        let mock_resource_task = spawn_listener("should_show_the_smaller_image", proc(port: Port<resource_task::ControlMsg>) {
+            let mut loads = 0;
+            loop {
+                match port.recv() {
+                    resource_task::Load(_, response) => {
+                        loads += 1;
+                        if loads == 2 {
+                            // Don't send the data again until the client has asked for the image
+                            wait_port.recv();
+                        }
+                        let chan = start_sending(response, Metadata::default(parse_url("file:///fake", None)));
+                        chan.send(resource_task::Payload(test_image_bin()));
+                        chan.send(resource_task::Done(Ok(())));
+                    }
+                    resource_task::Exit => break
+                }
+            }
+        });
+
+        let image_cache_task = ImageCacheTask(mock_resource_task.clone());
+        let url = parse_url("file", None);
+
+        image_cache_task.send(Prefetch(url.clone()));
+        image_cache_task.send(DecodeToSize(url.clone(), Size2D(1, 1)));
+        let (response_port, response_chan) = Chan::new();
+        image_cache_task.send(WaitForImage(url.clone(), response_chan));
+        let small_image = match response_port.recv() {
+          ImageReady(image, _) => image,
+          _ => fail!("expected the decoded image")
+        };
+
+        image_cache_task.send(DecodeToSize(url.clone(), Size2D(1000, 1000)));
+        let (response_port, response_chan) = Chan::new();
+        image_cache_task.send(GetImage(url.clone(), response_chan));
+        assert!(response_port.recv() == ImagePartiallyReady(small_image.clone()));
+
+        wait_chan.send(());
+        let (response_port, response_chan) = Chan::new();
+        image_cache_task.send(WaitForImage(url, response_chan));
+        match response_port.recv() {
+          ImageReady(image, _) => assert!(image.get().width > small_image.get().width),
+          _ => fail!("expected the image decoded at the larger size")
+        }
+
+        image_cache_task.exit();
+        mock_resource_task.send(resource_task::Exit);
+    }
+
+    fn mock_counting_resource_task(name: &'static str,
+                                   url_requested_chan: Chan<Url>,
+                                   resource_task_exited_chan: Chan<()>)
+                                   -> ResourceTask {
+        spawn_listener(name, proc(port: Port<resource_task::ControlMsg>) {
+            loop {
+                match port.recv() {
+                    resource_task::Load(url, response) => {
+                        let chan = start_sending(response, Metadata::default(parse_url("file:///fake", None)));
+                        chan.send(resource_task::Payload(test_image_bin()));
+                        chan.send(resource_task::Done(Ok(())));
+                        url_requested_chan.send(url);
+                    }
+                    resource_task::Exit => {
+                        resource_task_exited_chan.send(());
+                        break
+                    }
+                }
+            }
+        })
+    }
+
+    fn load_and_wait(image_cache_task: &ImageCacheTask, url: &Url) {
+        image_cache_task.send(Prefetch(url.clone()));
+        image_cache_task.send(Decode(url.clone()));
+
+        let (response_port, response_chan) = Chan::new();
+        image_cache_task.send(WaitForImage(url.clone(), response_chan));
+        match response_port.recv() {
+          ImageReady(..) => (),
           _ => fail!("bleh")
         }
+    }
+
+    #[test]
+    fn should_refetch_evicted_image_when_requested_again() {
+        let (url_requested, url_requested_chan) = Chan::new();
+        let (resource_task_exited, resource_task_exited_chan) = Chan::new();
+        let mock_resource_task = mock_counting_resource_task("should_refetch_evicted_image",
+                                                             url_requested_chan,
+                                                             resource_task_exited_chan);
+
+        let image_cache_task = ImageCacheTask(mock_resource_task.clone());
+        // Every image is bigger than this, so only the most recently used image is kept
+        image_cache_task.send(SetByteBudget(Some(1)));
+        let url1 = parse_url("file:///1.jpeg", None);
+        let url2 = parse_url("file:///2.jpeg", None);
+
+        load_and_wait(&image_cache_task, &url1);
+        load_and_wait(&image_cache_task, &url2);
+        assert!(url_requested.recv() == url1);
+        assert!(url_requested.recv() == url2);
+
+        // Storing the second image evicted the first, so it has to be fetched again
+        let (response_port, response_chan) = Chan::new();
+        image_cache_task.send(GetImage(url1.clone(), response_chan));
+        assert!(response_port.recv() == ImageNotReady);
+
+        let (response_port, response_chan) = Chan::new();
+        image_cache_task.send(WaitForImage(url1.clone(), response_chan));
+        match response_port.recv() {
+          ImageReady(..) => (),
+          _ => fail!("bleh")
+        }
+        assert!(url_requested.recv() == url1);
 
         image_cache_task.exit();
         mock_resource_task.send(resource_task::Exit);
+        resource_task_exited.recv();
+    }
+
+    #[test]
+    fn should_not_evict_images_within_budget() {
+        let (url_requested, url_requested_chan) = Chan::new();
+        let (resource_task_exited, resource_task_exited_chan) = Chan::new();
+        let mock_resource_task = mock_counting_resource_task("should_not_evict_images",
+                                                             url_requested_chan,
+                                                             resource_task_exited_chan);
+
+        let image_cache_task = ImageCacheTask(mock_resource_task.clone());
+        image_cache_task.send(SetByteBudget(Some(100 * 1024 * 1024)));
+        let url1 = parse_url("file:///1.jpeg", None);
+        let url2 = parse_url("file:///2.jpeg", None);
+
+        load_and_wait(&image_cache_task, &url1);
+        load_and_wait(&image_cache_task, &url2);
+
+        let (response_port, response_chan) = Chan::new();
+        image_cache_task.send(GetImage(url1, response_chan));
+        match response_port.recv() {
+          ImageReady(..) => (),
+          _ => fail!("bleh")
+        }
+
+        image_cache_task.exit();
+        mock_resource_task.send(resource_task::Exit);
+        resource_task_exited.recv();
+
+        // Each image was only fetched once
+        url_requested.recv();
+        url_requested.recv();
+        assert!(url_requested.try_recv().is_none());
+    }
+
+    #[test]
+    fn should_evict_images_when_budget_is_lowered() {
+        let (url_requested, url_requested_chan) = Chan::new();
+        let (resource_task_exited, resource_task_exited_chan) = Chan::new();
+        let mock_resource_task = mock_counting_resource_task("should_evict_when_lowered",
+                                                             url_requested_chan,
+                                                             resource_task_exited_chan);
+
+        let image_cache_task = ImageCacheTask(mock_resource_task.clone());
+        let url = parse_url("file:///1.jpeg", None);
+        load_and_wait(&image_cache_task, &url);
+        assert!(url_requested.recv() == url);
+
+        image_cache_task.send(SetByteBudget(Some(0)));
+        let (response_port, response_chan) = Chan::new();
+        image_cache_task.send(GetImage(url.clone(), response_chan));
+        assert!(response_port.recv() == ImageNotReady);
+        assert!(url_requested.recv() == url);
+
+        image_cache_task.exit();
+        mock_resource_task.send(resource_task::Exit);
+        resource_task_exited.recv();
     }
 }
@@ -8,9 +8,11 @@ extra message traffic, it also avoids waiting on the same image
 multiple times and thus triggering reflows multiple times.
 */
 
-use image_cache_task::{Decode, GetImage, ImageCacheTask, ImageFailed, ImageNotReady, ImageReady};
-use image_cache_task::{ImageResponseMsg, Prefetch, WaitForImage};
+use image_cache_task::{Decode, DecodeToSize, GetImage, ImageCacheTask, ImageFailed};
+use image_cache_task::{ImageNotReady, ImagePartiallyReady, ImageReady, ImageResponseMsg};
+use image_cache_task::{Prefetch, WaitForImageProgress};
 
+use geom::size::Size2D;
 use std::comm::Port;
 use servo_util::url::{UrlMap, url_map};
 use extra::url::Url;
@@ -40,6 +42,8 @@ pub struct LocalImageCache {
 struct ImageState {
     prefetched: bool,
     decoded: bool,
+    /// The largest size a decode has been asked for at, if it has not been asked for at full size
+    decoded_size: Option<Size2D<uint>>,
     last_request_round: uint,
     last_response: ImageResponseMsg
 }
@@ -77,6 +81,24 @@ impl LocalImageCache {
         self.image_cache_task.send(Decode((*url).clone()));
     }
 
+    /// Like `decode`, but for an image that will be drawn no larger than the given size.
+    pub fn decode_to_size(&mut self, url: &Url, size: Size2D<uint>) {
+        {
+            let state = self.get_state(url);
+            if state.decoded {
+                return
+            }
+            match state.decoded_size {
+                Some(decoded_size) if decoded_size.width >= size.width &&
+                                      decoded_size.height >= size.height => return,
+                _ => {}
+            }
+            state.decoded_size = Some(size);
+        }
+
+        self.image_cache_task.send(DecodeToSize((*url).clone(), size));
+    }
+
     // FIXME: Should return a Future
     pub fn get_image(&mut self, url: &Url) -> Port<ImageResponseMsg> {
         {
@@ -88,15 +110,15 @@ impl LocalImageCache {
             state.last_request_round = self.round_number;
 
             match state.last_response {
-                ImageReady(ref image) => {
+                ImageReady(ref image, ref animation) => {
                     let (port, chan) = Chan::new();
-                    chan.send(ImageReady(image.clone()));
+                    chan.send(ImageReady(image.clone(), animation.clone()));
                     return port;
                 }
-                ImageNotReady => {
+                ImagePartiallyReady(..) | ImageNotReady => {
                     if last_round == self.round_number {
                         let (port, chan) = Chan::new();
-                        chan.send(ImageNotReady);
+                        chan.send(state.last_response.clone());
                         return port;
                     } else {
                         // We haven't requested the image from the
@@ -116,8 +138,8 @@ impl LocalImageCache {
 
         let response = response_port.recv();
         match response {
-            ImageNotReady => {
-                // Need to reflow when the image is available
+            ImagePartiallyReady(..) | ImageNotReady => {
+                // Need to reflow when more of the image is available
                 // FIXME: Instead we should be just passing a Future
                 // to the caller, then to the display list. Finally,
                 // the compositor should be resonsible for waiting
@@ -128,7 +150,7 @@ impl LocalImageCache {
                 let url = (*url).clone();
                 do spawn_named("LocalImageCache") {
                     let (response_port, response_chan) = Chan::new();
-                    image_cache_task.send(WaitForImage(url.clone(), response_chan));
+                    image_cache_task.send(WaitForImageProgress(url.clone(), response_chan));
                     on_image_available(response_port.recv());
                 }
             }
@@ -137,7 +159,8 @@ impl LocalImageCache {
 
         // Put a copy of the response in the cache
         let response_copy = match response {
-            ImageReady(ref image) => ImageReady(image.clone()),
+            ImageReady(ref image, ref animation) => ImageReady(image.clone(), animation.clone()),
+            ImagePartiallyReady(ref image) => ImagePartiallyReady(image.clone()),
             ImageNotReady => ImageNotReady,
             ImageFailed => ImageFailed
         };
@@ -153,6 +176,7 @@ impl LocalImageCache {
             let new_state = ImageState {
                 prefetched: false,
                 decoded: false,
+                decoded_size: None,
                 last_request_round: 0,
                 last_response: ImageNotReady
             };
@@ -21,6 +21,7 @@ extern mod png;
 /// caching is involved) and as a result it must live in here.
 pub mod image {
     pub mod base;
+    pub mod gif;
     pub mod holder;
 }
 
@@ -30,5 +31,6 @@ pub mod data_loader;
 pub mod image_cache_task;
 pub mod local_image_cache;
 pub mod resource_task;
+pub mod storage_task;
 pub mod util;
 
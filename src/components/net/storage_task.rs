@@ -0,0 +1,377 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A task that keeps the key/value maps behind `sessionStorage` and `localStorage`, one map per
+//! origin. Session storage lives as long as the task, which is owned by the constellation of the
+//! single top-level browsing context. Local storage is written to a directory if one is given.
+
+use extra::hex::ToHex;
+use extra::json;
+use extra::json::{Object, String};
+use extra::treemap::TreeMap;
+use servo_util::io::result;
+use std::comm::{Chan, Port, SharedChan};
+use std::hashmap::HashMap;
+use std::io::File;
+use std::io::fs;
+use std::str;
+use util::spawn_listener;
+
+#[cfg(test)]
+use extra::tempfile::TempDir;
+#[cfg(test)]
+use std::vec;
+
+/// The number of bytes of keys and values that each origin can store.
+static QUOTA_SIZE: uint = 5 * 1024 * 1024;
+
+#[deriving(Clone, Eq, Encodable)]
+pub enum StorageType {
+    SessionStorage,
+    LocalStorage,
+}
+
+/// Messages sent to the storage task. Each names the storage type and the origin it is for, in
+/// the ASCII serialization of origins.
+pub enum StorageTaskMsg {
+    /// Gets the number of key/value pairs.
+    Length(Chan<uint>, StorageType, ~str),
+    /// Gets the key at the given index, in key order.
+    Key(Chan<Option<~str>>, StorageType, ~str, uint),
+    /// Gets the value of the given key.
+    GetItem(Chan<Option<~str>>, StorageType, ~str, ~str),
+    /// Sets the value of the given key. Replies with the old value if the value changed, or
+    /// `Err` if the origin would exceed its quota.
+    SetItem(Chan<Result<Option<Option<~str>>, ()>>, StorageType, ~str, ~str, ~str),
+    /// Removes the given key, replying with its old value.
+    RemoveItem(Chan<Option<~str>>, StorageType, ~str, ~str),
+    /// Removes every key, replying with true if there were any.
+    Clear(Chan<bool>, StorageType, ~str),
+    Exit,
+}
+
+/// Handle to a storage task
+pub type StorageTask = SharedChan<StorageTaskMsg>;
+
+/// Creates a storage task that persists local storage in `local_storage_dir`, if given.
+pub fn StorageTask(local_storage_dir: Option<Path>) -> StorageTask {
+    spawn_listener("StorageManager", proc(port) {
+        let mut manager = StorageManager::new(port, local_storage_dir);
+        manager.start();
+    })
+}
+
+/// The key/value pairs of one origin. The number of bytes they take up is kept alongside, so
+/// that quota checks don't need to walk the whole map.
+struct OriginStorage {
+    items: TreeMap<~str, ~str>,
+    size: uint,
+}
+
+impl OriginStorage {
+    fn new(items: TreeMap<~str, ~str>) -> OriginStorage {
+        let size = items.iter().fold(0, |size, (key, value)| size + key.len() + value.len());
+        OriginStorage {
+            items: items,
+            size: size,
+        }
+    }
+
+    /// Sets the value of the given key, returning its old value.
+    fn insert(&mut self, key: ~str, value: ~str) -> Option<~str> {
+        let key_len = key.len();
+        let value_len = value.len();
+        match self.items.swap(key, value) {
+            Some(old_value) => {
+                self.size = self.size - old_value.len() + value_len;
+                Some(old_value)
+            }
+            None => {
+                self.size += key_len + value_len;
+                None
+            }
+        }
+    }
+
+    /// Removes the given key, returning its old value.
+    fn pop(&mut self, key: &~str) -> Option<~str> {
+        let old_value = self.items.pop(key);
+        for old_value in old_value.iter() {
+            self.size -= key.len() + old_value.len();
+        }
+        old_value
+    }
+
+    fn clear(&mut self) {
+        self.items.clear();
+        self.size = 0;
+    }
+}
+
+struct StorageManager {
+    port: Port<StorageTaskMsg>,
+    session_data: HashMap<~str, OriginStorage>,
+    local_data: HashMap<~str, OriginStorage>,
+    local_storage_dir: Option<Path>,
+}
+
+impl StorageManager {
+    fn new(port: Port<StorageTaskMsg>, local_storage_dir: Option<Path>) -> StorageManager {
+        StorageManager {
+            port: port,
+            session_data: HashMap::new(),
+            local_data: HashMap::new(),
+            local_storage_dir: local_storage_dir,
+        }
+    }
+
+    fn start(&mut self) {
+        loop {
+            match self.port.recv() {
+                Length(sender, storage_type, origin) => {
+                    sender.send(self.data(storage_type, origin).items.len())
+                }
+                Key(sender, storage_type, origin, index) => {
+                    let data = self.data(storage_type, origin);
+                    let key = data.items.iter().nth(index).map(|(key, _)| key.clone());
+                    sender.send(key)
+                }
+                GetItem(sender, storage_type, origin, key) => {
+                    let data = self.data(storage_type, origin);
+                    sender.send(data.items.find(&key).map(|value| value.clone()))
+                }
+                SetItem(sender, storage_type, origin, key, value) => {
+                    sender.send(self.set_item(storage_type, origin, key, value))
+                }
+                RemoveItem(sender, storage_type, origin, key) => {
+                    let old_value = self.data(storage_type, origin.clone()).pop(&key);
+                    if old_value.is_some() {
+                        self.save(storage_type, origin);
+                    }
+                    sender.send(old_value)
+                }
+                Clear(sender, storage_type, origin) => {
+                    let changed = {
+                        let data = self.data(storage_type, origin.clone());
+                        let changed = !data.items.is_empty();
+                        data.clear();
+                        changed
+                    };
+                    if changed {
+                        self.save(storage_type, origin);
+                    }
+                    sender.send(changed)
+                }
+                Exit => break,
+            }
+        }
+    }
+
+    fn set_item(&mut self, storage_type: StorageType, origin: ~str, key: ~str, value: ~str)
+                -> Result<Option<Option<~str>>, ()> {
+        let old_value = {
+            let data = self.data(storage_type, origin.clone());
+            let old_size = match data.items.find(&key) {
+                Some(old_value) if *old_value == value => return Ok(None),
+                Some(old_value) => key.len() + old_value.len(),
+                None => 0,
+            };
+            if data.size - old_size + key.len() + value.len() > QUOTA_SIZE {
+                return Err(());
+            }
+
+            data.insert(key, value)
+        };
+        self.save(storage_type, origin);
+        Ok(Some(old_value))
+    }
+
+    /// Returns the map of the given origin, loading it from disk the first time local storage is
+    /// used.
+    fn data<'a>(&'a mut self, storage_type: StorageType, origin: ~str)
+                -> &'a mut OriginStorage {
+        match storage_type {
+            SessionStorage => {
+                self.session_data.find_or_insert_with(origin, |_| {
+                    OriginStorage::new(TreeMap::new())
+                })
+            }
+            LocalStorage => {
+                let path = self.path(origin.as_slice());
+                self.local_data.find_or_insert_with(origin, |_| {
+                    OriginStorage::new(path.as_ref().map_default(TreeMap::new(), |path| load(path)))
+                })
+            }
+        }
+    }
+
+    /// Writes the local storage of the given origin to disk, if local storage is persisted. The
+    /// data is written to a temporary file that then replaces the old one, so that a failed write
+    /// doesn't lose what was stored before.
+    fn save(&mut self, storage_type: StorageType, origin: ~str) {
+        if storage_type != LocalStorage {
+            return;
+        }
+        let path = match self.path(origin.as_slice()) {
+            Some(path) => path,
+            None => return,
+        };
+
+        let mut object = ~TreeMap::new();
+        for (key, value) in self.data(storage_type, origin).items.iter() {
+            object.insert(key.clone(), String(value.clone()));
+        }
+        let json = Object(object).to_str();
+        let temp_path = path.with_extension("tmp");
+        let written = result(|| {
+            match File::create(&temp_path) {
+                Some(mut file) => file.write(json.as_bytes()),
+                None => return,
+            }
+            fs::rename(&temp_path, &path);
+        });
+        if written.is_err() {
+            error!("storage: couldn't write local storage to {}", path.display());
+        }
+    }
+
+    /// Returns the file that holds the local storage of the given origin. The origin is hex
+    /// encoded so that it makes a valid file name.
+    fn path(&self, origin: &str) -> Option<Path> {
+        self.local_storage_dir.as_ref().map(|dir| dir.join(origin.as_bytes().to_hex()))
+    }
+}
+
+/// Reads a local storage file written by `StorageManager::save`. Missing or malformed files
+/// result in an empty storage.
+fn load(path: &Path) -> TreeMap<~str, ~str> {
+    let mut data = TreeMap::new();
+    if !path.exists() {
+        return data;
+    }
+
+    let contents = match result(|| File::open(path).map(|mut file| file.read_to_end())) {
+        Ok(Some(contents)) => contents,
+        _ => return data,
+    };
+    let contents = match str::from_utf8_opt(contents) {
+        Some(contents) => contents,
+        None => return data,
+    };
+    match json::from_str(contents) {
+        Ok(Object(object)) => {
+            for (key, value) in object.move_iter() {
+                match value {
+                    String(value) => { data.insert(key, value); }
+                    _ => {}
+                }
+            }
+        }
+        _ => error!("storage: ignoring malformed local storage file {}", path.display()),
+    }
+    data
+}
+
+#[cfg(test)]
+fn set_item(storage_task: &StorageTask, storage_type: StorageType, key: &str, value: &str)
+            -> Result<Option<Option<~str>>, ()> {
+    let (port, chan) = Chan::new();
+    storage_task.send(SetItem(chan, storage_type, ~"http://example.com", key.to_owned(),
+                              value.to_owned()));
+    port.recv()
+}
+
+#[cfg(test)]
+fn get_item(storage_task: &StorageTask, storage_type: StorageType, key: &str) -> Option<~str> {
+    let (port, chan) = Chan::new();
+    storage_task.send(GetItem(chan, storage_type, ~"http://example.com", key.to_owned()));
+    port.recv()
+}
+
+#[test]
+fn test_set_get_and_remove() {
+    let storage_task = StorageTask(None);
+    assert!(set_item(&storage_task, SessionStorage, "a", "1") == Ok(Some(None)));
+    assert!(set_item(&storage_task, SessionStorage, "a", "1") == Ok(None));
+    assert!(set_item(&storage_task, SessionStorage, "a", "2") == Ok(Some(Some(~"1"))));
+    assert!(get_item(&storage_task, SessionStorage, "a") == Some(~"2"));
+
+    let (port, chan) = Chan::new();
+    storage_task.send(RemoveItem(chan, SessionStorage, ~"http://example.com", ~"a"));
+    assert!(port.recv() == Some(~"2"));
+    assert!(get_item(&storage_task, SessionStorage, "a") == None);
+    storage_task.send(Exit);
+}
+
+#[test]
+fn test_keys_are_ordered() {
+    let storage_task = StorageTask(None);
+    set_item(&storage_task, LocalStorage, "b", "");
+    set_item(&storage_task, LocalStorage, "a", "");
+
+    let (port, chan) = Chan::new();
+    storage_task.send(Length(chan, LocalStorage, ~"http://example.com"));
+    assert!(port.recv() == 2);
+    let (port, chan) = Chan::new();
+    storage_task.send(Key(chan, LocalStorage, ~"http://example.com", 0));
+    assert!(port.recv() == Some(~"a"));
+    let (port, chan) = Chan::new();
+    storage_task.send(Key(chan, LocalStorage, ~"http://example.com", 2));
+    assert!(port.recv() == None);
+    storage_task.send(Exit);
+}
+
+#[test]
+fn test_storage_types_and_origins_are_separate() {
+    let storage_task = StorageTask(None);
+    set_item(&storage_task, LocalStorage, "a", "1");
+    assert!(get_item(&storage_task, SessionStorage, "a") == None);
+
+    let (port, chan) = Chan::new();
+    storage_task.send(GetItem(chan, LocalStorage, ~"http://example.org", ~"a"));
+    assert!(port.recv() == None);
+    storage_task.send(Exit);
+}
+
+#[test]
+fn test_quota() {
+    let storage_task = StorageTask(None);
+    let value = str::from_chars(vec::from_elem(QUOTA_SIZE, 'a'));
+    assert!(set_item(&storage_task, SessionStorage, "a", value).is_err());
+    assert!(get_item(&storage_task, SessionStorage, "a") == None);
+    storage_task.send(Exit);
+}
+
+#[test]
+fn test_local_storage_is_persisted() {
+    let dir = TempDir::new("servo-storage").unwrap();
+
+    let storage_task = StorageTask(Some(dir.path().clone()));
+    set_item(&storage_task, LocalStorage, "a", "1");
+    set_item(&storage_task, SessionStorage, "b", "2");
+    storage_task.send(Exit);
+
+    assert!(fs::readdir(dir.path()).len() == 1);
+
+    let storage_task = StorageTask(Some(dir.path().clone()));
+    assert!(get_item(&storage_task, LocalStorage, "a") == Some(~"1"));
+    assert!(get_item(&storage_task, SessionStorage, "b") == None);
+    storage_task.send(Exit);
+}
+
+#[test]
+fn test_quota_counts_replaced_and_removed_items() {
+    let storage_task = StorageTask(None);
+    let value = str::from_chars(vec::from_elem(QUOTA_SIZE - 2, 'a'));
+    assert!(set_item(&storage_task, SessionStorage, "a", value).is_ok());
+    assert!(set_item(&storage_task, SessionStorage, "bb", "").is_err());
+    assert!(set_item(&storage_task, SessionStorage, "a", "").is_ok());
+    assert!(set_item(&storage_task, SessionStorage, "b", value).is_ok());
+
+    let (port, chan) = Chan::new();
+    storage_task.send(RemoveItem(chan, SessionStorage, ~"http://example.com", ~"b"));
+    port.recv();
+    assert!(set_item(&storage_task, SessionStorage, "c", value).is_ok());
+    storage_task.send(Exit);
+}
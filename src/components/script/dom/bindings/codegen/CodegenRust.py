@@ -718,7 +718,7 @@ def getJSToNativeConversionTemplate(type, descriptorProvider, failureCode=None,
             if isinstance(defaultValue, IDLNullValue):
                 assert(type.nullable())
                 return handleDefault(conversionCode,
-                                     "${declName}.SetNull()")
+                                     "${declName} = None")
 
             value = "str::from_utf8(data).to_owned()"
             if type.nullable():
@@ -814,9 +814,11 @@ def getJSToNativeConversionTemplate(type, descriptorProvider, failureCode=None,
     if type.isAny():
         assert not isEnforceRange and not isClamp
 
-        if isMember:
-            raise TypeError("Can't handle member 'any'; need to sort out "
-                            "rooting issues")
+        # Dictionaries only live on the stack for the duration of the call,
+        # so their 'any' members are kept alive by the conservative stack
+        # scanner like any other argument.
+        if isMember and isOptional:
+            raise TypeError("Can't handle member 'any' without a default value")
 
         declType = CGGeneric("JSVal")
         value = CGGeneric("${val}")
@@ -1035,9 +1037,6 @@ class CGArgumentConverter(CGThing):
                  invalidEnumValueFatal=True):
         CGThing.__init__(self)
         self.argument = argument
-        if argument.variadic:
-            raise TypeError("We don't support variadic arguments yet " +
-                            str(argument.location))
         assert(not argument.defaultValue or argument.optional)
 
         replacer = {
@@ -1063,8 +1062,11 @@ class CGArgumentConverter(CGThing):
         else:
             self.argcAndIndex = None
         self.invalidEnumValueFatal = invalidEnumValueFatal
+        self.variadicReplacer = replacer
 
     def define(self):
+        if self.argument.variadic:
+            return self.defineVariadic()
         return instantiateJSToNativeConversionTemplate(
             getJSToNativeConversionTemplate(self.argument.type,
                                             self.descriptorProvider,
@@ -1077,6 +1079,45 @@ class CGArgumentConverter(CGThing):
             self.replacementVariables,
             self.argcAndIndex).define()
 
+    def defineVariadic(self):
+        # A variadic argument is converted into a vector holding every remaining
+        # argument, each converted as if it were a single argument.
+        template = getJSToNativeConversionTemplate(self.argument.type,
+                                                   self.descriptorProvider,
+                                                   invalidEnumValueFatal=self.invalidEnumValueFatal,
+                                                   treatNullAs=self.argument.treatNullAs,
+                                                   isEnforceRange=self.argument.enforceRange,
+                                                   isClamp=self.argument.clamp)
+        (_, declType, _, _, _) = template
+        if declType is None:
+            raise TypeError("Can't handle variadic arguments without a declared type " +
+                            str(self.argument.location))
+        val = string.Template("(*${argv}.offset(variadicArg as int))").substitute(
+            self.variadicReplacer)
+        conversion = instantiateJSToNativeConversionTemplate(
+            template,
+            {
+                "declName" : "slot",
+                "holderName" : "slot_holder",
+                "val" : val,
+                "valPtr" : "&" + val
+            })
+        replacer = dict(self.variadicReplacer)
+        replacer["declName"] = self.replacementVariables["declName"]
+        replacer["declType"] = declType.define()
+        return CGList([
+            CGGeneric(string.Template(
+                "let mut ${declName}: ~[${declType}] = ~[];\n"
+                "if ${argc} > ${index} {\n"
+                "  ${declName}.reserve((${argc} - ${index}) as uint);\n"
+                "  for variadicArg in range(${index}, ${argc}) {").substitute(replacer)),
+            CGIndenter(conversion, 4),
+            CGGeneric(string.Template(
+                "    ${declName}.push(slot);\n"
+                "  }\n"
+                "}").substitute(replacer))
+        ], "\n").define()
+
 def getWrapTemplateForType(type, descriptorProvider, result, successCode,
                            isCreator, exceptionCode):
     """
@@ -2344,12 +2385,15 @@ class CGDefineDOMInterfaceMethod(CGAbstractMethod):
         body = ""
         #XXXjdm This self.descriptor.concrete check shouldn't be necessary
         if not self.descriptor.concrete or self.descriptor.proxy:
+            deleter = "ptr::null()"
+            if self.descriptor.concrete and self.descriptor.operations['NamedDeleter']:
+                deleter = "delete_"
             body += """  let traps = ProxyTraps {
     getPropertyDescriptor: getPropertyDescriptor,
     getOwnPropertyDescriptor: getOwnPropertyDescriptor,
     defineProperty: defineProperty,
     getOwnPropertyNames: ptr::null(),
-    delete_: ptr::null(),
+    delete_: %s,
     enumerate: ptr::null(),
 
     has: ptr::null(),
@@ -2378,7 +2422,8 @@ class CGDefineDOMInterfaceMethod(CGAbstractMethod):
   js_info.dom_static.proxy_handlers.insert(PrototypeList::id::%s as uint,
                                            CreateProxyHandler(ptr::to_unsafe_ptr(&traps), ptr::to_unsafe_ptr(&Class) as *libc::c_void));
 
-""" % (FINALIZE_HOOK_NAME,
+""" % (deleter,
+       FINALIZE_HOOK_NAME,
        ('Some(%s)' % TRACE_HOOK_NAME),
        self.descriptor.name)
         else:
@@ -3835,6 +3880,10 @@ class CGProxySpecialOperation(CGPerSignatureCall):
 
     def getArguments(self):
         args = [(a, a.identifier.name) for a in self.arguments]
+        if self.idlNode.isNamed():
+            # The callers put the name of the property in `name`, whatever the
+            # argument is called in the IDL.
+            args[0] = (self.arguments[0], "name")
         if self.idlNode.isGetter():
             args.append((FakeArgument(BuiltinTypes[IDLBuiltinType.Types.boolean],
                                       self.idlNode),
@@ -3881,6 +3930,13 @@ class CGProxyNamedSetter(CGProxySpecialOperation):
     def __init__(self, descriptor):
         CGProxySpecialOperation.__init__(self, descriptor, 'NamedSetter')
 
+class CGProxyNamedDeleter(CGProxySpecialOperation):
+    """
+    Class to generate a call to a named deleter.
+    """
+    def __init__(self, descriptor):
+        CGProxySpecialOperation.__init__(self, descriptor, 'NamedDeleter')
+
 class CGProxyUnwrap(CGAbstractMethod):
     def __init__(self, descriptor):
         args = [Argument('*JSObject', 'obj')]
@@ -3933,7 +3989,7 @@ class CGDOMJSProxyHandler_getOwnPropertyDescriptor(CGAbstractExternMethod):
                                     "    return 1;\n" +
                                     "  }\n")
             if self.descriptor.operations['NamedSetter']:
-                setOrIndexedGet += "  if RUST_JSID_IS_STRING(id) {\n"
+                setOrIndexedGet += "  if RUST_JSID_IS_STRING(id) != 0 {\n"
                 if not 'NamedCreator' in self.descriptor.operations:
                     # FIXME need to check that this is a 'supported property name'
                     assert False
@@ -4022,9 +4078,10 @@ class CGDOMJSProxyHandler_defineProperty(CGAbstractExternMethod):
                 raise TypeError("Can't handle creator that's different from the setter")
             set += ("if RUST_JSID_IS_STRING(id) != 0 {\n" +
                     "  let name = Some(jsid_to_str(cx, id));\n" +
-                    "  let this: *%s = UnwrapProxy(proxy);\n" +
+                    "  let this: *mut %s = UnwrapProxy(proxy) as *mut %s;\n" +
                     CGIndenter(CGProxyNamedSetter(self.descriptor)).define() + "\n" +
-                    "}\n") % (self.descriptor.concreteType)
+                    "  return 1;\n" +
+                    "}\n") % (self.descriptor.concreteType, self.descriptor.concreteType)
         elif self.descriptor.operations['NamedGetter']:
             set += ("if RUST_JSID_IS_STRING(id) {\n" +
                     "  let name = Some(jsid_to_str(cx, id));\n" +
@@ -4130,12 +4187,12 @@ if expando.is_not_null() {
             getIndexedOrExpando = getFromExpando + "\n"
 
         namedGetter = self.descriptor.operations['NamedGetter']
-        if namedGetter and False: #XXXjdm unfinished
-            getNamed = ("if (JSID_IS_STRING(id)) {\n" +
+        if namedGetter:
+            getNamed = ("if RUST_JSID_IS_STRING(id) != 0 {\n" +
                         "  let name = Some(jsid_to_str(cx, id));\n" +
-                        "  let this = UnwrapProxy(proxy);\n" +
+                        "  let this: *%s = UnwrapProxy(proxy);\n" +
                         CGIndenter(CGProxyNamedGetter(self.descriptor, templateValues)).define() +
-                        "}\n") % (self.descriptor.concreteType)
+                        "\n}\n") % (self.descriptor.concreteType)
         else:
             getNamed = ""
 
@@ -4158,6 +4215,30 @@ return 1;""" % (getIndexedOrExpando, getNamed)
     def definition_body(self):
         return self.getBody()
 
+class CGDOMJSProxyHandler_delete(CGAbstractExternMethod):
+    def __init__(self, descriptor):
+        args = [Argument('*JSContext', 'cx'), Argument('*JSObject', 'proxy'),
+                Argument('jsid', 'id'), Argument('*mut bool', 'bp')]
+        CGAbstractExternMethod.__init__(self, descriptor, "delete_", "JSBool", args)
+        self.descriptor = descriptor
+    def getBody(self):
+        if self.descriptor.operations['IndexedDeleter']:
+            raise TypeError("Can't handle indexed deleters yet")
+
+        # Names that are on the prototype are deleted from the expando object, like any
+        # other property that isn't a supported property name.
+        delete = ("if RUST_JSID_IS_STRING(id) != 0 && !HasPropertyOnPrototype(cx, proxy, id) {\n" +
+                  "  let name = Some(jsid_to_str(cx, id));\n" +
+                  "  let this: *mut %s = UnwrapProxy(proxy) as *mut %s;\n" +
+                  CGIndenter(CGProxyNamedDeleter(self.descriptor)).define() + "\n" +
+                  "  *bp = true;\n" +
+                  "  return 1;\n" +
+                  "}\n") % (self.descriptor.concreteType, self.descriptor.concreteType)
+        return delete + """return proxyhandler::delete_(%s);""" % ", ".join(a.name for a in self.args)
+
+    def definition_body(self):
+        return self.getBody()
+
 class CGDOMJSProxyHandler_obj_toString(CGAbstractExternMethod):
     def __init__(self, descriptor):
         args = [Argument('*JSContext', 'cx'), Argument('*JSObject', 'proxy')]
@@ -4371,6 +4452,8 @@ class CGDescriptor(CGThing):
                 cgThings.append(CGDOMJSProxyHandler_hasOwn(descriptor))
                 if descriptor.operations['IndexedSetter'] or descriptor.operations['NamedSetter']:
                     cgThings.append(CGDOMJSProxyHandler_defineProperty(descriptor))
+                if descriptor.operations['NamedDeleter']:
+                    cgThings.append(CGDOMJSProxyHandler_delete(descriptor))
 
                 #cgThings.append(CGDOMJSProxyHandler(descriptor))
                 #cgThings.append(CGIsMethod(descriptor))
@@ -4510,6 +4593,8 @@ class CGDictionary(CGThing):
                 return '~""'
             elif ty.startswith("Option"):
                 return "None"
+            elif ty == "JSVal":
+                return "NullValue()"
             else:
                 return "/* uh oh: %s */" % ty
 
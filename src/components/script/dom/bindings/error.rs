@@ -10,12 +10,19 @@ use js::glue::{ReportError};
 #[deriving(ToStr)]
 pub enum Error {
     FailureUnknown,
+    IndexSize,
     NotFound,
     HierarchyRequest,
+    WrongDocument,
     InvalidCharacter,
     NotSupported,
     InvalidState,
-    NamespaceError
+    Syntax,
+    InvalidNodeType,
+    NamespaceError,
+    Security,
+    DataClone,
+    QuotaExceeded
 }
 
 pub type Fallible<T> = Result<T, Error>;
@@ -27,7 +34,11 @@ pub fn throw_method_failed_with_details<T>(cx: *JSContext,
                                            interface: &'static str,
                                            member: &'static str) -> JSBool {
     assert!(result.is_err());
-    assert!(unsafe { JS_IsExceptionPending(cx) } == 0);
+    // An exception thrown by script that the method called, such as a node filter, is
+    // propagated as it is.
+    if unsafe { JS_IsExceptionPending(cx) } != 0 {
+        return 0;
+    }
     let message = format!("Method failed: {}.{}", interface, member);
     message.with_c_str(|string| {
         unsafe { ReportError(cx, string) };
@@ -7,8 +7,11 @@ use dom::window::Window;
 use js::jsapi::{JSContext, JSObject};
 use layout_interface::TrustedNodeAddress;
 
+use extra::serialize::{Encoder, Encodable};
 use std::cast;
 use std::cell::RefCell;
+use std::ptr;
+use std::rc::Rc;
 use std::unstable::raw::Box;
 
 pub struct JS<T> {
@@ -107,3 +110,74 @@ impl<From, To> JS<From> {
         cast::transmute_copy(self)
     }
 }
+
+/// A reference to a DOM object that neither keeps it alive nor is traced, for the objects that a
+/// node tells about its changes, like its live ranges. It is cleared when the object is freed.
+pub struct Weak<T> {
+    priv ptr: Rc<RefCell<Option<*mut T>>>,
+}
+
+impl<T> Eq for Weak<T> {
+    fn eq(&self, other: &Weak<T>) -> bool {
+        ptr::to_unsafe_ptr(self.ptr.borrow()) == ptr::to_unsafe_ptr(other.ptr.borrow())
+    }
+}
+
+impl<T> Clone for Weak<T> {
+    fn clone(&self) -> Weak<T> {
+        Weak {
+            ptr: self.ptr.clone()
+        }
+    }
+}
+
+impl<T: Reflectable> Weak<T> {
+    /// Returns the object, or `None` if it has been freed.
+    pub fn upgrade(&self) -> Option<JS<T>> {
+        let ptr = self.ptr.borrow().borrow();
+        (*ptr.get()).map(|ptr| unsafe { JS::from_raw(ptr) })
+    }
+}
+
+impl<S: Encoder, T> Encodable<S> for Weak<T> {
+    fn encode(&self, _s: &mut S) {
+    }
+}
+
+/// The end of the `Weak` references to a DOM object that the object itself holds. It clears
+/// them when the object is finalized.
+pub struct WeakReferent<T> {
+    priv ptr: Rc<RefCell<Option<*mut T>>>,
+}
+
+impl<T: Reflectable> WeakReferent<T> {
+    pub fn new() -> WeakReferent<T> {
+        WeakReferent {
+            ptr: Rc::from_mut(RefCell::new(None))
+        }
+    }
+
+    /// Returns a weak reference to `obj`, which must be the object that holds this referent.
+    pub fn downgrade(&self, obj: &JS<T>) -> Weak<T> {
+        {
+            let mut ptr = self.ptr.borrow().borrow_mut();
+            *ptr.get() = Some(unsafe { obj.unsafe_get() });
+        }
+        Weak {
+            ptr: self.ptr.clone()
+        }
+    }
+}
+
+#[unsafe_destructor]
+impl<T> Drop for WeakReferent<T> {
+    fn drop(&mut self) {
+        let mut ptr = self.ptr.borrow().borrow_mut();
+        *ptr.get() = None;
+    }
+}
+
+impl<S: Encoder, T> Encodable<S> for WeakReferent<T> {
+    fn encode(&self, _s: &mut S) {
+    }
+}
@@ -6,7 +6,8 @@ use dom::bindings::utils::is_dom_proxy;
 use js::jsapi::{JSContext, jsid, JSPropertyDescriptor, JSObject, JSString, jschar};
 use js::jsapi::{JS_GetPropertyDescriptorById, JS_NewUCString, JS_malloc, JS_free};
 use js::jsapi::{JSBool, JS_DefinePropertyById, JS_NewObjectWithGivenProto};
-use js::jsval::ObjectValue;
+use js::jsapi::{JS_DeletePropertyById2};
+use js::jsval::{ObjectValue, UndefinedValue};
 use js::glue::GetProxyExtra;
 use js::glue::{GetObjectProto, GetObjectParent, SetProxyExtra, GetProxyHandler};
 use js::glue::InvokeGetOwnPropertyDescriptor;
@@ -72,6 +73,26 @@ pub extern fn defineProperty(cx: *JSContext, proxy: *JSObject, id: jsid,
     defineProperty_(cx, proxy, id, desc)
 }
 
+/// Deletes a property that isn't a supported property name of the proxy from its expando
+/// object.
+pub fn delete_(cx: *JSContext, proxy: *JSObject, id: jsid, bp: *mut bool) -> JSBool {
+    unsafe {
+        let expando = GetExpandoObject(proxy);
+        if expando.is_null() {
+            *bp = true;
+            return 1;
+        }
+
+        let value = UndefinedValue();
+        if JS_DeletePropertyById2(cx, expando, id, &value) == 0 {
+            return 0;
+        }
+
+        *bp = value.to_boolean();
+        return 1;
+    }
+}
+
 pub fn _obj_toString(cx: *JSContext, className: *libc::c_char) -> *JSString {
   unsafe {
     let name = str::raw::from_c_str(className);
@@ -6,6 +6,7 @@ use dom::bindings::js::JS;
 use dom::bindings::utils::{Reflectable, Reflector};
 
 use js::jsapi::{JSTracer, JS_CallTracer, JSTRACE_OBJECT};
+use js::jsval::JSVal;
 
 use std::cast;
 use std::libc;
@@ -34,6 +35,22 @@ pub trait Traceable {
     fn trace(&self, trc: *mut JSTracer);
 }
 
+pub fn trace_jsval(tracer: *mut JSTracer, description: &str, val: JSVal) {
+    if !val.is_markable() {
+        return;
+    }
+
+    unsafe {
+        description.to_c_str().with_ref(|name| {
+            (*tracer).debugPrinter = ptr::null();
+            (*tracer).debugPrintIndex = -1;
+            (*tracer).debugPrintArg = name as *libc::c_void;
+            debug!("tracing value {:s}", description);
+            JS_CallTracer(tracer as *JSTracer, val.to_gcthing(), val.trace_kind());
+        });
+    }
+}
+
 pub fn trace_reflector(tracer: *mut JSTracer, description: &str, reflector: &Reflector) {
     unsafe {
         description.to_c_str().with_ref(|name| {
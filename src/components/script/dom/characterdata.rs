@@ -4,14 +4,17 @@
 
 //! DOM bindings for `CharacterData`.
 
-use dom::bindings::codegen::InheritTypes::CharacterDataDerived;
+use dom::bindings::codegen::InheritTypes::{CharacterDataDerived, NodeCast};
 use dom::bindings::js::JS;
-use dom::bindings::error::{Fallible, ErrorResult};
+use dom::bindings::error::{Fallible, ErrorResult, IndexSize};
 use dom::bindings::utils::{Reflectable, Reflector};
 use dom::document::Document;
 use dom::eventtarget::{EventTarget, NodeTargetTypeId};
+use dom::mutationobserver::queue_mutation_record;
+use dom::mutationrecord::CharacterDataMutation;
 use dom::node::{CommentNodeTypeId, Node, NodeTypeId, TextNodeTypeId, ProcessingInstructionNodeTypeId};
-use servo_util::str::DOMString;
+use dom::range::update_ranges_for_replaced_data;
+use servo_util::str::{DOMString, utf16_len, utf16_offset_to_byte_index};
 
 #[deriving(Encodable)]
 pub struct CharacterData {
@@ -38,38 +41,100 @@ impl CharacterData {
         }
     }
     
-    pub fn Data(&self) -> DOMString {
-        self.data.clone()
+    /// Queues a mutation record for a change of the data, with the current data as the old
+    /// value.
+    fn queue_data_mutation_record(&self, abstract_self: &JS<CharacterData>) {
+        let node: JS<Node> = NodeCast::from(abstract_self);
+        queue_mutation_record(&node, CharacterDataMutation(self.data.clone()));
+    }
+
+    /// Returns the byte indices in the data of the `count` UTF-16 code units starting at
+    /// `offset`, with `count` clamped to the end of the data. Offsets past the end of the data,
+    /// and offsets that would split a surrogate pair, which a `DOMString` can't represent, are
+    /// index size errors.
+    fn byte_range(&self, offset: u32, count: u32) -> Fallible<(uint, uint)> {
+        let length = self.Length();
+        if offset > length {
+            return Err(IndexSize);
+        }
+        let count = if count > length - offset { length - offset } else { count };
+        let start = utf16_offset_to_byte_index(self.data.as_slice(), offset as uint);
+        let end = utf16_offset_to_byte_index(self.data.as_slice(), (offset + count) as uint);
+        match (start, end) {
+            (Some(start), Some(end)) => Ok((start, end)),
+            _ => Err(IndexSize),
+        }
     }
 
-    pub fn SetData(&mut self, arg: DOMString) -> ErrorResult {
-        self.data = arg;
+    /// Replaces `count` UTF-16 code units of the data, starting at `offset`, with `data`.
+    // http://dom.spec.whatwg.org/#concept-cd-replace
+    pub fn replace_data(&mut self, abstract_self: &JS<CharacterData>, offset: u32, count: u32,
+                        data: DOMString) -> ErrorResult {
+        // Step 1-3.
+        let (start, end) = match self.byte_range(offset, count) {
+            Ok(range) => range,
+            Err(error) => return Err(error),
+        };
+        let count = utf16_len(self.data.slice(start, end)) as u32;
+
+        // Step 4.
+        self.queue_data_mutation_record(abstract_self);
+
+        // Step 5-7.
+        let mut new_data = self.data.slice_to(start).to_owned();
+        new_data.push_str(data.as_slice());
+        new_data.push_str(self.data.slice_from(end));
+        self.data = new_data;
+
+        // Step 8-11.
+        let node: JS<Node> = NodeCast::from(abstract_self);
+        update_ranges_for_replaced_data(&node, offset, count, utf16_len(data.as_slice()) as u32);
         Ok(())
     }
 
+    pub fn Data(&self, _abstract_self: &JS<CharacterData>) -> DOMString {
+        self.data.clone()
+    }
+
+    pub fn SetData(&mut self, abstract_self: &JS<CharacterData>, arg: DOMString) -> ErrorResult {
+        let length = self.Length();
+        self.replace_data(abstract_self, 0, length, arg)
+    }
+
     pub fn Length(&self) -> u32 {
-        self.data.len() as u32
+        utf16_len(self.data.as_slice()) as u32
     }
 
+    // http://dom.spec.whatwg.org/#dom-characterdata-substringdata
     pub fn SubstringData(&self, offset: u32, count: u32) -> Fallible<DOMString> {
-        Ok(self.data.slice(offset as uint, count as uint).to_str())
+        match self.byte_range(offset, count) {
+            Ok((start, end)) => Ok(self.data.slice(start, end).to_str()),
+            Err(error) => Err(error),
+        }
     }
 
-    pub fn AppendData(&mut self, arg: DOMString) -> ErrorResult {
-        self.data.push_str(arg);
-        Ok(())
+    pub fn AppendData(&mut self, abstract_self: &JS<CharacterData>, arg: DOMString)
+                      -> ErrorResult {
+        let length = self.Length();
+        self.replace_data(abstract_self, length, 0, arg)
     }
 
-    pub fn InsertData(&mut self, _offset: u32, _arg: DOMString) -> ErrorResult {
-        fail!("CharacterData::InsertData() is unimplemented")
+    // http://dom.spec.whatwg.org/#dom-characterdata-insertdata
+    pub fn InsertData(&mut self, abstract_self: &JS<CharacterData>, offset: u32, arg: DOMString)
+                      -> ErrorResult {
+        self.replace_data(abstract_self, offset, 0, arg)
     }
 
-    pub fn DeleteData(&mut self, _offset: u32, _count: u32) -> ErrorResult {
-        fail!("CharacterData::DeleteData() is unimplemented")
+    // http://dom.spec.whatwg.org/#dom-characterdata-deletedata
+    pub fn DeleteData(&mut self, abstract_self: &JS<CharacterData>, offset: u32, count: u32)
+                      -> ErrorResult {
+        self.replace_data(abstract_self, offset, count, ~"")
     }
 
-    pub fn ReplaceData(&mut self, _offset: u32, _count: u32, _arg: DOMString) -> ErrorResult {
-        fail!("CharacterData::ReplaceData() is unimplemented")
+    // http://dom.spec.whatwg.org/#dom-characterdata-replacedata
+    pub fn ReplaceData(&mut self, abstract_self: &JS<CharacterData>, offset: u32, count: u32,
+                       arg: DOMString) -> ErrorResult {
+        self.replace_data(abstract_self, offset, count, arg)
     }
 }
 
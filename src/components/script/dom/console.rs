@@ -6,42 +6,68 @@ use dom::bindings::codegen::ConsoleBinding;
 use dom::bindings::js::JS;
 use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
 use dom::window::Window;
+use script_task::Page;
+use servo_msg::devtools_msg::{ConsoleLevel, DebugLevel, ErrorLevel, InfoLevel, LogLevel};
+use servo_msg::devtools_msg::WarnLevel;
 use servo_util::str::DOMString;
+use std::rc::Rc;
+
+use extra::serialize::{Encoder, Encodable};
 
 #[deriving(Encodable)]
 pub struct Console {
-    reflector_: Reflector
+    reflector_: Reflector,
+    priv extra: Untraceable,
+}
+
+struct Untraceable {
+    page: Rc<Page>,
+}
+
+impl<S: Encoder> Encodable<S> for Untraceable {
+    fn encode(&self, s: &mut S) {
+        self.page.borrow().encode(s);
+    }
 }
 
 impl Console {
-    pub fn new_inherited() -> Console {
+    pub fn new_inherited(page: Rc<Page>) -> Console {
         Console {
-            reflector_: Reflector::new()
+            reflector_: Reflector::new(),
+            extra: Untraceable {
+                page: page
+            }
         }
     }
 
-    pub fn new(window: &JS<Window>) -> JS<Console> {
-        reflect_dom_object(~Console::new_inherited(), window, ConsoleBinding::Wrap)
+    pub fn new(window: &JS<Window>, page: Rc<Page>) -> JS<Console> {
+        reflect_dom_object(~Console::new_inherited(page), window, ConsoleBinding::Wrap)
     }
 
-    pub fn Log(&self, message: DOMString) {
+    /// Prints a message and sends it to any devtools clients listening to the page.
+    fn report(&self, level: ConsoleLevel, message: &str) {
         println!("{:s}", message);
+        self.extra.page.borrow().send_console_message(level, message);
+    }
+
+    pub fn Log(&self, message: DOMString) {
+        self.report(LogLevel, message);
     }
 
     pub fn Debug(&self, message: DOMString) {
-        println!("{:s}", message);
+        self.report(DebugLevel, message);
     }
 
     pub fn Info(&self, message: DOMString) {
-        println!("{:s}", message);
+        self.report(InfoLevel, message);
     }
 
     pub fn Warn(&self, message: DOMString) {
-        println!("{:s}", message);
+        self.report(WarnLevel, message);
     }
 
     pub fn Error(&self, message: DOMString) {
-        println!("{:s}", message);
+        self.report(ErrorLevel, message);
     }
 }
 
@@ -0,0 +1,93 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use dom::bindings::codegen::CustomEventBinding;
+use dom::bindings::codegen::InheritTypes::CustomEventDerived;
+use dom::bindings::error::Fallible;
+use dom::bindings::js::JS;
+use dom::bindings::trace::trace_jsval;
+use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
+use dom::event::{Event, CustomEventTypeId};
+use dom::window::Window;
+use servo_util::str::DOMString;
+
+use js::jsapi::{JSContext, JSTracer};
+use js::jsval::{JSVal, NullValue};
+
+use extra::serialize::{Encoder, Encodable};
+use std::cast;
+
+/// The script value passed along with a `CustomEvent`.
+struct Detail {
+    value: JSVal,
+}
+
+impl<S: Encoder> Encodable<S> for Detail {
+    fn encode(&self, s: &mut S) {
+        let tracer: *mut JSTracer = unsafe { cast::transmute(s) };
+        trace_jsval(tracer, "custom event detail", self.value);
+    }
+}
+
+#[deriving(Encodable)]
+pub struct CustomEvent {
+    event: Event,
+    detail: Detail,
+}
+
+impl CustomEventDerived for Event {
+    fn is_customevent(&self) -> bool {
+        self.type_id == CustomEventTypeId
+    }
+}
+
+impl CustomEvent {
+    pub fn new_inherited() -> CustomEvent {
+        CustomEvent {
+            event: Event::new_inherited(CustomEventTypeId),
+            detail: Detail { value: NullValue() },
+        }
+    }
+
+    pub fn new(window: &JS<Window>) -> JS<CustomEvent> {
+        reflect_dom_object(~CustomEvent::new_inherited(),
+                           window,
+                           CustomEventBinding::Wrap)
+    }
+
+    pub fn Constructor(global: &JS<Window>,
+                       type_: DOMString,
+                       init: &CustomEventBinding::CustomEventInit) -> Fallible<JS<CustomEvent>> {
+        let mut ev = CustomEvent::new(global);
+        ev.get_mut().init_custom_event(type_, init.parent.bubbles, init.parent.cancelable,
+                                       init.detail);
+        Ok(ev)
+    }
+
+    pub fn Detail(&self, _cx: *JSContext) -> JSVal {
+        self.detail.value
+    }
+
+    // http://dom.spec.whatwg.org/#dom-customevent-initcustomevent
+    pub fn InitCustomEvent(&mut self, _cx: *JSContext, type_: DOMString, bubbles: bool,
+                           cancelable: bool, detail: JSVal) {
+        self.init_custom_event(type_, bubbles, cancelable, detail);
+    }
+
+    fn init_custom_event(&mut self, type_: DOMString, bubbles: bool, cancelable: bool,
+                         detail: JSVal) {
+        self.event.InitEvent(type_, bubbles, cancelable);
+        self.detail.value = detail;
+    }
+}
+
+impl Reflectable for CustomEvent {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        self.event.reflector()
+    }
+
+    fn mut_reflector<'a>(&'a mut self) -> &'a mut Reflector {
+        self.event.mut_reflector()
+    }
+}
@@ -12,6 +12,7 @@ use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
 use dom::bindings::error::{ErrorResult, Fallible, NotSupported, InvalidCharacter, HierarchyRequest};
 use dom::bindings::utils::{xml_name_type, InvalidXMLName};
 use dom::comment::Comment;
+use dom::customevent::CustomEvent;
 use dom::documentfragment::DocumentFragment;
 use dom::documenttype::DocumentType;
 use dom::domimplementation::DOMImplementation;
@@ -19,7 +20,7 @@ use dom::element::{Element};
 use dom::element::{HTMLHtmlElementTypeId, HTMLHeadElementTypeId, HTMLTitleElementTypeId};
 use dom::element::{HTMLBodyElementTypeId, HTMLFrameSetElementTypeId};
 use dom::event::Event;
-use dom::eventtarget::{EventTarget, NodeTargetTypeId};
+use dom::eventtarget::{EventTarget, EventTargetHelpers, NodeTargetTypeId};
 use dom::htmlcollection::HTMLCollection;
 use dom::nodelist::NodeList;
 use dom::htmlelement::HTMLElement;
@@ -28,8 +29,13 @@ use dom::htmlhtmlelement::HTMLHtmlElement;
 use dom::htmltitleelement::HTMLTitleElement;
 use dom::mouseevent::MouseEvent;
 use dom::node::{Node, ElementNodeTypeId, DocumentNodeTypeId, NodeHelpers, INode};
+use dom::nodefilter::Filter;
+use dom::nodeiterator::NodeIterator;
 use dom::text::Text;
 use dom::processinginstruction::ProcessingInstruction;
+use dom::range::Range;
+use dom::storageevent::StorageEvent;
+use dom::treewalker::TreeWalker;
 use dom::uievent::UIEvent;
 use dom::window::Window;
 use html::hubbub_html_parser::build_element_from_tag;
@@ -40,6 +46,7 @@ use servo_util::str::DOMString;
 
 use extra::url::{Url, from_str};
 use js::jsapi::{JSObject, JSContext};
+use js::jsval::JSVal;
 use std::ascii::StrAsciiExt;
 use std::hashmap::HashMap;
 
@@ -61,6 +68,9 @@ pub struct Document {
     content_type: DOMString,
     encoding_name: DOMString,
     is_html_document: bool,
+    /// False for documents that are not displayed, such as those made by `DOMParser`. Such
+    /// documents do not load subresources.
+    has_browsing_context: bool,
     priv extra: Untraceable,
 }
 
@@ -129,6 +139,7 @@ impl Document {
             // http://dom.spec.whatwg.org/#concept-document-encoding
             encoding_name: ~"utf-8",
             is_html_document: is_html_document == HTMLDocument,
+            has_browsing_context: true,
         }
     }
 
@@ -138,6 +149,18 @@ impl Document {
     }
 }
 
+impl EventTargetHelpers for Document {
+    fn event_target<'a>(&'a self) -> &'a EventTarget {
+        &self.node.eventtarget
+    }
+
+    fn mut_event_target<'a>(&'a mut self) -> &'a mut EventTarget {
+        &mut self.node.eventtarget
+    }
+}
+
+global_event_handlers!(Document)
+
 impl Document {
     pub fn url<'a>(&'a self) -> &'a Url {
         &self.extra.url
@@ -296,14 +319,42 @@ impl Document {
 
     // http://dom.spec.whatwg.org/#dom-document-createevent
     pub fn CreateEvent(&self, interface: DOMString) -> Fallible<JS<Event>> {
-        match interface.as_slice() {
-            "UIEvents" => Ok(EventCast::from(&UIEvent::new(&self.window))),
-            "MouseEvents" => Ok(EventCast::from(&MouseEvent::new(&self.window))),
-            "HTMLEvents" => Ok(Event::new(&self.window)),
+        match interface.to_ascii_lower().as_slice() {
+            "customevent" => Ok(EventCast::from(&CustomEvent::new(&self.window))),
+            "event" | "events" | "htmlevents" => Ok(Event::new(&self.window)),
+            "mouseevent" | "mouseevents" => Ok(EventCast::from(&MouseEvent::new(&self.window))),
+            "storageevent" => {
+                let event = StorageEvent::new(&self.window, None, None, None, ~"", None);
+                Ok(EventCast::from(&event))
+            }
+            "uievent" | "uievents" => Ok(EventCast::from(&UIEvent::new(&self.window))),
             _ => Err(NotSupported)
         }
     }
 
+    // http://dom.spec.whatwg.org/#dom-document-createrange
+    pub fn CreateRange(&self, abstract_self: &JS<Document>) -> JS<Range> {
+        Range::new(abstract_self)
+    }
+
+    // http://dom.spec.whatwg.org/#dom-document-createnodeiterator
+    pub fn CreateNodeIterator(&self, _cx: *JSContext, root: &mut JS<Node>, what_to_show: u32,
+                              filter: Option<JSVal>) -> Fallible<JS<NodeIterator>> {
+        match Filter::new(what_to_show, filter) {
+            Ok(filter) => Ok(NodeIterator::new(&self.window, root, filter)),
+            Err(error) => Err(error),
+        }
+    }
+
+    // http://dom.spec.whatwg.org/#dom-document-createtreewalker
+    pub fn CreateTreeWalker(&self, _cx: *JSContext, root: &mut JS<Node>, what_to_show: u32,
+                            filter: Option<JSVal>) -> Fallible<JS<TreeWalker>> {
+        match Filter::new(what_to_show, filter) {
+            Ok(filter) => Ok(TreeWalker::new(&self.window, root, filter)),
+            Err(error) => Err(error),
+        }
+    }
+
     // http://www.whatwg.org/specs/web-apps/current-work/#document.title
     pub fn Title(&self, _: &JS<Document>) -> DOMString {
         let mut title = ~"";
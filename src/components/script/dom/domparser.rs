@@ -4,13 +4,27 @@
 
 use dom::bindings::codegen::DOMParserBinding;
 use dom::bindings::codegen::DOMParserBinding::SupportedTypeValues::{Text_html, Text_xml};
+use dom::bindings::codegen::DOMParserBinding::SupportedTypeValues::{Application_xml};
+use dom::bindings::codegen::DOMParserBinding::SupportedTypeValues::{Application_xhtml_xml};
+use dom::bindings::codegen::DOMParserBinding::SupportedTypeValues::{Image_svg_xml};
+use dom::bindings::codegen::InheritTypes::NodeCast;
 use dom::bindings::js::JS;
 use dom::bindings::utils::{Reflector, Reflectable, reflect_dom_object};
-use dom::bindings::error::{Fallible, FailureUnknown};
-use dom::document::{Document, HTMLDocument, NonHTMLDocument};
+use dom::bindings::error::Fallible;
+use dom::document::{Document, HTMLDocument, IsHTMLDocument, NonHTMLDocument};
+use dom::element::Element;
+use dom::node::{INode, Node};
+use dom::text::Text;
 use dom::window::Window;
+use html::hubbub_html_parser::parse_html_string;
+use html::xml_parser::{XmlParseError, parse_xml};
+use servo_util::namespace::Namespace;
 use servo_util::str::DOMString;
 
+/// The namespace of the root element of a document that failed to parse, as in Gecko.
+static PARSER_ERROR_NAMESPACE: &'static str =
+    "http://www.mozilla.org/newlayout/xml/parsererror.xml";
+
 #[deriving(Encodable)]
 pub struct DOMParser {
     owner: JS<Window>, //XXXjdm Document instead?
@@ -34,24 +48,61 @@ impl DOMParser {
         Ok(DOMParser::new(owner))
     }
 
+    // http://domparsing.spec.whatwg.org/#dom-domparser-parsefromstring
     pub fn ParseFromString(&self,
-                           _s: DOMString,
+                           s: DOMString,
                            ty: DOMParserBinding::SupportedType)
                            -> Fallible<JS<Document>> {
+        let content_type = match ty {
+            Text_html => ~"text/html",
+            Text_xml => ~"text/xml",
+            Application_xml => ~"application/xml",
+            Application_xhtml_xml => ~"application/xhtml+xml",
+            Image_svg_xml => ~"image/svg+xml",
+        };
         match ty {
             Text_html => {
-                Ok(Document::new(&self.owner, None, HTMLDocument, Some(~"text/html")))
-            }
-            Text_xml => {
-                Ok(Document::new(&self.owner, None, NonHTMLDocument, Some(~"text/xml")))
+                let mut document = self.new_document(HTMLDocument, content_type);
+                parse_html_string(&mut document, s);
+                Ok(document)
             }
-            _ => {
-                Err(FailureUnknown)
+            Text_xml | Application_xml | Application_xhtml_xml | Image_svg_xml => {
+                let document = self.new_document(NonHTMLDocument, content_type.clone());
+                match parse_xml(&document, s) {
+                    Ok(()) => Ok(document),
+                    Err(error) => Ok(self.parser_error_document(error, content_type)),
+                }
             }
         }
     }
 }
 
+impl DOMParser {
+    /// Makes a document with no browsing context, so that parsing into it loads nothing.
+    fn new_document(&self, is_html_document: IsHTMLDocument, content_type: DOMString)
+                    -> JS<Document> {
+        let mut document = Document::new(&self.owner, None, is_html_document,
+                                         Some(content_type));
+        document.get_mut().has_browsing_context = false;
+        document
+    }
+
+    /// Makes the document returned in place of one that is not well-formed, whose root is a
+    /// `parsererror` element describing the error.
+    fn parser_error_document(&self, error: XmlParseError, content_type: DOMString)
+                             -> JS<Document> {
+        let document = self.new_document(NonHTMLDocument, content_type);
+        let root = Element::new(~"parsererror", Namespace::from_str(PARSER_ERROR_NAMESPACE),
+                                &document);
+        let mut root: JS<Node> = NodeCast::from(&root);
+        let mut text: JS<Node> = NodeCast::from(&Text::new(error.description(), &document));
+        root.AppendChild(&mut text);
+        let mut document_node: JS<Node> = NodeCast::from(&document);
+        document_node.AppendChild(&mut root);
+        document
+    }
+}
+
 impl Reflectable for DOMParser {
     fn reflector<'a>(&'a self) -> &'a Reflector {
         &self.reflector_
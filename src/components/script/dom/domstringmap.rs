@@ -0,0 +1,181 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use dom::bindings::codegen::DOMStringMapBinding;
+use dom::bindings::error::{ErrorResult, InvalidCharacter, Syntax};
+use dom::bindings::js::JS;
+use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
+use dom::bindings::utils::{InvalidXMLName, xml_name_type};
+use dom::element::Element;
+use dom::window::Window;
+use servo_util::namespace::Null;
+use servo_util::str::DOMString;
+
+use std::ascii::AsciiCast;
+
+/// The `data-*` attributes of an element, as named properties of `HTMLElement.dataset`.
+#[deriving(Encodable)]
+pub struct DOMStringMap {
+    reflector_: Reflector,
+    element: JS<Element>,
+}
+
+impl DOMStringMap {
+    pub fn new_inherited(element: JS<Element>) -> DOMStringMap {
+        DOMStringMap {
+            reflector_: Reflector::new(),
+            element: element,
+        }
+    }
+
+    pub fn new(window: &JS<Window>, element: &JS<Element>) -> JS<DOMStringMap> {
+        reflect_dom_object(~DOMStringMap::new_inherited(element.clone()),
+                           window, DOMStringMapBinding::Wrap)
+    }
+}
+
+impl DOMStringMap {
+    // http://www.whatwg.org/html/#dom-domstringmap-nameditem
+    pub fn NamedGetter(&self, maybe_name: Option<DOMString>, found: &mut bool) -> DOMString {
+        *found = false;
+        let name = match maybe_name {
+            Some(name) => name,
+            None => return ~"",
+        };
+
+        let element = self.element.get();
+        for attr in element.attrs.iter() {
+            let attr = attr.get();
+            if attr.namespace != Null {
+                continue;
+            }
+            match property_name(attr.local_name.as_slice()) {
+                Some(ref property) if *property == name => {
+                    *found = true;
+                    return attr.Value();
+                }
+                _ => (),
+            }
+        }
+        ~""
+    }
+
+    // http://www.whatwg.org/html/#dom-domstringmap-setitem
+    pub fn NamedSetter(&mut self, maybe_name: Option<DOMString>, value: DOMString)
+                       -> ErrorResult {
+        let name = match maybe_name {
+            Some(name) => name,
+            None => return Ok(()),
+        };
+
+        // Step 1-3.
+        let local_name = match attribute_name(name) {
+            Some(local_name) => local_name,
+            None => return Err(Syntax),
+        };
+
+        // Step 4.
+        match xml_name_type(local_name.as_slice()) {
+            InvalidXMLName => return Err(InvalidCharacter),
+            _ => (),
+        }
+
+        // Step 5.
+        let mut element = self.element.clone();
+        element.get_mut().set_attribute(&self.element, Null, local_name, value)
+    }
+
+    // http://www.whatwg.org/html/#dom-domstringmap-removeitem
+    pub fn NamedDeleter(&mut self, maybe_name: Option<DOMString>) {
+        let name = match maybe_name {
+            Some(name) => name,
+            None => return,
+        };
+
+        // Step 1-3.
+        let local_name = match attribute_name(name) {
+            Some(local_name) => local_name,
+            None => return,
+        };
+
+        // Step 4.
+        let mut element = self.element.clone();
+        match element.get_mut().remove_attribute(&self.element, Null, local_name) {
+            Ok(()) => {}
+            // The deleter can't throw, and removing an attribute that may not exist reports
+            // no error; anything else would be a bug in `remove_attribute`.
+            Err(error) => fail!("removing a data-* attribute failed: {:?}", error),
+        }
+    }
+}
+
+impl Reflectable for DOMStringMap {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        &self.reflector_
+    }
+
+    fn mut_reflector<'a>(&'a mut self) -> &'a mut Reflector {
+        &mut self.reflector_
+    }
+}
+
+/// Returns the name of the property that reflects the attribute with the given local name, if
+/// it is a `data-*` attribute: `data-foo-bar` is reflected by `fooBar`.
+// http://www.whatwg.org/html/#concept-domstringmap-pairs
+fn property_name(local_name: &str) -> Option<DOMString> {
+    if !local_name.starts_with("data-") || local_name.chars().any(is_ascii_upper) {
+        return None;
+    }
+
+    let mut property = ~"";
+    let mut chars = local_name.slice_from("data-".len()).chars().peekable();
+    loop {
+        match chars.next() {
+            Some('-') => match chars.peek().map(|c| *c) {
+                Some(c) if is_ascii_lower(c) => {
+                    property.push_char(c.to_ascii().to_upper().to_char());
+                    chars.next();
+                }
+                _ => property.push_char('-'),
+            },
+            Some(c) => property.push_char(c),
+            None => return Some(property),
+        }
+    }
+}
+
+/// Returns the local name of the `data-*` attribute that the given property reflects, or `None`
+/// if a `-` is followed by a lowercase letter in the property name.
+fn attribute_name(property: DOMString) -> Option<DOMString> {
+    let mut local_name = ~"data-";
+    let mut chars = property.chars().peekable();
+    loop {
+        match chars.next() {
+            Some('-') => match chars.peek().map(|c| *c) {
+                Some(c) if is_ascii_lower(c) => return None,
+                _ => local_name.push_char('-'),
+            },
+            Some(c) if is_ascii_upper(c) => {
+                local_name.push_char('-');
+                local_name.push_char(c.to_ascii().to_lower().to_char());
+            }
+            Some(c) => local_name.push_char(c),
+            None => return Some(local_name),
+        }
+    }
+}
+
+fn is_ascii_upper(c: char) -> bool {
+    match c {
+        'A' .. 'Z' => true,
+        _ => false,
+    }
+}
+
+fn is_ascii_lower(c: char) -> bool {
+    match c {
+        'a' .. 'z' => true,
+        _ => false,
+    }
+}
@@ -0,0 +1,212 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use dom::bindings::codegen::DOMTokenListBinding;
+use dom::bindings::error::{ErrorResult, Fallible, InvalidCharacter, Syntax};
+use dom::bindings::js::JS;
+use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
+use dom::element::Element;
+use dom::window::Window;
+use servo_util::namespace::Null;
+use servo_util::str::{DOMString, HTML_SPACE_CHARACTERS};
+
+/// The live set of the space-separated tokens of an attribute of an element, such as `class` for
+/// `Element.classList`.
+#[deriving(Encodable)]
+pub struct DOMTokenList {
+    reflector_: Reflector,
+    element: JS<Element>,
+    local_name: ~str,
+}
+
+impl DOMTokenList {
+    pub fn new_inherited(element: JS<Element>, local_name: ~str) -> DOMTokenList {
+        DOMTokenList {
+            reflector_: Reflector::new(),
+            element: element,
+            local_name: local_name,
+        }
+    }
+
+    pub fn new(window: &JS<Window>, element: &JS<Element>, local_name: &str)
+               -> JS<DOMTokenList> {
+        reflect_dom_object(~DOMTokenList::new_inherited(element.clone(), local_name.to_owned()),
+                           window, DOMTokenListBinding::Wrap)
+    }
+
+    /// Returns the tokens of the attribute, in order and without duplicates.
+    // http://dom.spec.whatwg.org/#concept-ordered-set-parser
+    fn tokens(&self) -> ~[DOMString] {
+        let value = self.element.get().get_string_attribute(self.local_name.as_slice());
+        let mut tokens: ~[DOMString] = ~[];
+        for token in value.split(HTML_SPACE_CHARACTERS.as_slice()) {
+            if !token.is_empty() && !tokens.iter().any(|t| t.as_slice() == token) {
+                tokens.push(token.to_owned());
+            }
+        }
+        tokens
+    }
+
+    // http://dom.spec.whatwg.org/#concept-dtl-update
+    fn update(&self, tokens: ~[DOMString]) {
+        // Changing the attribute queues a mutation record, and damages the document as for any
+        // other change to the attribute.
+        let local_name = self.local_name.as_slice();
+        let mut element = self.element.clone();
+        if tokens.is_empty() && element.get().get_attribute(Null, local_name).is_none() {
+            return;
+        }
+        element.get_mut().set_string_attribute(&self.element, local_name, tokens.connect(" "));
+    }
+}
+
+impl DOMTokenList {
+    // http://dom.spec.whatwg.org/#dom-domtokenlist-length
+    pub fn Length(&self) -> u32 {
+        self.tokens().len() as u32
+    }
+
+    // http://dom.spec.whatwg.org/#dom-domtokenlist-item
+    pub fn Item(&self, index: u32) -> Option<DOMString> {
+        let tokens = self.tokens();
+        tokens.get_opt(index as uint).map(|token| token.clone())
+    }
+
+    pub fn IndexedGetter(&self, index: u32, found: &mut bool) -> Option<DOMString> {
+        let item = self.Item(index);
+        *found = item.is_some();
+        item
+    }
+
+    // http://dom.spec.whatwg.org/#dom-domtokenlist-contains
+    pub fn Contains(&self, token: DOMString) -> bool {
+        self.tokens().iter().any(|t| *t == token)
+    }
+
+    // http://dom.spec.whatwg.org/#dom-domtokenlist-add
+    pub fn Add(&self, tokens: ~[DOMString]) -> ErrorResult {
+        // Step 1.
+        for token in tokens.iter() {
+            match validate_token(token) {
+                Ok(()) => (),
+                Err(error) => return Err(error),
+            }
+        }
+
+        // Step 2.
+        let mut set = self.tokens();
+        for token in tokens.move_iter() {
+            if !set.contains(&token) {
+                set.push(token);
+            }
+        }
+
+        // Step 3.
+        self.update(set);
+        Ok(())
+    }
+
+    // http://dom.spec.whatwg.org/#dom-domtokenlist-remove
+    pub fn Remove(&self, tokens: ~[DOMString]) -> ErrorResult {
+        // Step 1.
+        for token in tokens.iter() {
+            match validate_token(token) {
+                Ok(()) => (),
+                Err(error) => return Err(error),
+            }
+        }
+
+        // Step 2.
+        let set: ~[DOMString] = self.tokens().move_iter().filter(|t| !tokens.contains(t)).collect();
+
+        // Step 3.
+        self.update(set);
+        Ok(())
+    }
+
+    // http://dom.spec.whatwg.org/#dom-domtokenlist-toggle
+    pub fn Toggle(&self, token: DOMString, force: Option<bool>) -> Fallible<bool> {
+        // Step 1-2.
+        match validate_token(&token) {
+            Ok(()) => (),
+            Err(error) => return Err(error),
+        }
+
+        let mut set = self.tokens();
+        match set.iter().position(|t| *t == token) {
+            // Step 3.
+            Some(index) => {
+                if force == Some(true) {
+                    return Ok(true);
+                }
+                set.remove(index);
+                self.update(set);
+                Ok(false)
+            }
+            // Step 4.
+            None => {
+                if force == Some(false) {
+                    return Ok(false);
+                }
+                set.push(token);
+                self.update(set);
+                Ok(true)
+            }
+        }
+    }
+
+    // http://dom.spec.whatwg.org/#dom-domtokenlist-replace
+    pub fn Replace(&self, token: DOMString, new_token: DOMString) -> ErrorResult {
+        // Step 1-2.
+        match validate_token(&token) {
+            Ok(()) => (),
+            Err(error) => return Err(error),
+        }
+        match validate_token(&new_token) {
+            Ok(()) => (),
+            Err(error) => return Err(error),
+        }
+
+        // Step 3.
+        let tokens = self.tokens();
+        if !tokens.contains(&token) {
+            return Ok(());
+        }
+
+        // Step 4. Only the first of `token` and `new_token` is kept, as `new_token`.
+        let mut set: ~[DOMString] = ~[];
+        for t in tokens.move_iter() {
+            let t = if t == token { new_token.clone() } else { t };
+            if !set.contains(&t) {
+                set.push(t);
+            }
+        }
+
+        // Step 5.
+        self.update(set);
+        Ok(())
+    }
+}
+
+impl Reflectable for DOMTokenList {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        &self.reflector_
+    }
+
+    fn mut_reflector<'a>(&'a mut self) -> &'a mut Reflector {
+        &mut self.reflector_
+    }
+}
+
+/// Throws a `SyntaxError` for an empty token, and an `InvalidCharacterError` for a token
+/// containing a space.
+fn validate_token(token: &DOMString) -> ErrorResult {
+    if token.is_empty() {
+        return Err(Syntax);
+    }
+    if token.find(HTML_SPACE_CHARACTERS.as_slice()).is_some() {
+        return Err(InvalidCharacter);
+    }
+    Ok(())
+}
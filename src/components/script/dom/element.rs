@@ -6,23 +6,29 @@
 
 use dom::attr::Attr;
 use dom::attrlist::AttrList;
+use dom::bindings::codegen::ElementBinding;
 use dom::bindings::codegen::InheritTypes::{ElementDerived, HTMLImageElementCast};
 use dom::bindings::codegen::InheritTypes::{HTMLIFrameElementCast, NodeCast};
 use dom::bindings::codegen::InheritTypes::HTMLObjectElementCast;
 use dom::bindings::js::JS;
-use dom::bindings::utils::{Reflectable, Reflector};
+use dom::bindings::utils::{Reflectable, Reflector, cx_for_dom_object};
 use dom::bindings::error::{ErrorResult, Fallible, NamespaceError, InvalidCharacter};
 use dom::bindings::utils::{QName, Name, InvalidXMLName, xml_name_type};
 use dom::htmlcollection::HTMLCollection;
 use dom::clientrect::ClientRect;
 use dom::clientrectlist::ClientRectList;
 use dom::document::Document;
+use dom::domtokenlist::DOMTokenList;
 use dom::eventtarget::{EventTarget, NodeTargetTypeId};
+use dom::htmlelement::HTMLElement;
 use dom::htmlimageelement::HTMLImageElement;
 use dom::htmliframeelement::HTMLIFrameElement;
 use dom::htmlobjectelement::HTMLObjectElement;
+use dom::mutationobserver::queue_mutation_record;
+use dom::mutationrecord::AttributeMutation;
 use dom::node::{ElementNodeTypeId, Node, NodeHelpers, NodeIterator, document_from_node};
 use dom::htmlserializer::serialize;
+use dom::window::Window;
 use layout_interface::{ContentBoxQuery, ContentBoxResponse, ContentBoxesQuery};
 use layout_interface::{ContentBoxesResponse, ContentChangedDocumentDamage};
 use layout_interface::{MatchSelectorsDocumentDamage};
@@ -41,7 +47,8 @@ pub struct Element {
     namespace: Namespace,
     attrs: ~[JS<Attr>],
     style_attribute: Option<style::PropertyDeclarationBlock>,
-    attr_list: Option<JS<AttrList>>
+    attr_list: Option<JS<AttrList>>,
+    class_list: Option<JS<DOMTokenList>>,
 }
 
 impl ElementDerived for EventTarget {
@@ -65,6 +72,8 @@ impl Reflectable for Element {
 
 #[deriving(Eq,Encodable)]
 pub enum ElementTypeId {
+    /// An element that is not in the HTML namespace, such as one created by the XML parser.
+    ElementTypeId_,
     HTMLElementTypeId,
     HTMLAnchorElementTypeId,
     HTMLAppletElementTypeId,
@@ -147,10 +156,18 @@ impl Element {
             namespace: namespace,
             attrs: ~[],
             attr_list: None,
+            class_list: None,
             style_attribute: None,
         }
     }
 
+    pub fn new(local_name: DOMString, namespace: Namespace, document: &JS<Document>)
+               -> JS<Element> {
+        let element = Element::new_inherited(ElementTypeId_, local_name, namespace,
+                                             document.clone());
+        Node::reflect_node(~element, document, ElementBinding::Wrap)
+    }
+
     pub fn html_element_in_html_document(&self) -> bool {
         self.namespace == namespace::HTML &&
         self.node.owner_doc().get().is_html_document
@@ -219,6 +236,11 @@ impl Element {
             attr.get().local_name == local_name
         });
 
+        let old_value = idx.map(|idx| self.attrs[idx].get().Value());
+        let node: JS<Node> = NodeCast::from(abstract_self);
+        queue_mutation_record(&node, AttributeMutation(local_name.clone(), namespace.clone(),
+                                                       old_value));
+
         match idx {
             Some(idx) => {
                 if namespace == namespace::Null {
@@ -249,6 +271,13 @@ impl Element {
                       local_name: DOMString,
                       value: DOMString) {
 
+        match self.event_handler_type(local_name.as_slice()) {
+            Some(ty) => {
+                self.set_event_handler_content_attribute(abstract_self, ty, Some(value.clone()))
+            }
+            None => {}
+        }
+
         match local_name.as_slice() {
             "style" => {
                 let doc = self.node.owner_doc();
@@ -304,6 +333,11 @@ impl Element {
         match idx {
             None => (),
             Some(idx) => {
+                let node: JS<Node> = NodeCast::from(abstract_self);
+                let old_value = Some(self.attrs[idx].get().Value());
+                queue_mutation_record(&node, AttributeMutation(local_name.clone(),
+                                                               namespace.clone(), old_value));
+
                 if namespace == namespace::Null {
                     let removed_raw_value = self.attrs[idx].get().Value();
                     self.before_remove_attr(abstract_self, local_name, removed_raw_value);
@@ -320,6 +354,11 @@ impl Element {
                           abstract_self: &JS<Element>,
                           local_name: DOMString,
                           old_value: DOMString) {
+        match self.event_handler_type(local_name.as_slice()) {
+            Some(ty) => self.set_event_handler_content_attribute(abstract_self, ty, None),
+            None => {}
+        }
+
         match local_name.as_slice() {
             "style" => {
                 self.style_attribute = None
@@ -354,6 +393,66 @@ impl Element {
         self.notify_attribute_changed(abstract_self, local_name);
     }
 
+    /// Returns the type of the events handled by an event handler content attribute, such as
+    /// `click` for `onclick`, or `None` if the attribute is not one. Documents without a
+    /// browsing context, such as those made by `DOMParser`, have no event handlers.
+    fn event_handler_type<'a>(&self, local_name: &'a str) -> Option<&'a str> {
+        if self.namespace != namespace::HTML || !local_name.starts_with("on") {
+            return None
+        }
+        if !self.node.owner_doc().get().has_browsing_context {
+            return None
+        }
+        let ty = local_name.slice_from(2);
+        let is_window_handler = match self.node.type_id {
+            ElementNodeTypeId(HTMLBodyElementTypeId) |
+            ElementNodeTypeId(HTMLFrameSetElementTypeId) => Window::has_window_event_handler(ty),
+            _ => false,
+        };
+        if HTMLElement::has_global_event_handler(ty) || is_window_handler {
+            Some(ty)
+        } else {
+            None
+        }
+    }
+
+    // http://www.whatwg.org/specs/web-apps/current-work/#event-handler-content-attributes
+    /// Compiles the value of an `on*` content attribute into an event handler, or clears the
+    /// event handler if the attribute was removed. The handlers of `<body>` and `<frameset>` for
+    /// the window's events are set on the window.
+    fn set_event_handler_content_attribute(&mut self,
+                                           abstract_self: &JS<Element>,
+                                           ty: &str,
+                                           source: Option<DOMString>) {
+        let doc = self.node.owner_doc().clone();
+        let mut window = doc.get().window.clone();
+        let sets_window_handler = match self.node.type_id {
+            ElementNodeTypeId(HTMLBodyElementTypeId) |
+            ElementNodeTypeId(HTMLFrameSetElementTypeId) => Window::has_window_event_handler(ty),
+            _ => false,
+        };
+
+        match source {
+            Some(source) => {
+                let cx = cx_for_dom_object(abstract_self.get());
+                let url = doc.get().url().clone();
+                if sets_window_handler {
+                    let scope = window.get().reflector().get_jsobject();
+                    window.get_mut().eventtarget.set_event_handler_uncompiled(cx, url, scope,
+                                                                              ty, source);
+                } else {
+                    let scope = abstract_self.get().reflector().get_jsobject();
+                    self.node.eventtarget.set_event_handler_uncompiled(cx, url, scope, ty,
+                                                                       source);
+                }
+            }
+            None if sets_window_handler => {
+                window.get_mut().eventtarget.set_event_handler_common(ty, None)
+            }
+            None => self.node.eventtarget.set_event_handler_common(ty, None),
+        }
+    }
+
     fn notify_attribute_changed(&self,
                                 abstract_self: &JS<Element>,
                                 local_name: DOMString) {
@@ -440,6 +539,20 @@ impl Element {
         self.set_string_attribute(abstract_self, "class", class);
     }
 
+    // http://dom.spec.whatwg.org/#dom-element-classlist
+    pub fn ClassList(&mut self, abstract_self: &JS<Element>) -> JS<DOMTokenList> {
+        match self.class_list {
+            None => {
+                let doc = self.node.owner_doc();
+                let doc = doc.get();
+                let list = DOMTokenList::new(&doc.window, abstract_self, "class");
+                self.class_list = Some(list.clone());
+                list
+            }
+            Some(ref list) => list.clone()
+        }
+    }
+
     // http://dom.spec.whatwg.org/#dom-element-attributes
     pub fn Attributes(&mut self, abstract_self: &JS<Element>) -> JS<AttrList> {
         match self.attr_list {
@@ -33,9 +33,11 @@ pub enum EventPhase {
 #[deriving(Eq, Encodable)]
 pub enum EventTypeId {
     HTMLEventTypeId,
+    CustomEventTypeId,
     UIEventTypeId,
     MouseEventTypeId,
-    KeyEventTypeId
+    KeyEventTypeId,
+    StorageEventTypeId
 }
 
 #[deriving(Encodable)]
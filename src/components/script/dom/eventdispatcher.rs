@@ -2,12 +2,46 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use dom::bindings::callback::eReportExceptions;
+use dom::bindings::callback::{CallbackContainer, eReportExceptions};
 use dom::bindings::codegen::InheritTypes::{EventTargetCast, NodeCast, NodeDerived};
 use dom::bindings::js::JS;
-use dom::eventtarget::{Capturing, Bubbling, EventTarget};
+use dom::bindings::utils::{Reflectable, cx_for_dom_object};
+use dom::eventtarget::{Additive, Capturing, Bubbling, EventListenerType, EventTarget, Inline};
 use dom::event::{Event, Phase_At_Target, Phase_None, Phase_Bubbling, Phase_Capturing};
 use dom::node::{Node, NodeHelpers};
+use js::jsapi::{JS_CallFunctionValue, JS_ReportPendingException};
+use js::jsval::{ObjectValue, UndefinedValue};
+
+/// Calls a listener. An event handler is called with the target as `this`, and cancels the event
+/// by returning false.
+fn invoke_listener(listener: &EventListenerType,
+                   target: &JS<EventTarget>,
+                   event: &mut JS<Event>) {
+    match *listener {
+        Additive(ref listener) => listener.HandleEvent__(event, eReportExceptions),
+        Inline(ref handler) => {
+            let cx = cx_for_dom_object(target.get());
+            let this = target.get().reflector().get_jsobject();
+            let argv = [ObjectValue(unsafe { &*event.get().reflector().get_jsobject() })];
+            let rval = UndefinedValue();
+            let ok = unsafe {
+                JS_CallFunctionValue(cx, this, ObjectValue(&*handler.parent.callback()),
+                                     argv.len() as u32, argv.as_ptr(), &rval)
+            };
+
+            if ok == 0 {
+                // The handler threw; report the exception instead of leaving it pending.
+                unsafe { JS_ReportPendingException(cx) };
+                return
+            }
+
+            // http://www.whatwg.org/specs/web-apps/current-work/#the-event-handler-processing-algorithm
+            if rval.is_boolean() && !rval.to_boolean() {
+                event.get_mut().PreventDefault();
+            }
+        }
+    }
+}
 
 // See http://dom.spec.whatwg.org/#concept-event-dispatch for the full dispatch algorithm
 pub fn dispatch_event(target: &JS<EventTarget>,
@@ -45,7 +79,7 @@ pub fn dispatch_event(target: &JS<EventTarget>,
             Some(listeners) => {
                 event.get_mut().current_target = Some(cur_target.clone());
                 for listener in listeners.iter() {
-                    listener.HandleEvent__(event, eReportExceptions);
+                    invoke_listener(listener, cur_target, event);
 
                     if event.get().stop_immediate {
                         break;
@@ -73,7 +107,7 @@ pub fn dispatch_event(target: &JS<EventTarget>,
         let opt_listeners = target.get().get_listeners(type_);
         for listeners in opt_listeners.iter() {
             for listener in listeners.iter() {
-                listener.HandleEvent__(event, eReportExceptions);
+                invoke_listener(listener, target, event);
                 if event.get().stop_immediate {
                     break;
                 }
@@ -90,7 +124,7 @@ pub fn dispatch_event(target: &JS<EventTarget>,
                 Some(listeners) => {
                     event.get_mut().current_target = Some(cur_target.clone());
                     for listener in listeners.iter() {
-                        listener.HandleEvent__(event, eReportExceptions);
+                        invoke_listener(listener, cur_target, event);
 
                         if event.get().stop_immediate {
                             break;
@@ -2,6 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use dom::bindings::callback::CallbackContainer;
 use dom::bindings::js::JS;
 use dom::bindings::utils::{Reflectable, Reflector};
 use dom::bindings::error::{Fallible, InvalidState};
@@ -9,9 +10,16 @@ use dom::bindings::codegen::EventListenerBinding::EventListener;
 use dom::event::Event;
 use dom::eventdispatcher::dispatch_event;
 use dom::node::NodeTypeId;
+use js::jsapi::{JSContext, JSObject, JS_CloneFunctionObject, JS_CompileUCFunction};
+use js::jsapi::{JS_GetFunctionObject, JS_ObjectIsCallable};
+use js::jsval::{JSVal, NullValue, ObjectValue};
 use servo_util::str::DOMString;
 
+use extra::url::Url;
 use std::hashmap::HashMap;
+use std::c_str::CString;
+use std::libc::{c_char, c_uint, size_t};
+use std::ptr;
 
 #[deriving(Eq,Encodable)]
 pub enum ListenerPhase {
@@ -25,10 +33,25 @@ pub enum EventTargetTypeId {
     NodeTargetTypeId(NodeTypeId)
 }
 
+/// A listener added with `addEventListener`, or the event handler set by an `on*` attribute.
+#[deriving(Eq,Encodable)]
+pub enum EventListenerType {
+    Additive(EventListener),
+    Inline(EventListener),
+}
+
+impl EventListenerType {
+    fn get_listener(&self) -> EventListener {
+        match *self {
+            Additive(listener) | Inline(listener) => listener,
+        }
+    }
+}
+
 #[deriving(Eq,Encodable)]
 struct EventListenerEntry {
     phase: ListenerPhase,
-    listener: EventListener
+    listener: EventListenerType
 }
 
 #[deriving(Encodable)]
@@ -47,14 +70,14 @@ impl EventTarget {
         }
     }
 
-    pub fn get_listeners(&self, type_: &str) -> Option<~[EventListener]> {
+    pub fn get_listeners(&self, type_: &str) -> Option<~[EventListenerType]> {
         self.handlers.find_equiv(&type_).map(|listeners| {
             listeners.iter().map(|entry| entry.listener).collect()
         })
     }
 
     pub fn get_listeners_for(&self, type_: &str, desired_phase: ListenerPhase)
-        -> Option<~[EventListener]> {
+        -> Option<~[EventListenerType]> {
         self.handlers.find_equiv(&type_).map(|listeners| {
             let filtered = listeners.iter().filter(|entry| entry.phase == desired_phase);
             filtered.map(|entry| entry.listener).collect()
@@ -70,7 +93,7 @@ impl EventTarget {
             let phase = if capture { Capturing } else { Bubbling };
             let new_entry = EventListenerEntry {
                 phase: phase,
-                listener: listener
+                listener: Additive(listener)
             };
             if entry.position_elem(&new_entry).is_none() {
                 entry.push(new_entry);
@@ -88,7 +111,7 @@ impl EventTarget {
                 let phase = if capture { Capturing } else { Bubbling };
                 let old_entry = EventListenerEntry {
                     phase: phase,
-                    listener: listener
+                    listener: Additive(listener)
                 };
                 let position = entry.position_elem(&old_entry);
                 for &position in position.iter() {
@@ -98,8 +121,18 @@ impl EventTarget {
         }
     }
 
+    // http://dom.spec.whatwg.org/#dom-eventtarget-dispatchevent
     pub fn DispatchEvent(&self, abstract_self: &JS<EventTarget>,
                          event: &mut JS<Event>) -> Fallible<bool> {
+        // Step 1.
+        if event.get().dispatching || !event.get().initialized {
+            return Err(InvalidState);
+        }
+
+        // Step 2. Only the events that the user agent dispatches itself are trusted.
+        event.get_mut().trusted = false;
+
+        // Step 3.
         self.dispatch_event_with_target(abstract_self, None, event)
     }
 
@@ -112,6 +145,121 @@ impl EventTarget {
         }
         Ok(dispatch_event(abstract_self, abstract_target, event))
     }
+
+    // http://www.whatwg.org/specs/web-apps/current-work/#event-handler-attributes
+    /// Sets or clears the event handler for the given event type. A handler keeps the position
+    /// among the listeners that it had when it was first set.
+    pub fn set_event_handler_common(&mut self, ty: &str, listener: Option<EventListener>) {
+        let entries = self.handlers.find_or_insert_with(ty.to_owned(), |_| ~[]);
+        let idx = entries.iter().position(|entry| {
+            match entry.listener {
+                Inline(_) => true,
+                Additive(_) => false,
+            }
+        });
+
+        match (idx, listener) {
+            (Some(idx), Some(listener)) => entries[idx].listener = Inline(listener),
+            (Some(idx), None) => {
+                entries.remove(idx);
+            }
+            (None, Some(listener)) => {
+                entries.push(EventListenerEntry {
+                    phase: Bubbling,
+                    listener: Inline(listener),
+                });
+            }
+            (None, None) => {}
+        }
+    }
+
+    pub fn get_event_handler_common(&self, ty: &str) -> Option<EventListener> {
+        self.handlers.find_equiv(&ty).and_then(|entries| {
+            entries.iter().find(|entry| {
+                match entry.listener {
+                    Inline(_) => true,
+                    Additive(_) => false,
+                }
+            }).map(|entry| entry.listener.get_listener())
+        })
+    }
+
+    /// Compiles the value of an `on*` content attribute into a function taking an `event`
+    /// argument, or the five arguments of the window's `onerror`, whose scope is `scope`, and
+    /// makes it the event handler for `ty`. A handler that does not compile clears the event
+    /// handler, and the syntax error is reported.
+    pub fn set_event_handler_uncompiled(&mut self,
+                                        cx: *JSContext,
+                                        url: Url,
+                                        scope: *JSObject,
+                                        ty: &str,
+                                        source: DOMString) {
+        let source: ~[u16] = source.to_utf16();
+        // http://www.whatwg.org/specs/web-apps/current-work/#getting-the-current-value-of-the-event-handler
+        let arg_names = if ty == "error" && self.type_id == WindowTypeId {
+            ~["event", "source", "lineno", "colno", "error"]
+        } else {
+            ~["event"]
+        };
+        let arg_names: ~[CString] = arg_names.iter().map(|name| name.to_c_str()).collect();
+        let arg_names: ~[*c_char] = arg_names.iter().map(|name| name.with_ref(|name| name))
+                                                   .collect();
+        // FIXME: hubbub does not report where attributes are in the source, so errors in
+        // handlers are reported at line 0 of the document.
+        let lineno = 0;
+
+        let handler = ty.with_c_str(|name| {
+            url.to_str().with_c_str(|filename| {
+                unsafe {
+                    let fun = JS_CompileUCFunction(cx, ptr::null(), name,
+                                                   arg_names.len() as c_uint,
+                                                   arg_names.as_ptr(), source.as_ptr(),
+                                                   source.len() as size_t, filename,
+                                                   lineno as c_uint);
+                    if fun.is_null() {
+                        ptr::null()
+                    } else {
+                        JS_CloneFunctionObject(cx, JS_GetFunctionObject(fun), scope)
+                    }
+                }
+            })
+        });
+
+        let listener = if handler.is_null() {
+            None
+        } else {
+            Some(EventListener::new(handler))
+        };
+        self.set_event_handler_common(ty, listener)
+    }
+
+    /// Implements the getter of an event handler IDL attribute, such as `onclick`.
+    pub fn get_event_handler_attribute(&self, ty: &str) -> JSVal {
+        match self.get_event_handler_common(ty) {
+            Some(listener) => unsafe { ObjectValue(&*listener.parent.callback()) },
+            None => NullValue(),
+        }
+    }
+
+    /// Implements the setter of an event handler IDL attribute. Values that are not callable
+    /// clear the event handler.
+    pub fn set_event_handler_attribute(&mut self, cx: *JSContext, ty: &str, value: JSVal) {
+        let callable = value.is_object() &&
+                       unsafe { JS_ObjectIsCallable(cx, value.to_object()) != 0 };
+        let listener = if callable {
+            Some(EventListener::new(value.to_object()))
+        } else {
+            None
+        };
+        self.set_event_handler_common(ty, listener)
+    }
+}
+
+/// Gives access to the `EventTarget` of the types that have event handler IDL attributes, which
+/// are defined for them with the `global_event_handlers!` and `window_event_handlers!` macros.
+pub trait EventTargetHelpers {
+    fn event_target<'a>(&'a self) -> &'a EventTarget;
+    fn mut_event_target<'a>(&'a mut self) -> &'a mut EventTarget;
 }
 
 impl Reflectable for EventTarget {
@@ -0,0 +1,201 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use dom::bindings::codegen::HistoryBinding;
+use dom::bindings::js::JS;
+use dom::bindings::trace::trace_jsval;
+use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object, cx_for_dom_object};
+use dom::bindings::error::{DataClone, ErrorResult, Fallible, Security};
+use dom::window::Window;
+use script_task::{NavigateMsg, PushStateMsg, ReplaceStateMsg};
+use servo_msg::constellation_msg::{Back, Forward, SessionHistoryState};
+use servo_util::str::DOMString;
+use servo_util::url::parse_url;
+
+use js::jsapi::{JSBool, JSContext, JSTracer, JS_ClearPendingException, JS_ParseJSON};
+use js::jsapi::{JS_Stringify, jschar};
+use js::jsval::{JSVal, NullValue};
+
+use extra::serialize::{Encoder, Encodable};
+use extra::url::Url;
+use std::cast;
+use std::libc;
+use std::ptr;
+use std::str;
+use std::vec;
+
+#[deriving(Encodable)]
+pub struct History {
+    reflector_: Reflector,
+    window: JS<Window>, //XXXjdm cycle: window->History->window
+    /// The state of the current session history entry, parsed once from its JSON serialization
+    /// so that every get of `state` returns the same object.
+    state: StateObject,
+}
+
+/// The state object of a session history entry, or null.
+struct StateObject {
+    value: JSVal,
+}
+
+impl<S: Encoder> Encodable<S> for StateObject {
+    fn encode(&self, s: &mut S) {
+        let tracer: *mut JSTracer = unsafe { cast::transmute(s) };
+        trace_jsval(tracer, "history state", self.value);
+    }
+}
+
+impl History {
+    pub fn new_inherited(window: &JS<Window>) -> History {
+        History {
+            reflector_: Reflector::new(),
+            window: window.clone(),
+            state: StateObject { value: NullValue() },
+        }
+    }
+
+    pub fn new(window: &JS<Window>) -> JS<History> {
+        reflect_dom_object(~History::new_inherited(window),
+                           window,
+                           HistoryBinding::Wrap)
+    }
+
+    /// Sets the state of the session history entry that was just traversed to.
+    pub fn set_state(&mut self, state: Option<~str>) {
+        let cx = cx_for_dom_object(self);
+        self.state.value = match state {
+            Some(state) => parse_state(cx, state.as_slice()),
+            None => NullValue(),
+        };
+    }
+
+    pub fn State(&self, _cx: *JSContext) -> JSVal {
+        self.state.value
+    }
+
+    pub fn Go(&self, delta: i32) {
+        // XXX A delta of zero should reload the page, but reloading isn't supported yet.
+        let direction = if delta < 0 { Back } else { Forward };
+        for _ in range(0, (delta as i64).abs()) {
+            self.window.get().script_chan.send(NavigateMsg(direction));
+        }
+    }
+
+    pub fn Back(&self) {
+        self.Go(-1);
+    }
+
+    pub fn Forward(&self) {
+        self.Go(1);
+    }
+
+    pub fn PushState(&mut self, cx: *JSContext, data: JSVal, title: DOMString,
+                     url: Option<DOMString>) -> ErrorResult {
+        self.add_state(cx, data, title, url, false)
+    }
+
+    pub fn ReplaceState(&mut self, cx: *JSContext, data: JSVal, title: DOMString,
+                        url: Option<DOMString>) -> ErrorResult {
+        self.add_state(cx, data, title, url, true)
+    }
+
+    // http://www.whatwg.org/html/#dom-history-pushstate
+    fn add_state(&mut self, cx: *JSContext, data: JSVal, title: DOMString,
+                 url: Option<DOMString>, replace: bool) -> ErrorResult {
+        let data = match serialize_state(cx, data) {
+            Ok(data) => data,
+            Err(error) => return Err(error),
+        };
+
+        let window = self.window.get();
+        let page = window.page();
+        let document_url = page.get_url();
+        let url = match url {
+            Some(url) => {
+                let url = parse_url(url, Some(document_url.clone()));
+                if !is_same_origin(&url, &document_url) {
+                    return Err(Security);
+                }
+                url
+            }
+            None => document_url,
+        };
+
+        {
+            let mut page_url = page.mut_url();
+            *page_url.get() = Some((url.clone(), false));
+        }
+        self.state.value = match data {
+            Some(ref data) => parse_state(cx, data.as_slice()),
+            None => NullValue(),
+        };
+
+        let state = SessionHistoryState {
+            data: data,
+            title: title,
+            url: url,
+        };
+        let msg = if replace {
+            ReplaceStateMsg(page.id, state)
+        } else {
+            PushStateMsg(page.id, state)
+        };
+        window.script_chan.send(msg);
+        Ok(())
+    }
+}
+
+impl Reflectable for History {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        &self.reflector_
+    }
+
+    fn mut_reflector<'a>(&'a mut self) -> &'a mut Reflector {
+        &mut self.reflector_
+    }
+}
+
+fn is_same_origin(a: &Url, b: &Url) -> bool {
+    a.scheme == b.scheme && a.host == b.host && a.port == b.port
+}
+
+/// Serializes a state object as JSON. States that can't be serialized throw a `DataCloneError`.
+fn serialize_state(cx: *JSContext, data: JSVal) -> Fallible<Option<~str>> {
+    let mut data = data;
+    let mut json: ~[u16] = ~[];
+    let ok = unsafe {
+        JS_Stringify(cx, &mut data, ptr::null(), NullValue(), write_json,
+                     &mut json as *mut ~[u16] as *libc::c_void)
+    };
+    if ok == 0 {
+        unsafe { JS_ClearPendingException(cx) };
+        return Err(DataClone);
+    }
+    // Values that have no JSON serialization, such as undefined, write nothing.
+    if json.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(str::from_utf16(json)))
+}
+
+extern fn write_json(buf: *jschar, len: u32, data: *libc::c_void) -> JSBool {
+    unsafe {
+        let json: &mut ~[u16] = cast::transmute(data);
+        vec::raw::buf_as_slice(buf, len as uint, |chunk| json.push_all(chunk));
+    }
+    1
+}
+
+/// Parses the JSON serialization of a state object into a new object.
+fn parse_state(cx: *JSContext, state: &str) -> JSVal {
+    let json = state.to_utf16();
+    let value = NullValue();
+    unsafe {
+        if JS_ParseJSON(cx, json.as_ptr(), json.len() as u32, &value) == 0 {
+            JS_ClearPendingException(cx);
+            return NullValue();
+        }
+    }
+    value
+}
@@ -3,10 +3,11 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use dom::bindings::codegen::HTMLAnchorElementBinding;
-use dom::bindings::codegen::InheritTypes::HTMLAnchorElementDerived;
+use dom::bindings::codegen::InheritTypes::{ElementCast, HTMLAnchorElementDerived};
 use dom::bindings::js::JS;
 use dom::bindings::error::ErrorResult;
 use dom::document::Document;
+use dom::domtokenlist::DOMTokenList;
 use dom::element::HTMLAnchorElementTypeId;
 use dom::eventtarget::{EventTarget, NodeTargetTypeId};
 use dom::htmlelement::HTMLElement;
@@ -15,7 +16,8 @@ use servo_util::str::DOMString;
 
 #[deriving(Encodable)]
 pub struct HTMLAnchorElement {
-    htmlelement: HTMLElement
+    htmlelement: HTMLElement,
+    rel_list: Option<JS<DOMTokenList>>,
 }
 
 impl HTMLAnchorElementDerived for EventTarget {
@@ -30,7 +32,8 @@ impl HTMLAnchorElementDerived for EventTarget {
 impl HTMLAnchorElement {
     pub fn new_inherited(localName: DOMString, document: JS<Document>) -> HTMLAnchorElement {
         HTMLAnchorElement {
-            htmlelement: HTMLElement::new_inherited(HTMLAnchorElementTypeId, localName, document)
+            htmlelement: HTMLElement::new_inherited(HTMLAnchorElementTypeId, localName, document),
+            rel_list: None,
         }
     }
 
@@ -73,14 +76,31 @@ impl HTMLAnchorElement {
         Ok(())
     }
 
-    pub fn Rel(&self) -> DOMString {
-        ~""
+    pub fn Rel(&self, _abstract_self: &JS<HTMLAnchorElement>) -> DOMString {
+        self.htmlelement.element.get_string_attribute("rel")
     }
 
-    pub fn SetRel(&self, _rel: DOMString) -> ErrorResult {
+    pub fn SetRel(&mut self, abstract_self: &JS<HTMLAnchorElement>, rel: DOMString) -> ErrorResult {
+        self.htmlelement.element.set_string_attribute(&ElementCast::from(abstract_self),
+                                                      "rel",
+                                                      rel);
         Ok(())
     }
 
+    // http://www.whatwg.org/html/#dom-a-rellist
+    pub fn RelList(&mut self, abstract_self: &JS<HTMLAnchorElement>) -> JS<DOMTokenList> {
+        match self.rel_list {
+            None => {
+                let doc = self.htmlelement.element.node.owner_doc();
+                let doc = doc.get();
+                let list = DOMTokenList::new(&doc.window, &ElementCast::from(abstract_self), "rel");
+                self.rel_list = Some(list.clone());
+                list
+            }
+            Some(ref list) => list.clone()
+        }
+    }
+
     pub fn Hreflang(&self) -> DOMString {
         ~""
     }
@@ -3,12 +3,13 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use dom::bindings::codegen::HTMLElementBinding;
-use dom::bindings::codegen::InheritTypes::HTMLElementDerived;
+use dom::bindings::codegen::InheritTypes::{ElementCast, HTMLElementDerived};
 use dom::bindings::js::JS;
 use dom::bindings::error::{ErrorResult, Fallible};
 use dom::document::Document;
-use dom::element::{Element, ElementTypeId, HTMLElementTypeId};
-use dom::eventtarget::{EventTarget, NodeTargetTypeId};
+use dom::domstringmap::DOMStringMap;
+use dom::element::{Element, ElementTypeId, ElementTypeId_, HTMLElementTypeId};
+use dom::eventtarget::{EventTarget, EventTargetHelpers, NodeTargetTypeId};
 use dom::node::{Node, ElementNodeTypeId};
 use js::jsapi::JSContext;
 use js::jsval::{JSVal, NullValue};
@@ -17,22 +18,37 @@ use servo_util::str::DOMString;
 
 #[deriving(Encodable)]
 pub struct HTMLElement {
-    element: Element
+    element: Element,
+    dataset: Option<JS<DOMStringMap>>,
 }
 
 impl HTMLElementDerived for EventTarget {
     fn is_htmlelement(&self) -> bool {
         match self.type_id {
+            NodeTargetTypeId(ElementNodeTypeId(ElementTypeId_)) => false,
             NodeTargetTypeId(ElementNodeTypeId(_)) => true,
             _ => false
         }
     }
 }
 
+impl EventTargetHelpers for HTMLElement {
+    fn event_target<'a>(&'a self) -> &'a EventTarget {
+        &self.element.node.eventtarget
+    }
+
+    fn mut_event_target<'a>(&'a mut self) -> &'a mut EventTarget {
+        &mut self.element.node.eventtarget
+    }
+}
+
+global_event_handlers!(HTMLElement)
+
 impl HTMLElement {
     pub fn new_inherited(type_id: ElementTypeId, tag_name: DOMString, document: JS<Document>) -> HTMLElement {
         HTMLElement {
-            element: Element::new_inherited(type_id, tag_name, namespace::HTML, document)
+            element: Element::new_inherited(type_id, tag_name, namespace::HTML, document),
+            dataset: None,
         }
     }
 
@@ -65,6 +81,20 @@ impl HTMLElement {
         Ok(())
     }
 
+    // http://www.whatwg.org/html/#dom-dataset
+    pub fn Dataset(&mut self, abstract_self: &JS<HTMLElement>) -> JS<DOMStringMap> {
+        match self.dataset {
+            None => {
+                let doc = self.element.node.owner_doc();
+                let doc = doc.get();
+                let map = DOMStringMap::new(&doc.window, &ElementCast::from(abstract_self));
+                self.dataset = Some(map.clone());
+                map
+            }
+            Some(ref map) => map.clone()
+        }
+    }
+
     pub fn GetItemValue(&self, _cx: *JSContext) -> Fallible<JSVal> {
         Ok(NullValue())
     }
@@ -11,6 +11,8 @@ use dom::element::HTMLFrameSetElementTypeId;
 use dom::eventtarget::{EventTarget, NodeTargetTypeId};
 use dom::htmlelement::HTMLElement;
 use dom::node::{Node, ElementNodeTypeId};
+use js::jsapi::JSContext;
+use js::jsval::{JSVal, NullValue};
 use servo_util::str::DOMString;
 
 #[deriving(Encodable)]
@@ -27,6 +29,8 @@ impl HTMLFrameSetElementDerived for EventTarget {
     }
 }
 
+window_reflecting_event_handlers!(HTMLFrameSetElement)
+
 impl HTMLFrameSetElement {
     pub fn new_inherited(localName: DOMString, document: JS<Document>) -> HTMLFrameSetElement {
         HTMLFrameSetElement {
@@ -81,6 +81,10 @@ impl HTMLImageElement {
                 let img_url = parse_url(src, url);
                 self.extra.image = Some(img_url.clone());
 
+                if !document.get().has_browsing_context {
+                    return
+                }
+
                 // inform the image cache to load this, but don't store a
                 // handle.
                 //
@@ -3,10 +3,11 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use dom::bindings::codegen::HTMLLinkElementBinding;
-use dom::bindings::codegen::InheritTypes::HTMLLinkElementDerived;
+use dom::bindings::codegen::InheritTypes::{ElementCast, HTMLLinkElementDerived};
 use dom::bindings::js::JS;
 use dom::bindings::error::ErrorResult;
 use dom::document::Document;
+use dom::domtokenlist::DOMTokenList;
 use dom::element::HTMLLinkElementTypeId;
 use dom::eventtarget::{EventTarget, NodeTargetTypeId};
 use dom::htmlelement::HTMLElement;
@@ -16,6 +17,7 @@ use servo_util::str::DOMString;
 #[deriving(Encodable)]
 pub struct HTMLLinkElement {
     htmlelement: HTMLElement,
+    rel_list: Option<JS<DOMTokenList>>,
 }
 
 impl HTMLLinkElementDerived for EventTarget {
@@ -30,7 +32,8 @@ impl HTMLLinkElementDerived for EventTarget {
 impl HTMLLinkElement {
     pub fn new_inherited(localName: DOMString, document: JS<Document>) -> HTMLLinkElement {
         HTMLLinkElement {
-            htmlelement: HTMLElement::new_inherited(HTMLLinkElementTypeId, localName, document)
+            htmlelement: HTMLElement::new_inherited(HTMLLinkElementTypeId, localName, document),
+            rel_list: None,
         }
     }
 
@@ -64,14 +67,31 @@ impl HTMLLinkElement {
         Ok(())
     }
 
-    pub fn Rel(&self) -> DOMString {
-        ~""
+    pub fn Rel(&self, _abstract_self: &JS<HTMLLinkElement>) -> DOMString {
+        self.htmlelement.element.get_string_attribute("rel")
     }
 
-    pub fn SetRel(&mut self, _rel: DOMString) -> ErrorResult {
+    pub fn SetRel(&mut self, abstract_self: &JS<HTMLLinkElement>, rel: DOMString) -> ErrorResult {
+        self.htmlelement.element.set_string_attribute(&ElementCast::from(abstract_self),
+                                                      "rel",
+                                                      rel);
         Ok(())
     }
 
+    // http://www.whatwg.org/html/#dom-link-rellist
+    pub fn RelList(&mut self, abstract_self: &JS<HTMLLinkElement>) -> JS<DOMTokenList> {
+        match self.rel_list {
+            None => {
+                let doc = self.htmlelement.element.node.owner_doc();
+                let doc = doc.get();
+                let list = DOMTokenList::new(&doc.window, &ElementCast::from(abstract_self), "rel");
+                self.rel_list = Some(list.clone());
+                list
+            }
+            Some(ref list) => list.clone()
+        }
+    }
+
     pub fn Media(&self) -> DOMString {
         ~""
     }
@@ -0,0 +1,444 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use dom::bindings::codegen::MutationObserverBinding;
+use dom::bindings::conversions::{Default, FromJSValConvertible, ToJSValConvertible};
+use dom::bindings::error::{ErrorResult, Fallible, FailureUnknown};
+use dom::bindings::js::JS;
+use dom::bindings::trace::trace_jsval;
+use dom::bindings::utils::{Reflectable, Reflector, cx_for_dom_object, reflect_dom_object};
+use dom::bindings::utils::with_gc_enabled;
+use dom::mutationrecord::{AttributeMutation, CharacterDataMutation, ChildListMutation};
+use dom::mutationrecord::{Mutation, MutationRecord};
+use dom::node::{Node, NodeHelpers, window_from_node};
+use dom::window::Window;
+use servo_util::namespace;
+use servo_util::str::DOMString;
+
+use js::jsapi::{JSContext, JSObject, JSTracer, JS_CallFunctionValue, JS_GetArrayLength};
+use js::jsapi::JS_ReportPendingException;
+use js::jsapi::{JS_GetElement, JS_GetProperty, JS_NewArrayObject, JS_ObjectIsCallable};
+use js::jsval::{JSVal, NullValue, ObjectValue, UndefinedValue};
+
+use extra::serialize::{Encoder, Encodable};
+use std::cast;
+use std::libc::c_int;
+use std::util::replace;
+
+/// The options that a node is observed with.
+#[deriving(Clone, Encodable)]
+pub struct ObserverOptions {
+    child_list: bool,
+    attributes: bool,
+    character_data: bool,
+    subtree: bool,
+    attribute_old_value: bool,
+    character_data_old_value: bool,
+    attribute_filter: Option<~[DOMString]>,
+}
+
+/// A node observed by a `MutationObserver`.
+#[deriving(Encodable)]
+struct RegisteredObserver {
+    target: JS<Node>,
+    options: ObserverOptions,
+    /// Whether this registration was added for a node removed from an observed subtree. These
+    /// are dropped the next time records are delivered.
+    transient: bool,
+}
+
+/// The function that records are delivered to.
+struct MutationCallback {
+    callback: JSVal,
+}
+
+impl<S: Encoder> Encodable<S> for MutationCallback {
+    fn encode(&self, s: &mut S) {
+        let tracer: *mut JSTracer = unsafe { cast::transmute(s) };
+        trace_jsval(tracer, "mutation observer callback", self.callback);
+    }
+}
+
+/// An observer is kept in the `mutation_observers` of the windows of the nodes it observes,
+/// since records are queued at the window of the node that changed.
+#[deriving(Encodable)]
+pub struct MutationObserver {
+    reflector_: Reflector,
+    callback: MutationCallback,
+    registrations: ~[RegisteredObserver],
+    record_queue: ~[JS<MutationRecord>],
+}
+
+impl MutationObserver {
+    pub fn new_inherited(callback: JSVal) -> MutationObserver {
+        MutationObserver {
+            reflector_: Reflector::new(),
+            callback: MutationCallback { callback: callback },
+            registrations: ~[],
+            record_queue: ~[],
+        }
+    }
+
+    pub fn new(window: &JS<Window>, callback: JSVal) -> JS<MutationObserver> {
+        reflect_dom_object(~MutationObserver::new_inherited(callback),
+                           window,
+                           MutationObserverBinding::Wrap)
+    }
+
+    pub fn Constructor(cx: *JSContext, owner: &JS<Window>, callback: JSVal)
+                       -> Fallible<JS<MutationObserver>> {
+        // XXX This should throw a TypeError once callback functions are supported.
+        let callable = callback.is_object() &&
+            unsafe { JS_ObjectIsCallable(cx, callback.to_object()) != 0 };
+        if !callable {
+            return Err(FailureUnknown);
+        }
+        Ok(MutationObserver::new(owner, callback))
+    }
+
+    // http://dom.spec.whatwg.org/#dom-mutationobserver-observe
+    pub fn Observe(&mut self, cx: *JSContext, abstract_self: &JS<MutationObserver>,
+                   target: &mut JS<Node>, options: Option<JSVal>) -> ErrorResult {
+        let init = match ObserverInit::from_jsval(cx, options) {
+            Ok(init) => init,
+            Err(error) => return Err(error),
+        };
+
+        // Step 1.
+        let attributes = init.attributes.unwrap_or(init.attribute_old_value.is_some() ||
+                                                   init.attribute_filter.is_some());
+        // Step 2.
+        let character_data =
+            init.character_data.unwrap_or(init.character_data_old_value.is_some());
+
+        let options = ObserverOptions {
+            child_list: init.child_list,
+            attributes: attributes,
+            character_data: character_data,
+            subtree: init.subtree,
+            attribute_old_value: init.attribute_old_value.unwrap_or(false),
+            character_data_old_value: init.character_data_old_value.unwrap_or(false),
+            attribute_filter: init.attribute_filter,
+        };
+
+        // Step 3-6.
+        // XXX These should throw TypeErrors.
+        if !options.child_list && !options.attributes && !options.character_data {
+            return Err(FailureUnknown);
+        }
+        if options.attribute_old_value && !options.attributes {
+            return Err(FailureUnknown);
+        }
+        if options.attribute_filter.is_some() && !options.attributes {
+            return Err(FailureUnknown);
+        }
+        if options.character_data_old_value && !options.character_data {
+            return Err(FailureUnknown);
+        }
+
+        // Step 7.
+        let existing = self.registrations.iter().position(|registration| {
+            !registration.transient && registration.target == *target
+        });
+        match existing {
+            Some(index) => self.registrations[index].options = options,
+            // Step 8.
+            None => {
+                self.registrations.push(RegisteredObserver {
+                    target: target.clone(),
+                    options: options,
+                    transient: false,
+                });
+                let mut window = window_from_node(target);
+                let window = window.get_mut();
+                if !window.mutation_observers.contains(abstract_self) {
+                    window.mutation_observers.push(abstract_self.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // http://dom.spec.whatwg.org/#dom-mutationobserver-disconnect
+    pub fn Disconnect(&mut self, abstract_self: &JS<MutationObserver>) {
+        for registration in self.registrations.iter() {
+            let mut window = window_from_node(&registration.target);
+            window.get_mut().mutation_observers.retain(|observer| observer != abstract_self);
+        }
+        self.registrations = ~[];
+        self.record_queue = ~[];
+    }
+
+    // http://dom.spec.whatwg.org/#dom-mutationobserver-takerecords
+    pub fn TakeRecords(&mut self, cx: *JSContext) -> JSVal {
+        let records = replace(&mut self.record_queue, ~[]);
+        records_to_jsval(cx, records)
+    }
+
+    /// Returns whether this observer is interested in a mutation of the given node, and if so,
+    /// whether it wants the old value.
+    fn interest(&self, target: &JS<Node>, mutation: &Mutation) -> Option<bool> {
+        let mut interested = false;
+        let mut with_old_value = false;
+        for registration in self.registrations.iter() {
+            let options = &registration.options;
+            if registration.target != *target &&
+               !(options.subtree && registration.target.is_inclusive_ancestor_of(target)) {
+                continue;
+            }
+
+            let wants_old_value = match *mutation {
+                AttributeMutation(ref name, ref namespace, _) => {
+                    if !options.attributes {
+                        continue;
+                    }
+                    match options.attribute_filter {
+                        Some(ref filter) if *namespace != namespace::Null ||
+                                            !filter.contains(name) => continue,
+                        _ => (),
+                    }
+                    options.attribute_old_value
+                }
+                CharacterDataMutation(_) => {
+                    if !options.character_data {
+                        continue;
+                    }
+                    options.character_data_old_value
+                }
+                ChildListMutation(..) => {
+                    if !options.child_list {
+                        continue;
+                    }
+                    false
+                }
+            };
+            interested = true;
+            with_old_value = with_old_value || wants_old_value;
+        }
+
+        if interested {
+            Some(with_old_value)
+        } else {
+            None
+        }
+    }
+}
+
+impl Reflectable for MutationObserver {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        &self.reflector_
+    }
+
+    fn mut_reflector<'a>(&'a mut self) -> &'a mut Reflector {
+        &mut self.reflector_
+    }
+}
+
+// http://dom.spec.whatwg.org/#queue-a-mutation-record
+pub fn queue_mutation_record(target: &JS<Node>, mutation: Mutation) {
+    let window = window_from_node(target);
+    if window.get().mutation_observers.is_empty() {
+        return;
+    }
+
+    let observers = window.get().mutation_observers.clone();
+    for observer in observers.iter() {
+        let mut observer = observer.clone();
+        // Step 1-4.
+        let with_old_value = match observer.get().interest(target, &mutation) {
+            Some(with_old_value) => with_old_value,
+            None => continue,
+        };
+        // Step 5.
+        let record = MutationRecord::new(&window, target, &mutation, with_old_value);
+        observer.get_mut().record_queue.push(record);
+    }
+}
+
+/// Lets the observers of the subtrees that contained `parent` keep observing `node` after it is
+/// removed from `parent`, until records are next delivered.
+// http://dom.spec.whatwg.org/#concept-node-remove
+pub fn add_transient_observers(node: &JS<Node>, parent: &JS<Node>) {
+    let window = window_from_node(node);
+    let observers = window.get().mutation_observers.clone();
+    for observer in observers.iter() {
+        let mut observer = observer.clone();
+        let observer = observer.get_mut();
+        let transients: ~[RegisteredObserver] = observer.registrations.iter().filter(|r| {
+            r.options.subtree && r.target.is_inclusive_ancestor_of(parent)
+        }).map(|registration| {
+            RegisteredObserver {
+                target: node.clone(),
+                options: registration.options.clone(),
+                transient: true,
+            }
+        }).collect();
+        observer.registrations.push_all_move(transients);
+    }
+}
+
+/// Delivers the queued records of every observer of the window to their callbacks, which run in
+/// the context of the window that each observer was made in. Returns whether any callback ran,
+/// in which case more records may have been queued.
+// http://dom.spec.whatwg.org/#notify-mutation-observers
+pub fn notify_mutation_observers(window: &JS<Window>) -> bool {
+    let mut notified = false;
+    let observers = window.get().mutation_observers.clone();
+    for observer in observers.iter() {
+        let (records, callback) = {
+            let mut observer = observer.clone();
+            let observer = observer.get_mut();
+            observer.registrations.retain(|registration| !registration.transient);
+            (replace(&mut observer.record_queue, ~[]), observer.callback.callback)
+        };
+        if records.is_empty() {
+            continue;
+        }
+
+        let cx = cx_for_dom_object(observer.get());
+        let this = observer.reflector().get_jsobject();
+        let args = [records_to_jsval(cx, records), ObjectValue(unsafe { &*this })];
+        let rval = NullValue();
+        with_gc_enabled(cx, || {
+            unsafe {
+                if JS_CallFunctionValue(cx, this, callback, 2, args.as_ptr(), &rval) == 0 {
+                    // The callback threw; report the exception instead of leaving it pending.
+                    JS_ReportPendingException(cx);
+                }
+            }
+        });
+        notified = true;
+    }
+    notified
+}
+
+/// Converts records to a JavaScript array.
+// XXX Sequence return values aren't supported by the bindings yet.
+fn records_to_jsval(cx: *JSContext, records: ~[JS<MutationRecord>]) -> JSVal {
+    let values: ~[JSVal] = records.iter().map(|record| record.to_jsval(cx)).collect();
+    let array = unsafe { JS_NewArrayObject(cx, values.len() as c_int, values.as_ptr()) };
+    if array.is_null() {
+        return NullValue();
+    }
+    ObjectValue(unsafe { &*array })
+}
+
+/// The `MutationObserverInit` dictionary.
+// XXX The bindings can't convert dictionaries with sequence members or members without
+//     default values yet, so the options are read by hand.
+struct ObserverInit {
+    child_list: bool,
+    attributes: Option<bool>,
+    character_data: Option<bool>,
+    subtree: bool,
+    attribute_old_value: Option<bool>,
+    character_data_old_value: Option<bool>,
+    attribute_filter: Option<~[DOMString]>,
+}
+
+impl ObserverInit {
+    fn from_jsval(cx: *JSContext, value: Option<JSVal>) -> Fallible<ObserverInit> {
+        let object = match value {
+            Some(value) if value.is_object() => Some(value.to_object()),
+            Some(value) if !value.is_null_or_undefined() => return Err(FailureUnknown),
+            _ => None,
+        };
+
+        let child_list = match get_bool_member(cx, object, "childList") {
+            Ok(child_list) => child_list.unwrap_or(false),
+            Err(error) => return Err(error),
+        };
+        let attributes = match get_bool_member(cx, object, "attributes") {
+            Ok(attributes) => attributes,
+            Err(error) => return Err(error),
+        };
+        let character_data = match get_bool_member(cx, object, "characterData") {
+            Ok(character_data) => character_data,
+            Err(error) => return Err(error),
+        };
+        let subtree = match get_bool_member(cx, object, "subtree") {
+            Ok(subtree) => subtree.unwrap_or(false),
+            Err(error) => return Err(error),
+        };
+        let attribute_old_value = match get_bool_member(cx, object, "attributeOldValue") {
+            Ok(attribute_old_value) => attribute_old_value,
+            Err(error) => return Err(error),
+        };
+        let character_data_old_value =
+            match get_bool_member(cx, object, "characterDataOldValue") {
+                Ok(character_data_old_value) => character_data_old_value,
+                Err(error) => return Err(error),
+            };
+        let attribute_filter = match get_member(cx, object, "attributeFilter") {
+            Ok(Some(filter)) => match string_sequence_from_jsval(cx, filter) {
+                Ok(filter) => Some(filter),
+                Err(error) => return Err(error),
+            },
+            Ok(None) => None,
+            Err(error) => return Err(error),
+        };
+
+        Ok(ObserverInit {
+            child_list: child_list,
+            attributes: attributes,
+            character_data: character_data,
+            subtree: subtree,
+            attribute_old_value: attribute_old_value,
+            character_data_old_value: character_data_old_value,
+            attribute_filter: attribute_filter,
+        })
+    }
+}
+
+/// Gets a member of a dictionary, or `None` if it's missing.
+fn get_member(cx: *JSContext, object: Option<*JSObject>, name: &str) -> Fallible<Option<JSVal>> {
+    let object = match object {
+        Some(object) => object,
+        None => return Ok(None),
+    };
+    let value = UndefinedValue();
+    let found = name.to_c_str().with_ref(|name| unsafe {
+        JS_GetProperty(cx, object, name, &value)
+    });
+    if found == 0 {
+        return Err(FailureUnknown);
+    }
+    Ok(if value.is_undefined() { None } else { Some(value) })
+}
+
+fn get_bool_member(cx: *JSContext, object: Option<*JSObject>, name: &str)
+                   -> Fallible<Option<bool>> {
+    match get_member(cx, object, name) {
+        Ok(Some(value)) => match FromJSValConvertible::from_jsval(cx, value, ()) {
+            Ok(value) => Ok(Some(value)),
+            Err(()) => Err(FailureUnknown),
+        },
+        Ok(None) => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+fn string_sequence_from_jsval(cx: *JSContext, value: JSVal) -> Fallible<~[DOMString]> {
+    if !value.is_object() {
+        return Err(FailureUnknown);
+    }
+    let object = value.to_object();
+    let length = 0u32;
+    if unsafe { JS_GetArrayLength(cx, object, &length) } == 0 {
+        return Err(FailureUnknown);
+    }
+
+    let mut strings = ~[];
+    for index in range(0, length) {
+        let element = UndefinedValue();
+        if unsafe { JS_GetElement(cx, object, index, &element) } == 0 {
+            return Err(FailureUnknown);
+        }
+        match FromJSValConvertible::from_jsval(cx, element, Default) {
+            Ok(string) => strings.push(string),
+            Err(()) => return Err(FailureUnknown),
+        }
+    }
+    Ok(strings)
+}
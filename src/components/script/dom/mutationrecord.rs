@@ -0,0 +1,130 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use dom::bindings::codegen::MutationRecordBinding;
+use dom::bindings::js::JS;
+use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
+use dom::node::Node;
+use dom::nodelist::NodeList;
+use dom::window::Window;
+use servo_util::namespace::Namespace;
+use servo_util::str::DOMString;
+
+/// A change to the DOM that mutation observers can be told about.
+pub enum Mutation {
+    /// Children were added to and removed from the target, between the given previous and next
+    /// siblings.
+    ChildListMutation(~[JS<Node>], ~[JS<Node>], Option<JS<Node>>, Option<JS<Node>>),
+    /// The attribute of the target with the given local name and namespace changed. Carries the
+    /// old value, if the attribute was set.
+    AttributeMutation(DOMString, Namespace, Option<DOMString>),
+    /// The data of the target changed. Carries the old data.
+    CharacterDataMutation(DOMString),
+}
+
+#[deriving(Encodable)]
+pub struct MutationRecord {
+    reflector_: Reflector,
+    type_: DOMString,
+    target: JS<Node>,
+    added_nodes: JS<NodeList>,
+    removed_nodes: JS<NodeList>,
+    previous_sibling: Option<JS<Node>>,
+    next_sibling: Option<JS<Node>>,
+    attribute_name: Option<DOMString>,
+    attribute_namespace: Option<DOMString>,
+    old_value: Option<DOMString>,
+}
+
+impl MutationRecord {
+    pub fn new_inherited(window: &JS<Window>, target: &JS<Node>, mutation: &Mutation,
+                         with_old_value: bool) -> MutationRecord {
+        let (added_nodes, removed_nodes, previous_sibling, next_sibling) = match *mutation {
+            ChildListMutation(ref added, ref removed, ref previous, ref next) => {
+                (added.clone(), removed.clone(), previous.clone(), next.clone())
+            }
+            _ => (~[], ~[], None, None),
+        };
+        let (type_, attribute_name, attribute_namespace, old_value) = match *mutation {
+            ChildListMutation(..) => (~"childList", None, None, None),
+            AttributeMutation(ref name, ref namespace, ref old_value) => {
+                let namespace = match namespace.to_str() {
+                    "" => None,
+                    url => Some(url.to_owned()),
+                };
+                (~"attributes", Some(name.clone()), namespace, old_value.clone())
+            }
+            CharacterDataMutation(ref old_value) => {
+                (~"characterData", None, None, Some(old_value.clone()))
+            }
+        };
+
+        MutationRecord {
+            reflector_: Reflector::new(),
+            type_: type_,
+            target: target.clone(),
+            added_nodes: NodeList::new_simple_list(window, added_nodes),
+            removed_nodes: NodeList::new_simple_list(window, removed_nodes),
+            previous_sibling: previous_sibling,
+            next_sibling: next_sibling,
+            attribute_name: attribute_name,
+            attribute_namespace: attribute_namespace,
+            old_value: if with_old_value { old_value } else { None },
+        }
+    }
+
+    pub fn new(window: &JS<Window>, target: &JS<Node>, mutation: &Mutation,
+               with_old_value: bool) -> JS<MutationRecord> {
+        reflect_dom_object(~MutationRecord::new_inherited(window, target, mutation,
+                                                          with_old_value),
+                           window,
+                           MutationRecordBinding::Wrap)
+    }
+
+    pub fn Type(&self) -> DOMString {
+        self.type_.clone()
+    }
+
+    pub fn Target(&self) -> JS<Node> {
+        self.target.clone()
+    }
+
+    pub fn AddedNodes(&self) -> JS<NodeList> {
+        self.added_nodes.clone()
+    }
+
+    pub fn RemovedNodes(&self) -> JS<NodeList> {
+        self.removed_nodes.clone()
+    }
+
+    pub fn GetPreviousSibling(&self) -> Option<JS<Node>> {
+        self.previous_sibling.clone()
+    }
+
+    pub fn GetNextSibling(&self) -> Option<JS<Node>> {
+        self.next_sibling.clone()
+    }
+
+    pub fn GetAttributeName(&self) -> Option<DOMString> {
+        self.attribute_name.clone()
+    }
+
+    pub fn GetAttributeNamespace(&self) -> Option<DOMString> {
+        self.attribute_namespace.clone()
+    }
+
+    pub fn GetOldValue(&self) -> Option<DOMString> {
+        self.old_value.clone()
+    }
+}
+
+impl Reflectable for MutationRecord {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        &self.reflector_
+    }
+
+    fn mut_reflector<'a>(&'a mut self) -> &'a mut Reflector {
+        &mut self.reflector_
+    }
+}
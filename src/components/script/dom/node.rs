@@ -10,7 +10,7 @@ use dom::bindings::codegen::InheritTypes::{ElementCast, TextCast, NodeCast};
 use dom::bindings::codegen::InheritTypes::{CharacterDataCast, NodeBase, NodeDerived};
 use dom::bindings::codegen::InheritTypes::ProcessingInstructionCast;
 use dom::bindings::codegen::NodeBinding::NodeConstants;
-use dom::bindings::js::JS;
+use dom::bindings::js::{JS, Weak};
 use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
 use dom::bindings::error::{ErrorResult, Fallible, NotFound, HierarchyRequest};
 use dom::bindings::utils;
@@ -21,7 +21,12 @@ use dom::documentfragment::DocumentFragment;
 use dom::documenttype::DocumentType;
 use dom::element::{Element, ElementTypeId, HTMLAnchorElementTypeId, IElement};
 use dom::eventtarget::{EventTarget, NodeTargetTypeId};
+use dom::mutationobserver::{add_transient_observers, queue_mutation_record};
+use dom::mutationrecord::ChildListMutation;
+use dom::nodeiterator::update_node_iterators_for_removal;
+use DOMNodeIterator = dom::nodeiterator::NodeIterator;
 use dom::nodelist::{NodeList};
+use dom::range::{Range, update_ranges_for_insertion, update_ranges_for_removal};
 use dom::text::Text;
 use dom::processinginstruction::ProcessingInstruction;
 use dom::window::Window;
@@ -35,8 +40,9 @@ use js::jsfriendapi;
 use std::cast::transmute;
 use std::cast;
 use std::cell::{RefCell, Ref, RefMut};
-use std::iter::{Map, Filter};
+use std::iter::{AdditiveIterator, Map, Filter};
 use std::libc::uintptr_t;
+use std::mem;
 use std::ptr;
 use std::unstable::raw::Box;
 use std::util;
@@ -80,6 +86,14 @@ pub struct Node {
     /// A bitfield of flags for node items.
     priv flags: NodeFlags,
 
+    /// Weak references to the live ranges with a boundary point in this node, and possibly to
+    /// some that have moved elsewhere or been freed since, which are forgotten when this node
+    /// next changes.
+    ranges: ~[Weak<Range>],
+
+    /// Weak references to the node iterators rooted at this node.
+    node_iterators: ~[Weak<DOMNodeIterator>],
+
     /// Layout information. Only the layout task may touch this data.
     ///
     /// FIXME(pcwalton): We need to send these back to the layout task to be destroyed when this
@@ -223,12 +237,20 @@ pub enum NodeTypeId {
 }
 
 pub trait INode {
+    fn InsertBefore(&mut self, node: &mut JS<Node>, child: Option<JS<Node>>)
+                    -> Fallible<JS<Node>>;
     fn AppendChild(&mut self, node: &mut JS<Node>) -> Fallible<JS<Node>>;
     fn ReplaceChild(&mut self, node: &mut JS<Node>, child: &mut JS<Node>) -> Fallible<JS<Node>>;
     fn RemoveChild(&mut self, node: &mut JS<Node>) -> Fallible<JS<Node>>;
 }
 
 impl INode for JS<Node> {
+    fn InsertBefore(&mut self, node: &mut JS<Node>, child: Option<JS<Node>>)
+                    -> Fallible<JS<Node>> {
+        let mut self_node = self.clone();
+        self.get_mut().InsertBefore(&mut self_node, node, child)
+    }
+
     fn AppendChild(&mut self, node: &mut JS<Node>) -> Fallible<JS<Node>> {
         let mut self_node = self.clone();
         self.get_mut().AppendChild(&mut self_node, node)
@@ -253,6 +275,9 @@ pub trait NodeHelpers {
     fn is_in_doc(&self) -> bool;
     fn is_inclusive_ancestor_of(&self, parent: &JS<Node>) -> bool;
     fn is_parent_of(&self, child: &JS<Node>) -> bool;
+    fn root(&self) -> JS<Node>;
+    fn index(&self) -> u32;
+    fn length(&self) -> u32;
 
     fn type_id(&self) -> NodeTypeId;
 
@@ -284,6 +309,8 @@ pub trait NodeHelpers {
     fn sequential_traverse_postorder(&self) -> TreeIterator;
     fn inclusively_following_siblings(&self) -> AbstractNodeChildrenIterator;
 
+    fn heap_size_of_subtree(&self) -> uint;
+
     fn from_untrusted_node_address(runtime: *JSRuntime, candidate: UntrustedNodeAddress) -> Self;
     fn to_trusted_node_address(&self) -> TrustedNodeAddress;
 }
@@ -528,6 +555,35 @@ impl NodeHelpers for JS<Node> {
         TreeIterator::new(nodes)
     }
 
+    /// Estimates the heap memory used by this node and its descendants, for the memory profiler.
+    /// Elements are counted as plain `Element`s, so the fields specific to each kind of element
+    /// are missed, as is anything owned by the JavaScript engine.
+    fn heap_size_of_subtree(&self) -> uint {
+        let mut size = 0;
+        for node in self.traverse_preorder() {
+            size += match node.type_id() {
+                ElementNodeTypeId(..) => {
+                    let element: JS<Element> = ElementCast::to(&node);
+                    let element = element.get();
+                    let attrs_size = element.attrs.iter().map(|attr| {
+                        let attr = attr.get();
+                        mem::size_of::<Attr>() + attr.local_name.len() + attr.value.len() +
+                            attr.name.len() + attr.prefix.as_ref().map_or(0, |prefix| prefix.len())
+                    }).sum();
+                    mem::size_of::<Element>() + element.tag_name.len() + attrs_size
+                }
+                TextNodeTypeId | CommentNodeTypeId | ProcessingInstructionNodeTypeId => {
+                    let characterdata: JS<CharacterData> = CharacterDataCast::to(&node);
+                    mem::size_of::<CharacterData>() + characterdata.get().data.len()
+                }
+                DoctypeNodeTypeId | DocumentFragmentNodeTypeId | DocumentNodeTypeId => {
+                    mem::size_of::<Node>()
+                }
+            };
+        }
+        size
+    }
+
     fn inclusively_following_siblings(&self) -> AbstractNodeChildrenIterator {
         AbstractNodeChildrenIterator {
             current_node: Some(self.clone()),
@@ -551,6 +607,32 @@ impl NodeHelpers for JS<Node> {
         }
     }
 
+    // http://dom.spec.whatwg.org/#concept-tree-root
+    fn root(&self) -> JS<Node> {
+        self.ancestors().last().unwrap_or(self.clone())
+    }
+
+    // http://dom.spec.whatwg.org/#concept-tree-index
+    fn index(&self) -> u32 {
+        self.parent_node().map_default(0, |parent| {
+            parent.children().take_while(|child| child != self).len() as u32
+        })
+    }
+
+    // http://dom.spec.whatwg.org/#concept-node-length
+    fn length(&self) -> u32 {
+        match self.type_id() {
+            DoctypeNodeTypeId => 0,
+            CommentNodeTypeId |
+            TextNodeTypeId |
+            ProcessingInstructionNodeTypeId => {
+                let characterdata: JS<CharacterData> = CharacterDataCast::to(self);
+                characterdata.get().Length()
+            }
+            _ => self.children().len() as u32,
+        }
+    }
+
     /// If the given untrusted node address represents a valid DOM node in the given runtime,
     /// returns it.
     fn from_untrusted_node_address(runtime: *JSRuntime, candidate: UntrustedNodeAddress)
@@ -799,6 +881,9 @@ impl Node {
 
             flags: NodeFlags::new(type_id),
 
+            ranges: ~[],
+            node_iterators: ~[],
+
             layout_data: LayoutDataRef::new(),
         }
     }
@@ -928,7 +1013,7 @@ impl Node {
             TextNodeTypeId |
             ProcessingInstructionNodeTypeId => {
                 let chardata: JS<CharacterData> = CharacterDataCast::to(abstract_self);
-                Some(chardata.get().Data())
+                Some(chardata.get().data.clone())
             }
             _ => {
                 None
@@ -968,7 +1053,7 @@ impl Node {
             TextNodeTypeId |
             ProcessingInstructionNodeTypeId => {
                 let characterdata: JS<CharacterData> = CharacterDataCast::to(abstract_self);
-                Some(characterdata.get().Data())
+                Some(characterdata.get().data.clone())
             }
             DoctypeNodeTypeId |
             DocumentNodeTypeId => {
@@ -1000,7 +1085,13 @@ impl Node {
                 self.wait_until_safe_to_modify_dom();
 
                 let mut characterdata: JS<CharacterData> = CharacterDataCast::to(abstract_self);
-                characterdata.get_mut().data = value.clone();
+                let length = characterdata.get().Length();
+                let characterdata_clone = characterdata.clone();
+                match characterdata.get_mut().replace_data(&characterdata_clone, 0, length,
+                                                           value.clone()) {
+                    Ok(()) => {}
+                    Err(error) => return Err(error),
+                }
 
                 // Notify the document that the content of this node is different
                 let document = self.owner_doc();
@@ -1031,9 +1122,9 @@ impl Node {
         // If node is an element, it is _affected by a base URL change_.
     }
 
-    // http://dom.spec.whatwg.org/#concept-node-pre-insert
-    fn pre_insert(node: &mut JS<Node>, parent: &mut JS<Node>, child: Option<JS<Node>>)
-                  -> Fallible<JS<Node>> {
+    // http://dom.spec.whatwg.org/#concept-node-ensure-pre-insertion-validity
+    pub fn ensure_pre_insertion_validity(node: &JS<Node>, parent: &JS<Node>,
+                                         child: &Option<JS<Node>>) -> ErrorResult {
         // Step 1.
         match parent.type_id() {
             DocumentNodeTypeId |
@@ -1048,7 +1139,7 @@ impl Node {
         }
 
         // Step 3.
-        match child {
+        match *child {
             Some(ref child) if !parent.is_parent_of(child) => return Err(NotFound),
             _ => ()
         }
@@ -1093,7 +1184,7 @@ impl Node {
                                 if parent.child_elements().len() > 0 {
                                     return Err(HierarchyRequest);
                                 }
-                                match child {
+                                match *child {
                                     Some(ref child) if child.inclusively_following_siblings()
                                                             .any(|child| child.is_doctype()) => {
                                         return Err(HierarchyRequest);
@@ -1112,7 +1203,7 @@ impl Node {
                         if parent.child_elements().len() > 0 {
                             return Err(HierarchyRequest);
                         }
-                        match child {
+                        match *child {
                             Some(ref child) if child.inclusively_following_siblings()
                                                     .any(|child| child.is_doctype()) => {
                                 return Err(HierarchyRequest);
@@ -1125,7 +1216,7 @@ impl Node {
                         if parent.children().any(|c| c.is_doctype()) {
                             return Err(HierarchyRequest);
                         }
-                        match child {
+                        match *child {
                             Some(ref child) => {
                                 if parent.children()
                                     .take_while(|c| c != child)
@@ -1151,6 +1242,18 @@ impl Node {
             _ => (),
         }
 
+        Ok(())
+    }
+
+    // http://dom.spec.whatwg.org/#concept-node-pre-insert
+    fn pre_insert(node: &mut JS<Node>, parent: &mut JS<Node>, child: Option<JS<Node>>)
+                  -> Fallible<JS<Node>> {
+        // Step 1-6.
+        match Node::ensure_pre_insertion_validity(node, parent, &child) {
+            Ok(()) => (),
+            Err(error) => return Err(error),
+        }
+
         // Step 7-8.
         let referenceChild = match child {
             Some(ref child) if child == node => node.next_sibling(),
@@ -1173,25 +1276,48 @@ impl Node {
               child: Option<JS<Node>>,
               suppress_observers: SuppressObserver) {
         // XXX assert owner_doc
-        // Step 1-3: ranges.
+        // Step 1.
+        let count = match node.type_id() {
+            DocumentFragmentNodeTypeId => node.children().len() as u32,
+            _ => 1,
+        };
+
+        // Step 2-3.
+        match child {
+            Some(ref child) => update_ranges_for_insertion(parent, child.index(), count),
+            None => (),
+        }
+
         // Step 4.
         let mut nodes = match node.type_id() {
             DocumentFragmentNodeTypeId => node.children().collect(),
             _ => ~[node.clone()],
         };
 
-        // Step 5: DocumentFragment, mutation records.
-        // Step 6: DocumentFragment.
+        // Step 5-6: DocumentFragment.
         match node.type_id() {
             DocumentFragmentNodeTypeId => {
                 for mut c in node.children() {
                     Node::remove(&mut c, node, Suppressed);
                 }
+                queue_mutation_record(node, ChildListMutation(~[], nodes.clone(), None, None));
             },
             _ => (),
         }
 
-        // Step 7: mutation records.
+        // Step 7.
+        match suppress_observers {
+            Unsuppressed => {
+                let previous_sibling = match child {
+                    Some(ref child) => child.prev_sibling(),
+                    None => parent.last_child(),
+                };
+                queue_mutation_record(parent, ChildListMutation(nodes.clone(), ~[],
+                                                                previous_sibling, child.clone()));
+            }
+            Suppressed => ()
+        }
+
         // Step 8.
         for node in nodes.mut_iter() {
             parent.add_child(node, child.clone());
@@ -1240,7 +1366,11 @@ impl Node {
             None => (),
         }
 
-        // Step 6: mutation records.
+        // Step 6.
+        if !addedNodes.is_empty() || !removedNodes.is_empty() {
+            queue_mutation_record(parent, ChildListMutation(addedNodes.clone(),
+                                                            removedNodes.clone(), None, None));
+        }
 
         // Step 7.
         for removedNode in removedNodes.iter() {
@@ -1270,8 +1400,27 @@ impl Node {
     fn remove(node: &mut JS<Node>, parent: &mut JS<Node>, suppress_observers: SuppressObserver) {
         assert!(node.parent_node().map_default(false, |ref node_parent| node_parent == parent));
 
-        // Step 1-5: ranges.
-        // Step 6-7: mutation observers.
+        // Step 1-5.
+        update_ranges_for_removal(node, parent, node.index());
+
+        // NodeIterator pre-removing steps.
+        update_node_iterators_for_removal(node);
+
+        let previous_sibling = node.prev_sibling();
+        let next_sibling = node.next_sibling();
+
+        // Step 6.
+        match suppress_observers {
+            Suppressed => (),
+            Unsuppressed => {
+                queue_mutation_record(parent, ChildListMutation(~[], ~[node.clone()],
+                                                                previous_sibling, next_sibling));
+            }
+        }
+
+        // Step 7.
+        add_transient_observers(node, parent);
+
         // Step 8.
         parent.remove_child(node);
         node.get_mut().flags.set_is_in_doc(false);
@@ -1519,26 +1668,35 @@ impl Node {
             Some(ref sibling) if sibling == node => node.next_sibling(),
             _ => next_sibling
         };
+        let previous_sibling = match child.prev_sibling() {
+            Some(ref sibling) if sibling == node => node.prev_sibling(),
+            previous_sibling => previous_sibling
+        };
 
         // Step 9.
         Node::adopt(node, &document_from_node(parent));
 
+        // Step 12.
+        let nodes = match node.type_id() {
+            DocumentFragmentNodeTypeId => node.children().collect(),
+            _ => ~[node.clone()],
+        };
+
         {
             // Step 10.
             Node::remove(child, parent, Suppressed);
 
             // Step 11.
-            Node::insert(node, parent, reference_child, Suppressed);
+            Node::insert(node, parent, reference_child.clone(), Suppressed);
         }
 
-        // Step 12-14.
-        // Step 13: mutation records.
+        // Step 13.
+        queue_mutation_record(parent, ChildListMutation(nodes.clone(), ~[child.clone()],
+                                                        previous_sibling, reference_child));
+
+        // Step 14.
         child.node_removed();
-        if node.type_id() == DocumentFragmentNodeTypeId {
-            for child_node in node.children() {
-                child_node.node_inserted();
-            }
-        } else {
+        for node in nodes.iter() {
             node.node_inserted();
         }
 
@@ -1564,7 +1722,9 @@ impl Node {
                     match prev_text {
                         Some(ref text_node) => {
                             let mut prev_characterdata: JS<CharacterData> = CharacterDataCast::to(text_node);
-                            prev_characterdata.get_mut().AppendData(characterdata.get().Data());
+                            let prev_clone = prev_characterdata.clone();
+                            let data = characterdata.get().data.clone();
+                            prev_characterdata.get_mut().AppendData(&prev_clone, data);
                             abstract_self.remove_child(&mut child);
                         },
                         None => prev_text = Some(child)
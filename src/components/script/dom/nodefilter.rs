@@ -0,0 +1,144 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The `NodeFilter` constants, and the filtering shared by `TreeWalker` and `NodeIterator`.
+
+use dom::bindings::codegen::NodeFilterBinding::NodeFilterConstants;
+use dom::bindings::conversions::{FromJSValConvertible, ToJSValConvertible};
+use dom::bindings::error::{Fallible, FailureUnknown, InvalidState};
+use dom::bindings::js::JS;
+use dom::bindings::trace::trace_jsval;
+use dom::bindings::utils::{Reflectable, Reflector, cx_for_dom_object, with_gc_enabled};
+use dom::node::{Node, window_from_node};
+
+use js::jsapi::{JSContext, JSTracer, JS_CallFunctionValue, JS_GetProperty, JS_ObjectIsCallable};
+use js::jsval::{JSVal, NullValue, UndefinedValue};
+
+use extra::serialize::{Encoder, Encodable};
+use std::cast;
+
+/// Only exposes the constants of the `NodeFilter` interface. Filters themselves are script
+/// values, see `Filter`.
+#[deriving(Encodable)]
+pub struct NodeFilter {
+    reflector_: Reflector,
+}
+
+impl Reflectable for NodeFilter {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        &self.reflector_
+    }
+
+    fn mut_reflector<'a>(&'a mut self) -> &'a mut Reflector {
+        &mut self.reflector_
+    }
+}
+
+/// The `whatToShow` mask and the script filter of a `TreeWalker` or `NodeIterator`.
+pub struct Filter {
+    what_to_show: u32,
+    /// A function, an object with an `acceptNode` method, or null.
+    // XXX This should be a `NodeFilter` callback interface once the bindings can convert the
+    //     return values of callbacks.
+    callback: JSVal,
+    /// Set while the callback runs, so that it can't reenter the traversal.
+    active: bool,
+}
+
+impl<S: Encoder> Encodable<S> for Filter {
+    fn encode(&self, s: &mut S) {
+        let tracer: *mut JSTracer = unsafe { cast::transmute(s) };
+        trace_jsval(tracer, "node filter", self.callback);
+    }
+}
+
+impl Filter {
+    pub fn new(what_to_show: u32, callback: Option<JSVal>) -> Fallible<Filter> {
+        let callback = match callback {
+            Some(callback) if callback.is_object() => callback,
+            Some(callback) if !callback.is_null_or_undefined() => {
+                // XXX This should throw a TypeError.
+                return Err(FailureUnknown);
+            }
+            _ => NullValue(),
+        };
+        Ok(Filter {
+            what_to_show: what_to_show,
+            callback: callback,
+            active: false,
+        })
+    }
+
+    pub fn what_to_show(&self) -> u32 {
+        self.what_to_show
+    }
+
+    pub fn callback(&self) -> JSVal {
+        self.callback
+    }
+
+    /// Returns whether the given node is accepted, rejected or skipped, as one of the
+    /// `FILTER_*` constants.
+    // http://dom.spec.whatwg.org/#concept-node-filter
+    pub fn accept_node(&mut self, node: &JS<Node>) -> Fallible<u16> {
+        // Step 1.
+        if self.active {
+            return Err(InvalidState);
+        }
+
+        // Step 2-3.
+        let n = node.get().NodeType() - 1;
+        if self.what_to_show & (1 << n) == 0 {
+            return Ok(NodeFilterConstants::FILTER_SKIP);
+        }
+
+        // Step 4.
+        if self.callback.is_null_or_undefined() {
+            return Ok(NodeFilterConstants::FILTER_ACCEPT);
+        }
+
+        // Step 5-8.
+        self.active = true;
+        let result = self.call(node);
+        self.active = false;
+        result
+    }
+
+    /// Calls the callback, or its `acceptNode` method, with the given node. Exceptions thrown by
+    /// the callback are left pending.
+    fn call(&self, node: &JS<Node>) -> Fallible<u16> {
+        let cx = cx_for_dom_object(node.get());
+        let object = self.callback.to_object();
+        let (this, function) = if unsafe { JS_ObjectIsCallable(cx, object) } != 0 {
+            (window_from_node(node).reflector().get_jsobject(), self.callback)
+        } else {
+            let function = UndefinedValue();
+            let found = "acceptNode".to_c_str().with_ref(|name| unsafe {
+                JS_GetProperty(cx, object, name, &function)
+            });
+            if found == 0 || !is_callable(cx, function) {
+                // XXX This should throw a TypeError if acceptNode isn't callable.
+                return Err(FailureUnknown);
+            }
+            (object, function)
+        };
+
+        let args = [node.to_jsval(cx)];
+        let rval = UndefinedValue();
+        let ok = with_gc_enabled(cx, || {
+            unsafe { JS_CallFunctionValue(cx, this, function, 1, args.as_ptr(), &rval) }
+        });
+        if ok == 0 {
+            return Err(FailureUnknown);
+        }
+        match FromJSValConvertible::from_jsval(cx, rval, ()) {
+            Ok(result) => Ok(result),
+            Err(()) => Err(FailureUnknown),
+        }
+    }
+}
+
+fn is_callable(cx: *JSContext, value: JSVal) -> bool {
+    value.is_object() && unsafe { JS_ObjectIsCallable(cx, value.to_object()) } != 0
+}
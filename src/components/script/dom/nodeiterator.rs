@@ -0,0 +1,231 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use dom::bindings::codegen::NodeFilterBinding::NodeFilterConstants;
+use dom::bindings::codegen::NodeIteratorBinding;
+use dom::bindings::error::Fallible;
+use dom::bindings::js::{JS, WeakReferent};
+use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
+use dom::node::{Node, NodeHelpers};
+use dom::nodefilter::Filter;
+use dom::window::Window;
+
+use js::jsapi::JSContext;
+use js::jsval::JSVal;
+
+enum Direction {
+    Next,
+    Previous,
+}
+
+#[deriving(Encodable)]
+pub struct NodeIterator {
+    reflector_: Reflector,
+    root: JS<Node>,
+    reference_node: JS<Node>,
+    pointer_before_reference_node: bool,
+    filter: Filter,
+    /// Clears the reference to this iterator that its root keeps.
+    priv weak_referent: WeakReferent<NodeIterator>,
+}
+
+impl NodeIterator {
+    pub fn new_inherited(root: &JS<Node>, filter: Filter) -> NodeIterator {
+        NodeIterator {
+            reflector_: Reflector::new(),
+            root: root.clone(),
+            reference_node: root.clone(),
+            pointer_before_reference_node: true,
+            filter: filter,
+            weak_referent: WeakReferent::new(),
+        }
+    }
+
+    pub fn new(window: &JS<Window>, root: &JS<Node>, filter: Filter) -> JS<NodeIterator> {
+        let iterator = reflect_dom_object(~NodeIterator::new_inherited(root, filter),
+                                          window,
+                                          NodeIteratorBinding::Wrap);
+        // The root only keeps a weak reference, so that iterators that scripts no longer use can
+        // be freed.
+        let mut root = root.clone();
+        let iterators = &mut root.get_mut().node_iterators;
+        iterators.retain(|iterator| iterator.upgrade().is_some());
+        iterators.push(iterator.get().weak_referent.downgrade(&iterator));
+        iterator
+    }
+
+    pub fn Root(&self) -> JS<Node> {
+        self.root.clone()
+    }
+
+    pub fn ReferenceNode(&self) -> JS<Node> {
+        self.reference_node.clone()
+    }
+
+    pub fn PointerBeforeReferenceNode(&self) -> bool {
+        self.pointer_before_reference_node
+    }
+
+    pub fn WhatToShow(&self) -> u32 {
+        self.filter.what_to_show()
+    }
+
+    pub fn Filter(&self, _cx: *JSContext) -> JSVal {
+        self.filter.callback()
+    }
+
+    // http://dom.spec.whatwg.org/#dom-nodeiterator-nextnode
+    pub fn NextNode(&mut self) -> Fallible<Option<JS<Node>>> {
+        self.traverse(Next)
+    }
+
+    // http://dom.spec.whatwg.org/#dom-nodeiterator-previousnode
+    pub fn PreviousNode(&mut self) -> Fallible<Option<JS<Node>>> {
+        self.traverse(Previous)
+    }
+
+    // http://dom.spec.whatwg.org/#dom-nodeiterator-detach
+    pub fn Detach(&self) {
+        // This does nothing, and the iterator stays live.
+    }
+
+    // http://dom.spec.whatwg.org/#concept-nodeiterator-traverse
+    fn traverse(&mut self, direction: Direction) -> Fallible<Option<JS<Node>>> {
+        // Step 1-2.
+        let mut node = self.reference_node.clone();
+        let mut before_node = self.pointer_before_reference_node;
+
+        // Step 3.
+        loop {
+            // Step 3.1.
+            match direction {
+                Next if before_node => before_node = false,
+                Next => {
+                    node = match following_node(&node, &self.root) {
+                        Some(following) => following,
+                        None => return Ok(None),
+                    };
+                }
+                Previous if !before_node => before_node = true,
+                Previous => {
+                    node = match preceding_node(&node, &self.root) {
+                        Some(preceding) => preceding,
+                        None => return Ok(None),
+                    };
+                }
+            }
+
+            // Step 3.2-3.
+            match self.filter.accept_node(&node) {
+                Ok(result) if result == NodeFilterConstants::FILTER_ACCEPT => break,
+                Ok(_) => (),
+                Err(error) => return Err(error),
+            }
+        }
+
+        // Step 4-6.
+        self.reference_node = node.clone();
+        self.pointer_before_reference_node = before_node;
+        Ok(Some(node))
+    }
+}
+
+impl Reflectable for NodeIterator {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        &self.reflector_
+    }
+
+    fn mut_reflector<'a>(&'a mut self) -> &'a mut Reflector {
+        &mut self.reflector_
+    }
+}
+
+/// Moves the reference nodes of node iterators out of `node`, as it is about to be removed from
+/// its parent. Only the iterators rooted at an ancestor or a descendant of `node` can have their
+/// reference node in it.
+// http://dom.spec.whatwg.org/#nodeiterator-pre-removing-steps
+pub fn update_node_iterators_for_removal(node: &JS<Node>) {
+    let mut iterators = ~[];
+    for root in node.ancestors().chain(node.traverse_preorder()) {
+        for iterator in root.get().node_iterators.iter() {
+            match iterator.upgrade() {
+                Some(iterator) => iterators.push(iterator),
+                None => (),
+            }
+        }
+    }
+
+    for iterator in iterators.iter() {
+        let mut iterator = iterator.clone();
+        let iterator = iterator.get_mut();
+
+        // Step 1.
+        if !node.is_inclusive_ancestor_of(&iterator.reference_node) || *node == iterator.root {
+            continue;
+        }
+
+        // Step 2.
+        if iterator.pointer_before_reference_node {
+            match following_node_skipping_children(node, &iterator.root) {
+                Some(next) => {
+                    iterator.reference_node = next;
+                    continue;
+                }
+                None => iterator.pointer_before_reference_node = false,
+            }
+        }
+
+        // Step 3.
+        iterator.reference_node = match node.prev_sibling() {
+            Some(sibling) => last_inclusive_descendant(&sibling),
+            None => node.parent_node().expect("removed node has no parent"),
+        };
+    }
+}
+
+/// Returns the node that follows `node` in tree order, within the tree rooted at `root`.
+fn following_node(node: &JS<Node>, root: &JS<Node>) -> Option<JS<Node>> {
+    match node.first_child() {
+        Some(child) => Some(child),
+        None => following_node_skipping_children(node, root),
+    }
+}
+
+/// Returns the node that follows `node` and its descendants in tree order, within the tree
+/// rooted at `root`.
+fn following_node_skipping_children(node: &JS<Node>, root: &JS<Node>) -> Option<JS<Node>> {
+    let mut node = node.clone();
+    while node != *root {
+        match node.next_sibling() {
+            Some(sibling) => return Some(sibling),
+            None => (),
+        }
+        node = match node.parent_node() {
+            Some(parent) => parent,
+            None => return None,
+        };
+    }
+    None
+}
+
+/// Returns the node that precedes `node` in tree order, within the tree rooted at `root`.
+fn preceding_node(node: &JS<Node>, root: &JS<Node>) -> Option<JS<Node>> {
+    if node == root {
+        return None;
+    }
+    match node.prev_sibling() {
+        Some(sibling) => Some(last_inclusive_descendant(&sibling)),
+        None => node.parent_node(),
+    }
+}
+
+fn last_inclusive_descendant(node: &JS<Node>) -> JS<Node> {
+    let mut node = node.clone();
+    loop {
+        node = match node.last_child() {
+            Some(child) => child,
+            None => return node,
+        };
+    }
+}
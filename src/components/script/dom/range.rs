@@ -0,0 +1,1068 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use dom::bindings::codegen::InheritTypes::{CharacterDataCast, NodeCast, TextCast};
+use dom::bindings::codegen::RangeBinding;
+use dom::bindings::codegen::RangeBinding::RangeConstants;
+use dom::bindings::error::{ErrorResult, Fallible, HierarchyRequest, IndexSize, InvalidNodeType};
+use dom::bindings::error::{InvalidState, NotSupported, WrongDocument};
+use dom::bindings::js::{JS, WeakReferent};
+use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
+use dom::characterdata::CharacterData;
+use dom::clientrect::ClientRect;
+use dom::clientrectlist::ClientRectList;
+use dom::document::Document;
+use dom::documentfragment::DocumentFragment;
+use dom::node::{CommentNodeTypeId, DoctypeNodeTypeId, DocumentFragmentNodeTypeId};
+use dom::node::{DocumentNodeTypeId, INode, Node, NodeHelpers, ProcessingInstructionNodeTypeId};
+use dom::node::{TextNodeTypeId, document_from_node, window_from_node};
+use dom::text::Text;
+use dom::window::Window;
+use layout_interface::{ContentBoxesQuery, ContentBoxesResponse};
+use servo_util::geometry::Au;
+use servo_util::str::utf16_offset_to_byte_index;
+
+use geom::point::Point2D;
+use geom::rect::Rect;
+use geom::size::Size2D;
+use std::comm::Chan;
+
+/// A node and an offset into its children, or into its data for character data nodes.
+// http://dom.spec.whatwg.org/#concept-range-bp
+#[deriving(Clone, Encodable)]
+struct BoundaryPoint {
+    node: JS<Node>,
+    offset: u32,
+}
+
+impl BoundaryPoint {
+    fn new(node: &JS<Node>, offset: u32) -> BoundaryPoint {
+        BoundaryPoint {
+            node: node.clone(),
+            offset: offset,
+        }
+    }
+}
+
+/// The position of a boundary point relative to another.
+#[deriving(Eq)]
+enum Position {
+    Before,
+    Equal,
+    After,
+}
+
+/// Whether the contents of a range are copied or moved into a fragment.
+enum ContentsMode {
+    Cloning,
+    Extracting,
+}
+
+#[deriving(Encodable)]
+pub struct Range {
+    reflector_: Reflector,
+    priv start: BoundaryPoint,
+    priv end: BoundaryPoint,
+    /// Clears the references to this range that the nodes of its boundary points keep.
+    priv weak_referent: WeakReferent<Range>,
+}
+
+impl Range {
+    fn new_inherited(start: BoundaryPoint, end: BoundaryPoint) -> Range {
+        Range {
+            reflector_: Reflector::new(),
+            start: start,
+            end: end,
+            weak_referent: WeakReferent::new(),
+        }
+    }
+
+    fn new_with_boundary_points(window: &JS<Window>, start: BoundaryPoint, end: BoundaryPoint)
+                                -> JS<Range> {
+        let range = reflect_dom_object(~Range::new_inherited(start, end),
+                                       window,
+                                       RangeBinding::Wrap);
+        register_boundary_points(&range);
+        range
+    }
+
+    /// Sets the boundary points of this range, and registers it with their nodes so that it is
+    /// updated when they change.
+    fn set_boundary_points(&mut self, start: BoundaryPoint, end: BoundaryPoint) {
+        self.start = start;
+        self.end = end;
+        register_boundary_points(&unsafe { JS::from_raw(self as *mut Range) });
+    }
+
+    /// Creates a range that is collapsed at the start of the given document.
+    pub fn new(document: &JS<Document>) -> JS<Range> {
+        let node: JS<Node> = NodeCast::from(document);
+        let point = BoundaryPoint::new(&node, 0);
+        Range::new_with_boundary_points(&document.get().window, point.clone(), point)
+    }
+
+    pub fn Constructor(owner: &JS<Window>) -> Fallible<JS<Range>> {
+        Ok(Range::new(&owner.get().Document()))
+    }
+
+    // http://dom.spec.whatwg.org/#concept-range-root
+    fn root(&self) -> JS<Node> {
+        self.start.node.root()
+    }
+
+    pub fn StartContainer(&self) -> JS<Node> {
+        self.start.node.clone()
+    }
+
+    pub fn StartOffset(&self) -> u32 {
+        self.start.offset
+    }
+
+    pub fn EndContainer(&self) -> JS<Node> {
+        self.end.node.clone()
+    }
+
+    pub fn EndOffset(&self) -> u32 {
+        self.end.offset
+    }
+
+    // http://dom.spec.whatwg.org/#dom-range-collapsed
+    pub fn Collapsed(&self) -> bool {
+        self.start.node == self.end.node && self.start.offset == self.end.offset
+    }
+
+    // http://dom.spec.whatwg.org/#dom-range-commonancestorcontainer
+    pub fn CommonAncestorContainer(&self) -> JS<Node> {
+        common_ancestor(&self.start, &self.end)
+    }
+
+    // http://dom.spec.whatwg.org/#concept-range-bp-set
+    fn check_boundary_point(node: &JS<Node>, offset: u32) -> ErrorResult {
+        // Step 1.
+        if node.is_doctype() {
+            return Err(InvalidNodeType);
+        }
+
+        // Step 2.
+        if offset > node.length() {
+            return Err(IndexSize);
+        }
+
+        // A `DOMString` can't be split between the two halves of a surrogate pair.
+        if is_character_data(node) {
+            let characterdata: JS<CharacterData> = CharacterDataCast::to(node);
+            let data = characterdata.get().data.as_slice();
+            if utf16_offset_to_byte_index(data, offset as uint).is_none() {
+                return Err(IndexSize);
+            }
+        }
+        Ok(())
+    }
+
+    // http://dom.spec.whatwg.org/#concept-range-bp-set
+    fn set_start(&mut self, node: &JS<Node>, offset: u32) -> ErrorResult {
+        // Step 1-2.
+        match Range::check_boundary_point(node, offset) {
+            Ok(()) => (),
+            Err(error) => return Err(error),
+        }
+
+        // Step 3-4.
+        let point = BoundaryPoint::new(node, offset);
+        let end = if node.root() != self.root() || position(&point, &self.end) == After {
+            point.clone()
+        } else {
+            self.end.clone()
+        };
+        self.set_boundary_points(point, end);
+        Ok(())
+    }
+
+    // http://dom.spec.whatwg.org/#concept-range-bp-set
+    fn set_end(&mut self, node: &JS<Node>, offset: u32) -> ErrorResult {
+        // Step 1-2.
+        match Range::check_boundary_point(node, offset) {
+            Ok(()) => (),
+            Err(error) => return Err(error),
+        }
+
+        // Step 3-4.
+        let point = BoundaryPoint::new(node, offset);
+        let start = if node.root() != self.root() || position(&point, &self.start) == Before {
+            point.clone()
+        } else {
+            self.start.clone()
+        };
+        self.set_boundary_points(start, point);
+        Ok(())
+    }
+
+    // http://dom.spec.whatwg.org/#dom-range-setstart
+    pub fn SetStart(&mut self, node: &mut JS<Node>, offset: u32) -> ErrorResult {
+        self.set_start(node, offset)
+    }
+
+    // http://dom.spec.whatwg.org/#dom-range-setend
+    pub fn SetEnd(&mut self, node: &mut JS<Node>, offset: u32) -> ErrorResult {
+        self.set_end(node, offset)
+    }
+
+    // http://dom.spec.whatwg.org/#dom-range-setstartbefore
+    pub fn SetStartBefore(&mut self, node: &mut JS<Node>) -> ErrorResult {
+        match node.parent_node() {
+            Some(parent) => self.set_start(&parent, node.index()),
+            None => Err(InvalidNodeType),
+        }
+    }
+
+    // http://dom.spec.whatwg.org/#dom-range-setstartafter
+    pub fn SetStartAfter(&mut self, node: &mut JS<Node>) -> ErrorResult {
+        match node.parent_node() {
+            Some(parent) => self.set_start(&parent, node.index() + 1),
+            None => Err(InvalidNodeType),
+        }
+    }
+
+    // http://dom.spec.whatwg.org/#dom-range-setendbefore
+    pub fn SetEndBefore(&mut self, node: &mut JS<Node>) -> ErrorResult {
+        match node.parent_node() {
+            Some(parent) => self.set_end(&parent, node.index()),
+            None => Err(InvalidNodeType),
+        }
+    }
+
+    // http://dom.spec.whatwg.org/#dom-range-setendafter
+    pub fn SetEndAfter(&mut self, node: &mut JS<Node>) -> ErrorResult {
+        match node.parent_node() {
+            Some(parent) => self.set_end(&parent, node.index() + 1),
+            None => Err(InvalidNodeType),
+        }
+    }
+
+    // http://dom.spec.whatwg.org/#dom-range-collapse
+    pub fn Collapse(&mut self, to_start: bool) {
+        if to_start {
+            self.end = self.start.clone();
+        } else {
+            self.start = self.end.clone();
+        }
+    }
+
+    // http://dom.spec.whatwg.org/#concept-range-select
+    fn select(&mut self, node: &JS<Node>) -> ErrorResult {
+        // Step 1-2.
+        let parent = match node.parent_node() {
+            Some(parent) => parent,
+            None => return Err(InvalidNodeType),
+        };
+
+        // Step 3-5.
+        let index = node.index();
+        self.set_boundary_points(BoundaryPoint::new(&parent, index),
+                                 BoundaryPoint::new(&parent, index + 1));
+        Ok(())
+    }
+
+    // http://dom.spec.whatwg.org/#dom-range-selectnode
+    pub fn SelectNode(&mut self, node: &mut JS<Node>) -> ErrorResult {
+        self.select(node)
+    }
+
+    // http://dom.spec.whatwg.org/#dom-range-selectnodecontents
+    pub fn SelectNodeContents(&mut self, node: &mut JS<Node>) -> ErrorResult {
+        // Step 1.
+        if node.is_doctype() {
+            return Err(InvalidNodeType);
+        }
+
+        // Step 2-4.
+        self.set_boundary_points(BoundaryPoint::new(node, 0),
+                                 BoundaryPoint::new(node, node.length()));
+        Ok(())
+    }
+
+    // http://dom.spec.whatwg.org/#dom-range-compareboundarypoints
+    pub fn CompareBoundaryPoints(&self, how: u16, source_range: &mut JS<Range>) -> Fallible<i16> {
+        let source_range = source_range.get();
+
+        // Step 2.
+        if self.root() != source_range.root() {
+            return Err(WrongDocument);
+        }
+
+        // Step 1, 3.
+        let (this_point, other_point) = match how {
+            RangeConstants::START_TO_START => (&self.start, &source_range.start),
+            RangeConstants::START_TO_END => (&self.end, &source_range.start),
+            RangeConstants::END_TO_END => (&self.end, &source_range.end),
+            RangeConstants::END_TO_START => (&self.start, &source_range.end),
+            _ => return Err(NotSupported),
+        };
+
+        // Step 4.
+        Ok(match position(this_point, other_point) {
+            Before => -1,
+            Equal => 0,
+            After => 1,
+        })
+    }
+
+    // http://dom.spec.whatwg.org/#dom-range-deletecontents
+    pub fn DeleteContents(&mut self) -> ErrorResult {
+        // Step 1.
+        if self.Collapsed() {
+            return Ok(());
+        }
+
+        // Step 2.
+        let start = self.start.clone();
+        let end = self.end.clone();
+
+        // Step 3.
+        if start.node == end.node && is_character_data(&start.node) {
+            delete_data(&start.node, start.offset, end.offset - start.offset);
+            return Ok(());
+        }
+
+        // Step 4.
+        let nodes_to_remove: ~[JS<Node>] =
+            common_ancestor(&start, &end).traverse_preorder().filter(|node| {
+                is_contained(node, &start, &end) &&
+                    !node.parent_node().map_default(false, |parent| {
+                        is_contained(&parent, &start, &end)
+                    })
+            }).collect();
+
+        // Step 5-6.
+        let new_point = point_after_removal(&start, &end);
+
+        // Step 7.
+        if is_character_data(&start.node) {
+            let count = start.node.length() - start.offset;
+            delete_data(&start.node, start.offset, count);
+        }
+
+        // Step 8.
+        for node in nodes_to_remove.iter() {
+            let mut node = node.clone();
+            let mut parent = node.parent_node().expect("contained node has no parent");
+            match parent.RemoveChild(&mut node) {
+                Ok(_) => (),
+                Err(error) => return Err(error),
+            }
+        }
+
+        // Step 9.
+        if is_character_data(&end.node) {
+            delete_data(&end.node, 0, end.offset);
+        }
+
+        // Step 10.
+        self.set_boundary_points(new_point.clone(), new_point);
+        Ok(())
+    }
+
+    // http://dom.spec.whatwg.org/#dom-range-extractcontents
+    pub fn ExtractContents(&mut self) -> Fallible<JS<DocumentFragment>> {
+        let start = self.start.clone();
+        let end = self.end.clone();
+
+        // Step 10-11 of extracting, which need the nodes that are about to be extracted.
+        let new_point = point_after_removal(&start, &end);
+
+        let fragment = match process_contents(&start, &end, Extracting) {
+            Ok(fragment) => fragment,
+            Err(error) => return Err(error),
+        };
+
+        // Step 17 of extracting.
+        self.set_boundary_points(new_point.clone(), new_point);
+        Ok(fragment)
+    }
+
+    // http://dom.spec.whatwg.org/#dom-range-clonecontents
+    pub fn CloneContents(&self) -> Fallible<JS<DocumentFragment>> {
+        process_contents(&self.start, &self.end, Cloning)
+    }
+
+    // http://dom.spec.whatwg.org/#dom-range-insertnode
+    pub fn InsertNode(&mut self, node: &mut JS<Node>) -> ErrorResult {
+        let start = self.start.clone();
+
+        // Step 1.
+        match start.node.type_id() {
+            CommentNodeTypeId |
+            ProcessingInstructionNodeTypeId => return Err(HierarchyRequest),
+            TextNodeTypeId if start.node.parent_node().is_none() => return Err(HierarchyRequest),
+            _ => (),
+        }
+        if start.node == *node {
+            return Err(HierarchyRequest);
+        }
+
+        // Step 2-4.
+        let reference_node = if start.node.is_text() {
+            Some(start.node.clone())
+        } else {
+            start.node.children().nth(start.offset as uint)
+        };
+
+        // Step 5.
+        let mut parent = match reference_node {
+            Some(ref reference_node) => reference_node.parent_node().unwrap(),
+            None => start.node.clone(),
+        };
+
+        // Step 6.
+        match Node::ensure_pre_insertion_validity(node, &parent, &reference_node) {
+            Ok(()) => (),
+            Err(error) => return Err(error),
+        }
+
+        // Step 7.
+        let reference_node = if start.node.is_text() {
+            let mut text: JS<Text> = TextCast::to(&start.node);
+            let abstract_text = text.clone();
+            match text.get_mut().SplitText(&abstract_text, start.offset) {
+                Ok(new_text) => Some(NodeCast::from(&new_text)),
+                Err(error) => return Err(error),
+            }
+        } else {
+            reference_node
+        };
+
+        // Step 8.
+        let reference_node = if reference_node == Some(node.clone()) {
+            node.next_sibling()
+        } else {
+            reference_node
+        };
+
+        // Step 9.
+        match node.parent_node() {
+            Some(mut old_parent) => {
+                match old_parent.RemoveChild(node) {
+                    Ok(_) => (),
+                    Err(error) => return Err(error),
+                }
+            }
+            None => (),
+        }
+
+        // Step 10.
+        let new_offset = match reference_node {
+            Some(ref reference_node) => reference_node.index(),
+            None => parent.length(),
+        };
+
+        // Step 11.
+        let new_offset = new_offset + match node.type_id() {
+            DocumentFragmentNodeTypeId => node.length(),
+            _ => 1,
+        };
+
+        // Step 12.
+        match parent.InsertBefore(node, reference_node) {
+            Ok(_) => (),
+            Err(error) => return Err(error),
+        }
+
+        // Step 13.
+        if self.Collapsed() {
+            let start = self.start.clone();
+            self.set_boundary_points(start, BoundaryPoint::new(&parent, new_offset));
+        }
+        Ok(())
+    }
+
+    // http://dom.spec.whatwg.org/#dom-range-surroundcontents
+    pub fn SurroundContents(&mut self, new_parent: &mut JS<Node>) -> ErrorResult {
+        // Step 1.
+        let partially_contains_non_text = {
+            let (start, end) = (&self.start, &self.end);
+            common_ancestor(start, end).traverse_preorder().any(|node| {
+                !node.is_text() && is_partially_contained(&node, start, end)
+            })
+        };
+        if partially_contains_non_text {
+            return Err(InvalidState);
+        }
+
+        // Step 2.
+        match new_parent.type_id() {
+            DocumentNodeTypeId |
+            DoctypeNodeTypeId |
+            DocumentFragmentNodeTypeId => return Err(InvalidNodeType),
+            _ => (),
+        }
+
+        // Step 3.
+        let fragment = match self.ExtractContents() {
+            Ok(fragment) => fragment,
+            Err(error) => return Err(error),
+        };
+
+        // Step 4.
+        if new_parent.first_child().is_some() {
+            Node::replace_all(None, new_parent);
+        }
+
+        // Step 5.
+        match self.InsertNode(new_parent) {
+            Ok(()) => (),
+            Err(error) => return Err(error),
+        }
+
+        // Step 6.
+        let mut fragment: JS<Node> = NodeCast::from(&fragment);
+        match new_parent.AppendChild(&mut fragment) {
+            Ok(_) => (),
+            Err(error) => return Err(error),
+        }
+
+        // Step 7.
+        self.select(new_parent)
+    }
+
+    // http://dom.spec.whatwg.org/#dom-range-clonerange
+    pub fn CloneRange(&self) -> JS<Range> {
+        let window = window_from_node(&self.start.node);
+        Range::new_with_boundary_points(&window, self.start.clone(), self.end.clone())
+    }
+
+    // http://dom.spec.whatwg.org/#dom-range-detach
+    pub fn Detach(&self) {
+        // This does nothing, and the range stays live.
+    }
+
+    // http://dom.spec.whatwg.org/#dom-range-ispointinrange
+    pub fn IsPointInRange(&self, node: &mut JS<Node>, offset: u32) -> Fallible<bool> {
+        // Step 1.
+        if node.root() != self.root() {
+            return Ok(false);
+        }
+
+        // Step 2-3.
+        match Range::check_boundary_point(node, offset) {
+            Ok(()) => (),
+            Err(error) => return Err(error),
+        }
+
+        // Step 4-5.
+        let point = BoundaryPoint::new(node, offset);
+        Ok(position(&point, &self.start) != Before && position(&point, &self.end) != After)
+    }
+
+    // http://dom.spec.whatwg.org/#dom-range-comparepoint
+    pub fn ComparePoint(&self, node: &mut JS<Node>, offset: u32) -> Fallible<i16> {
+        // Step 1.
+        if node.root() != self.root() {
+            return Err(WrongDocument);
+        }
+
+        // Step 2-3.
+        match Range::check_boundary_point(node, offset) {
+            Ok(()) => (),
+            Err(error) => return Err(error),
+        }
+
+        // Step 4-6.
+        let point = BoundaryPoint::new(node, offset);
+        if position(&point, &self.start) == Before {
+            Ok(-1)
+        } else if position(&point, &self.end) == After {
+            Ok(1)
+        } else {
+            Ok(0)
+        }
+    }
+
+    // http://dom.spec.whatwg.org/#dom-range-intersectsnode
+    pub fn IntersectsNode(&self, node: &mut JS<Node>) -> bool {
+        // Step 1.
+        if node.root() != self.root() {
+            return false;
+        }
+
+        // Step 2-3.
+        let parent = match node.parent_node() {
+            Some(parent) => parent,
+            None => return true,
+        };
+
+        // Step 4-6.
+        let offset = node.index();
+        position(&BoundaryPoint::new(&parent, offset), &self.end) == Before &&
+            position(&BoundaryPoint::new(&parent, offset + 1), &self.start) == After
+    }
+
+    /// Returns the boxes of the elements and text that the range selects. The whole boxes of
+    /// partially selected text are included, rather than only the selected part.
+    // http://dev.w3.org/csswg/cssom-view/#dom-range-getclientrects
+    fn content_boxes(&self, window: &JS<Window>) -> ~[Rect<Au>] {
+        let (start, end) = (&self.start, &self.end);
+        let mut boxes = ~[];
+        for node in common_ancestor(start, end).traverse_preorder() {
+            let contained = is_contained(&node, start, end);
+            let selected = if node.is_element() {
+                contained && !node.parent_node().map_default(false, |parent| {
+                    is_contained(&parent, start, end)
+                })
+            } else {
+                node.is_text() && (contained || node == start.node || node == end.node)
+            };
+            if !selected {
+                continue;
+            }
+
+            let (port, chan) = Chan::new();
+            let addr = node.to_trusted_node_address();
+            match window.get().page().query_layout(ContentBoxesQuery(addr, chan), port) {
+                ContentBoxesResponse(rects) => boxes.push_all_move(rects),
+            }
+        }
+        boxes
+    }
+
+    // http://dev.w3.org/csswg/cssom-view/#dom-range-getclientrects
+    pub fn GetClientRects(&self) -> JS<ClientRectList> {
+        let window = window_from_node(&self.start.node);
+        let rects = self.content_boxes(&window).map(|r| {
+            ClientRect::new(
+                &window,
+                r.origin.y,
+                r.origin.y + r.size.height,
+                r.origin.x,
+                r.origin.x + r.size.width)
+        });
+        ClientRectList::new(&window, rects)
+    }
+
+    // http://dev.w3.org/csswg/cssom-view/#dom-range-getboundingclientrect
+    pub fn GetBoundingClientRect(&self) -> JS<ClientRect> {
+        let window = window_from_node(&self.start.node);
+        let boxes = self.content_boxes(&window);
+        let non_empty: ~[&Rect<Au>] = boxes.iter().filter(|rect| {
+            rect.size.width != Au(0) && rect.size.height != Au(0)
+        }).collect();
+        let rect = if non_empty.is_empty() {
+            boxes.head_opt().map_default(Rect(Point2D(Au(0), Au(0)), Size2D(Au(0), Au(0))),
+                                         |rect| *rect)
+        } else {
+            non_empty.iter().skip(1).fold(*non_empty[0], |union, rect| union.union(*rect))
+        };
+        ClientRect::new(
+            &window,
+            rect.origin.y,
+            rect.origin.y + rect.size.height,
+            rect.origin.x,
+            rect.origin.x + rect.size.width)
+    }
+}
+
+impl Reflectable for Range {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        &self.reflector_
+    }
+
+    fn mut_reflector<'a>(&'a mut self) -> &'a mut Reflector {
+        &mut self.reflector_
+    }
+}
+
+/// Registers `range` with the nodes of its boundary points, so that it is updated when they
+/// change. The nodes only keep weak references, so that ranges that scripts no longer use can
+/// be freed.
+fn register_boundary_points(range: &JS<Range>) {
+    let weak_range = range.get().weak_referent.downgrade(range);
+    let nodes = [range.get().start.node.clone(), range.get().end.node.clone()];
+    for node in nodes.iter() {
+        let mut node = node.clone();
+        let ranges = &mut node.get_mut().ranges;
+        ranges.retain(|range| range.upgrade().is_some());
+        if !ranges.contains(&weak_range) {
+            ranges.push(weak_range.clone());
+        }
+    }
+}
+
+/// Calls `f` with every boundary point of the live ranges that have a boundary point in one of
+/// `nodes`. Ranges registered with those nodes that have since moved elsewhere, or that have been
+/// freed, are forgotten.
+fn for_each_boundary_point(nodes: &[JS<Node>], f: |&mut BoundaryPoint|) {
+    let mut ranges: ~[JS<Range>] = ~[];
+    for node in nodes.iter() {
+        let mut node = node.clone();
+        let abstract_node = node.clone();
+        let node_ranges = &mut node.get_mut().ranges;
+        node_ranges.retain(|range| {
+            match range.upgrade() {
+                Some(range) => {
+                    range.get().start.node == abstract_node ||
+                        range.get().end.node == abstract_node
+                }
+                None => false,
+            }
+        });
+        for range in node_ranges.iter() {
+            let range = range.upgrade().unwrap();
+            if !ranges.contains(&range) {
+                ranges.push(range);
+            }
+        }
+    }
+
+    for range in ranges.iter() {
+        {
+            let mut range = range.clone();
+            let range = range.get_mut();
+            f(&mut range.start);
+            f(&mut range.end);
+        }
+        register_boundary_points(range);
+    }
+}
+
+/// Moves the boundary points of live ranges that are after the child of `parent` at `index`,
+/// as `count` nodes are about to be inserted before that child.
+// http://dom.spec.whatwg.org/#concept-node-insert
+pub fn update_ranges_for_insertion(parent: &JS<Node>, index: u32, count: u32) {
+    for_each_boundary_point(&[parent.clone()], |point| {
+        // Step 2-3.
+        if point.node == *parent && point.offset > index {
+            point.offset += count;
+        }
+    });
+}
+
+/// Moves the boundary points of live ranges out of `node`, as it is about to be removed from
+/// `parent`, where it is at `index`.
+// http://dom.spec.whatwg.org/#concept-node-remove
+pub fn update_ranges_for_removal(node: &JS<Node>, parent: &JS<Node>, index: u32) {
+    let mut nodes: ~[JS<Node>] = node.traverse_preorder().collect();
+    nodes.push(parent.clone());
+    for_each_boundary_point(nodes.as_slice(), |point| {
+        if node.is_inclusive_ancestor_of(&point.node) {
+            // Step 2-3.
+            *point = BoundaryPoint::new(parent, index);
+        } else if point.node == *parent && point.offset > index {
+            // Step 4-5.
+            point.offset -= 1;
+        }
+    });
+}
+
+/// Moves the boundary points of live ranges in the data of `node`, after `count` units at
+/// `offset` were replaced with `data_length` units.
+// http://dom.spec.whatwg.org/#concept-cd-replace
+pub fn update_ranges_for_replaced_data(node: &JS<Node>, offset: u32, count: u32,
+                                       data_length: u32) {
+    for_each_boundary_point(&[node.clone()], |point| {
+        if point.node == *node && point.offset > offset {
+            if point.offset <= offset + count {
+                // Step 8-9.
+                point.offset = offset;
+            } else {
+                // Step 10-11.
+                point.offset = point.offset + data_length - count;
+            }
+        }
+    });
+}
+
+/// Moves the boundary points of live ranges after `offset` in the data of `node` into
+/// `new_node`, which was split off of it and inserted after it.
+// http://dom.spec.whatwg.org/#concept-text-split
+pub fn update_ranges_for_split(node: &JS<Node>, new_node: &JS<Node>, offset: u32) {
+    let parent = node.parent_node();
+    let index = node.index();
+    let nodes = match parent {
+        Some(ref parent) => ~[node.clone(), parent.clone()],
+        None => ~[node.clone()],
+    };
+    for_each_boundary_point(nodes.as_slice(), |point| {
+        if point.node == *node && point.offset > offset {
+            // Step 7.2-3.
+            *point = BoundaryPoint::new(new_node, point.offset - offset);
+        } else if Some(point.node.clone()) == parent && point.offset == index + 1 {
+            // Step 7.4-5.
+            point.offset += 1;
+        }
+    });
+}
+
+/// Returns the inclusive ancestors of `node`, starting at its root.
+fn inclusive_ancestors_from_root(node: &JS<Node>) -> ~[JS<Node>] {
+    let mut ancestors: ~[JS<Node>] = node.ancestors().collect();
+    ancestors.reverse();
+    ancestors.push(node.clone());
+    ancestors
+}
+
+/// Returns whether `a` precedes `b` in tree order.
+// http://dom.spec.whatwg.org/#concept-tree-preceding
+fn precedes(a: &JS<Node>, b: &JS<Node>) -> bool {
+    let a_ancestors = inclusive_ancestors_from_root(a);
+    let b_ancestors = inclusive_ancestors_from_root(b);
+    for (a_ancestor, b_ancestor) in a_ancestors.iter().zip(b_ancestors.iter()) {
+        if a_ancestor != b_ancestor {
+            return a_ancestor.index() < b_ancestor.index();
+        }
+    }
+    a_ancestors.len() < b_ancestors.len()
+}
+
+/// Returns the position of boundary point `a` relative to boundary point `b`, which must have
+/// the same root.
+// http://dom.spec.whatwg.org/#concept-range-bp-position
+fn position(a: &BoundaryPoint, b: &BoundaryPoint) -> Position {
+    // Step 1.
+    if a.node == b.node {
+        return if a.offset == b.offset {
+            Equal
+        } else if a.offset < b.offset {
+            Before
+        } else {
+            After
+        };
+    }
+
+    // Step 2.
+    if precedes(&b.node, &a.node) {
+        return match position(b, a) {
+            Before => After,
+            Equal => Equal,
+            After => Before,
+        };
+    }
+
+    // Step 3.
+    if a.node.is_inclusive_ancestor_of(&b.node) {
+        let child = b.node.ancestors().take_while(|ancestor| *ancestor != a.node).last();
+        let child = child.unwrap_or(b.node.clone());
+        if child.index() < a.offset {
+            return After;
+        }
+    }
+
+    // Step 4.
+    Before
+}
+
+// http://dom.spec.whatwg.org/#concept-range-contained
+fn is_contained(node: &JS<Node>, start: &BoundaryPoint, end: &BoundaryPoint) -> bool {
+    node.root() == start.node.root() &&
+        position(&BoundaryPoint::new(node, 0), start) == After &&
+        position(&BoundaryPoint::new(node, node.length()), end) == Before
+}
+
+// http://dom.spec.whatwg.org/#concept-range-partially-contained
+fn is_partially_contained(node: &JS<Node>, start: &BoundaryPoint, end: &BoundaryPoint) -> bool {
+    node.is_inclusive_ancestor_of(&start.node) != node.is_inclusive_ancestor_of(&end.node)
+}
+
+/// Returns the closest inclusive ancestor of the start node that is an inclusive ancestor of
+/// the end node.
+fn common_ancestor(start: &BoundaryPoint, end: &BoundaryPoint) -> JS<Node> {
+    let mut ancestor = start.node.clone();
+    while !ancestor.is_inclusive_ancestor_of(&end.node) {
+        ancestor = ancestor.parent_node().expect("boundary points have different roots");
+    }
+    ancestor
+}
+
+/// Returns where a range collapses to once the nodes that it contains are removed.
+// http://dom.spec.whatwg.org/#dom-range-deletecontents, step 5-6
+fn point_after_removal(start: &BoundaryPoint, end: &BoundaryPoint) -> BoundaryPoint {
+    if start.node.is_inclusive_ancestor_of(&end.node) {
+        return start.clone();
+    }
+    let mut reference = start.node.clone();
+    loop {
+        let parent = reference.parent_node().expect("boundary points have different roots");
+        if parent.is_inclusive_ancestor_of(&end.node) {
+            return BoundaryPoint::new(&parent, reference.index() + 1);
+        }
+        reference = parent;
+    }
+}
+
+fn is_character_data(node: &JS<Node>) -> bool {
+    match node.type_id() {
+        CommentNodeTypeId |
+        TextNodeTypeId |
+        ProcessingInstructionNodeTypeId => true,
+        _ => false,
+    }
+}
+
+fn clone_node(node: &JS<Node>, deep: bool) -> JS<Node> {
+    let mut abstract_node = node.clone();
+    node.get().CloneNode(&mut abstract_node, deep)
+}
+
+/// Clones a character data node, keeping only `count` units of its data at `offset`.
+fn clone_character_data(node: &JS<Node>, offset: u32, count: u32) -> JS<Node> {
+    let characterdata: JS<CharacterData> = CharacterDataCast::to(node);
+    let data = characterdata.get().SubstringData(offset, count);
+    let clone = clone_node(node, false);
+    let mut clone_characterdata: JS<CharacterData> = CharacterDataCast::to(&clone);
+    clone_characterdata.get_mut().data = data.unwrap();
+    clone
+}
+
+fn delete_data(node: &JS<Node>, offset: u32, count: u32) {
+    let mut characterdata: JS<CharacterData> = CharacterDataCast::to(node);
+    let abstract_characterdata = characterdata.clone();
+    let result = characterdata.get_mut().replace_data(&abstract_characterdata, offset, count,
+                                                      ~"");
+    assert!(result.is_ok());
+}
+
+fn append(parent: &JS<Node>, child: &JS<Node>) -> ErrorResult {
+    let mut parent = parent.clone();
+    let mut child = child.clone();
+    parent.AppendChild(&mut child).map(|_| ())
+}
+
+/// Copies or moves the contents between two boundary points into a new fragment.
+// http://dom.spec.whatwg.org/#concept-range-clone
+// http://dom.spec.whatwg.org/#concept-range-extract
+fn process_contents(start: &BoundaryPoint, end: &BoundaryPoint, mode: ContentsMode)
+                    -> Fallible<JS<DocumentFragment>> {
+    // Step 1.
+    let fragment = DocumentFragment::new(&document_from_node(&start.node));
+    let fragment_node: JS<Node> = NodeCast::from(&fragment);
+
+    // Step 2.
+    if start.node == end.node && start.offset == end.offset {
+        return Ok(fragment);
+    }
+
+    // Step 3-4.
+    if start.node == end.node && is_character_data(&start.node) {
+        let count = end.offset - start.offset;
+        let clone = clone_character_data(&start.node, start.offset, count);
+        match append(&fragment_node, &clone) {
+            Ok(()) => (),
+            Err(error) => return Err(error),
+        }
+        match mode {
+            Extracting => delete_data(&start.node, start.offset, count),
+            Cloning => (),
+        }
+        return Ok(fragment);
+    }
+
+    // Step 5.
+    let common_ancestor = common_ancestor(start, end);
+
+    // Step 6.
+    let first_partially_contained_child = if start.node.is_inclusive_ancestor_of(&end.node) {
+        None
+    } else {
+        common_ancestor.children().find(|child| is_partially_contained(child, start, end))
+    };
+
+    // Step 7.
+    let last_partially_contained_child = if end.node.is_inclusive_ancestor_of(&start.node) {
+        None
+    } else {
+        common_ancestor.children().filter(|child| {
+            is_partially_contained(child, start, end)
+        }).last()
+    };
+
+    // Step 8.
+    let contained_children: ~[JS<Node>] = common_ancestor.children().filter(|child| {
+        is_contained(child, start, end)
+    }).collect();
+
+    // Step 9.
+    if contained_children.iter().any(|child| child.is_doctype()) {
+        return Err(HierarchyRequest);
+    }
+
+    // Step 10-11 of extracting are done by the caller.
+
+    // Step 12-13.
+    match first_partially_contained_child {
+        Some(ref child) if is_character_data(child) => {
+            let count = start.node.length() - start.offset;
+            let clone = clone_character_data(&start.node, start.offset, count);
+            match append(&fragment_node, &clone) {
+                Ok(()) => (),
+                Err(error) => return Err(error),
+            }
+            match mode {
+                Extracting => delete_data(&start.node, start.offset, count),
+                Cloning => (),
+            }
+        }
+        Some(ref child) => {
+            let clone = clone_node(child, false);
+            match append(&fragment_node, &clone) {
+                Ok(()) => (),
+                Err(error) => return Err(error),
+            }
+            let subrange_end = BoundaryPoint::new(child, child.length());
+            let subfragment = match process_contents(start, &subrange_end, mode) {
+                Ok(subfragment) => subfragment,
+                Err(error) => return Err(error),
+            };
+            match append(&clone, &NodeCast::from(&subfragment)) {
+                Ok(()) => (),
+                Err(error) => return Err(error),
+            }
+        }
+        None => (),
+    }
+
+    // Step 14.
+    for child in contained_children.iter() {
+        let child = match mode {
+            Extracting => child.clone(),
+            Cloning => clone_node(child, true),
+        };
+        match append(&fragment_node, &child) {
+            Ok(()) => (),
+            Err(error) => return Err(error),
+        }
+    }
+
+    // Step 15-16.
+    match last_partially_contained_child {
+        Some(ref child) if is_character_data(child) => {
+            let clone = clone_character_data(&end.node, 0, end.offset);
+            match append(&fragment_node, &clone) {
+                Ok(()) => (),
+                Err(error) => return Err(error),
+            }
+            match mode {
+                Extracting => delete_data(&end.node, 0, end.offset),
+                Cloning => (),
+            }
+        }
+        Some(ref child) => {
+            let clone = clone_node(child, false);
+            match append(&fragment_node, &clone) {
+                Ok(()) => (),
+                Err(error) => return Err(error),
+            }
+            let subrange_start = BoundaryPoint::new(child, 0);
+            let subfragment = match process_contents(&subrange_start, end, mode) {
+                Ok(subfragment) => subfragment,
+                Err(error) => return Err(error),
+            };
+            match append(&clone, &NodeCast::from(&subfragment)) {
+                Ok(()) => (),
+                Err(error) => return Err(error),
+            }
+        }
+        None => (),
+    }
+
+    // Step 17-18.
+    Ok(fragment)
+}
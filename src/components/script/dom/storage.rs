@@ -0,0 +1,143 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use dom::bindings::codegen::StorageBinding;
+use dom::bindings::js::JS;
+use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
+use dom::bindings::error::{ErrorResult, QuotaExceeded};
+use dom::window::Window;
+use script_task::StorageChangedMsg;
+use servo_msg::constellation_msg::StorageChange;
+use servo_net::storage_task::{Clear, GetItem, Key, Length, LocalStorage, RemoveItem, SetItem};
+use servo_net::storage_task::{StorageTaskMsg, StorageType};
+use servo_util::str::DOMString;
+use servo_util::url::serialize_origin;
+
+use std::comm::{Chan, Port};
+
+#[deriving(Encodable)]
+pub struct Storage {
+    reflector_: Reflector,
+    window: JS<Window>, //XXXjdm cycle: window->Storage->window
+    storage_type: StorageType,
+}
+
+impl Storage {
+    pub fn new_inherited(window: &JS<Window>, storage_type: StorageType) -> Storage {
+        Storage {
+            reflector_: Reflector::new(),
+            window: window.clone(),
+            storage_type: storage_type,
+        }
+    }
+
+    pub fn new(window: &JS<Window>, storage_type: StorageType) -> JS<Storage> {
+        reflect_dom_object(~Storage::new_inherited(window, storage_type),
+                           window,
+                           StorageBinding::Wrap)
+    }
+
+    fn origin(&self) -> ~str {
+        serialize_origin(&self.window.get().get_url())
+    }
+
+    /// Sends a message built by `msg` to the storage task and waits for the reply.
+    fn send<T: Send>(&self, msg: |Chan<T>, StorageType, ~str| -> StorageTaskMsg) -> T {
+        let (port, chan): (Port<T>, Chan<T>) = Chan::new();
+        self.window.get().storage_task().send(msg(chan, self.storage_type, self.origin()));
+        port.recv()
+    }
+
+    /// Tells the other browsing contexts of this storage area's origin that it changed, so that
+    /// they fire storage events.
+    fn broadcast_change(&self, key: Option<DOMString>, old_value: Option<DOMString>,
+                        new_value: Option<DOMString>) {
+        let window = self.window.get();
+        let page = window.page();
+        let change = StorageChange {
+            is_local: self.storage_type == LocalStorage,
+            key: key,
+            old_value: old_value,
+            new_value: new_value,
+            url: page.get_url(),
+        };
+        window.script_chan.send(StorageChangedMsg(page.id, change));
+    }
+
+    // http://www.w3.org/TR/webstorage/#dom-storage-length
+    pub fn Length(&self) -> u32 {
+        self.send(|chan, storage_type, origin| Length(chan, storage_type, origin)) as u32
+    }
+
+    // http://www.w3.org/TR/webstorage/#dom-storage-key
+    pub fn Key(&self, index: u32) -> Option<DOMString> {
+        self.send(|chan, storage_type, origin| Key(chan, storage_type, origin, index as uint))
+    }
+
+    // http://www.w3.org/TR/webstorage/#dom-storage-getitem
+    pub fn GetItem(&self, key: DOMString) -> Option<DOMString> {
+        self.send(|chan, storage_type, origin| GetItem(chan, storage_type, origin, key.clone()))
+    }
+
+    // http://www.w3.org/TR/webstorage/#dom-storage-setitem
+    pub fn SetItem(&self, key: DOMString, value: DOMString) -> ErrorResult {
+        let result = self.send(|chan, storage_type, origin| {
+            SetItem(chan, storage_type, origin, key.clone(), value.clone())
+        });
+        match result {
+            Ok(Some(old_value)) => self.broadcast_change(Some(key), old_value, Some(value)),
+            Ok(None) => {}
+            Err(()) => return Err(QuotaExceeded),
+        }
+        Ok(())
+    }
+
+    // http://www.w3.org/TR/webstorage/#dom-storage-removeitem
+    pub fn RemoveItem(&self, key: DOMString) {
+        let old_value = self.send(|chan, storage_type, origin| {
+            RemoveItem(chan, storage_type, origin, key.clone())
+        });
+        if old_value.is_some() {
+            self.broadcast_change(Some(key), old_value, None);
+        }
+    }
+
+    // http://www.w3.org/TR/webstorage/#dom-storage-clear
+    pub fn Clear(&self) {
+        if self.send(|chan, storage_type, origin| Clear(chan, storage_type, origin)) {
+            self.broadcast_change(None, None, None);
+        }
+    }
+
+    pub fn NamedGetter(&self, maybe_name: Option<DOMString>, found: &mut bool)
+                       -> Option<DOMString> {
+        let item = maybe_name.and_then(|name| self.GetItem(name));
+        *found = item.is_some();
+        item
+    }
+
+    pub fn NamedSetter(&self, maybe_name: Option<DOMString>, value: DOMString) -> ErrorResult {
+        match maybe_name {
+            Some(name) => self.SetItem(name, value),
+            None => Ok(()),
+        }
+    }
+
+    pub fn NamedDeleter(&self, maybe_name: Option<DOMString>) {
+        match maybe_name {
+            Some(name) => self.RemoveItem(name),
+            None => (),
+        }
+    }
+}
+
+impl Reflectable for Storage {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        &self.reflector_
+    }
+
+    fn mut_reflector<'a>(&'a mut self) -> &'a mut Reflector {
+        &mut self.reflector_
+    }
+}
@@ -0,0 +1,87 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use dom::bindings::codegen::StorageEventBinding;
+use dom::bindings::codegen::InheritTypes::StorageEventDerived;
+use dom::bindings::js::JS;
+use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
+use dom::event::{Event, StorageEventTypeId};
+use dom::storage::Storage;
+use dom::window::Window;
+use servo_util::str::DOMString;
+
+#[deriving(Encodable)]
+pub struct StorageEvent {
+    event: Event,
+    key: Option<DOMString>,
+    old_value: Option<DOMString>,
+    new_value: Option<DOMString>,
+    url: DOMString,
+    storage_area: Option<JS<Storage>>
+}
+
+impl StorageEventDerived for Event {
+    fn is_storageevent(&self) -> bool {
+        self.type_id == StorageEventTypeId
+    }
+}
+
+impl StorageEvent {
+    pub fn new_inherited(key: Option<DOMString>,
+                         old_value: Option<DOMString>,
+                         new_value: Option<DOMString>,
+                         url: DOMString,
+                         storage_area: Option<JS<Storage>>) -> StorageEvent {
+        StorageEvent {
+            event: Event::new_inherited(StorageEventTypeId),
+            key: key,
+            old_value: old_value,
+            new_value: new_value,
+            url: url,
+            storage_area: storage_area
+        }
+    }
+
+    pub fn new(window: &JS<Window>,
+               key: Option<DOMString>,
+               old_value: Option<DOMString>,
+               new_value: Option<DOMString>,
+               url: DOMString,
+               storage_area: Option<JS<Storage>>) -> JS<StorageEvent> {
+        reflect_dom_object(~StorageEvent::new_inherited(key, old_value, new_value, url,
+                                                        storage_area),
+                           window,
+                           StorageEventBinding::Wrap)
+    }
+
+    pub fn GetKey(&self) -> Option<DOMString> {
+        self.key.clone()
+    }
+
+    pub fn GetOldValue(&self) -> Option<DOMString> {
+        self.old_value.clone()
+    }
+
+    pub fn GetNewValue(&self) -> Option<DOMString> {
+        self.new_value.clone()
+    }
+
+    pub fn Url(&self) -> DOMString {
+        self.url.clone()
+    }
+
+    pub fn GetStorageArea(&self) -> Option<JS<Storage>> {
+        self.storage_area.clone()
+    }
+}
+
+impl Reflectable for StorageEvent {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        self.event.reflector()
+    }
+
+    fn mut_reflector<'a>(&'a mut self) -> &'a mut Reflector {
+        self.event.mut_reflector()
+    }
+}
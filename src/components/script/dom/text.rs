@@ -3,13 +3,14 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use dom::bindings::codegen::TextBinding;
-use dom::bindings::codegen::InheritTypes::TextDerived;
+use dom::bindings::codegen::InheritTypes::{CharacterDataCast, NodeCast, TextDerived};
 use dom::bindings::js::JS;
-use dom::bindings::error::Fallible;
+use dom::bindings::error::{Fallible, IndexSize};
 use dom::characterdata::CharacterData;
 use dom::document::Document;
 use dom::eventtarget::{EventTarget, NodeTargetTypeId};
-use dom::node::{Node, TextNodeTypeId};
+use dom::node::{INode, Node, NodeHelpers, TextNodeTypeId, document_from_node};
+use dom::range::update_ranges_for_split;
 use dom::window::Window;
 use servo_util::str::DOMString;
 
@@ -44,8 +45,46 @@ impl Text {
         Ok(Text::new(text.clone(), &owner.get().Document()))
     }
 
-    pub fn SplitText(&self, _offset: u32) -> Fallible<JS<Text>> {
-        fail!("unimplemented")
+    // http://dom.spec.whatwg.org/#dom-text-splittext
+    pub fn SplitText(&mut self, abstract_self: &JS<Text>, offset: u32) -> Fallible<JS<Text>> {
+        // Step 1.
+        let length = self.characterdata.Length();
+
+        // Step 2.
+        if offset > length {
+            return Err(IndexSize);
+        }
+
+        // Step 3-4. This fails if `offset` would split a surrogate pair.
+        let count = length - offset;
+        let new_data = match self.characterdata.SubstringData(offset, count) {
+            Ok(new_data) => new_data,
+            Err(error) => return Err(error),
+        };
+
+        // Step 5.
+        let new_text = Text::new(new_data, &document_from_node(abstract_self));
+
+        // Step 6-7.
+        let node: JS<Node> = NodeCast::from(abstract_self);
+        match node.parent_node() {
+            Some(mut parent) => {
+                let mut new_node: JS<Node> = NodeCast::from(&new_text);
+                match parent.InsertBefore(&mut new_node, node.next_sibling()) {
+                    Ok(_) => (),
+                    Err(error) => return Err(error),
+                }
+                update_ranges_for_split(&node, &new_node, offset);
+            }
+            None => (),
+        }
+
+        // Step 8.
+        let characterdata = CharacterDataCast::from(abstract_self);
+        match self.characterdata.replace_data(&characterdata, offset, count, ~"") {
+            Ok(()) => Ok(new_text),
+            Err(error) => Err(error),
+        }
     }
 
     pub fn GetWholeText(&self) -> Fallible<DOMString> {
@@ -0,0 +1,358 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use dom::bindings::codegen::NodeFilterBinding::NodeFilterConstants;
+use dom::bindings::codegen::TreeWalkerBinding;
+use dom::bindings::error::Fallible;
+use dom::bindings::js::JS;
+use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
+use dom::node::{Node, NodeHelpers};
+use dom::nodefilter::Filter;
+use dom::window::Window;
+
+use js::jsapi::JSContext;
+use js::jsval::JSVal;
+
+/// The direction to traverse children or siblings in. Traversing children in the `Next`
+/// direction starts at the first child.
+enum Direction {
+    Next,
+    Previous,
+}
+
+#[deriving(Encodable)]
+pub struct TreeWalker {
+    reflector_: Reflector,
+    root: JS<Node>,
+    current_node: JS<Node>,
+    filter: Filter,
+}
+
+impl TreeWalker {
+    pub fn new_inherited(root: &JS<Node>, filter: Filter) -> TreeWalker {
+        TreeWalker {
+            reflector_: Reflector::new(),
+            root: root.clone(),
+            current_node: root.clone(),
+            filter: filter,
+        }
+    }
+
+    pub fn new(window: &JS<Window>, root: &JS<Node>, filter: Filter) -> JS<TreeWalker> {
+        reflect_dom_object(~TreeWalker::new_inherited(root, filter),
+                           window,
+                           TreeWalkerBinding::Wrap)
+    }
+
+    pub fn Root(&self) -> JS<Node> {
+        self.root.clone()
+    }
+
+    pub fn WhatToShow(&self) -> u32 {
+        self.filter.what_to_show()
+    }
+
+    pub fn Filter(&self, _cx: *JSContext) -> JSVal {
+        self.filter.callback()
+    }
+
+    pub fn CurrentNode(&self) -> JS<Node> {
+        self.current_node.clone()
+    }
+
+    pub fn SetCurrentNode(&mut self, node: &mut JS<Node>) {
+        self.current_node = node.clone();
+    }
+
+    // http://dom.spec.whatwg.org/#dom-treewalker-parentnode
+    pub fn ParentNode(&mut self) -> Fallible<Option<JS<Node>>> {
+        // Step 1.
+        let mut node = self.current_node.clone();
+
+        // Step 2.
+        while node != self.root {
+            // Step 2.1.
+            node = match node.parent_node() {
+                Some(parent) => parent,
+                None => return Ok(None),
+            };
+
+            // Step 2.2.
+            match self.filter.accept_node(&node) {
+                Ok(result) if result == NodeFilterConstants::FILTER_ACCEPT => {
+                    self.current_node = node.clone();
+                    return Ok(Some(node));
+                }
+                Ok(_) => (),
+                Err(error) => return Err(error),
+            }
+        }
+
+        // Step 3.
+        Ok(None)
+    }
+
+    // http://dom.spec.whatwg.org/#dom-treewalker-firstchild
+    pub fn FirstChild(&mut self) -> Fallible<Option<JS<Node>>> {
+        self.traverse_children(Next)
+    }
+
+    // http://dom.spec.whatwg.org/#dom-treewalker-lastchild
+    pub fn LastChild(&mut self) -> Fallible<Option<JS<Node>>> {
+        self.traverse_children(Previous)
+    }
+
+    // http://dom.spec.whatwg.org/#dom-treewalker-previoussibling
+    pub fn PreviousSibling(&mut self) -> Fallible<Option<JS<Node>>> {
+        self.traverse_siblings(Previous)
+    }
+
+    // http://dom.spec.whatwg.org/#dom-treewalker-nextsibling
+    pub fn NextSibling(&mut self) -> Fallible<Option<JS<Node>>> {
+        self.traverse_siblings(Next)
+    }
+
+    // http://dom.spec.whatwg.org/#dom-treewalker-previousnode
+    pub fn PreviousNode(&mut self) -> Fallible<Option<JS<Node>>> {
+        // Step 1.
+        let mut node = self.current_node.clone();
+
+        // Step 2.
+        while node != self.root {
+            // Step 2.1.
+            let mut sibling = node.prev_sibling();
+
+            // Step 2.2.
+            loop {
+                node = match sibling {
+                    Some(sibling) => sibling,
+                    None => break,
+                };
+
+                let mut result = match self.filter.accept_node(&node) {
+                    Ok(result) => result,
+                    Err(error) => return Err(error),
+                };
+                while result != NodeFilterConstants::FILTER_REJECT {
+                    node = match node.last_child() {
+                        Some(child) => child,
+                        None => break,
+                    };
+                    result = match self.filter.accept_node(&node) {
+                        Ok(result) => result,
+                        Err(error) => return Err(error),
+                    };
+                }
+
+                if result == NodeFilterConstants::FILTER_ACCEPT {
+                    self.current_node = node.clone();
+                    return Ok(Some(node));
+                }
+                sibling = node.prev_sibling();
+            }
+
+            // Step 2.3.
+            if node == self.root {
+                return Ok(None);
+            }
+            node = match node.parent_node() {
+                Some(parent) => parent,
+                None => return Ok(None),
+            };
+
+            // Step 2.4-5.
+            match self.filter.accept_node(&node) {
+                Ok(result) if result == NodeFilterConstants::FILTER_ACCEPT => {
+                    self.current_node = node.clone();
+                    return Ok(Some(node));
+                }
+                Ok(_) => (),
+                Err(error) => return Err(error),
+            }
+        }
+
+        // Step 3.
+        Ok(None)
+    }
+
+    // http://dom.spec.whatwg.org/#dom-treewalker-nextnode
+    pub fn NextNode(&mut self) -> Fallible<Option<JS<Node>>> {
+        // Step 1-2.
+        let mut node = self.current_node.clone();
+        let mut result = NodeFilterConstants::FILTER_ACCEPT;
+
+        // Step 3.
+        loop {
+            // Step 3.1.
+            while result != NodeFilterConstants::FILTER_REJECT {
+                node = match node.first_child() {
+                    Some(child) => child,
+                    None => break,
+                };
+                result = match self.filter.accept_node(&node) {
+                    Ok(result) => result,
+                    Err(error) => return Err(error),
+                };
+                if result == NodeFilterConstants::FILTER_ACCEPT {
+                    self.current_node = node.clone();
+                    return Ok(Some(node));
+                }
+            }
+
+            // Step 3.2-4.
+            let mut temporary = node.clone();
+            loop {
+                if temporary == self.root {
+                    return Ok(None);
+                }
+                match temporary.next_sibling() {
+                    Some(sibling) => {
+                        node = sibling;
+                        break;
+                    }
+                    None => (),
+                }
+                temporary = match temporary.parent_node() {
+                    Some(parent) => parent,
+                    None => return Ok(None),
+                };
+            }
+
+            // Step 3.5-6.
+            result = match self.filter.accept_node(&node) {
+                Ok(result) => result,
+                Err(error) => return Err(error),
+            };
+            if result == NodeFilterConstants::FILTER_ACCEPT {
+                self.current_node = node.clone();
+                return Ok(Some(node));
+            }
+        }
+    }
+
+    // http://dom.spec.whatwg.org/#concept-traverse-children
+    fn traverse_children(&mut self, direction: Direction) -> Fallible<Option<JS<Node>>> {
+        // Step 1.
+        let mut node = match child(&self.current_node, direction) {
+            Some(node) => node,
+            None => return Ok(None),
+        };
+
+        // Step 2.
+        loop {
+            // Step 2.1-2.
+            let result = match self.filter.accept_node(&node) {
+                Ok(result) => result,
+                Err(error) => return Err(error),
+            };
+            if result == NodeFilterConstants::FILTER_ACCEPT {
+                self.current_node = node.clone();
+                return Ok(Some(node));
+            }
+
+            // Step 2.3.
+            if result == NodeFilterConstants::FILTER_SKIP {
+                match child(&node, direction) {
+                    Some(child) => {
+                        node = child;
+                        continue;
+                    }
+                    None => (),
+                }
+            }
+
+            // Step 2.4.
+            loop {
+                match sibling(&node, direction) {
+                    Some(sibling) => {
+                        node = sibling;
+                        break;
+                    }
+                    None => (),
+                }
+                node = match node.parent_node() {
+                    Some(ref parent) if *parent == self.root ||
+                                        *parent == self.current_node => return Ok(None),
+                    Some(parent) => parent,
+                    None => return Ok(None),
+                };
+            }
+        }
+    }
+
+    // http://dom.spec.whatwg.org/#concept-traverse-siblings
+    fn traverse_siblings(&mut self, direction: Direction) -> Fallible<Option<JS<Node>>> {
+        // Step 1.
+        let mut node = self.current_node.clone();
+
+        // Step 2.
+        if node == self.root {
+            return Ok(None);
+        }
+
+        // Step 3.
+        loop {
+            // Step 3.1.
+            let mut next = sibling(&node, direction);
+
+            // Step 3.2.
+            loop {
+                node = match next {
+                    Some(next) => next,
+                    None => break,
+                };
+                let result = match self.filter.accept_node(&node) {
+                    Ok(result) => result,
+                    Err(error) => return Err(error),
+                };
+                if result == NodeFilterConstants::FILTER_ACCEPT {
+                    self.current_node = node.clone();
+                    return Ok(Some(node));
+                }
+                next = child(&node, direction);
+                if result == NodeFilterConstants::FILTER_REJECT || next.is_none() {
+                    next = sibling(&node, direction);
+                }
+            }
+
+            // Step 3.3-4.
+            node = match node.parent_node() {
+                Some(ref parent) if *parent == self.root => return Ok(None),
+                Some(parent) => parent,
+                None => return Ok(None),
+            };
+
+            // Step 3.5.
+            match self.filter.accept_node(&node) {
+                Ok(result) if result == NodeFilterConstants::FILTER_ACCEPT => return Ok(None),
+                Ok(_) => (),
+                Err(error) => return Err(error),
+            }
+        }
+    }
+}
+
+impl Reflectable for TreeWalker {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        &self.reflector_
+    }
+
+    fn mut_reflector<'a>(&'a mut self) -> &'a mut Reflector {
+        &mut self.reflector_
+    }
+}
+
+fn child(node: &JS<Node>, direction: Direction) -> Option<JS<Node>> {
+    match direction {
+        Next => node.first_child(),
+        Previous => node.last_child(),
+    }
+}
+
+fn sibling(node: &JS<Node>, direction: Direction) -> Option<JS<Node>> {
+    match direction {
+        Next => node.next_sibling(),
+        Previous => node.prev_sibling(),
+    }
+}
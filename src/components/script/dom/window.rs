@@ -3,24 +3,30 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use dom::bindings::codegen::WindowBinding;
+use dom::bindings::conversions::{Default, FromJSValConvertible};
 use dom::bindings::js::JS;
+use dom::bindings::trace::trace_jsval;
 use dom::bindings::utils::{Reflectable, Reflector};
 use dom::document::Document;
 use dom::element::Element;
-use dom::eventtarget::{EventTarget, WindowTypeId};
+use dom::eventtarget::{EventTarget, EventTargetHelpers, WindowTypeId};
 use dom::console::Console;
+use dom::history::History;
 use dom::location::Location;
+use dom::mutationobserver::MutationObserver;
 use dom::navigator::Navigator;
+use dom::storage::Storage;
 
 use layout_interface::{ReflowForDisplay, DocumentDamageLevel};
 use script_task::{ExitWindowMsg, FireTimerMsg, Page, ScriptChan};
 use servo_msg::compositor_msg::ScriptListener;
 use servo_net::image_cache_task::ImageCacheTask;
+use servo_net::storage_task::{LocalStorage, SessionStorage, StorageTask};
 use servo_util::str::DOMString;
 use servo_util::task::{spawn_named};
 
 use js::glue::*;
-use js::jsapi::{JSObject, JSContext, JS_DefineProperty};
+use js::jsapi::{JSObject, JSContext, JSTracer, JS_DefineProperty, JS_ObjectIsCallable};
 use js::jsval::JSVal;
 use js::jsval::{NullValue, ObjectValue};
 use js::JSPROP_ENUMERATE;
@@ -28,46 +34,47 @@ use js::JSPROP_ENUMERATE;
 use std::cast;
 use std::comm::SharedChan;
 use std::comm::Select;
-use std::hashmap::HashSet;
+use std::hashmap::HashMap;
 use std::io::timer::Timer;
 use std::num;
 use std::rc::Rc;
-use std::to_bytes::Cb;
+use std::util::replace;
 
 use extra::serialize::{Encoder, Encodable};
+use extra::time::precise_time_ns;
 use extra::url::{Url};
 
+/// Timers nested deeper than this are clamped to `MIN_TIMER_TIMEOUT`.
+static MAX_TIMER_NESTING_LEVEL: u32 = 5;
+/// The minimum timeout, in milliseconds, of clamped timers and intervals.
+static MIN_TIMER_TIMEOUT: u64 = 4;
+
 pub enum TimerControlMsg {
-    TimerMessage_Fire(~TimerData),
+    TimerMessage_Fire(TimerId),
     TimerMessage_Close,
     TimerMessage_TriggerExit //XXXjdm this is just a quick hack to talk to the script task
 }
 
+/// The handle returned by `setTimeout` and `setInterval`.
+#[deriving(Eq, IterBytes, Encodable)]
+pub struct TimerId(i32);
+
 pub struct TimerHandle {
-    handle: i32,
+    handle: TimerId,
+    data: TimerData,
     cancel_chan: Option<Chan<()>>,
 }
 
 impl<S: Encoder> Encodable<S> for TimerHandle {
-    fn encode(&self, _: &mut S) {
-    }
-}
-
-impl IterBytes for TimerHandle {
-    fn iter_bytes(&self, lsb0: bool, f: Cb) -> bool {
-        self.handle.iter_bytes(lsb0, f)
-    }
-}
-
-impl Eq for TimerHandle {
-    fn eq(&self, other: &TimerHandle) -> bool {
-        self.handle == other.handle
+    fn encode(&self, s: &mut S) {
+        self.data.encode(s);
     }
 }
 
 impl TimerHandle {
-    fn cancel(&self) {
-        self.cancel_chan.as_ref().map(|chan| chan.send(()));
+    fn cancel(&mut self) {
+        // The timer task may already have exited, so ignore send failures.
+        self.cancel_chan.as_ref().map(|chan| chan.try_send(()));
     }
 }
 
@@ -76,11 +83,25 @@ pub struct Window {
     eventtarget: EventTarget,
     script_chan: ScriptChan,
     console: Option<JS<Console>>,
+    history: Option<JS<History>>,
     location: Option<JS<Location>>,
     navigator: Option<JS<Navigator>>,
+    local_storage: Option<JS<Storage>>,
+    session_storage: Option<JS<Storage>>,
     image_cache_task: ImageCacheTask,
-    active_timers: ~HashSet<TimerHandle>,
+    active_timers: ~HashMap<TimerId, TimerHandle>,
     next_timer_handle: i32,
+    timer_nesting_level: u32,
+    animation_frame_callbacks: ~[AnimationFrameCallback],
+    running_animation_frame_callbacks: ~[AnimationFrameCallback],
+    next_animation_frame_handle: i32,
+    in_animation_frame: bool,
+    /// Whether the compositor was last told that animation frame callbacks are pending.
+    animation_frame_callbacks_pending: bool,
+    /// The time, in nanoseconds, at which this window was created.
+    navigation_start: u64,
+    /// The mutation observers that observe nodes of this window's document.
+    mutation_observers: ~[JS<MutationObserver>],
     priv extra: Untraceable
 }
 
@@ -88,6 +109,7 @@ struct Untraceable {
     page: Rc<Page>,
     compositor: ~ScriptListener,
     timer_chan: SharedChan<TimerControlMsg>,
+    storage_task: StorageTask,
 }
 
 impl<S: Encoder> Encodable<S> for Untraceable {
@@ -110,25 +132,135 @@ impl Window {
     pub fn get_url(&self) -> Url {
         self.page().get_url()
     }
+
+    pub fn storage_task(&self) -> StorageTask {
+        self.extra.storage_task.clone()
+    }
+
+    /// Returns the `localStorage` or `sessionStorage` object, if it has been created.
+    pub fn storage_area(&self, is_local: bool) -> Option<JS<Storage>> {
+        if is_local {
+            self.local_storage.clone()
+        } else {
+            self.session_storage.clone()
+        }
+    }
+
+    /// Converts a frame time in nanoseconds into a `DOMHighResTimeStamp`, in milliseconds
+    /// since this window was created.
+    pub fn high_resolution_time(&self, time: u64) -> f64 {
+        if time < self.navigation_start {
+            return 0.0;
+        }
+        (time - self.navigation_start) as f64 / 1000000.0
+    }
+
+    /// Starts running the animation frame callbacks requested so far. Reflows are deferred
+    /// until `end_animation_frame` is called.
+    pub fn begin_animation_frame(&mut self) {
+        self.running_animation_frame_callbacks = replace(&mut self.animation_frame_callbacks,
+                                                         ~[]);
+        self.in_animation_frame = true;
+    }
+
+    /// Returns the next animation frame callback to run, if any remain.
+    pub fn next_animation_frame_callback(&mut self) -> Option<JSVal> {
+        if self.running_animation_frame_callbacks.is_empty() {
+            return None;
+        }
+        Some(self.running_animation_frame_callbacks.shift().callback)
+    }
+
+    pub fn end_animation_frame(&mut self) {
+        self.in_animation_frame = false;
+        self.update_animation_frame_callbacks_pending();
+    }
+
+    /// Tells the compositor whether animation frame callbacks are waiting for the next frame
+    /// tick, if that changed since it was last told.
+    fn update_animation_frame_callbacks_pending(&mut self) {
+        let pending = !self.animation_frame_callbacks.is_empty();
+        if pending == self.animation_frame_callbacks_pending {
+            return;
+        }
+        self.animation_frame_callbacks_pending = pending;
+        self.extra.compositor.set_animation_frame_callbacks_pending(self.page().id, pending);
+    }
+}
+
+impl EventTargetHelpers for Window {
+    fn event_target<'a>(&'a self) -> &'a EventTarget {
+        &self.eventtarget
+    }
+
+    fn mut_event_target<'a>(&'a mut self) -> &'a mut EventTarget {
+        &mut self.eventtarget
+    }
 }
 
+global_event_handlers!(Window)
+window_event_handlers!(Window)
+
 #[unsafe_destructor]
 impl Drop for Window {
     fn drop(&mut self) {
         self.extra.timer_chan.send(TimerMessage_Close);
-        for handle in self.active_timers.iter() {
+        for (_, handle) in self.active_timers.mut_iter() {
             handle.cancel();
         }
     }
 }
 
+/// A callback registered with `requestAnimationFrame`.
+pub struct AnimationFrameCallback {
+    handle: i32,
+    callback: JSVal,
+}
+
+impl<S: Encoder> Encodable<S> for AnimationFrameCallback {
+    fn encode(&self, s: &mut S) {
+        let tracer: *mut JSTracer = unsafe { cast::transmute(s) };
+        trace_jsval(tracer, "animation frame callback", self.callback);
+    }
+}
+
+/// The code to run when a timer fires: either a function value or a string
+/// of script to evaluate.
+pub enum TimerCallback {
+    StringTimerCallback(DOMString),
+    FunctionTimerCallback(JSVal),
+}
+
+impl Clone for TimerCallback {
+    fn clone(&self) -> TimerCallback {
+        match *self {
+            StringTimerCallback(ref code) => StringTimerCallback(code.clone()),
+            FunctionTimerCallback(funval) => FunctionTimerCallback(funval),
+        }
+    }
+}
+
 // Holder for the various JS values associated with setTimeout
 // (ie. function value to invoke and all arguments to pass
 //      to the function when calling it)
 pub struct TimerData {
-    handle: i32,
-    funval: JSVal,
+    is_interval: bool,
+    callback: TimerCallback,
     args: ~[JSVal],
+    nesting_level: u32,
+}
+
+impl<S: Encoder> Encodable<S> for TimerData {
+    fn encode(&self, s: &mut S) {
+        let tracer: *mut JSTracer = unsafe { cast::transmute(s) };
+        match self.callback {
+            FunctionTimerCallback(funval) => trace_jsval(tracer, "timer callback", funval),
+            StringTimerCallback(_) => (),
+        }
+        for arg in self.args.iter() {
+            trace_jsval(tracer, "timer argument", *arg);
+        }
+    }
 }
 
 impl Window {
@@ -184,9 +316,16 @@ impl Window {
         self.location.get_ref().clone()
     }
 
+    pub fn History(&mut self, abstract_self: &JS<Window>) -> JS<History> {
+        if self.history.is_none() {
+            self.history = Some(History::new(abstract_self));
+        }
+        self.history.get_ref().clone()
+    }
+
     pub fn Console(&mut self, abstract_self: &JS<Window>) -> JS<Console> {
         if self.console.is_none() {
-            self.console = Some(Console::new(abstract_self));
+            self.console = Some(Console::new(abstract_self, self.extra.page.clone()));
         }
         self.console.get_ref().clone()
     }
@@ -198,6 +337,20 @@ impl Window {
         self.navigator.get_ref().clone()
     }
 
+    pub fn LocalStorage(&mut self, abstract_self: &JS<Window>) -> JS<Storage> {
+        if self.local_storage.is_none() {
+            self.local_storage = Some(Storage::new(abstract_self, LocalStorage));
+        }
+        self.local_storage.get_ref().clone()
+    }
+
+    pub fn SessionStorage(&mut self, abstract_self: &JS<Window>) -> JS<Storage> {
+        if self.session_storage.is_none() {
+            self.session_storage = Some(Storage::new(abstract_self, SessionStorage));
+        }
+        self.session_storage.get_ref().clone()
+    }
+
     pub fn Confirm(&self, _message: DOMString) -> bool {
         false
     }
@@ -225,9 +378,45 @@ impl Reflectable for Window {
 }
 
 impl Window {
-    pub fn SetTimeout(&mut self, _cx: *JSContext, callback: JSVal, timeout: i32) -> i32 {
-        let timeout = num::max(0, timeout) as u64;
-        let handle = self.next_timer_handle;
+    pub fn SetTimeout(&mut self, cx: *JSContext, handler: JSVal, timeout: i32,
+                      args: ~[JSVal]) -> i32 {
+        self.set_timeout_or_interval(cx, handler, timeout, args, false)
+    }
+
+    pub fn ClearTimeout(&mut self, handle: i32) {
+        self.clear_timeout_or_interval(handle);
+    }
+
+    pub fn SetInterval(&mut self, cx: *JSContext, handler: JSVal, timeout: i32,
+                       args: ~[JSVal]) -> i32 {
+        self.set_timeout_or_interval(cx, handler, timeout, args, true)
+    }
+
+    pub fn ClearInterval(&mut self, handle: i32) {
+        self.clear_timeout_or_interval(handle);
+    }
+
+    // http://www.whatwg.org/html/#timer-initialisation-steps
+    fn set_timeout_or_interval(&mut self, cx: *JSContext, handler: JSVal, timeout: i32,
+                               args: ~[JSVal], is_interval: bool) -> i32 {
+        let callable = handler.is_object() &&
+            unsafe { JS_ObjectIsCallable(cx, handler.to_object()) != 0 };
+        let callback = if callable {
+            FunctionTimerCallback(handler)
+        } else {
+            let code: Result<DOMString, ()> = FromJSValConvertible::from_jsval(cx, handler,
+                                                                               Default);
+            StringTimerCallback(code.unwrap_or(~""))
+        };
+
+        let nesting_level = self.timer_nesting_level + 1;
+        let mut timeout = num::max(0, timeout) as u64;
+        if nesting_level > MAX_TIMER_NESTING_LEVEL || is_interval {
+            // Deeply nested timers and repeating intervals must not spin the event loop.
+            timeout = num::max(timeout, MIN_TIMER_TIMEOUT);
+        }
+
+        let handle = TimerId(self.next_timer_handle);
         self.next_timer_handle += 1;
 
         // Post a delayed message to the per-window timer task; it will dispatch it
@@ -235,31 +424,72 @@ impl Window {
         let tm = Timer::new().unwrap();
         let (cancel_port, cancel_chan) = Chan::new();
         let chan = self.extra.timer_chan.clone();
-        spawn_named("Window:SetTimeout", proc() {
+        spawn_named(if is_interval { "Window:SetInterval" } else { "Window:SetTimeout" },
+                    proc() {
             let mut tm = tm;
-            let mut timeout_port = tm.oneshot(timeout);
+            let mut timeout_port = if is_interval {
+                tm.periodic(timeout)
+            } else {
+                tm.oneshot(timeout)
+            };
             let mut cancel_port = cancel_port;
 
             let select = Select::new();
-            let timeout_handle = select.add(&mut timeout_port);
+            let mut timeout_handle = select.add(&mut timeout_port);
             let _cancel_handle = select.add(&mut cancel_port);
-            let id = select.wait();
-            if id == timeout_handle.id {
-                chan.send(TimerMessage_Fire(~TimerData {
-                    handle: handle,
-                    funval: callback,
-                    args: ~[],
-                }));
+            loop {
+                let id = select.wait();
+                if id != timeout_handle.id {
+                    break;
+                }
+                timeout_handle.recv();
+                if !chan.try_send(TimerMessage_Fire(handle)) || !is_interval {
+                    break;
+                }
             }
         });
-        self.active_timers.insert(TimerHandle { handle: handle, cancel_chan: Some(cancel_chan) });
+
+        self.active_timers.insert(handle, TimerHandle {
+            handle: handle,
+            data: TimerData {
+                is_interval: is_interval,
+                callback: callback,
+                args: args,
+                nesting_level: nesting_level,
+            },
+            cancel_chan: Some(cancel_chan),
+        });
+        *handle
+    }
+
+    // http://www.w3.org/TR/animation-timing/#dom-windowanimationtiming-requestanimationframe
+    pub fn RequestAnimationFrame(&mut self, cx: *JSContext, callback: JSVal) -> i32 {
+        let handle = self.next_animation_frame_handle;
+        self.next_animation_frame_handle += 1;
+        // XXX This should throw a TypeError once callback functions are supported.
+        let callable = callback.is_object() &&
+            unsafe { JS_ObjectIsCallable(cx, callback.to_object()) != 0 };
+        if callable {
+            self.animation_frame_callbacks.push(AnimationFrameCallback {
+                handle: handle,
+                callback: callback,
+            });
+            self.update_animation_frame_callbacks_pending();
+        }
         handle
     }
 
-    pub fn ClearTimeout(&mut self, handle: i32) {
-        // FIXME(#1477): active_timers should be a HashMap and this should
-        // cancel the removed timer.
-        self.active_timers.remove(&TimerHandle { handle: handle, cancel_chan: None });
+    pub fn CancelAnimationFrame(&mut self, handle: i32) {
+        self.animation_frame_callbacks.retain(|callback| callback.handle != handle);
+        self.running_animation_frame_callbacks.retain(|callback| callback.handle != handle);
+        self.update_animation_frame_callbacks_pending();
+    }
+
+    fn clear_timeout_or_interval(&mut self, handle: i32) {
+        match self.active_timers.pop(&TimerId(handle)) {
+            Some(mut timer) => timer.cancel(),
+            None => (),
+        }
     }
 
     pub fn damage_and_reflow(&self, damage: DocumentDamageLevel) {
@@ -267,6 +497,10 @@ impl Window {
         // currently rely on the display list, which means we can't destroy it by
         // doing a query reflow.
         self.page().damage(damage);
+        // Animation frame callbacks share a single reflow once they have all run.
+        if self.in_animation_frame {
+            return;
+        }
         self.page().reflow(ReflowForDisplay, self.script_chan.clone(), self.extra.compositor);
     }
 
@@ -280,7 +514,8 @@ impl Window {
                page: Rc<Page>,
                script_chan: ScriptChan,
                compositor: ~ScriptListener,
-               image_cache_task: ImageCacheTask)
+               image_cache_task: ImageCacheTask,
+               storage_task: StorageTask)
                -> JS<Window> {
         let mut win = ~Window {
             eventtarget: EventTarget::new_inherited(WindowTypeId),
@@ -302,13 +537,25 @@ impl Window {
                         }
                     });
                     timer_chan
-                }
+                },
+                storage_task: storage_task,
             },
+            history: None,
             location: None,
             navigator: None,
+            local_storage: None,
+            session_storage: None,
             image_cache_task: image_cache_task,
-            active_timers: ~HashSet::new(),
-            next_timer_handle: 0
+            active_timers: ~HashMap::new(),
+            next_timer_handle: 0,
+            timer_nesting_level: 0,
+            animation_frame_callbacks: ~[],
+            running_animation_frame_callbacks: ~[],
+            next_animation_frame_handle: 1,
+            in_animation_frame: false,
+            animation_frame_callbacks_pending: false,
+            navigation_start: precise_time_ns(),
+            mutation_observers: ~[],
         };
 
         let raw: *mut Window = &mut *win;
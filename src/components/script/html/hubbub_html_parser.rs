@@ -303,6 +303,150 @@ pub fn parse_html(page: &Page,
 
     let next_subpage_id = RefCell::new(next_subpage_id);
 
+    let on_element = |element: &JS<Element>| {
+        // Spawn additional parsing, network loads, etc. from tag and attrs
+        match element.get().node.type_id {
+            // Handle CSS style sheets from <link> elements
+            ElementNodeTypeId(HTMLLinkElementTypeId) => {
+                match (element.get().get_attribute(Null, "rel"),
+                       element.get().get_attribute(Null, "href")) {
+                    (Some(ref rel), Some(ref href)) if rel.get()
+                                                          .value_ref()
+                                                          .split(HTML_SPACE_CHARACTERS.
+                                                                 as_slice())
+                                                          .any(|s| {
+                                s.eq_ignore_ascii_case("stylesheet")
+                            }) => {
+                        debug!("found CSS stylesheet: {:s}", href.get().value_ref());
+                        let url = parse_url(href.get().value_ref(), Some(url2.clone()));
+                        css_chan2.send(CSSTaskNewFile(UrlProvenance(url)));
+                    }
+                    _ => {}
+                }
+            }
+
+            ElementNodeTypeId(HTMLIFrameElementTypeId) => {
+                let iframe_chan = discovery_chan.clone();
+                let mut iframe_element: JS<HTMLIFrameElement> =
+                    HTMLIFrameElementCast::to(element);
+                let sandboxed = iframe_element.get().is_sandboxed();
+                let elem: JS<Element> = ElementCast::from(&iframe_element);
+                let src_opt = elem.get().get_attribute(Null, "src").map(|x| x.get().Value());
+                for src in src_opt.iter() {
+                    let iframe_url = parse_url(*src, Some(url2.clone()));
+                    iframe_element.get_mut().set_frame(iframe_url.clone());
+
+                    // Subpage Id
+                    let subpage_id = next_subpage_id.get();
+                    next_subpage_id.set(SubpageId(*subpage_id + 1));
+
+                    iframe_element.get_mut().size = Some(IFrameSize {
+                        pipeline_id: pipeline_id,
+                        subpage_id: subpage_id,
+                    });
+                    iframe_chan.send(HtmlDiscoveredIFrame((iframe_url,
+                                                           subpage_id,
+                                                           sandboxed)));
+                }
+            }
+            _ => {}
+        }
+    };
+
+    let complete_script = |script: hubbub::NodeDataPtr| {
+        unsafe {
+            let script: JS<Element> = NodeWrapping::from_hubbub_node(script);
+            match script.get().get_attribute(Null, "src") {
+                Some(src) => {
+                    debug!("found script: {:s}", src.get().Value());
+                    let new_url = parse_url(src.get().value_ref(), Some(url3.clone()));
+                    js_chan2.send(JSTaskNewFile(new_url));
+                }
+                None => {
+                    let mut data = ~[];
+                    let scriptnode: JS<Node> = NodeCast::from(&script);
+                    debug!("iterating over children {:?}", scriptnode.first_child());
+                    for child in scriptnode.children() {
+                        debug!("child = {:?}", child);
+                        let text: JS<Text> = TextCast::to(&child);
+                        data.push(text.get().characterdata.data.to_str());  // FIXME: Bad copy.
+                    }
+
+                    debug!("script data = {:?}", data);
+                    js_chan2.send(JSTaskNewInlineScript(data.concat(), url3.clone()));
+                }
+            }
+        }
+        debug!("complete script");
+    };
+
+    let complete_style = |style: hubbub::NodeDataPtr| {
+        // We've reached the end of a <style> so we can submit all the text to the parser.
+        unsafe {
+            let style: JS<Node> = NodeWrapping::from_hubbub_node(style);
+            let mut data = ~[];
+            debug!("iterating over children {:?}", style.first_child());
+            for child in style.children() {
+                debug!("child = {:?}", child);
+                let text: JS<Text> = TextCast::to(&child);
+                data.push(text.get().characterdata.data.to_str());  // FIXME: Bad copy.
+            }
+
+            debug!("style data = {:?}", data);
+            let provenance = InlineProvenance(base_url.clone(), data.concat());
+            css_chan3.send(CSSTaskNewFile(provenance));
+        }
+    };
+
+    build_tree(&mut parser, document, on_element, complete_script, complete_style, |parser| {
+        debug!("loaded page");
+        loop {
+            match load_response.progress_port.recv() {
+                Payload(data) => {
+                    debug!("received data");
+                    parser.parse_chunk(data);
+                }
+                Done(Err(..)) => {
+                    fail!("Failed to load page URL {:s}", url.to_str());
+                }
+                Done(..) => {
+                    break;
+                }
+            }
+        }
+    });
+
+    css_chan.send(CSSTaskExit);
+    js_chan.send(JSTaskExit);
+
+    HtmlParserResult {
+        discovery_port: discovery_port,
+    }
+}
+
+/// Parses a string of HTML into the given document without running scripts or loading any
+/// subresources, as `DOMParser` does.
+pub fn parse_html_string(document: &mut JS<Document>, input: &str) {
+    debug!("Hubbub: parsing string");
+    let mut parser = hubbub::Parser("UTF-8", false);
+    parser.set_document_node(unsafe { document.to_hubbub_node() });
+    parser.enable_scripting(false);
+    parser.enable_styling(false);
+
+    build_tree(&mut parser, document, |_element| {}, |_script| {}, |_style| {}, |parser| {
+        parser.parse_chunk(input.as_bytes());
+    });
+}
+
+/// Builds the nodes of `document` while `parse` feeds its markup to the parser. `on_element` is
+/// called with each element once its attributes are set, and `complete_script` and
+/// `complete_style` with each `<script>` and `<style>` element once it has been parsed.
+fn build_tree(parser: &mut hubbub::Parser,
+              document: &mut JS<Document>,
+              on_element: |&JS<Element>|,
+              complete_script: |hubbub::NodeDataPtr|,
+              complete_style: |hubbub::NodeDataPtr|,
+              parse: |&mut hubbub::Parser|) {
     let tree_handler = hubbub::TreeHandler {
         create_comment: |data: ~str| {
             debug!("create comment");
@@ -332,53 +476,7 @@ pub fn parse_html(page: &Page,
                                              attr.value.clone());
             }
 
-            // Spawn additional parsing, network loads, etc. from tag and attrs
-            match element.get().node.type_id {
-                // Handle CSS style sheets from <link> elements
-                ElementNodeTypeId(HTMLLinkElementTypeId) => {
-                    match (element.get().get_attribute(Null, "rel"),
-                           element.get().get_attribute(Null, "href")) {
-                        (Some(ref rel), Some(ref href)) if rel.get()
-                                                              .value_ref()
-                                                              .split(HTML_SPACE_CHARACTERS.
-                                                                     as_slice())
-                                                              .any(|s| {
-                                    s.eq_ignore_ascii_case("stylesheet")
-                                }) => {
-                            debug!("found CSS stylesheet: {:s}", href.get().value_ref());
-                            let url = parse_url(href.get().value_ref(), Some(url2.clone()));
-                            css_chan2.send(CSSTaskNewFile(UrlProvenance(url)));
-                        }
-                        _ => {}
-                    }
-                }
-
-                ElementNodeTypeId(HTMLIFrameElementTypeId) => {
-                    let iframe_chan = discovery_chan.clone();
-                    let mut iframe_element: JS<HTMLIFrameElement> =
-                        HTMLIFrameElementCast::to(&element);
-                    let sandboxed = iframe_element.get().is_sandboxed();
-                    let elem: JS<Element> = ElementCast::from(&iframe_element);
-                    let src_opt = elem.get().get_attribute(Null, "src").map(|x| x.get().Value());
-                    for src in src_opt.iter() {
-                        let iframe_url = parse_url(*src, Some(url2.clone()));
-                        iframe_element.get_mut().set_frame(iframe_url.clone());
-
-                        // Subpage Id
-                        let subpage_id = next_subpage_id.get();
-                        next_subpage_id.set(SubpageId(*subpage_id + 1));
-
-                        iframe_element.get_mut().size = Some(IFrameSize {
-                            pipeline_id: pipeline_id,
-                            subpage_id: subpage_id,
-                        });
-                        iframe_chan.send(HtmlDiscoveredIFrame((iframe_url,
-                                                               subpage_id,
-                                                               sandboxed)));
-                    }
-                }
-                _ => {}
-            }
+            on_element(&element);
 
             unsafe { element.to_hubbub_node() }
         },
@@ -437,74 +535,11 @@ pub fn parse_html(page: &Page,
             debug!("encoding change");
             document.get_mut().set_encoding_name(encname);
         },
-        complete_script: |script| {
-            unsafe {
-                let script: JS<Element> = NodeWrapping::from_hubbub_node(script);
-                match script.get().get_attribute(Null, "src") {
-                    Some(src) => {
-                        debug!("found script: {:s}", src.get().Value());
-                        let new_url = parse_url(src.get().value_ref(), Some(url3.clone()));
-                        js_chan2.send(JSTaskNewFile(new_url));
-                    }
-                    None => {
-                        let mut data = ~[];
-                        let scriptnode: JS<Node> = NodeCast::from(&script);
-                        debug!("iterating over children {:?}", scriptnode.first_child());
-                        for child in scriptnode.children() {
-                            debug!("child = {:?}", child);
-                            let text: JS<Text> = TextCast::to(&child);
-                            data.push(text.get().characterdata.data.to_str());  // FIXME: Bad copy.
-                        }
-
-                        debug!("script data = {:?}", data);
-                        js_chan2.send(JSTaskNewInlineScript(data.concat(), url3.clone()));
-                    }
-                }
-            }
-            debug!("complete script");
-        },
-        complete_style: |style| {
-            // We've reached the end of a <style> so we can submit all the text to the parser.
-            unsafe {
-                let style: JS<Node> = NodeWrapping::from_hubbub_node(style);
-                let mut data = ~[];
-                debug!("iterating over children {:?}", style.first_child());
-                for child in style.children() {
-                    debug!("child = {:?}", child);
-                    let text: JS<Text> = TextCast::to(&child);
-                    data.push(text.get().characterdata.data.to_str());  // FIXME: Bad copy.
-                }
-
-                debug!("style data = {:?}", data);
-                let provenance = InlineProvenance(base_url.clone(), data.concat());
-                css_chan3.send(CSSTaskNewFile(provenance));
-            }
-        },
+        complete_script: |script| complete_script(script),
+        complete_style: |style| complete_style(style),
     };
     parser.set_tree_handler(&tree_handler);
     debug!("set tree handler");
 
-    debug!("loaded page");
-    loop {
-        match load_response.progress_port.recv() {
-            Payload(data) => {
-                debug!("received data");
-                parser.parse_chunk(data);
-            }
-            Done(Err(..)) => {
-                fail!("Failed to load page URL {:s}", url.to_str());
-            }
-            Done(..) => {
-                break;
-            }
-        }
-    }
-
-    css_chan.send(CSSTaskExit);
-    js_chan.send(JSTaskExit);
-
-    HtmlParserResult {
-        discovery_port: discovery_port,
-    }
+    parse(parser);
 }
-
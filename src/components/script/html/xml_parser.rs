@@ -0,0 +1,569 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A namespace-aware XML parser that builds a DOM tree, used by `DOMParser`.
+//!
+//! Only the internal predefined entities and character references are expanded; a document type
+//! declaration is turned into a `DocumentType` node, but its internal subset is skipped. The
+//! first well-formedness error stops the parse.
+
+use dom::bindings::codegen::InheritTypes::NodeCast;
+use dom::bindings::js::JS;
+use dom::bindings::utils::{QName, xml_name_type};
+use dom::document::Document;
+use dom::element::Element;
+use dom::node::{INode, Node};
+use dom::types::{Comment, DocumentType, ProcessingInstruction, Text};
+use html::hubbub_html_parser::build_element_from_tag;
+
+use servo_util::namespace;
+use servo_util::namespace::Namespace;
+use servo_util::str::DOMString;
+use std::ascii::StrAsciiExt;
+use std::char;
+use std::num;
+
+macro_rules! try_parse(
+    ($result: expr) => (
+        match $result {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        }
+    )
+)
+
+/// A well-formedness error, and where in the input it was found.
+pub struct XmlParseError {
+    message: ~str,
+    /// The line of the error, counting from 1.
+    line: uint,
+    /// The column of the error, counting from 1.
+    column: uint,
+}
+
+impl XmlParseError {
+    pub fn description(&self) -> ~str {
+        format!("XML parsing error: {:s}\nLine number {:u}, column {:u}",
+                self.message, self.line, self.column)
+    }
+}
+
+type ParseResult<T> = Result<T, XmlParseError>;
+
+/// An element whose end tag has not been seen yet.
+struct OpenElement {
+    node: JS<Node>,
+    /// The qualified name, which the end tag must repeat.
+    name: ~str,
+    /// The namespace prefixes declared on the element, with `""` standing for the default
+    /// namespace.
+    bindings: ~[(~str, Namespace)],
+}
+
+struct XmlParser<'a> {
+    document: &'a JS<Document>,
+    input: ~[char],
+    position: uint,
+    line: uint,
+    column: uint,
+    open_elements: ~[OpenElement],
+    seen_doctype: bool,
+    seen_root: bool,
+}
+
+/// Parses `input` into `document`, which should be empty. On error, `document` is left holding
+/// whatever was parsed before the error.
+pub fn parse_xml(document: &JS<Document>, input: &str) -> Result<(), XmlParseError> {
+    // http://www.w3.org/TR/xml/#sec-line-ends
+    let input = input.replace("\r\n", "\n").replace("\r", "\n");
+    let mut parser = XmlParser {
+        document: document,
+        input: input.chars().collect(),
+        position: 0,
+        line: 1,
+        column: 1,
+        open_elements: ~[],
+        seen_doctype: false,
+        seen_root: false,
+    };
+    parser.parse_document()
+}
+
+/// Splits a qualified name into its prefix, if it has one, and its local name.
+fn split_qname<'a>(name: &'a str) -> (Option<&'a str>, &'a str) {
+    match name.find(':') {
+        Some(index) => (Some(name.slice_to(index)), name.slice_from(index + 1)),
+        None => (None, name),
+    }
+}
+
+fn is_xml_whitespace(c: char) -> bool {
+    c == ' ' || c == '\t' || c == '\n' || c == '\r'
+}
+
+/// Returns true for the characters that cannot appear in a name, so that a name is read up to
+/// the first of them and then checked.
+fn ends_name(c: char) -> bool {
+    is_xml_whitespace(c) || match c {
+        '<' | '>' | '/' | '=' | '?' | '!' | '&' | ';' | '"' | '\'' | '[' | ']' => true,
+        _ => false,
+    }
+}
+
+impl<'a> XmlParser<'a> {
+    fn error<T>(&self, message: ~str) -> ParseResult<T> {
+        Err(XmlParseError {
+            message: message,
+            line: self.line,
+            column: self.column,
+        })
+    }
+
+    fn peek(&self) -> Option<char> {
+        if self.position < self.input.len() {
+            Some(self.input[self.position])
+        } else {
+            None
+        }
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        match c {
+            Some('\n') => {
+                self.line += 1;
+                self.column = 1;
+            }
+            Some(_) => self.column += 1,
+            None => return None,
+        }
+        self.position += 1;
+        c
+    }
+
+    fn looking_at(&self, string: &str) -> bool {
+        let mut position = self.position;
+        for c in string.chars() {
+            if position >= self.input.len() || self.input[position] != c {
+                return false
+            }
+            position += 1;
+        }
+        true
+    }
+
+    /// Consumes `string` if the input continues with it.
+    fn consume(&mut self, string: &str) -> bool {
+        if !self.looking_at(string) {
+            return false
+        }
+        for _ in string.chars() {
+            self.next();
+        }
+        true
+    }
+
+    fn expect(&mut self, string: &str) -> ParseResult<()> {
+        if self.consume(string) {
+            Ok(())
+        } else {
+            self.error(format!("expected `{:s}`", string))
+        }
+    }
+
+    /// Skips whitespace, returning true if there was any.
+    fn skip_whitespace(&mut self) -> bool {
+        let mut skipped = false;
+        loop {
+            match self.peek() {
+                Some(c) if is_xml_whitespace(c) => {
+                    self.next();
+                    skipped = true;
+                }
+                _ => return skipped,
+            }
+        }
+    }
+
+    /// Reads up to the next occurrence of `terminator`, which is consumed but not returned.
+    fn read_until(&mut self, terminator: &str, what: &str) -> ParseResult<~str> {
+        let mut string = ~"";
+        while !self.looking_at(terminator) {
+            match self.next() {
+                Some(c) => string.push_char(c),
+                None => return self.error(format!("unterminated {:s}", what)),
+            }
+        }
+        self.consume(terminator);
+        Ok(string)
+    }
+
+    /// Reads a name, which must be a valid qualified name since namespaces are in use.
+    fn parse_name(&mut self) -> ParseResult<~str> {
+        let mut name = ~"";
+        loop {
+            match self.peek() {
+                Some(c) if !ends_name(c) => {
+                    self.next();
+                    name.push_char(c);
+                }
+                _ => break,
+            }
+        }
+        if name.is_empty() {
+            return self.error(~"expected a name")
+        }
+        if xml_name_type(name) != QName {
+            return self.error(format!("`{:s}` is not a valid qualified name", name))
+        }
+        Ok(name)
+    }
+
+    fn parse_quoted(&mut self) -> ParseResult<~str> {
+        match self.next() {
+            Some('"') => self.read_until("\"", "quoted string"),
+            Some('\'') => self.read_until("'", "quoted string"),
+            _ => self.error(~"expected a quoted string"),
+        }
+    }
+
+    /// Parses a character or entity reference, after its `&`.
+    fn parse_reference(&mut self) -> ParseResult<char> {
+        if self.consume("#") {
+            let radix = if self.consume("x") { 16 } else { 10 };
+            let digits = try_parse!(self.read_until(";", "character reference"));
+            let code: Option<u32> = num::from_str_radix(digits, radix);
+            return match code.and_then(char::from_u32) {
+                Some(c) if c != '\0' => Ok(c),
+                _ => self.error(format!("invalid character reference `{:s}`", digits)),
+            }
+        }
+        let name = try_parse!(self.read_until(";", "entity reference"));
+        match name.as_slice() {
+            "lt" => Ok('<'),
+            "gt" => Ok('>'),
+            "amp" => Ok('&'),
+            "apos" => Ok('\''),
+            "quot" => Ok('"'),
+            _ => self.error(format!("undefined entity `&{:s};`", name)),
+        }
+    }
+
+    // http://www.w3.org/TR/xml/#AVNormalize
+    fn parse_attribute_value(&mut self) -> ParseResult<~str> {
+        let quote = match self.next() {
+            Some(c) if c == '"' || c == '\'' => c,
+            _ => return self.error(~"expected a quoted attribute value"),
+        };
+        let mut value = ~"";
+        loop {
+            match self.next() {
+                Some(c) if c == quote => return Ok(value),
+                Some('<') => return self.error(~"`<` in attribute value"),
+                Some('&') => value.push_char(try_parse!(self.parse_reference())),
+                Some(c) if is_xml_whitespace(c) => value.push_char(' '),
+                Some(c) => value.push_char(c),
+                None => return self.error(~"unterminated attribute value"),
+            }
+        }
+    }
+
+    /// Finds the namespace bound to a prefix, or to the default namespace if `prefix` is `""`,
+    /// by the open elements and then `bindings`.
+    fn lookup_namespace(&self, prefix: &str, bindings: &[(~str, Namespace)])
+                        -> Option<Namespace> {
+        match prefix {
+            "xml" => return Some(namespace::XML),
+            "xmlns" => return Some(namespace::XMLNS),
+            _ => {}
+        }
+        fn find(prefix: &str, bindings: &[(~str, Namespace)]) -> Option<Namespace> {
+            bindings.iter().find(|&&(ref bound_prefix, _)| bound_prefix.as_slice() == prefix)
+                           .map(|&(_, ref namespace)| namespace.clone())
+        }
+
+        match find(prefix, bindings) {
+            Some(namespace) => return Some(namespace),
+            None => {}
+        }
+        for open in self.open_elements.rev_iter() {
+            match find(prefix, open.bindings) {
+                Some(namespace) => return Some(namespace),
+                None => {}
+            }
+        }
+        None
+    }
+
+    fn append(&mut self, node: &JS<Node>) {
+        let mut parent = match self.open_elements.last() {
+            Some(open) => open.node.clone(),
+            None => NodeCast::from(self.document),
+        };
+        let mut child = node.clone();
+        parent.AppendChild(&mut child);
+    }
+
+    fn parse_document(&mut self) -> ParseResult<()> {
+        self.consume("\uFEFF");
+        if self.looking_at("<?xml") {
+            try_parse!(self.read_until("?>", "XML declaration"));
+        }
+
+        loop {
+            if self.peek().is_none() {
+                break
+            }
+            if self.consume("<!--") {
+                try_parse!(self.parse_comment());
+            } else if self.consume("<![CDATA[") {
+                try_parse!(self.parse_cdata());
+            } else if self.consume("<!DOCTYPE") {
+                try_parse!(self.parse_doctype());
+            } else if self.consume("<?") {
+                try_parse!(self.parse_processing_instruction());
+            } else if self.consume("</") {
+                try_parse!(self.parse_end_tag());
+            } else if self.consume("<") {
+                try_parse!(self.parse_start_tag());
+            } else {
+                try_parse!(self.parse_text());
+            }
+        }
+
+        match self.open_elements.last() {
+            Some(open) => return self.error(format!("no end tag for `<{:s}>`", open.name)),
+            None => {}
+        }
+        if !self.seen_root {
+            return self.error(~"no root element")
+        }
+        Ok(())
+    }
+
+    fn parse_comment(&mut self) -> ParseResult<()> {
+        let data = try_parse!(self.read_until("-->", "comment"));
+        if data.contains("--") || data.ends_with("-") {
+            return self.error(~"`--` in comment")
+        }
+        let comment: JS<Node> = NodeCast::from(&Comment::new(data, self.document));
+        self.append(&comment);
+        Ok(())
+    }
+
+    fn parse_cdata(&mut self) -> ParseResult<()> {
+        if self.open_elements.is_empty() {
+            return self.error(~"CDATA section outside of the root element")
+        }
+        let data = try_parse!(self.read_until("]]>", "CDATA section"));
+        let text: JS<Node> = NodeCast::from(&Text::new(data, self.document));
+        self.append(&text);
+        Ok(())
+    }
+
+    fn parse_doctype(&mut self) -> ParseResult<()> {
+        if self.seen_doctype || self.seen_root {
+            return self.error(~"misplaced document type declaration")
+        }
+        self.seen_doctype = true;
+        if !self.skip_whitespace() {
+            return self.error(~"expected whitespace after `<!DOCTYPE`")
+        }
+        let name = try_parse!(self.parse_name());
+        self.skip_whitespace();
+
+        let (public_id, system_id) = if self.consume("PUBLIC") {
+            self.skip_whitespace();
+            let public_id = try_parse!(self.parse_quoted());
+            self.skip_whitespace();
+            (Some(public_id), Some(try_parse!(self.parse_quoted())))
+        } else if self.consume("SYSTEM") {
+            self.skip_whitespace();
+            (None, Some(try_parse!(self.parse_quoted())))
+        } else {
+            (None, None)
+        };
+        self.skip_whitespace();
+
+        // Skip the internal subset, minding `]` in quoted strings.
+        if self.consume("[") {
+            loop {
+                match self.peek() {
+                    Some(']') => {
+                        self.next();
+                        break
+                    }
+                    Some('"') | Some('\'') => {
+                        try_parse!(self.parse_quoted());
+                    }
+                    Some(_) => {
+                        self.next();
+                    }
+                    None => return self.error(~"unterminated internal subset"),
+                }
+            }
+            self.skip_whitespace();
+        }
+        try_parse!(self.expect(">"));
+
+        let doctype = DocumentType::new(name, public_id, system_id, self.document);
+        let doctype: JS<Node> = NodeCast::from(&doctype);
+        self.append(&doctype);
+        Ok(())
+    }
+
+    fn parse_processing_instruction(&mut self) -> ParseResult<()> {
+        let target = try_parse!(self.parse_name());
+        if target.eq_ignore_ascii_case("xml") {
+            return self.error(~"XML declaration not at the start of the document")
+        }
+        let data = if self.skip_whitespace() {
+            try_parse!(self.read_until("?>", "processing instruction"))
+        } else {
+            try_parse!(self.expect("?>"));
+            ~""
+        };
+        let pi = ProcessingInstruction::new(target, data, self.document);
+        let pi: JS<Node> = NodeCast::from(&pi);
+        self.append(&pi);
+        Ok(())
+    }
+
+    fn parse_start_tag(&mut self) -> ParseResult<()> {
+        if self.open_elements.is_empty() && self.seen_root {
+            return self.error(~"more than one root element")
+        }
+        let name = try_parse!(self.parse_name());
+
+        let mut attributes: ~[(~str, ~str)] = ~[];
+        let mut empty = false;
+        loop {
+            let had_whitespace = self.skip_whitespace();
+            if self.consume(">") {
+                break
+            }
+            if self.consume("/>") {
+                empty = true;
+                break
+            }
+            if !had_whitespace {
+                return self.error(~"expected whitespace between attributes")
+            }
+            let attribute_name = try_parse!(self.parse_name());
+            self.skip_whitespace();
+            try_parse!(self.expect("="));
+            self.skip_whitespace();
+            let value = try_parse!(self.parse_attribute_value());
+            if attributes.iter().any(|&(ref other, _)| *other == attribute_name) {
+                return self.error(format!("duplicate attribute `{:s}`", attribute_name))
+            }
+            attributes.push((attribute_name, value));
+        }
+
+        // http://www.w3.org/TR/xml-names/#ns-decl
+        let mut bindings = ~[];
+        for &(ref attribute_name, ref value) in attributes.iter() {
+            let prefix = match split_qname(attribute_name.as_slice()) {
+                (None, "xmlns") => "",
+                (Some("xmlns"), prefix) => prefix,
+                _ => continue,
+            };
+            let binds_xml_namespace = value.as_slice() == namespace::XML.to_str();
+            if prefix == "xmlns" || (prefix == "xml") != binds_xml_namespace {
+                return self.error(format!("cannot bind the reserved prefix `{:s}`", prefix))
+            }
+            if !prefix.is_empty() && value.is_empty() {
+                return self.error(format!("cannot undeclare the prefix `{:s}`", prefix))
+            }
+            bindings.push((prefix.to_owned(), Namespace::from_str(*value)));
+        }
+
+        let (prefix, local_name) = split_qname(name);
+        let namespace = match self.lookup_namespace(prefix.unwrap_or(""), bindings) {
+            Some(namespace) => namespace,
+            None if prefix.is_none() => namespace::Null,
+            None => return self.error(format!("unbound prefix in `<{:s}>`", name)),
+        };
+        let mut element = if namespace == namespace::HTML {
+            build_element_from_tag(local_name.to_owned(), self.document)
+        } else {
+            Element::new(local_name.to_owned(), namespace, self.document)
+        };
+
+        for (attribute_name, value) in attributes.move_iter() {
+            let namespace = match split_qname(attribute_name.as_slice()) {
+                (None, "xmlns") => namespace::XMLNS,
+                (None, _) => namespace::Null,
+                (Some(prefix), _) => match self.lookup_namespace(prefix, bindings) {
+                    Some(namespace) => namespace,
+                    None => {
+                        return self.error(format!("unbound prefix in attribute `{:s}`",
+                                                  attribute_name))
+                    }
+                },
+            };
+            let elem = element.clone();
+            if element.get_mut().set_attribute(&elem, namespace, attribute_name.clone(),
+                                               value).is_err() {
+                return self.error(format!("invalid attribute `{:s}`", attribute_name))
+            }
+        }
+
+        let node: JS<Node> = NodeCast::from(&element);
+        self.append(&node);
+        self.seen_root = true;
+        if !empty {
+            self.open_elements.push(OpenElement {
+                node: node,
+                name: name,
+                bindings: bindings,
+            });
+        }
+        Ok(())
+    }
+
+    fn parse_end_tag(&mut self) -> ParseResult<()> {
+        let name = try_parse!(self.parse_name());
+        self.skip_whitespace();
+        try_parse!(self.expect(">"));
+        match self.open_elements.pop() {
+            Some(ref open) if open.name == name => Ok(()),
+            Some(open) => {
+                self.error(format!("end tag `</{:s}>` does not match `<{:s}>`", name, open.name))
+            }
+            None => self.error(format!("end tag `</{:s}>` without a start tag", name)),
+        }
+    }
+
+    fn parse_text(&mut self) -> ParseResult<()> {
+        let mut data: DOMString = ~"";
+        loop {
+            match self.peek() {
+                None | Some('<') => break,
+                Some('&') => {
+                    self.next();
+                    data.push_char(try_parse!(self.parse_reference()));
+                }
+                Some(_) if self.looking_at("]]>") => {
+                    return self.error(~"`]]>` in text")
+                }
+                Some(c) => {
+                    self.next();
+                    data.push_char(c);
+                }
+            }
+        }
+
+        if self.open_elements.is_empty() {
+            // Only whitespace may appear outside the root element, and it is not kept.
+            if data.chars().all(is_xml_whitespace) {
+                return Ok(())
+            }
+            return self.error(~"text outside of the root element")
+        }
+        let text: JS<Node> = NodeCast::from(&Text::new(data, self.document));
+        self.append(&text);
+        Ok(())
+    }
+}
@@ -14,6 +14,7 @@ use geom::rect::Rect;
 use geom::size::Size2D;
 use script_task::{ScriptChan};
 use servo_util::geometry::Au;
+use servo_util::memory::{MemoryReporter, MemoryReportsChan};
 use std::cmp;
 use std::comm::{Chan, SharedChan};
 use std::libc::c_void;
@@ -39,6 +40,9 @@ pub enum Msg {
     /// TODO(pcwalton): Maybe think about batching to avoid message traffic.
     ReapLayoutDataMsg(LayoutDataRef),
 
+    /// Asks for the memory used by the flow tree and the style data of this layout task.
+    CollectMemoryReportsMsg(MemoryReportsChan),
+
     /// Requests that the layout task enter a quiescent state in which no more messages are
     /// accepted except `ExitMsg`. A response message will be sent on the supplied channel when
     /// this happens.
@@ -124,6 +128,8 @@ pub struct Reflow {
     script_chan: ScriptChan,
     /// The current window size.
     window_size: Size2D<uint>,
+    /// The number of device pixels that each CSS pixel is drawn at.
+    device_pixel_ratio: f32,
     /// The channel that we send a notification to.
     script_join_chan: Chan<()>,
     /// Unique identifier
@@ -141,6 +147,13 @@ impl LayoutChan {
     }
 }
 
+impl MemoryReporter for LayoutChan {
+    fn collect_reports(&self, reports_chan: MemoryReportsChan) -> bool {
+        let LayoutChan(ref chan) = *self;
+        chan.try_send(CollectMemoryReportsMsg(reports_chan))
+    }
+}
+
 #[test]
 fn test_add_damage() {
     fn assert_add(mut a: DocumentDamageLevel, b: DocumentDamageLevel,
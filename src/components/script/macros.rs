@@ -20,3 +20,164 @@ macro_rules! bitfield(
     )
 )
 
+
+/// Defines the getters and setters of event handler IDL attributes, such as `Onclick` and
+/// `SetOnclick`, on a type that implements `EventTargetHelpers`, and a static method named
+/// `$has_handler` that tells whether an event type is among them, or among the events in the
+/// optional leading list.
+macro_rules! event_handlers(
+    ($type_:ident, $has_handler:ident, [$($other:expr),+],
+     $($event:expr => $getter:ident, $setter:ident;)+) => (
+        impl $type_ {
+            pub fn $has_handler(ty: &str) -> bool {
+                $(
+                    if ty == $other {
+                        return true
+                    }
+                )+
+                $(
+                    if ty == $event {
+                        return true
+                    }
+                )+
+                false
+            }
+
+            $(
+                pub fn $getter(&self, _cx: *JSContext) -> JSVal {
+                    self.event_target().get_event_handler_attribute($event)
+                }
+
+                pub fn $setter(&mut self, cx: *JSContext, handler: JSVal) {
+                    self.mut_event_target().set_event_handler_attribute(cx, $event, handler)
+                }
+            )+
+        }
+    );
+    ($type_:ident, $has_handler:ident, $($event:expr => $getter:ident, $setter:ident;)+) => (
+        impl $type_ {
+            pub fn $has_handler(ty: &str) -> bool {
+                $(
+                    if ty == $event {
+                        return true
+                    }
+                )+
+                false
+            }
+
+            $(
+                pub fn $getter(&self, _cx: *JSContext) -> JSVal {
+                    self.event_target().get_event_handler_attribute($event)
+                }
+
+                pub fn $setter(&mut self, cx: *JSContext, handler: JSVal) {
+                    self.mut_event_target().set_event_handler_attribute(cx, $event, handler)
+                }
+            )+
+        }
+    )
+)
+
+// http://www.whatwg.org/specs/web-apps/current-work/#globaleventhandlers
+macro_rules! global_event_handlers(
+    ($type_:ident) => (
+        event_handlers!($type_, has_global_event_handler,
+            "abort" => Onabort, SetOnabort;
+            "blur" => Onblur, SetOnblur;
+            "change" => Onchange, SetOnchange;
+            "click" => Onclick, SetOnclick;
+            "dblclick" => Ondblclick, SetOndblclick;
+            "error" => Onerror, SetOnerror;
+            "focus" => Onfocus, SetOnfocus;
+            "input" => Oninput, SetOninput;
+            "keydown" => Onkeydown, SetOnkeydown;
+            "keypress" => Onkeypress, SetOnkeypress;
+            "keyup" => Onkeyup, SetOnkeyup;
+            "load" => Onload, SetOnload;
+            "mousedown" => Onmousedown, SetOnmousedown;
+            "mousemove" => Onmousemove, SetOnmousemove;
+            "mouseout" => Onmouseout, SetOnmouseout;
+            "mouseover" => Onmouseover, SetOnmouseover;
+            "mouseup" => Onmouseup, SetOnmouseup;
+            "reset" => Onreset, SetOnreset;
+            "resize" => Onresize, SetOnresize;
+            "scroll" => Onscroll, SetOnscroll;
+            "select" => Onselect, SetOnselect;
+            "submit" => Onsubmit, SetOnsubmit;
+        )
+    )
+)
+
+// http://www.whatwg.org/specs/web-apps/current-work/#windoweventhandlers
+/// `has_window_event_handler` tells which of the window's event handlers `<body>` and `<frameset>`
+/// reflect: these and the global event handlers listed first, which `global_event_handlers!`
+/// defines the IDL attributes of.
+macro_rules! window_event_handlers(
+    ($type_:ident) => (
+        event_handlers!($type_, has_window_event_handler,
+            ["blur", "error", "focus", "load", "resize", "scroll"],
+            "beforeunload" => Onbeforeunload, SetOnbeforeunload;
+            "hashchange" => Onhashchange, SetOnhashchange;
+            "message" => Onmessage, SetOnmessage;
+            "offline" => Onoffline, SetOnoffline;
+            "online" => Ononline, SetOnonline;
+            "pagehide" => Onpagehide, SetOnpagehide;
+            "pageshow" => Onpageshow, SetOnpageshow;
+            "popstate" => Onpopstate, SetOnpopstate;
+            "storage" => Onstorage, SetOnstorage;
+            "unload" => Onunload, SetOnunload;
+        )
+    )
+)
+
+/// Defines the event handler IDL attributes of `<body>` or `<frameset>` that are the window's,
+/// forwarding them to the window of the element's document. Elements of documents without a
+/// browsing context have no such event handlers.
+// http://www.whatwg.org/specs/web-apps/current-work/#the-body-element
+macro_rules! window_reflecting_event_handlers(
+    ($type_:ident) => (
+        forwarded_event_handlers!($type_,
+            Onblur, SetOnblur;
+            Onerror, SetOnerror;
+            Onfocus, SetOnfocus;
+            Onload, SetOnload;
+            Onresize, SetOnresize;
+            Onscroll, SetOnscroll;
+            Onbeforeunload, SetOnbeforeunload;
+            Onhashchange, SetOnhashchange;
+            Onmessage, SetOnmessage;
+            Onoffline, SetOnoffline;
+            Ononline, SetOnonline;
+            Onpagehide, SetOnpagehide;
+            Onpageshow, SetOnpageshow;
+            Onpopstate, SetOnpopstate;
+            Onstorage, SetOnstorage;
+            Onunload, SetOnunload;
+        )
+    )
+)
+
+macro_rules! forwarded_event_handlers(
+    ($type_:ident, $($getter:ident, $setter:ident;)+) => (
+        impl $type_ {
+            $(
+                pub fn $getter(&self, cx: *JSContext) -> JSVal {
+                    let doc = self.htmlelement.element.node.owner_doc().get();
+                    if !doc.has_browsing_context {
+                        return NullValue()
+                    }
+                    doc.window.get().$getter(cx)
+                }
+
+                pub fn $setter(&mut self, cx: *JSContext, handler: JSVal) {
+                    let doc = self.htmlelement.element.node.owner_doc().get();
+                    if !doc.has_browsing_context {
+                        return
+                    }
+                    let mut window = doc.window.clone();
+                    window.get_mut().$setter(cx, handler)
+                }
+            )+
+        }
+    )
+)
@@ -57,17 +57,21 @@ pub mod dom {
     pub mod clientrectlist;
     pub mod comment;
     pub mod console;
+    pub mod customevent;
     pub mod document;
     pub mod documentfragment;
     pub mod documenttype;
     pub mod domexception;
     pub mod domimplementation;
     pub mod domparser;
+    pub mod domstringmap;
+    pub mod domtokenlist;
     pub mod element;
     pub mod event;
     pub mod eventdispatcher;
     pub mod eventtarget;
     pub mod formdata;
+    pub mod history;
     pub mod htmlanchorelement;
     pub mod htmlappletelement;
     pub mod htmlareaelement;
@@ -141,12 +145,20 @@ pub mod dom {
     pub mod htmlunknownelement;
     pub mod location;
     pub mod mouseevent;
+    pub mod mutationobserver;
+    pub mod mutationrecord;
     pub mod navigator;
     pub mod node;
+    pub mod nodefilter;
+    pub mod nodeiterator;
     pub mod nodelist;
     pub mod processinginstruction;
+    pub mod range;
+    pub mod storage;
+    pub mod storageevent;
     pub mod uievent;
     pub mod text;
+    pub mod treewalker;
     pub mod validitystate;
     pub mod window;
     pub mod windowproxy;
@@ -157,6 +169,7 @@ pub mod dom {
 pub mod html {
     pub mod cssparse;
     pub mod hubbub_html_parser;
+    pub mod xml_parser;
 }
 
 pub mod layout_interface;
@@ -7,16 +7,20 @@
 
 use dom::bindings::codegen::RegisterBindings;
 use dom::bindings::codegen::InheritTypes::{EventTargetCast, NodeCast, ElementCast, EventCast};
+use dom::bindings::conversions::{Default, FromJSValConvertible, ToJSValConvertible};
 use dom::bindings::js::JS;
 use dom::bindings::utils::{Reflectable, GlobalStaticData, with_gc_enabled};
 use dom::document::{Document, HTMLDocument};
 use dom::element::Element;
 use dom::event::{Event_, ResizeEvent, ReflowEvent, ClickEvent, MouseDownEvent, MouseMoveEvent, MouseUpEvent};
 use dom::event::Event;
+use dom::storageevent::StorageEvent;
 use dom::uievent::UIEvent;
 use dom::eventtarget::EventTarget;
-use dom::node::{Node, NodeHelpers};
-use dom::window::{TimerData, TimerHandle, Window};
+use dom::htmlserializer::serialize;
+use dom::mutationobserver::notify_mutation_observers;
+use dom::node::{Node, NodeHelpers, NodeIterator};
+use dom::window::{FunctionTimerCallback, StringTimerCallback, TimerId, Window};
 use dom::windowproxy::WindowProxy;
 use html::hubbub_html_parser::HtmlParserResult;
 use html::hubbub_html_parser::{HtmlDiscoveredStyle, HtmlDiscoveredIFrame, HtmlDiscoveredScript};
@@ -33,25 +37,31 @@ use extra::url::Url;
 use geom::point::Point2D;
 use geom::size::Size2D;
 use js::global::DEBUG_FNS;
-use js::jsapi::{JSObject, JS_InhibitGC, JS_AllowGC, JS_CallFunctionValue};
-use js::jsval::NullValue;
+use js::jsapi::{JSContext, JSObject, JS_InhibitGC, JS_AllowGC, JS_CallFunctionValue};
+use js::jsapi::{JS_EvaluateUCScript, JS_ReportPendingException};
+use js::jsval::{NullValue, UndefinedValue};
 use js::rust::{Compartment, Cx, CxUtils, RtUtils};
 use js;
 use servo_msg::compositor_msg::{FinishedLoading, Loading, PerformingLayout, ScriptListener};
 use servo_msg::constellation_msg::{ConstellationChan, IFrameSandboxed, IFrameUnsandboxed};
 use servo_msg::constellation_msg::{LoadIframeUrlMsg, LoadCompleteMsg, LoadUrlMsg, NavigationDirection};
+use servo_msg::constellation_msg::{SessionHistoryState, StorageChange};
 use servo_msg::constellation_msg::{PipelineId, SubpageId, Failure, FailureMsg};
 use servo_msg::constellation_msg;
+use servo_msg::devtools_msg::{ConsoleLevel, ConsoleMessage, DevtoolScriptControlMsg};
+use servo_msg::devtools_msg::{EvaluateJS, GetDocumentHTML, WantConsoleMessages};
 use servo_net::image_cache_task::ImageCacheTask;
 use servo_net::resource_task::ResourceTask;
+use servo_net::storage_task::StorageTask;
 use servo_util::geometry::to_frac_px;
-use servo_util::url::parse_url;
+use servo_util::memory::{MemoryReport, MemoryReporter, MemoryReportsChan};
+use servo_util::url::{parse_url, serialize_origin};
 use servo_util::task::send_on_failure;
 use servo_util::namespace::Null;
 use std::cast;
 use std::cell::{RefCell, Ref, RefMut};
 use std::comm::{Port, SharedChan};
-use std::ptr;
+use std::libc::c_uint;
 use std::rc::Rc;
 use std::task;
 use std::util::replace;
@@ -71,15 +81,37 @@ pub enum ScriptMsg {
     /// Window resized.  Sends a DOM event eventually, but first we combine events.
     ResizeMsg(PipelineId, Size2D<uint>),
     /// Fires a JavaScript timeout.
-    FireTimerMsg(PipelineId, ~TimerData),
+    FireTimerMsg(PipelineId, TimerId),
     /// Notifies script that reflow is finished.
     ReflowCompleteMsg(PipelineId, uint),
     /// Notifies script that window has been resized but to not take immediate action.
     ResizeInactiveMsg(PipelineId, Size2D<uint>),
+    /// Sets the number of device pixels that each CSS pixel of the page is drawn at.
+    DevicePixelRatioMsg(PipelineId, f32),
     /// Notifies the script that a pipeline should be closed.
     ExitPipelineMsg(PipelineId),
     /// Notifies the script that a window associated with a particular pipeline should be closed.
     ExitWindowMsg(PipelineId),
+    /// A request from the devtools server about the specified pipeline.
+    DevtoolsControlMsg(PipelineId, DevtoolScriptControlMsg),
+    /// Asks for the memory used by the DOM of the specified pipeline.
+    CollectMemoryReportsMsg(PipelineId, MemoryReportsChan),
+    /// Instructs the script task to add a same-document session history entry in the
+    /// constellation.
+    PushStateMsg(PipelineId, SessionHistoryState),
+    /// Instructs the script task to replace the state of the current session history entry in
+    /// the constellation.
+    ReplaceStateMsg(PipelineId, SessionHistoryState),
+    /// Traverses to a same-document session history entry with the given state and URL.
+    PopStateMsg(PipelineId, Option<~str>, Url),
+    /// Runs the animation frame callbacks of the specified pipeline. Carries the compositor's
+    /// frame time in nanoseconds.
+    AnimationTickMsg(PipelineId, u64),
+    /// Instructs the script task to tell the constellation that a storage area changed.
+    StorageChangedMsg(PipelineId, StorageChange),
+    /// Fires a storage event at the window of the specified pipeline, if it uses the storage
+    /// area that changed.
+    StorageEventMsg(PipelineId, StorageChange),
 }
 
 pub struct NewLayoutInfo {
@@ -105,6 +137,29 @@ impl ScriptChan {
     }
 }
 
+/// Measures the DOM of one pipeline. A script task can own several pipelines, so the reporter
+/// names the one it is for.
+pub struct ScriptMemoryReporter {
+    script_chan: ScriptChan,
+    pipeline_id: PipelineId,
+}
+
+impl ScriptMemoryReporter {
+    pub fn new(script_chan: ScriptChan, pipeline_id: PipelineId) -> ScriptMemoryReporter {
+        ScriptMemoryReporter {
+            script_chan: script_chan,
+            pipeline_id: pipeline_id,
+        }
+    }
+}
+
+impl MemoryReporter for ScriptMemoryReporter {
+    fn collect_reports(&self, reports_chan: MemoryReportsChan) -> bool {
+        let ScriptChan(ref chan) = self.script_chan;
+        chan.try_send(CollectMemoryReportsMsg(self.pipeline_id, reports_chan))
+    }
+}
+
 /// Encapsulates a handle to a frame and its associated layout information.
 pub struct Page {
     /// Pipeline id associated with this page.
@@ -128,6 +183,9 @@ pub struct Page {
     /// The current size of the window, in pixels.
     window_size: RefCell<Size2D<uint>>,
 
+    /// The number of device pixels that each CSS pixel is drawn at.
+    device_pixel_ratio: RefCell<f32>,
+
     js_info: RefCell<Option<JSPageInfo>>,
 
     /// Cached copy of the most recent url loaded by the script
@@ -142,7 +200,10 @@ pub struct Page {
     resize_event: RefCell<Option<Size2D<uint>>>,
 
     /// Pending scroll to fragment event, if any
-    fragment_node: RefCell<Option<JS<Element>>>
+    fragment_node: RefCell<Option<JS<Element>>>,
+
+    /// The devtools clients that want the console messages of this page.
+    devtools_listeners: RefCell<~[SharedChan<ConsoleMessage>]>,
 }
 
 impl<S: Encoder> Encodable<S> for Page {
@@ -162,7 +223,8 @@ pub struct PageTreeIterator<'a> {
 }
 
 impl PageTree {
-    fn new(id: PipelineId, layout_chan: LayoutChan, window_size: Size2D<uint>) -> PageTree {
+    fn new(id: PipelineId, layout_chan: LayoutChan, window_size: Size2D<uint>,
+           device_pixel_ratio: f32) -> PageTree {
         PageTree {
             page: unsafe { Rc::new_unchecked(Page {
                 id: id,
@@ -171,12 +233,14 @@ impl PageTree {
                 layout_join_port: RefCell::new(None),
                 damage: RefCell::new(None),
                 window_size: RefCell::new(window_size),
+                device_pixel_ratio: RefCell::new(device_pixel_ratio),
                 js_info: RefCell::new(None),
                 url: RefCell::new(None),
                 next_subpage_id: RefCell::new(SubpageId(0)),
                 resize_event: RefCell::new(None),
                 fragment_node: RefCell::new(None),
-                last_reflow_id: RefCell::new(0)
+                last_reflow_id: RefCell::new(0),
+                devtools_listeners: RefCell::new(~[]),
             }) },
             inner: ~[],
         }
@@ -251,6 +315,18 @@ impl<'a> Iterator<Rc<Page>> for PageTreeIterator<'a> {
 }
 
 impl Page {
+    /// Sends a console message to the devtools clients listening to this page, forgetting the
+    /// ones that have disconnected.
+    pub fn send_console_message(&self, level: ConsoleLevel, message: &str) {
+        let mut listeners = self.devtools_listeners.borrow_mut();
+        let message = ConsoleMessage {
+            pipeline_id: self.id,
+            level: level,
+            message: message.to_owned(),
+        };
+        listeners.get().retain(|listener| listener.try_send(message.clone()));
+    }
+
     pub fn mut_js_info<'a>(&'a self) -> RefMut<'a, Option<JSPageInfo>> {
         self.js_info.borrow_mut()
     }
@@ -305,6 +381,12 @@ impl Page {
         };
     }
 
+    /// Returns true if damage has been added since the last reflow.
+    pub fn has_damage(&self) -> bool {
+        let damage = self.damage.borrow();
+        damage.get().is_some()
+    }
+
     pub fn get_url(&self) -> Url {
         let url = self.url();
         url.get().get_ref().first().clone()
@@ -384,6 +466,7 @@ impl Page {
                 let root: JS<Node> = NodeCast::from(&root);
                 let mut damage = self.damage.borrow_mut();
                 let window_size = self.window_size.borrow();
+                let device_pixel_ratio = self.device_pixel_ratio.borrow();
 
                 // Send new document and relevant styles to layout.
                 let reflow = ~Reflow {
@@ -391,6 +474,7 @@ impl Page {
                     url: self.get_url(),
                     goal: goal,
                     window_size: *window_size.get(),
+                    device_pixel_ratio: *device_pixel_ratio.get(),
                     script_chan: script_chan,
                     script_join_chan: join_chan,
                     damage: replace(damage.get(), None).unwrap(),
@@ -461,6 +545,8 @@ pub struct ScriptTask {
     image_cache_task: ImageCacheTask,
     /// A handle to the resource task.
     resource_task: ResourceTask,
+    /// A handle to the storage task.
+    storage_task: StorageTask,
 
     /// The port on which the script task receives messages (load URL, exit, etc.)
     port: Port<ScriptMsg>,
@@ -489,16 +575,18 @@ impl ScriptTask {
                constellation_chan: ConstellationChan,
                resource_task: ResourceTask,
                img_cache_task: ImageCacheTask,
+               storage_task: StorageTask,
                window_size: Size2D<uint>)
                -> Rc<ScriptTask> {
         let js_runtime = js::rust::rt();
 
         unsafe {
           Rc::new_unchecked(ScriptTask {
-            page_tree: RefCell::new(PageTree::new(id, layout_chan, window_size)),
+            page_tree: RefCell::new(PageTree::new(id, layout_chan, window_size, 1.0)),
 
             image_cache_task: img_cache_task,
             resource_task: resource_task,
+            storage_task: storage_task,
 
             port: port,
             chan: chan,
@@ -529,6 +617,7 @@ impl ScriptTask {
                   failure_msg: Failure,
                   resource_task: ResourceTask,
                   image_cache_task: ImageCacheTask,
+                  storage_task: StorageTask,
                   window_size: Size2D<uint>) {
         let mut builder = task::task();
         send_on_failure(&mut builder, FailureMsg(failure_msg), (*constellation_chan).clone());
@@ -542,6 +631,7 @@ impl ScriptTask {
                                               constellation_chan,
                                               resource_task,
                                               image_cache_task,
+                                              storage_task,
                                               window_size);
             script_task.borrow().start();
         });
@@ -572,6 +662,7 @@ impl ScriptTask {
         for (id, Size2D { width, height }) in resizes.move_iter() {
             self.handle_event(id, ResizeEvent(width, height));
         }
+        self.notify_mutation_observers();
 
         // Store new resizes, and gather all other events.
         let mut sequential = ~[];
@@ -605,19 +696,70 @@ impl ScriptTask {
                 AttachLayoutMsg(new_layout_info) => self.handle_new_layout(new_layout_info),
                 LoadMsg(id, url) => self.load(id, url),
                 SendEventMsg(id, event) => self.handle_event(id, event),
-                FireTimerMsg(id, timer_data) => self.handle_fire_timer_msg(id, timer_data),
+                FireTimerMsg(id, timer_id) => self.handle_fire_timer_msg(id, timer_id),
                 NavigateMsg(direction) => self.handle_navigate_msg(direction),
                 ReflowCompleteMsg(id, reflow_id) => self.handle_reflow_complete_msg(id, reflow_id),
                 ResizeInactiveMsg(id, new_size) => self.handle_resize_inactive_msg(id, new_size),
+                DevicePixelRatioMsg(id, device_pixel_ratio) => {
+                    self.handle_device_pixel_ratio_msg(id, device_pixel_ratio)
+                }
                 ExitPipelineMsg(id) => if self.handle_exit_pipeline_msg(id) { return false },
                 ExitWindowMsg(id) => self.handle_exit_window_msg(id),
+                DevtoolsControlMsg(id, msg) => self.handle_devtools_msg(id, msg),
+                CollectMemoryReportsMsg(id, reports_chan) => {
+                    self.handle_collect_memory_reports_msg(id, reports_chan)
+                }
+                PushStateMsg(id, state) => {
+                    self.constellation_chan.send(constellation_msg::PushStateMsg(id, state))
+                }
+                ReplaceStateMsg(id, state) => {
+                    self.constellation_chan.send(constellation_msg::ReplaceStateMsg(id, state))
+                }
+                PopStateMsg(id, state, url) => self.handle_pop_state_msg(id, state, url),
+                AnimationTickMsg(id, frame_time) => self.handle_animation_tick_msg(id, frame_time),
+                StorageChangedMsg(id, change) => {
+                    self.constellation_chan.send(constellation_msg::StorageChangedMsg(id, change))
+                }
+                StorageEventMsg(id, change) => self.handle_storage_event_msg(id, change),
                 ResizeMsg(..) => fail!("should have handled ResizeMsg already"),
             }
+            self.notify_mutation_observers();
         }
 
         true
     }
 
+    /// Delivers the records queued for mutation observers while handling a message, including
+    /// any that the observers' callbacks queue in turn.
+    fn notify_mutation_observers(&self) {
+        let mut windows = ~[];
+        {
+            let mut page_tree = self.page_tree.borrow_mut();
+            for page in page_tree.get().iter() {
+                let page = page.borrow();
+                let frame = page.frame();
+                let window = match *frame.get() {
+                    Some(ref frame) => frame.window.clone(),
+                    None => continue,
+                };
+                if window.get().mutation_observers.is_empty() {
+                    continue;
+                }
+                windows.push(window);
+            }
+        }
+
+        loop {
+            let mut notified = false;
+            for window in windows.iter() {
+                notified = notify_mutation_observers(window) || notified;
+            }
+            if !notified {
+                break;
+            }
+        }
+    }
+
     fn handle_new_layout(&self, new_layout_info: NewLayoutInfo) {
         debug!("Script: new layout: {:?}", new_layout_info);
         let NewLayoutInfo {
@@ -632,37 +774,181 @@ impl ScriptTask {
             task's page tree. This is a bug.");
         let new_page_tree = {
             let window_size = parent_page_tree.page().window_size.borrow();
-            PageTree::new(new_id, layout_chan, *window_size.get())
+            let device_pixel_ratio = parent_page_tree.page().device_pixel_ratio.borrow();
+            PageTree::new(new_id, layout_chan, *window_size.get(), *device_pixel_ratio.get())
         };
         parent_page_tree.inner.push(new_page_tree);
     }
 
     /// Handles a timer that fired.
-    fn handle_fire_timer_msg(&self, id: PipelineId, timer_data: ~TimerData) {
+    fn handle_fire_timer_msg(&self, id: PipelineId, timer_id: TimerId) {
         let mut page_tree = self.page_tree.borrow_mut();
         let page = page_tree.get().find(id).expect("ScriptTask: received fire timer msg for a
             pipeline ID not associated with this script task. This is a bug.").page();
         let frame = page.frame();
         let mut window = frame.get().get_ref().window.clone();
-        if !window.get().active_timers.contains(&TimerHandle { handle: timer_data.handle, cancel_chan: None }) {
+
+        // Timers that were cleared after firing are simply dropped. Intervals stay
+        // registered until they are cleared.
+        let (callback, args, nesting_level) = {
+            let timers = &mut window.get_mut().active_timers;
+            let is_interval = match timers.find(&timer_id) {
+                Some(timer) => timer.data.is_interval,
+                None => return,
+            };
+            if is_interval {
+                let data = &timers.get(&timer_id).data;
+                (data.callback.clone(), data.args.clone(), data.nesting_level)
+            } else {
+                let data = timers.pop(&timer_id).unwrap().data;
+                (data.callback, data.args, data.nesting_level)
+            }
+        };
+
+        let js_info = page.js_info();
+        let (cx, global) = {
+            let js_info = js_info.get().get_ref();
+            (js_info.js_context.clone(), js_info.js_compartment.borrow().global_obj.clone())
+        };
+
+        // Timers created while this one runs are nested inside it.
+        window.get_mut().timer_nesting_level = nesting_level;
+        with_gc_enabled(cx.borrow().ptr, || {
+            match callback {
+                StringTimerCallback(ref code) => {
+                    cx.borrow().evaluate_script(global.clone(), code.clone(),
+                                                page.get_url().to_str(), 1);
+                }
+                FunctionTimerCallback(funval) => {
+                    let rval = NullValue();
+                    let cx = cx.borrow().ptr;
+                    unsafe {
+                        if JS_CallFunctionValue(cx, global.borrow().ptr, funval,
+                                                args.len() as c_uint, args.as_ptr(), &rval) == 0 {
+                            // The handler threw; report the exception instead of leaving it
+                            // pending.
+                            JS_ReportPendingException(cx);
+                        }
+                    }
+                }
+            }
+        });
+        window.get_mut().timer_nesting_level = 0;
+    }
+
+    /// Runs the animation frame callbacks that were registered before this tick, then performs
+    /// a single reflow for any changes they made.
+    fn handle_animation_tick_msg(&self, id: PipelineId, frame_time: u64) {
+        let mut page_tree = self.page_tree.borrow_mut();
+        // The pipeline may have exited while the tick was in flight.
+        let page = match page_tree.get().find(id) {
+            Some(page_tree) => page_tree.page(),
+            None => return,
+        };
+        let frame = page.frame();
+        let mut window = match *frame.get() {
+            Some(ref frame) => frame.window.clone(),
+            None => return,
+        };
+        if window.get().animation_frame_callbacks.is_empty() {
             return;
         }
-        window.get_mut().active_timers.remove(&TimerHandle { handle: timer_data.handle, cancel_chan: None });
+
         let js_info = page.js_info();
-        let this_value = if timer_data.args.len() > 0 {
-            fail!("NYI")
-        } else {
-            js_info.get().get_ref().js_compartment.borrow().global_obj.borrow().ptr
+        let (cx, global) = {
+            let js_info = js_info.get().get_ref();
+            (js_info.js_context.borrow().ptr,
+             js_info.js_compartment.borrow().global_obj.borrow().ptr)
         };
+        let timestamp = window.get().high_resolution_time(frame_time).to_jsval(cx);
 
-        // TODO: Support extra arguments. This requires passing a `*JSVal` array as `argv`.
-        let rval = NullValue();
-        let cx = js_info.get().get_ref().js_context.borrow().ptr;
-        with_gc_enabled(cx, || {
-            unsafe {
-                JS_CallFunctionValue(cx, this_value, timer_data.funval, 0, ptr::null(), &rval);
+        // Callbacks requested while these run are kept for the next tick.
+        window.get_mut().begin_animation_frame();
+        loop {
+            let callback = match window.get_mut().next_animation_frame_callback() {
+                Some(callback) => callback,
+                None => break,
+            };
+            let rval = NullValue();
+            with_gc_enabled(cx, || {
+                unsafe {
+                    if JS_CallFunctionValue(cx, global, callback, 1, &timestamp, &rval) == 0 {
+                        // The callback threw; report the exception instead of leaving it pending,
+                        // and go on with the other callbacks.
+                        JS_ReportPendingException(cx);
+                    }
+                }
+            });
+        }
+        window.get_mut().end_animation_frame();
+
+        if page.has_damage() {
+            page.reflow(ReflowForDisplay, self.chan.clone(), self.compositor);
+        }
+    }
+
+    /// Handles a request from the devtools server.
+    fn handle_devtools_msg(&self, pipeline_id: PipelineId, msg: DevtoolScriptControlMsg) {
+        let mut page_tree = self.page_tree.borrow_mut();
+        let page = match page_tree.get().find(pipeline_id) {
+            Some(page_tree) => page_tree.page(),
+            // Dropping the reply channel tells the devtools server that the pipeline is gone.
+            None => return,
+        };
+
+        match msg {
+            GetDocumentHTML(reply_chan) => {
+                let frame = page.frame();
+                let html = match *frame.get() {
+                    Some(ref frame) => {
+                        let node: JS<Node> = NodeCast::from(&frame.document);
+                        serialize(&mut NodeIterator::new(node, false, false))
+                    }
+                    None => ~"",
+                };
+                reply_chan.try_send(html);
             }
-        });
+            EvaluateJS(script, reply_chan) => {
+                let js_info = page.js_info();
+                let result = match *js_info.get() {
+                    Some(ref js_info) => {
+                        let cx = js_info.js_context.borrow().ptr;
+                        let global = js_info.js_compartment.borrow().global_obj.borrow().ptr;
+                        evaluate_script_to_string(cx, global, script)
+                    }
+                    None => Err(~"the page has no script context"),
+                };
+                reply_chan.try_send(result);
+            }
+            WantConsoleMessages(listener) => {
+                let mut listeners = page.devtools_listeners.borrow_mut();
+                listeners.get().push(listener);
+            }
+        }
+    }
+
+    /// Measures the DOM of a pipeline for the memory profiler.
+    fn handle_collect_memory_reports_msg(&self,
+                                         pipeline_id: PipelineId,
+                                         reports_chan: MemoryReportsChan) {
+        let mut page_tree = self.page_tree.borrow_mut();
+        let page = match page_tree.get().find(pipeline_id) {
+            Some(page_tree) => page_tree.page(),
+            // Dropping the reports channel tells the memory profiler there is nothing to report.
+            None => return,
+        };
+
+        let frame = page.frame();
+        let dom_size = match *frame.get() {
+            Some(ref frame) => {
+                let node: JS<Node> = NodeCast::from(&frame.document);
+                node.heap_size_of_subtree()
+            }
+            None => 0,
+        };
+        let PipelineId(id) = pipeline_id;
+        let report = MemoryReport::new(format!("pipeline-{}/script/dom", id), dom_size);
+        reports_chan.try_send(~[report]);
     }
 
     /// Handles a notification that reflow completed.
@@ -686,6 +972,70 @@ impl ScriptTask {
         self.constellation_chan.send(constellation_msg::NavigateMsg(direction));
     }
 
+    /// Restores the URL and state of a same-document session history entry, then fires
+    /// `popstate` at the window.
+    fn handle_pop_state_msg(&self, id: PipelineId, state: Option<~str>, url: Url) {
+        let mut page_tree = self.page_tree.borrow_mut();
+        let page = match page_tree.get().find(id) {
+            Some(page_tree) => page_tree.page(),
+            None => return,
+        };
+        {
+            let mut page_url = page.mut_url();
+            *page_url.get() = Some((url, false));
+        }
+
+        let frame = page.frame();
+        let (document, mut window) = match *frame.get() {
+            Some(ref frame) => (frame.document.clone(), frame.window.clone()),
+            None => return,
+        };
+        let abstract_window = window.clone();
+        let mut history = window.get_mut().History(&abstract_window);
+        history.get_mut().set_state(state);
+
+        let mut event = Event::new(&window);
+        event.get_mut().InitEvent(~"popstate", false, false);
+        event.get_mut().trusted = true;
+        let doctarget = EventTargetCast::from(&document);
+        let mut wintarget: JS<EventTarget> = EventTargetCast::from(&window);
+        let winclone = wintarget.clone();
+        wintarget.get_mut().dispatch_event_with_target(&winclone, Some(doctarget), &mut event);
+    }
+
+    /// Fires a `storage` event at the window if its document shares the origin of the storage
+    /// area that changed and has used that storage area.
+    fn handle_storage_event_msg(&self, id: PipelineId, change: StorageChange) {
+        let mut page_tree = self.page_tree.borrow_mut();
+        let page = match page_tree.get().find(id) {
+            Some(page_tree) => page_tree.page(),
+            None => return,
+        };
+        if serialize_origin(&page.get_url()) != serialize_origin(&change.url) {
+            return;
+        }
+
+        let frame = page.frame();
+        let window = match *frame.get() {
+            Some(ref frame) => frame.window.clone(),
+            None => return,
+        };
+        let storage_area = match window.get().storage_area(change.is_local) {
+            Some(storage_area) => storage_area,
+            None => return,
+        };
+
+        let StorageChange { key, old_value, new_value, url, .. } = change;
+        let storageevent = StorageEvent::new(&window, key, old_value, new_value, url.to_str(),
+                                             Some(storage_area));
+        let event: &mut JS<Event> = &mut EventCast::from(&storageevent);
+        event.get_mut().InitEvent(~"storage", false, false);
+        event.get_mut().trusted = true;
+        let mut wintarget: JS<EventTarget> = EventTargetCast::from(&window);
+        let winclone = wintarget.clone();
+        wintarget.get_mut().dispatch_event_with_target(&winclone, None, event);
+    }
+
     /// Window was resized, but this script was not active, so don't reflow yet
     fn handle_resize_inactive_msg(&self, id: PipelineId, new_size: Size2D<uint>) {
         let mut page_tree = self.page_tree.borrow_mut();
@@ -700,6 +1050,27 @@ impl ScriptTask {
         }
     }
 
+    /// The page is drawn at a different number of device pixels per CSS pixel, so it is laid out
+    /// again to decode its images at the size they are drawn at.
+    fn handle_device_pixel_ratio_msg(&self, id: PipelineId, device_pixel_ratio: f32) {
+        let mut page_tree = self.page_tree.borrow_mut();
+        let page = page_tree.get().find(id).expect("ScriptTask: received a device pixel ratio for
+            a pipeline ID not associated with this script task. This is a bug.").page();
+        {
+            let mut page_device_pixel_ratio = page.device_pixel_ratio.borrow_mut();
+            if *page_device_pixel_ratio.get() == device_pixel_ratio {
+                return
+            }
+            *page_device_pixel_ratio.get() = device_pixel_ratio;
+        }
+
+        let frame = page.frame();
+        if frame.get().is_some() {
+            page.damage(ReflowDocumentDamage);
+            page.reflow(ReflowForDisplay, self.chan.clone(), self.compositor)
+        }
+    }
+
     /// We have gotten a window.close from script, which we pass on to the compositor.
     /// We do not shut down the script task now, because the compositor will ask the
     /// constellation to shut down the pipeline, which will clean everything up
@@ -779,7 +1150,8 @@ impl ScriptTask {
                                  page_tree.page.clone(),
                                  self.chan.clone(),
                                  self.compositor.dup(),
-                                 self.image_cache_task.clone());
+                                 self.image_cache_task.clone(),
+                                 self.storage_task.clone());
         page.initialize_js_info(cx.clone(), window.reflector().get_jsobject());
 
         {
@@ -887,6 +1259,7 @@ impl ScriptTask {
         // the initial load.
         let mut event = Event::new(&window);
         event.get_mut().InitEvent(~"load", false, false);
+        event.get_mut().trusted = true;
         let doctarget = EventTargetCast::from(&document);
         let mut wintarget: JS<EventTarget> = EventTargetCast::from(&window);
         let winclone = wintarget.clone();
@@ -969,6 +1342,7 @@ impl ScriptTask {
                         let mut uievent = UIEvent::new(&frame.window);
                         uievent.get_mut().InitUIEvent(~"resize", false, false, Some(window_proxy), 0i32);
                         let event: &mut JS<Event> = &mut EventCast::from(&uievent);
+                        event.get_mut().trusted = true;
 
                         // FIXME: this event should be dispatch on WindowProxy. See #1715
                         let mut wintarget: JS<EventTarget> = EventTargetCast::from(&frame.window);
@@ -1151,3 +1525,25 @@ fn shut_down_layout(page: &Page) {
     // Destroy the layout task. If there were node leaks, layout will now crash safely.
     page.layout_chan.send(layout_interface::ExitNowMsg);
 }
+
+/// Evaluates a script in the given global object and converts its completion value to a string,
+/// as the devtools console displays it.
+fn evaluate_script_to_string(cx: *JSContext, global: *JSObject, script: &str)
+                             -> Result<~str, ~str> {
+    let script_utf16 = script.to_utf16();
+    let rval = UndefinedValue();
+    let ok = with_gc_enabled(cx, || {
+        "devtools".with_c_str(|filename| {
+            unsafe {
+                JS_EvaluateUCScript(cx, global, script_utf16.as_ptr(),
+                                    script_utf16.len() as c_uint, filename, 1, &rval)
+            }
+        })
+    });
+    if ok == 0 {
+        // The exception has already been reported by the error reporter.
+        return Err(~"the script threw an exception")
+    }
+    let value: Result<~str, ()> = FromJSValConvertible::from_jsval(cx, rval, Default);
+    value.map_err(|_| ~"the completion value could not be converted to a string")
+}
@@ -43,7 +43,6 @@ pub mod specified {
                 _ => None
             }
         }
-        #[allow(dead_code)]
         pub fn parse(input: &ComponentValue) -> Option<Length> {
             Length::parse_internal(input, /* negative_ok = */ true)
         }
@@ -88,7 +87,6 @@ pub mod specified {
                 _ => None
             }
         }
-        #[allow(dead_code)]
         #[inline]
         pub fn parse(input: &ComponentValue) -> Option<LengthOrPercentage> {
             LengthOrPercentage::parse_internal(input, /* negative_ok = */ true)
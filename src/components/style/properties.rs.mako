@@ -431,6 +431,9 @@ pub mod longhands {
     // TODO: collapse. Well, do tables first.
     ${single_keyword("visibility", "visible hidden")}
 
+    // CSS 2.1, Section 9.10 - Text direction
+    ${single_keyword("direction", "ltr rtl")}
+
     // CSS 2.1, Section 12 - Generated content, automatic numbering, and lists
 
     ${switch_to_style_struct("Box")}
@@ -610,7 +613,7 @@ pub mod longhands {
 
 
     ${single_keyword("font-style", "normal italic oblique")}
-    ${single_keyword("font-variant", "normal")}  // Add small-caps when supported
+    ${single_keyword("font-variant", "normal small-caps")}
 
     <%self:single_component_value name="font-weight">
         #[deriving(Clone)]
@@ -737,6 +740,76 @@ pub mod longhands {
     // TODO: initial value should be 'start' (CSS Text Level 3, direction-dependent.)
     ${single_keyword("text-align", "left right center justify")}
 
+    <%self:single_component_value name="letter-spacing">
+        #[deriving(Clone)]
+        pub enum SpecifiedValue {
+            SpecifiedNormal,
+            SpecifiedLength(specified::Length),
+        }
+        pub mod computed_value {
+            use super::super::Au;
+            pub type T = Option<Au>;
+        }
+        #[inline]
+        pub fn get_initial_value() -> computed_value::T {
+            None
+        }
+        #[inline]
+        pub fn to_computed_value(value: SpecifiedValue, context: &computed::Context)
+                              -> computed_value::T {
+            match value {
+                SpecifiedNormal => None,
+                SpecifiedLength(value) => Some(computed::compute_Au(value, context))
+            }
+        }
+        /// normal | <length>
+        pub fn from_component_value(input: &ComponentValue, _base_url: &Url)
+                                    -> Option<SpecifiedValue> {
+            match input {
+                &Ident(ref value) if value.eq_ignore_ascii_case("normal")
+                => Some(SpecifiedNormal),
+                _ => specified::Length::parse(input).map(SpecifiedLength),
+            }
+        }
+    </%self:single_component_value>
+
+    <%self:single_component_value name="word-spacing">
+        #[deriving(Clone)]
+        pub enum SpecifiedValue {
+            SpecifiedNormal,
+            SpecifiedLength(specified::Length),
+        }
+        pub mod computed_value {
+            use super::super::Au;
+            pub type T = Au;
+        }
+        #[inline]
+        pub fn get_initial_value() -> computed_value::T {
+            Au(0)
+        }
+        #[inline]
+        pub fn to_computed_value(value: SpecifiedValue, context: &computed::Context)
+                              -> computed_value::T {
+            match value {
+                SpecifiedNormal => Au(0),
+                SpecifiedLength(value) => computed::compute_Au(value, context)
+            }
+        }
+        /// normal | <length>
+        pub fn from_component_value(input: &ComponentValue, _base_url: &Url)
+                                    -> Option<SpecifiedValue> {
+            match input {
+                &Ident(ref value) if value.eq_ignore_ascii_case("normal")
+                => Some(SpecifiedNormal),
+                _ => specified::Length::parse(input).map(SpecifiedLength),
+            }
+        }
+    </%self:single_component_value>
+
+    ${predefined_type("text-indent", "LengthOrPercentage", "computed::LP_Length(Au(0))")}
+
+    ${single_keyword("text-transform", "none capitalize uppercase lowercase")}
+
     ${new_style_struct("Text", is_inherited=False)}
 
     <%self:longhand name="text-decoration">
@@ -784,10 +857,15 @@ pub mod longhands {
         }
     </%self:longhand>
 
+    ${single_keyword("unicode-bidi", "normal embed bidi-override")}
+
     ${switch_to_style_struct("InheritedText")}
 
     ${single_keyword("white-space", "normal pre")}
 
+    // CSS Text Level 3, Section 6.2 - Overflow Wrapping
+    ${single_keyword("overflow-wrap", "normal break-word")}
+
     // CSS 2.1, Section 17 - Tables
 
     // CSS 2.1, Section 18 - User interface
@@ -1019,6 +1097,11 @@ pub mod shorthands {
         })
     </%self:shorthand>
 
+    // CSS Text Level 3, Section 6.2: `word-wrap` is a legacy alias of `overflow-wrap`.
+    <%self:shorthand name="word-wrap" sub_properties="overflow-wrap">
+        overflow_wrap::parse(input, base_url).map(|value| Longhands { overflow_wrap: Some(value) })
+    </%self:shorthand>
+
 }
 
 
@@ -5,6 +5,7 @@
 use extra::arc::Arc;
 use std::ascii::StrAsciiExt;
 use std::hashmap::HashMap;
+use std::mem;
 use std::to_bytes;
 
 use servo_util::namespace;
@@ -99,6 +100,21 @@ impl SelectorMap {
         }
     }
 
+    /// Returns an estimate of the heap memory used by the hashes and rule vectors of this map.
+    /// The selectors and declarations are shared with the stylesheets, so they are not counted.
+    fn heap_size(&self) -> uint {
+        fn hash_size(hash: &HashMap<DOMString, ~[Rule]>) -> uint {
+            let mut size = 0;
+            for (key, rules) in hash.iter() {
+                size += mem::size_of::<(DOMString, ~[Rule])>() + key.len() +
+                    rules.len() * mem::size_of::<Rule>();
+            }
+            size
+        }
+        hash_size(&self.id_hash) + hash_size(&self.class_hash) + hash_size(&self.element_hash) +
+            self.universal_rules.len() * mem::size_of::<Rule>()
+    }
+
     /// Append to `rule_list` all Rules in `self` that match node.
     ///
     /// Extract matching rules as per node's ID, classes, tag name, etc..
@@ -320,6 +336,11 @@ impl Stylist {
         }
     }
 
+    /// Returns an estimate of the heap memory used by the selector maps, for the memory profiler.
+    pub fn heap_size(&self) -> uint {
+        self.element_map.heap_size() + self.before_map.heap_size() + self.after_map.heap_size()
+    }
+
     pub fn add_stylesheet(&mut self, stylesheet: Stylesheet, origin: StylesheetOrigin) {
         let (mut element_map, mut before_map, mut after_map) = match origin {
             UserAgentOrigin => (
@@ -447,6 +468,10 @@ impl PerOriginSelectorMap {
             important: SelectorMap::new(),
         }
     }
+
+    fn heap_size(&self) -> uint {
+        self.normal.heap_size() + self.important.heap_size()
+    }
 }
 
 struct PerPseudoElementSelectorMap {
@@ -464,6 +489,10 @@ impl PerPseudoElementSelectorMap {
             user: PerOriginSelectorMap::new(),
         }
     }
+
+    fn heap_size(&self) -> uint {
+        self.user_agent.heap_size() + self.author.heap_size() + self.user.heap_size()
+    }
 }
 
 #[deriving(Clone)]
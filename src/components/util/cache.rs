@@ -2,7 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use std::hashmap::HashMap;
+use std::hashmap::{HashMap, HashMapIterator};
 use std::rand::Rng;
 use std::rand;
 use std::vec::VecIterator;
@@ -77,6 +77,10 @@ impl<K: Clone + Eq + Hash, V: Clone> HashCache<K,V> {
           entries: HashMap::new(),
         }
     }
+
+    pub fn iter<'a>(&'a self) -> HashMapIterator<'a, K, V> {
+        self.entries.iter()
+    }
 }
 
 impl<K: Clone + Eq + Hash, V: Clone> Cache<K,V> for HashCache<K,V> {
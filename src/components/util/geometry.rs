@@ -224,6 +224,15 @@ pub fn to_frac_px(au: Au) -> f64 {
     (*au as f64) / 60f64
 }
 
+/// Describes the given rectangle in CSS pixels, for debugging output.
+pub fn rect_to_px_str(rect: &Rect<Au>) -> ~str {
+    format!("({},{} {}x{})",
+            to_frac_px(rect.origin.x),
+            to_frac_px(rect.origin.y),
+            to_frac_px(rect.size.width),
+            to_frac_px(rect.size.height))
+}
+
 // assumes 72 points per inch, and 96 px per inch
 pub fn from_pt(pt: f64) -> Au {
     from_px((pt / 72f64 * 96f64) as int)
@@ -0,0 +1,294 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Memory profiling functions.
+//!
+//! Tasks that own large data structures register a `MemoryReporter` with the memory profiler.
+//! When it is time to print (every period given with `-m`, or on demand with Ctrl+M), it asks each
+//! reporter for a list of measurements, each named by a slash-separated path such as
+//! `pipeline-1/layout/flow-tree`, and prints them sorted by path.
+
+use std::comm::{Port, SharedChan};
+use std::hashmap::HashMap;
+use std::iter::AdditiveIterator;
+use task::spawn_named;
+use time::Timer;
+
+/// A single measurement of the heap memory used by some data structure.
+#[deriving(Clone)]
+pub struct MemoryReport {
+    /// The name of the measurement, as a slash-separated path.
+    path: ~str,
+    /// The size in bytes.
+    size: uint,
+}
+
+impl MemoryReport {
+    pub fn new(path: ~str, size: uint) -> MemoryReport {
+        MemoryReport {
+            path: path,
+            size: size,
+        }
+    }
+}
+
+/// The channel on which a reporter sends its measurements.
+pub type MemoryReportsChan = Chan<~[MemoryReport]>;
+
+/// Something that can measure the memory used by some task. Implementations typically forward
+/// the request to the task with a message, so that the measurements are taken on the task that
+/// owns the data.
+pub trait MemoryReporter {
+    /// Asks for the reports to be sent on the given channel. Returns false if the task being
+    /// measured has gone away, in which case the reporter is dropped.
+    fn collect_reports(&self, reports_chan: MemoryReportsChan) -> bool;
+}
+
+// front-end representation of the memory profiler used to communicate with it
+#[deriving(Clone)]
+pub struct MemoryProfilerChan {
+    priv chan: SharedChan<MemoryProfilerMsg>,
+}
+
+impl MemoryProfilerChan {
+    pub fn send(&self, msg: MemoryProfilerMsg) {
+        self.chan.send(msg);
+    }
+
+    /// Sends a message unless the memory profiler has already shut down. Returns false if it has.
+    pub fn try_send(&self, msg: MemoryProfilerMsg) -> bool {
+        self.chan.try_send(msg)
+    }
+}
+
+pub enum MemoryProfilerMsg {
+    /// Registers a reporter under the given name, replacing any reporter that had that name.
+    RegisterReporterMsg(~str, ~MemoryReporter:Send),
+    /// Unregisters the reporter with the given name.
+    UnregisterReporterMsg(~str),
+    /// Message used to force print the memory reports
+    PrintMsg,
+    /// Tells the memory profiler to shut down.
+    ExitMsg,
+}
+
+// back end of the memory profiler that collects and prints the reports
+pub struct MemoryProfiler {
+    port: Port<MemoryProfilerMsg>,
+    /// The registered reporters, by name.
+    reporters: HashMap<~str, ~MemoryReporter:Send>,
+}
+
+impl MemoryProfiler {
+    /// Starts the memory profiler. If a period is given, the reports are printed every `period`
+    /// seconds and whenever a `PrintMsg` is sent; otherwise all messages are ignored.
+    pub fn create(period: Option<f64>) -> MemoryProfilerChan {
+        let (port, chan) = SharedChan::new();
+        match period {
+            Some(period) => {
+                let period = (period * 1000f64) as u64;
+                let chan = chan.clone();
+                spawn_named("Memory profiler timer", proc() {
+                    loop {
+                        Timer::sleep(period);
+                        if !chan.try_send(PrintMsg) {
+                            break;
+                        }
+                    }
+                });
+                // Spawn the memory profiler
+                spawn_named("Memory profiler", proc() {
+                    let mut memory_profiler = MemoryProfiler::new(port);
+                    memory_profiler.start();
+                });
+            }
+            None => {
+                // no-op to handle messages when the memory profiler is inactive
+                spawn_named("Memory profiler", proc() {
+                    loop {
+                        match port.recv_opt() {
+                            None | Some(ExitMsg) => break,
+                            _ => {}
+                        }
+                    }
+                });
+            }
+        }
+
+        MemoryProfilerChan {
+            chan: chan,
+        }
+    }
+
+    pub fn new(port: Port<MemoryProfilerMsg>) -> MemoryProfiler {
+        MemoryProfiler {
+            port: port,
+            reporters: HashMap::new(),
+        }
+    }
+
+    pub fn start(&mut self) {
+        loop {
+            match self.port.recv_opt() {
+                Some(msg) => {
+                    if !self.handle_msg(msg) {
+                        break
+                    }
+                }
+                None => break
+            }
+        }
+    }
+
+    fn handle_msg(&mut self, msg: MemoryProfilerMsg) -> bool {
+        match msg {
+            RegisterReporterMsg(name, reporter) => {
+                self.reporters.insert(name, reporter);
+            }
+            UnregisterReporterMsg(name) => {
+                self.reporters.remove(&name);
+            }
+            PrintMsg => self.print_reports(),
+            ExitMsg => return false,
+        }
+        true
+    }
+
+    /// Collects the reports of every reporter and prints them.
+    fn print_reports(&mut self) {
+        let reports = self.collect_reports();
+
+        println(format!("{:-50s} {:15s}", "_path_", "_size (MiB)_"));
+        for report in reports.iter() {
+            println(format!("{:-50s} {:15.2f}", report.path, to_mebibytes(report.size)));
+        }
+        let total = reports.iter().map(|report| report.size).sum();
+        println(format!("{:-50s} {:15.2f}", "total", to_mebibytes(total)));
+        println("");
+    }
+
+    /// Collects the reports of every reporter, sorted by path, and drops the reporters whose
+    /// tasks have gone away. All of the requests are sent before any reply is waited for, so
+    /// that the tasks measure themselves in parallel.
+    fn collect_reports(&mut self) -> ~[MemoryReport] {
+        let mut ports = ~[];
+        let mut dead_reporters = ~[];
+        for (name, reporter) in self.reporters.iter() {
+            let (port, chan) = Chan::new();
+            if reporter.collect_reports(chan) {
+                ports.push(port);
+            } else {
+                dead_reporters.push(name.clone());
+            }
+        }
+        for name in dead_reporters.iter() {
+            self.reporters.remove(name);
+        }
+
+        let mut reports = ~[];
+        for port in ports.iter() {
+            // A reporter whose task exits before answering just has nothing to report.
+            match port.recv_opt() {
+                Some(task_reports) => reports.push_all_move(task_reports),
+                None => {}
+            }
+        }
+        reports.sort_by(|a, b| a.path.cmp(&b.path));
+        reports
+    }
+}
+
+fn to_mebibytes(size: uint) -> f64 {
+    (size as f64) / (1024.0 * 1024.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MemoryProfiler, MemoryReport, MemoryReporter, MemoryReportsChan};
+    use super::{RegisterReporterMsg, UnregisterReporterMsg};
+    use std::comm::SharedChan;
+
+    /// A reporter that always sends the same reports.
+    struct FixedReporter {
+        reports: ~[MemoryReport],
+    }
+
+    impl MemoryReporter for FixedReporter {
+        fn collect_reports(&self, reports_chan: MemoryReportsChan) -> bool {
+            reports_chan.send(self.reports.clone());
+            true
+        }
+    }
+
+    /// A reporter whose task has gone away.
+    struct DeadReporter;
+
+    impl MemoryReporter for DeadReporter {
+        fn collect_reports(&self, _: MemoryReportsChan) -> bool {
+            false
+        }
+    }
+
+    /// A reporter whose task exits before answering.
+    struct SilentReporter;
+
+    impl MemoryReporter for SilentReporter {
+        fn collect_reports(&self, _: MemoryReportsChan) -> bool {
+            true
+        }
+    }
+
+    fn paths_and_sizes(reports: &[MemoryReport]) -> ~[(~str, uint)] {
+        reports.iter().map(|report| (report.path.clone(), report.size)).collect()
+    }
+
+    #[test]
+    fn test_reports_are_gathered_and_sorted_by_path() {
+        let (port, _chan) = SharedChan::new();
+        let mut profiler = MemoryProfiler::new(port);
+        profiler.handle_msg(RegisterReporterMsg(~"layout", ~FixedReporter {
+            reports: ~[MemoryReport::new(~"pipeline-1/layout/flow-tree", 300),
+                       MemoryReport::new(~"pipeline-1/layout/boxes", 200)],
+        } as ~MemoryReporter:Send));
+        profiler.handle_msg(RegisterReporterMsg(~"images", ~FixedReporter {
+            reports: ~[MemoryReport::new(~"images/decoded", 1000)],
+        } as ~MemoryReporter:Send));
+        profiler.handle_msg(RegisterReporterMsg(~"silent",
+                                                ~SilentReporter as ~MemoryReporter:Send));
+        assert_eq!(paths_and_sizes(profiler.collect_reports()), ~[
+            (~"images/decoded", 1000),
+            (~"pipeline-1/layout/boxes", 200),
+            (~"pipeline-1/layout/flow-tree", 300),
+        ]);
+    }
+
+    #[test]
+    fn test_dead_and_unregistered_reporters_are_dropped() {
+        let (port, _chan) = SharedChan::new();
+        let mut profiler = MemoryProfiler::new(port);
+        profiler.handle_msg(RegisterReporterMsg(~"dead", ~DeadReporter as ~MemoryReporter:Send));
+        profiler.handle_msg(RegisterReporterMsg(~"images", ~FixedReporter {
+            reports: ~[MemoryReport::new(~"images/decoded", 1000)],
+        } as ~MemoryReporter:Send));
+        assert_eq!(profiler.collect_reports().len(), 1);
+        assert!(!profiler.reporters.contains_key(&~"dead"));
+
+        profiler.handle_msg(UnregisterReporterMsg(~"images"));
+        assert_eq!(profiler.collect_reports().len(), 0);
+        assert!(profiler.reporters.is_empty());
+    }
+
+    #[test]
+    fn test_registering_a_name_again_replaces_the_reporter() {
+        let (port, _chan) = SharedChan::new();
+        let mut profiler = MemoryProfiler::new(port);
+        profiler.handle_msg(RegisterReporterMsg(~"images", ~FixedReporter {
+            reports: ~[MemoryReport::new(~"images/decoded", 1000)],
+        } as ~MemoryReporter:Send));
+        profiler.handle_msg(RegisterReporterMsg(~"images", ~FixedReporter {
+            reports: ~[MemoryReport::new(~"images/decoded", 10)],
+        } as ~MemoryReporter:Send));
+        assert_eq!(paths_and_sizes(profiler.collect_reports()), ~[(~"images/decoded", 10)]);
+    }
+}
@@ -8,6 +8,7 @@
 use azure::azure_hl::{BackendType, CairoBackend, CoreGraphicsBackend};
 use azure::azure_hl::{CoreGraphicsAcceleratedBackend, Direct2DBackend, SkiaBackend};
 use extra::getopts::groups;
+use geom::size::Size2D;
 use std::num;
 use std::rt;
 use std::io;
@@ -22,9 +23,8 @@ pub struct Opts {
     /// The rendering backend to use (`-r`).
     render_backend: BackendType,
 
-    /// How many threads to use for CPU rendering (`-t`).
-    ///
-    /// FIXME(pcwalton): This is not currently used. All rendering is sequential.
+    /// How many threads to use for CPU rendering (`-t`). Tiles are rasterized in parallel when
+    /// this is greater than one. GPU rendering is always sequential.
     n_render_threads: uint,
 
     /// True to use CPU painting, false to use GPU painting via Skia-GL (`-c`). Note that
@@ -38,15 +38,32 @@ pub struct Opts {
     /// it to produce output on that interval (`-p`).
     profiler_period: Option<f64>,
 
+    /// The file to write a trace of every profiled span to when Servo exits, in the Chrome trace
+    /// event format (`--profiler-trace`). This includes the share of each parallel layout
+    /// traversal done by each worker thread.
+    profiler_trace_file: Option<~str>,
+
+    /// `None` to disable the memory profiler or `Some` with an interval in seconds to enable it and
+    /// cause it to print memory reports on that interval, as well as on demand (`-m`).
+    memory_profiler_period: Option<f64>,
+
     /// The number of threads to use for layout (`-y`). Defaults to 1, which results in a recursive
     /// sequential algorithm.
     layout_threads: uint,
 
+    /// The number of bytes of fetched and decoded images that the image cache keeps before it
+    /// starts evicting the least recently used ones (`--image-cache-size`, given in MiB).
+    image_cache_size: uint,
+
     /// True to exit after the page load (`-x`).
     exit_after_load: bool,
 
     output_file: Option<~str>,
     headless: bool,
+
+    /// The size of the viewport in headless mode, in pixels (`-w`).
+    headless_viewport_size: Size2D<uint>,
+
     hard_fail: bool,
 
     /// True if we should bubble intrinsic widths sequentially (`-b`). If this is true, then
@@ -54,6 +71,24 @@ pub struct Opts {
     /// may wish to turn this flag on in order to benchmark style recalculation against other
     /// browser engines.
     bubble_widths_separately: bool,
+
+    /// True to dump the flow tree after each reflow (`--dump-flow-tree`).
+    dump_flow_tree: bool,
+
+    /// True to dump the display list after each reflow (`--dump-display-list`).
+    dump_display_list: bool,
+
+    /// The file to append the flow tree and display list dumps to, or `None` to print them to
+    /// stdout (`--dump-file`).
+    dump_file: Option<~str>,
+
+    /// `None` to disable the devtools server or `Some` with a port on localhost to start it
+    /// listening there (`--devtools`).
+    devtools_port: Option<u16>,
+
+    /// The directory to persist `localStorage` in, or `None` to keep it in memory for the
+    /// lifetime of the process (`--local-storage-dir`).
+    local_storage_dir: Option<~str>,
 }
 
 fn print_usage(app: &str, opts: &[groups::OptGroup]) {
@@ -77,11 +112,20 @@ pub fn from_cmdline_args(args: &[~str]) -> Option<Opts> {
         groups::optopt("s", "size", "Size of tiles", "512"),
         groups::optopt("t", "threads", "Number of render threads", "1"),
         groups::optflagopt("p", "profile", "Profiler flag and output interval", "10"),
+        groups::optflagopt("m", "memory-profile", "Memory profiler flag and output interval", "10"),
+        groups::optopt("", "profiler-trace", "Write a trace of profiled spans to a file", "trace.json"),
         groups::optflag("x", "exit", "Exit after load flag"),
+        groups::optopt("", "image-cache-size", "Size of the image cache in MiB", "64"),
         groups::optopt("y", "layout-threads", "Number of threads to use for layout", "1"),
         groups::optflag("z", "headless", "Headless mode"),
+        groups::optopt("w", "viewport", "Size of the viewport in headless mode", "640x480"),
         groups::optflag("f", "hard-fail", "Exit on task failure instead of displaying about:failure"),
         groups::optflag("b", "bubble-widths", "Bubble intrinsic widths separately like other engines"),
+        groups::optflag("", "dump-flow-tree", "Dump the flow tree after each reflow"),
+        groups::optflag("", "dump-display-list", "Dump the display list after each reflow"),
+        groups::optopt("", "dump-file", "File to append layout dumps to instead of stdout", "layout.txt"),
+        groups::optopt("", "devtools", "Start the devtools server on the given port", "6000"),
+        groups::optopt("", "local-storage-dir", "Directory to persist localStorage in", "storage"),
         groups::optflag("h", "help", "Print this message")
     ];
 
@@ -140,6 +184,10 @@ pub fn from_cmdline_args(args: &[~str]) -> Option<Opts> {
         from_str(period).unwrap()
     });
 
+    let memory_profiler_period = opt_match.opt_default("m", "5").map(|period| {
+        from_str(period).unwrap()
+    });
+
     let cpu_painting = opt_match.opt_present("c");
 
     let layout_threads: uint = match opt_match.opt_str("y") {
@@ -147,6 +195,42 @@ pub fn from_cmdline_args(args: &[~str]) -> Option<Opts> {
         None => num::max(rt::default_sched_threads() * 3 / 4, 1),
     };
 
+    let headless_viewport_size = match opt_match.opt_str("w") {
+        Some(size_str) => {
+            let dimensions: ~[uint] = size_str.split('x').filter_map(|dimension| {
+                from_str(dimension)
+            }).collect();
+            if dimensions.len() != 2 {
+                args_fail("the viewport size must be of the form WIDTHxHEIGHT");
+                return None;
+            }
+            Size2D(dimensions[0], dimensions[1])
+        }
+        None => Size2D(640, 480),
+    };
+
+    let image_cache_size = match opt_match.opt_str("image-cache-size") {
+        Some(size_str) => match from_str::<uint>(size_str) {
+            Some(size) => size * 1024 * 1024,
+            None => {
+                args_fail("the image cache size must be a number");
+                return None;
+            }
+        },
+        None => 64 * 1024 * 1024,
+    };
+
+    let devtools_port = match opt_match.opt_str("devtools") {
+        Some(port_str) => match from_str(port_str) {
+            Some(port) => Some(port),
+            None => {
+                args_fail("the devtools port must be a number");
+                return None;
+            }
+        },
+        None => None,
+    };
+
     Some(Opts {
         urls: urls,
         render_backend: render_backend,
@@ -154,11 +238,20 @@ pub fn from_cmdline_args(args: &[~str]) -> Option<Opts> {
         cpu_painting: cpu_painting,
         tile_size: tile_size,
         profiler_period: profiler_period,
+        profiler_trace_file: opt_match.opt_str("profiler-trace"),
+        memory_profiler_period: memory_profiler_period,
         layout_threads: layout_threads,
+        image_cache_size: image_cache_size,
         exit_after_load: opt_match.opt_present("x"),
         output_file: opt_match.opt_str("o"),
         headless: opt_match.opt_present("z"),
+        headless_viewport_size: headless_viewport_size,
         hard_fail: opt_match.opt_present("f"),
         bubble_widths_separately: opt_match.opt_present("b"),
+        dump_flow_tree: opt_match.opt_present("dump-flow-tree"),
+        dump_display_list: opt_match.opt_present("dump-display-list"),
+        dump_file: opt_match.opt_str("dump-file"),
+        devtools_port: devtools_port,
+        local_storage_dir: opt_match.opt_str("local-storage-dir"),
     })
 }
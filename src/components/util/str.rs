@@ -38,3 +38,52 @@ pub static HTML_SPACE_CHARACTERS: [char, ..5] = [
     '\u000d',
 ];
 
+
+fn utf16_units(c: char) -> uint {
+    if (c as u32) > 0xFFFF { 2 } else { 1 }
+}
+
+/// Returns the length of `s` in UTF-16 code units, which is how the DOM measures strings.
+pub fn utf16_len(s: &str) -> uint {
+    s.chars().fold(0, |length, c| length + utf16_units(c))
+}
+
+/// Returns the byte index in `s` of the UTF-16 code unit `offset`, or `None` if `offset` is
+/// past the end of `s` or falls between the two halves of a surrogate pair.
+pub fn utf16_offset_to_byte_index(s: &str, offset: uint) -> Option<uint> {
+    let mut units = 0;
+    for (index, c) in s.char_indices() {
+        if units == offset {
+            return Some(index)
+        }
+        if units > offset {
+            return None
+        }
+        units += utf16_units(c);
+    }
+    if units == offset {
+        Some(s.len())
+    } else {
+        None
+    }
+}
+
+#[test]
+fn should_measure_strings_in_utf16_code_units() {
+    assert_eq!(utf16_len(""), 0);
+    assert_eq!(utf16_len("abc"), 3);
+    assert_eq!(utf16_len("été"), 3);
+    assert_eq!(utf16_len("a\U0001F600b"), 4);
+}
+
+#[test]
+fn should_find_the_byte_index_of_a_utf16_offset() {
+    let s = "éa\U0001F600b";
+    assert_eq!(utf16_offset_to_byte_index(s, 0), Some(0));
+    assert_eq!(utf16_offset_to_byte_index(s, 1), Some(2));
+    assert_eq!(utf16_offset_to_byte_index(s, 2), Some(3));
+    assert_eq!(utf16_offset_to_byte_index(s, 3), None);
+    assert_eq!(utf16_offset_to_byte_index(s, 4), Some(7));
+    assert_eq!(utf16_offset_to_byte_index(s, 5), Some(8));
+    assert_eq!(utf16_offset_to_byte_index(s, 6), None);
+}
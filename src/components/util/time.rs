@@ -4,10 +4,15 @@
 
 //! Timing functions.
 
+use extra::json::{Json, List, Number, Object, String};
 use extra::time::precise_time_ns;
 use extra::treemap::TreeMap;
+use io::result;
 use std::comm::{Port, SharedChan};
+use std::hashmap::HashMap;
+use std::io::File;
 use std::iter::AdditiveIterator;
+use std::task::with_task_name;
 use task::{spawn_named};
 
 // TODO: This code should be changed to use the commented code that uses timers
@@ -28,17 +33,24 @@ impl Timer {
 
 // front-end representation of the profiler used to communicate with the profiler
 #[deriving(Clone)]
-pub struct ProfilerChan(SharedChan<ProfilerMsg>);
+pub struct ProfilerChan {
+    priv chan: SharedChan<ProfilerMsg>,
+    /// True if the profiler is recording a trace file, in which case spans should be sent to it
+    /// with `TraceMsg`.
+    tracing: bool,
+}
 
 impl ProfilerChan {
     pub fn send(&self, msg: ProfilerMsg) {
-        (**self).send(msg);
+        self.chan.send(msg);
     }
 }
 
 pub enum ProfilerMsg {
     /// Normal message used for reporting time
     TimeMsg(ProfilerCategory, f64),
+    /// Message used to record a span in the trace file
+    TraceMsg(TraceEvent),
     /// Message used to force print the profiling metrics
     PrintMsg,
     /// Tells the profiler to shut down.
@@ -114,16 +126,34 @@ impl ProfilerCategory {
 
 type ProfilerBuckets = TreeMap<ProfilerCategory, ~[f64]>;
 
+/// A span of time spent by a task on one category of work, as recorded in the trace file.
+pub struct TraceEvent {
+    category: ProfilerCategory,
+    /// The name of the task or thread that did the work.
+    thread_name: ~str,
+    /// The start of the span, as returned by `precise_time_ns()`.
+    start_ns: u64,
+    /// The end of the span, as returned by `precise_time_ns()`.
+    end_ns: u64,
+    /// Extra measurements about the span, shown by trace viewers when it is selected.
+    args: ~[(&'static str, f64)],
+}
+
 // back end of the profiler that handles data aggregation and performance metrics
 pub struct Profiler {
     port: Port<ProfilerMsg>,
     buckets: ProfilerBuckets,
     last_msg: Option<ProfilerMsg>,
+    /// The file to write the trace to when the profiler exits, if tracing.
+    trace_file: Option<Path>,
+    /// The spans recorded so far, if tracing.
+    trace_events: ~[TraceEvent],
 }
 
 impl Profiler {
-    pub fn create(period: Option<f64>) -> ProfilerChan {
+    pub fn create(period: Option<f64>, trace_file: Option<~str>) -> ProfilerChan {
         let (port, chan) = SharedChan::new();
+        let tracing = trace_file.is_some();
         match period {
             Some(period) => {
                 let period = (period * 1000f64) as u64;
@@ -136,33 +166,41 @@ impl Profiler {
                         }
                     }
                 });
-                // Spawn the profiler
-                spawn_named("Profiler", proc() {
-                    let mut profiler = Profiler::new(port);
-                    profiler.start();
-                });
             }
-            None => {
-                // no-op to handle profiler messages when the profiler is inactive
-                spawn_named("Profiler", proc() {
-                    loop {
-                        match port.recv_opt() {
-                            None | Some(ExitMsg) => break,
-                            _ => {}
-                        }
+            None => {}
+        }
+
+        if period.is_some() || tracing {
+            // Spawn the profiler
+            spawn_named("Profiler", proc() {
+                let mut profiler = Profiler::new(port, trace_file.map(|path| Path::new(path)));
+                profiler.start();
+            });
+        } else {
+            // no-op to handle profiler messages when the profiler is inactive
+            spawn_named("Profiler", proc() {
+                loop {
+                    match port.recv_opt() {
+                        None | Some(ExitMsg) => break,
+                        _ => {}
                     }
-                });
-            }
+                }
+            });
         }
 
-        ProfilerChan(chan)
+        ProfilerChan {
+            chan: chan,
+            tracing: tracing,
+        }
     }
 
-    pub fn new(port: Port<ProfilerMsg>) -> Profiler {
+    pub fn new(port: Port<ProfilerMsg>, trace_file: Option<Path>) -> Profiler {
         Profiler {
             port: port,
             buckets: ProfilerCategory::empty_buckets(),
             last_msg: None,
+            trace_file: trace_file,
+            trace_events: ~[],
         }
     }
 
@@ -178,11 +216,19 @@ impl Profiler {
                None => break
             }
         }
+        self.write_trace();
     }
 
     fn handle_msg(&mut self, msg: ProfilerMsg) -> bool {
         match msg {
             TimeMsg(category, t) => self.buckets.find_mut(&category).unwrap().push(t),
+            TraceMsg(event) => {
+                if self.trace_file.is_some() {
+                    self.trace_events.push(event)
+                }
+                // Trace events don't count as new data for `PrintMsg`.
+                return true
+            }
             PrintMsg => match self.last_msg {
                 // only print if more data has arrived since the last printout
                 Some(TimeMsg(..)) => self.print_buckets(),
@@ -221,6 +267,71 @@ impl Profiler {
         }
         println("");
     }
+
+    /// Writes the recorded spans to the trace file in the Chrome trace event format, which can be
+    /// loaded into `chrome://tracing`. Each task or worker thread gets a row of its own.
+    fn write_trace(&mut self) {
+        let path = match self.trace_file {
+            Some(ref path) => path.clone(),
+            None => return,
+        };
+
+        let trace = trace_events_to_json(self.trace_events.as_slice());
+        let written = result(|| {
+            match File::create(&path) {
+                Some(mut file) => file.write(trace.to_str().as_bytes()),
+                None => {}
+            }
+        });
+        if written.is_err() {
+            error!("profiler: couldn't write the trace file {}", path.display());
+        }
+    }
+}
+
+/// Converts recorded spans to the Chrome trace event format. Timestamps are in microseconds since
+/// the start of the first span, and each task or worker thread is given a thread ID in the order
+/// it first appears, along with a metadata event naming it.
+fn trace_events_to_json(trace_events: &[TraceEvent]) -> Json {
+    let base_ns = trace_events.iter().map(|event| event.start_ns).min().unwrap_or(0);
+    let mut thread_ids = HashMap::new();
+    let mut events = ~[];
+    for event in trace_events.iter() {
+        let next_thread_id = thread_ids.len();
+        let thread_id = *thread_ids.find_or_insert_with(event.thread_name.clone(), |_| {
+            next_thread_id
+        });
+        if thread_id == next_thread_id {
+            events.push(trace_object(~[
+                (~"name", String(~"thread_name")),
+                (~"ph", String(~"M")),
+                (~"pid", Number(0.0)),
+                (~"tid", Number(thread_id as f64)),
+                (~"args", trace_object(~[(~"name", String(event.thread_name.clone()))])),
+            ]));
+        }
+
+        let args = event.args.iter().map(|&(name, value)| (name.to_owned(), Number(value)));
+        events.push(trace_object(~[
+            (~"name", String(format!("{:?}", event.category))),
+            (~"ph", String(~"X")),
+            (~"pid", Number(0.0)),
+            (~"tid", Number(thread_id as f64)),
+            (~"ts", Number((event.start_ns - base_ns) as f64 / 1000.0)),
+            (~"dur", Number((event.end_ns - event.start_ns) as f64 / 1000.0)),
+            (~"args", trace_object(args.collect())),
+        ]));
+    }
+
+    trace_object(~[(~"traceEvents", List(events))])
+}
+
+fn trace_object(fields: ~[(~str, Json)]) -> Json {
+    let mut object = ~TreeMap::new();
+    for (name, value) in fields.move_iter() {
+        object.insert(name, value);
+    }
+    Object(object)
 }
 
 
@@ -233,6 +344,15 @@ pub fn profile<T>(category: ProfilerCategory,
     let end_time = precise_time_ns();
     let ms = ((end_time - start_time) as f64 / 1000000f64);
     profiler_chan.send(TimeMsg(category, ms));
+    if profiler_chan.tracing {
+        profiler_chan.send(TraceMsg(TraceEvent {
+            category: category,
+            thread_name: with_task_name(|name| name.unwrap_or("<unnamed>").to_owned()),
+            start_ns: start_time,
+            end_ns: end_time,
+            args: ~[],
+        }));
+    }
     return val;
 }
 
@@ -253,3 +373,62 @@ fn check_order() {
     let buckets = ProfilerCategory::empty_buckets();
     assert!(buckets.len() == NumBuckets as uint);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{CompositingCategory, LayoutPerformCategory, LayoutSelectorMatchCategory};
+    use super::{ProfilerCategory, TraceEvent, trace_events_to_json};
+    use extra::json::{List, Object};
+
+    fn span(category: ProfilerCategory, thread_name: &str, start_ns: u64, end_ns: u64,
+            args: ~[(&'static str, f64)])
+            -> TraceEvent {
+        TraceEvent {
+            category: category,
+            thread_name: thread_name.to_owned(),
+            start_ns: start_ns,
+            end_ns: end_ns,
+            args: args,
+        }
+    }
+
+    /// Returns the events of a trace, as strings.
+    fn trace_event_strs(events: &[TraceEvent]) -> ~[~str] {
+        match trace_events_to_json(events) {
+            Object(trace) => {
+                match trace.find(&~"traceEvents") {
+                    Some(&List(ref events)) => events.iter().map(|event| event.to_str()).collect(),
+                    _ => fail!("expected a list of trace events"),
+                }
+            }
+            _ => fail!("expected a trace object"),
+        }
+    }
+
+    #[test]
+    fn test_trace_events_to_json() {
+        let events = ~[
+            span(LayoutPerformCategory, "LayoutTask", 1000000, 3500000, ~[]),
+            span(LayoutSelectorMatchCategory, "LayoutWorker 0", 2000000, 2500000,
+                 ~[("nodes", 12.0)]),
+            span(CompositingCategory, "LayoutTask", 4000000, 4001500, ~[]),
+        ];
+        assert_eq!(trace_event_strs(events.as_slice()), ~[
+            ~"{\"args\":{\"name\":\"LayoutTask\"},\"name\":\"thread_name\",\"ph\":\"M\"," +
+                "\"pid\":0,\"tid\":0}",
+            ~"{\"args\":{},\"dur\":2500,\"name\":\"LayoutPerformCategory\",\"ph\":\"X\"," +
+                "\"pid\":0,\"tid\":0,\"ts\":0}",
+            ~"{\"args\":{\"name\":\"LayoutWorker 0\"},\"name\":\"thread_name\",\"ph\":\"M\"," +
+                "\"pid\":0,\"tid\":1}",
+            ~"{\"args\":{\"nodes\":12},\"dur\":500,\"name\":\"LayoutSelectorMatchCategory\"," +
+                "\"ph\":\"X\",\"pid\":0,\"tid\":1,\"ts\":1000}",
+            ~"{\"args\":{},\"dur\":1.5,\"name\":\"CompositingCategory\",\"ph\":\"X\"," +
+                "\"pid\":0,\"tid\":0,\"ts\":3000}",
+        ]);
+    }
+
+    #[test]
+    fn test_empty_trace() {
+        assert_eq!(trace_events_to_json([]).to_str(), ~"{\"traceEvents\":[]}");
+    }
+}
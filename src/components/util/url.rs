@@ -87,6 +87,14 @@ pub fn parse_url(str_url: &str, base_url: Option<Url>) -> Url {
     url::from_str(str_url).ok().expect("URL parsing failed")
 }
 
+#[test]
+fn test_serialize_origin() {
+    let url = parse_url("http://example.com:8000/index.html?a#b", None);
+    assert!(serialize_origin(&url) == ~"http://example.com:8000");
+    let url = parse_url("https://example.com/", None);
+    assert!(serialize_origin(&url) == ~"https://example.com");
+}
+
 #[cfg(test)]
 mod parse_url_tests {
     use super::parse_url;
@@ -190,6 +198,14 @@ mod parse_url_tests {
 
 }
 
+/// Returns the ASCII serialization of the origin of a URL: its scheme, host and port.
+pub fn serialize_origin(url: &Url) -> ~str {
+    match url.port {
+        Some(ref port) => format!("{}://{}:{}", url.scheme, url.host, *port),
+        None => format!("{}://{}", url.scheme, url.host),
+    }
+}
+
 pub type UrlMap<T> = HashMap<Url, T>;
 
 pub fn url_map<T: Clone + 'static>() -> UrlMap<T> {
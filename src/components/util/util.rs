@@ -18,6 +18,7 @@ pub mod cowarc;
 pub mod debug;
 pub mod geometry;
 pub mod io;
+pub mod memory;
 pub mod namespace;
 pub mod opts;
 pub mod range;
@@ -7,6 +7,9 @@
 //! Data associated with queues is simply a pair of unsigned integers. It is expected that a
 //! higher-level API on top of this could allow safe fork-join parallelism.
 
+use time::{ProfilerCategory, ProfilerChan, TraceEvent, TraceMsg};
+
+use extra::time::precise_time_ns;
 use native;
 use std::cast;
 use std::rand::{Rng, XorShiftRng};
@@ -29,8 +32,12 @@ pub struct WorkUnit<QUD,WUD> {
 
 /// Messages from the supervisor to the worker.
 enum WorkerMsg<QUD,WUD> {
-    /// Tells the worker to start work.
-    StartMsg(Worker<WorkUnit<QUD,WUD>>, *mut AtomicUint, *QUD),
+    /// Tells the worker to start work. If a category and profiler are given, the worker reports
+    /// the span of time it spent on this run to the profiler's trace.
+    StartMsg(Worker<WorkUnit<QUD,WUD>>,
+             *mut AtomicUint,
+             *QUD,
+             Option<(ProfilerCategory, ProfilerChan)>),
 
     /// Tells the worker to stop. It can be restarted again with a `StartMsg`.
     StopMsg,
@@ -79,12 +86,19 @@ impl<QUD:Send,WUD:Send> WorkerThread<QUD,WUD> {
     pub fn start(&mut self) {
         loop {
             // Wait for a start message.
-            let (mut deque, ref_count, queue_data) = match self.port.recv() {
-                StartMsg(deque, ref_count, queue_data) => (deque, ref_count, queue_data),
+            let (mut deque, ref_count, queue_data, trace) = match self.port.recv() {
+                StartMsg(deque, ref_count, queue_data, trace) => {
+                    (deque, ref_count, queue_data, trace)
+                }
                 StopMsg => fail!("unexpected stop message"),
                 ExitMsg => return,
             };
 
+            // Statistics for the trace, if any.
+            let tracing = trace.is_some();
+            let start_ns = if tracing { precise_time_ns() } else { 0 };
+            let (mut work_unit_count, mut working_ns) = (0u, 0u64);
+
             // We're off!
             //
             // FIXME(pcwalton): Can't use labeled break or continue cross-crate due to a Rust bug.
@@ -141,7 +155,14 @@ impl<QUD:Send,WUD:Send> WorkerThread<QUD,WUD> {
                     ref_count: ref_count,
                     queue_data: queue_data,
                 };
-                (work_unit.fun)(work_unit.data, &mut proxy);
+                if tracing {
+                    let work_start_ns = precise_time_ns();
+                    (work_unit.fun)(work_unit.data, &mut proxy);
+                    working_ns += precise_time_ns() - work_start_ns;
+                    work_unit_count += 1;
+                } else {
+                    (work_unit.fun)(work_unit.data, &mut proxy);
+                }
 
                 // The work is done. Now decrement the count of outstanding work items. If this was
                 // the last work unit in the queue, then send a message on the channel.
@@ -152,6 +173,25 @@ impl<QUD:Send,WUD:Send> WorkerThread<QUD,WUD> {
                 }
             }
 
+            match trace {
+                Some((category, profiler_chan)) => {
+                    let end_ns = precise_time_ns();
+                    let idle_ns = (end_ns - start_ns) - working_ns;
+                    profiler_chan.send(TraceMsg(TraceEvent {
+                        category: category,
+                        thread_name: format!("Work queue worker {}", self.index),
+                        start_ns: start_ns,
+                        end_ns: end_ns,
+                        args: ~[
+                            ("work units", work_unit_count as f64),
+                            ("working (ms)", working_ns as f64 / 1000000.0),
+                            ("stealing or idle (ms)", idle_ns as f64 / 1000000.0),
+                        ],
+                    }))
+                }
+                None => {}
+            }
+
             // Give the deque back to the supervisor.
             self.chan.send(ReturnDequeMsg(self.index, deque))
         }
@@ -262,10 +302,28 @@ impl<QUD:Send,WUD:Send> WorkQueue<QUD,WUD> {
 
     /// Synchronously runs all the enqueued tasks and waits for them to complete.
     pub fn run(&mut self) {
+        self.run_helper(None)
+    }
+
+    /// Like `run`, but if the profiler is recording a trace, each worker adds the span of time it
+    /// spent on this run to it under the given category, along with how much of that time went to
+    /// work units as opposed to stealing or waiting.
+    pub fn run_traced(&mut self, category: ProfilerCategory, profiler_chan: &ProfilerChan) {
+        if profiler_chan.tracing {
+            self.run_helper(Some((category, profiler_chan.clone())))
+        } else {
+            self.run_helper(None)
+        }
+    }
+
+    fn run_helper(&mut self, trace: Option<(ProfilerCategory, ProfilerChan)>) {
         // Tell the workers to start.
         let mut work_count = AtomicUint::new(self.work_count);
         for worker in self.workers.mut_iter() {
-            worker.chan.send(StartMsg(worker.deque.take_unwrap(), &mut work_count, &self.data))
+            worker.chan.send(StartMsg(worker.deque.take_unwrap(),
+                                      &mut work_count,
+                                      &self.data,
+                                      trace.clone()))
         }
 
         // Wait for the work to finish.